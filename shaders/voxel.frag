@@ -0,0 +1,24 @@
+#version 450
+
+// One layer per block texture, indexed by `fragLayer`.
+layout(binding = 0) uniform sampler2DArray texSampler;
+
+layout(binding = 1) uniform Light {
+    vec4 to_light;
+    vec4 color;
+    vec4 ambient;
+} light;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragUv;
+layout(location = 2) in float fragAo;
+layout(location = 3) in vec3 fragNormal;
+layout(location = 4) flat in uint fragLayer;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    float diffuse = max(dot(normalize(fragNormal), light.to_light.xyz), 0.0);
+    vec3 lighting = light.ambient.rgb + light.color.rgb * diffuse;
+    outColor = texture(texSampler, vec3(fragUv, fragLayer)) * vec4(fragColor * fragAo * lighting, 1.0);
+}
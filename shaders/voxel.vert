@@ -0,0 +1,32 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_proj;
+} pc;
+
+// Block of the current draw in the dynamic uniform ring.
+layout(binding = 2) uniform Chunk {
+    vec4 offset;
+} chunk;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inUv;
+layout(location = 3) in float inAo;
+layout(location = 4) in vec3 inNormal;
+layout(location = 5) in uint inLayer;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragUv;
+layout(location = 2) out float fragAo;
+layout(location = 3) out vec3 fragNormal;
+layout(location = 4) flat out uint fragLayer;
+
+void main() {
+    gl_Position = pc.view_proj * vec4(inPosition + chunk.offset.xyz, 1.0);
+    fragColor = inColor;
+    fragUv = inUv;
+    fragAo = inAo;
+    fragNormal = inNormal;
+    fragLayer = inLayer;
+}
@@ -0,0 +1,116 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy_app::{App, Plugin, Update};
+use bevy_ecs::{
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::input::MouseState;
+
+/// Radians the camera turns per unit of mouse movement.
+pub const LOOK_SENSITIVITY: f32 = 0.002;
+
+/// Needs the [`InputPlugin`](crate::input::InputPlugin) for mouse-look.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Camera>()
+            .add_systems(Update, camera_look_system);
+    }
+}
+
+/// First-person camera in a right-handed, Y-up world.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Camera {
+    pub position: Vec3,
+    /// Rotation around the Y axis in radians. Zero looks along `-Z`.
+    pub yaw: f32,
+    /// Rotation above the horizon in radians.
+    pub pitch: f32,
+    /// Vertical field of view in radians.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 48.0, 0.0),
+            yaw: 0.0,
+            pitch: -0.5,
+            fov_y: 70f32.to_radians(),
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn forward(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vec3::new(-sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch)
+    }
+
+    /// Turns by `delta` mouse units, right and down for positive values.
+    /// The pitch stops short of straight up or down.
+    pub fn look(&mut self, delta: Vec2) {
+        const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+        self.yaw -= delta.x * LOOK_SENSITIVITY;
+        self.pitch = (self.pitch - delta.y * LOOK_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_to_rh(self.position, self.forward(), Vec3::Y)
+    }
+
+    /// Projection into Vulkan clip space: depth in `[0, 1]` and Y pointing down.
+    pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+        let mut projection = Mat4::perspective_rh(self.fov_y, aspect_ratio, self.near, self.far);
+        projection.y_axis.y *= -1.0;
+        projection
+    }
+
+    pub fn view_projection(&self, aspect_ratio: f32) -> Mat4 {
+        self.projection(aspect_ratio) * self.view()
+    }
+}
+
+/// Turns the camera with the mouse while the cursor is grabbed.
+fn camera_look_system(mouse: Res<MouseState>, mut camera: ResMut<Camera>) {
+    if mouse.grabbed && mouse.delta != Vec2::ZERO {
+        camera.look(mouse.delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looking_right_turns_towards_positive_x() {
+        let mut camera = Camera {
+            pitch: 0.0,
+            ..Default::default()
+        };
+        camera.look(Vec2::new(100.0, 0.0));
+        assert!(camera.forward().x > 0.0);
+        assert_eq!(camera.pitch, 0.0);
+    }
+
+    #[test]
+    fn pitch_stops_before_the_poles() {
+        let mut camera = Camera::default();
+        camera.look(Vec2::new(0.0, -1e6));
+        assert!(camera.pitch < FRAC_PI_2);
+        assert!(camera.forward().y < 1.0);
+
+        camera.look(Vec2::new(0.0, 1e6));
+        assert!(camera.pitch > -FRAC_PI_2);
+    }
+}
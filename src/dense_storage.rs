@@ -2,7 +2,7 @@ use std::{collections::VecDeque, sync::atomic::AtomicU32};
 
 use thiserror::Error;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Index {
     index: u32,
     generation: u32,
@@ -42,13 +42,22 @@ struct Entry<T> {
     generation: u32,
 }
 
-#[derive(Default)]
 pub struct DenseStorage<T> {
     buffer: Vec<Entry<T>>,
     len: u32,
     index_allocator: IndexAllocator,
 }
 
+impl<T> Default for DenseStorage<T> {
+    fn default() -> Self {
+        Self {
+            buffer: Vec::new(),
+            len: 0,
+            index_allocator: IndexAllocator::default(),
+        }
+    }
+}
+
 impl<T> DenseStorage<T> {
     /// Returns the number of stored items.
     pub fn len(&self) -> usize {
@@ -115,6 +124,32 @@ impl<T> DenseStorage<T> {
         }
     }
 
+    /// Returns `true` if an item is stored at `index` with the same generation.
+    pub fn contains(&self, index: Index) -> bool {
+        self.get(index).is_some()
+    }
+
+    /// Iterates over the stored items with their indices in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (Index, &T)> {
+        self.buffer.iter().enumerate().filter_map(|(i, entry)| {
+            let index = Index {
+                index: i as u32,
+                generation: entry.generation,
+            };
+            entry.value.as_ref().map(|value| (index, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Index, &mut T)> {
+        self.buffer.iter_mut().enumerate().filter_map(|(i, entry)| {
+            let index = Index {
+                index: i as u32,
+                generation: entry.generation,
+            };
+            entry.value.as_mut().map(|value| (index, value))
+        })
+    }
+
     pub fn get_mut(&mut self, index: Index) -> Option<&mut T> {
         let entry = self.buffer.get_mut(index.index as usize)?;
         if entry.generation == index.generation {
@@ -193,4 +228,24 @@ mod tests {
 
         assert_eq!(storage.buffer_len(), 3);
     }
+
+    #[test]
+    fn iterates_live_items() {
+        let mut storage = DenseStorage::<i32>::default();
+        let indices = [1, 2, 3].map(|value| {
+            let index = storage.index_allocator_mut().reserve();
+            storage.insert(index, value).unwrap();
+            index
+        });
+
+        storage.remove_recycle(indices[1]);
+        assert!(!storage.contains(indices[1]));
+        assert!(storage.contains(indices[2]));
+
+        for (_, value) in storage.iter_mut() {
+            *value *= 10;
+        }
+        let items = storage.iter().collect::<Vec<_>>();
+        assert_eq!(items, [(indices[0], &10), (indices[2], &30)]);
+    }
 }
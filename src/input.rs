@@ -0,0 +1,192 @@
+use bevy_app::{App, Plugin, PreUpdate};
+use bevy_ecs::{
+    event::EventReader,
+    resource::Resource,
+    schedule::IntoScheduleConfigs,
+    system::{Res, ResMut},
+};
+use glam::Vec2;
+use tracing::warn;
+use winit::{
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{CursorGrabMode, Window},
+};
+
+use crate::windowing::{AppWindows, RawDeviceMouseMotion, RawWnitWindowEvent};
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RawWnitWindowEvent>()
+            .add_event::<RawDeviceMouseMotion>()
+            .init_resource::<MouseState>()
+            .add_systems(
+                PreUpdate,
+                (grab_cursor_system, update_mouse_state_system).chain(),
+            );
+    }
+}
+
+/// Mouse position and the look delta accumulated over the current frame.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct MouseState {
+    /// Cursor position in physical pixels, `None` until the cursor enters the window.
+    pub position: Option<Vec2>,
+    /// Movement this frame, in pixels for the cursor or device units when grabbed.
+    pub delta: Vec2,
+    /// Whether the cursor is grabbed for mouse-look, from a click in the
+    /// primary window until Escape. Deltas then come from the raw device
+    /// motion, which is not clamped at the window edge.
+    pub grabbed: bool,
+}
+
+impl MouseState {
+    /// Delta for this frame from the cursor movement and raw device motion.
+    fn look_delta(&self, cursor_delta: Vec2, device_delta: Vec2) -> Vec2 {
+        if self.grabbed {
+            device_delta
+        } else {
+            cursor_delta
+        }
+    }
+}
+
+/// Whether `event` grabs the cursor, a click in the window, or releases it,
+/// Escape or the window losing focus.
+fn grab_change(event: &WindowEvent) -> Option<bool> {
+    match event {
+        WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+            ..
+        } => Some(true),
+        WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::Escape),
+                    state: ElementState::Pressed,
+                    ..
+                },
+            ..
+        }
+        | WindowEvent::Focused(false) => Some(false),
+        _ => None,
+    }
+}
+
+/// Hides and locks the cursor in `window`, or confines it where locking is
+/// not supported. Returns whether it is grabbed.
+fn grab_cursor(window: &Window) -> bool {
+    let grabbed = window
+        .set_cursor_grab(CursorGrabMode::Locked)
+        .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+    if let Err(err) = grabbed {
+        warn!("Failed to grab the cursor: {err}");
+        return false;
+    }
+
+    window.set_cursor_visible(false);
+    true
+}
+
+fn release_cursor(window: &Window) {
+    if let Err(err) = window.set_cursor_grab(CursorGrabMode::None) {
+        warn!("Failed to release the cursor: {err}");
+    }
+    window.set_cursor_visible(true);
+}
+
+/// Grabs the cursor for mouse-look when the primary window is clicked and
+/// releases it on Escape or when the window loses focus.
+fn grab_cursor_system(
+    windows: Option<Res<AppWindows>>,
+    mut mouse: ResMut<MouseState>,
+    mut window_events: EventReader<RawWnitWindowEvent>,
+) {
+    let Some(windows) = windows else {
+        window_events.clear();
+        return;
+    };
+
+    for event in window_events.read() {
+        if event.window_id != windows.primary.id() {
+            continue;
+        }
+
+        match grab_change(&event.event) {
+            Some(true) if !mouse.grabbed => mouse.grabbed = grab_cursor(&windows.primary),
+            Some(false) if mouse.grabbed => {
+                release_cursor(&windows.primary);
+                mouse.grabbed = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn update_mouse_state_system(
+    mut mouse: ResMut<MouseState>,
+    mut window_events: EventReader<RawWnitWindowEvent>,
+    mut device_motion: EventReader<RawDeviceMouseMotion>,
+) {
+    let mut cursor_delta = Vec2::ZERO;
+    for event in window_events.read() {
+        match event.event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vec2::new(position.x as f32, position.y as f32);
+                if let Some(previous) = mouse.position {
+                    cursor_delta += position - previous;
+                }
+                mouse.position = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => mouse.position = None,
+            _ => {}
+        }
+    }
+
+    let device_delta = device_motion.read().map(|motion| motion.delta).sum();
+
+    mouse.delta = mouse.look_delta(cursor_delta, device_delta);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicks_grab_and_escape_releases() {
+        let click = WindowEvent::MouseInput {
+            device_id: winit::event::DeviceId::dummy(),
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+        };
+        assert_eq!(grab_change(&click), Some(true));
+
+        let right_click = WindowEvent::MouseInput {
+            device_id: winit::event::DeviceId::dummy(),
+            state: ElementState::Pressed,
+            button: MouseButton::Right,
+        };
+        assert_eq!(grab_change(&right_click), None);
+
+        assert_eq!(grab_change(&WindowEvent::Focused(false)), Some(false));
+        assert_eq!(grab_change(&WindowEvent::Focused(true)), None);
+    }
+
+    #[test]
+    fn grabbed_cursor_prefers_device_delta() {
+        let cursor = Vec2::new(3.0, 0.0);
+        let device = Vec2::new(12.0, -4.0);
+
+        let free = MouseState::default();
+        assert_eq!(free.look_delta(cursor, device), cursor);
+
+        let grabbed = MouseState {
+            grabbed: true,
+            ..Default::default()
+        };
+        assert_eq!(grabbed.look_delta(cursor, device), device);
+    }
+}
@@ -1,4 +1,4 @@
-use bevy_app::{App, AppExit, Plugin, PluginsState};
+use bevy_app::{App, AppExit, Plugin, PluginGroup, PluginsState};
 use tracing::{error, info};
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 use winit::{
@@ -6,25 +6,48 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
 };
 
-use crate::{rendering::RenderingPlugin, windowing::WindowingPlugin};
+use crate::{
+    plugins::VoxelDefaultPlugins,
+    rendering::{RenderingPlugin, SampleCount, VALIDATION_TARGET},
+    world::{WorldPlugin, chunk_file::WorldSaveDir},
+};
 
+pub mod camera;
 pub mod dense_storage;
+pub mod input;
+pub mod plugins;
 mod rendering;
+pub mod time;
 pub mod utils;
 mod windowing;
+pub mod world;
+
+/// Linear light blue the frames are cleared to behind the terrain.
+const SKY_COLOR: [f32; 4] = [0.53, 0.81, 0.92, 1.0];
 
 fn main() {
     tracing_subscriber::registry()
-        .with(
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-        )
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+            format!(
+                "{}=debug,{VALIDATION_TARGET}=debug",
+                env!("CARGO_CRATE_NAME")
+            )
+            .into()
+        }))
         .with(tracing_subscriber::fmt::layer())
         .init();
 
     info!("Logging is successfully initialized");
 
     App::new()
-        .add_plugins((WindowingPlugin, RenderingPlugin))
+        .insert_resource(WorldSaveDir::game())
+        .add_plugins((
+            VoxelDefaultPlugins.build().set(
+                RenderingPlugin::default()
+                    .with_msaa(SampleCount::X4)
+                    .with_clear_color(SKY_COLOR),
+            ),
+            WorldPlugin,
+        ))
         .run();
 }
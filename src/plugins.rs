@@ -0,0 +1,81 @@
+use bevy_app::{PluginGroup, PluginGroupBuilder};
+
+use crate::{
+    camera::CameraPlugin,
+    input::InputPlugin,
+    rendering::{CommonStoragesPlugin, RenderingPlugin, StoragePlugin},
+    time::TimePlugin,
+    windowing::WindowingPlugin,
+};
+
+/// The engine plugins every app needs, in the order they depend on each other.
+///
+/// Members can be replaced or disabled through the builder, for example to run
+/// tests without a display:
+///
+/// ```ignore
+/// App::new().add_plugins(
+///     VoxelDefaultPlugins
+///         .build()
+///         .disable::<WindowingPlugin>()
+///         .add(HeadlessRunnerPlugin { updates: 10 }),
+/// );
+/// ```
+///
+/// Game content, like the [`WorldPlugin`](crate::world::WorldPlugin), is added
+/// separately.
+pub struct VoxelDefaultPlugins;
+
+impl PluginGroup for VoxelDefaultPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(WindowingPlugin::default())
+            .add(TimePlugin)
+            .add(InputPlugin)
+            .add(StoragePlugin)
+            .add(CommonStoragesPlugin)
+            .add(RenderingPlugin::default())
+            .add(CameraPlugin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{App, AppExit};
+
+    use super::*;
+    use crate::{
+        camera::Camera,
+        rendering::{DeferredDestroyQueue, FrameStats},
+        time::Time,
+        windowing::HeadlessRunnerPlugin,
+    };
+
+    #[test]
+    fn default_plugins_are_added() {
+        let mut app = App::new();
+        app.add_plugins(
+            VoxelDefaultPlugins
+                .build()
+                .disable::<WindowingPlugin>()
+                .add(HeadlessRunnerPlugin { updates: 1 }),
+        );
+
+        assert!(!app.is_plugin_added::<WindowingPlugin>());
+        assert!(app.is_plugin_added::<TimePlugin>());
+        assert!(app.is_plugin_added::<InputPlugin>());
+        assert!(app.is_plugin_added::<StoragePlugin>());
+        assert!(app.is_plugin_added::<CommonStoragesPlugin>());
+        assert!(app.is_plugin_added::<RenderingPlugin>());
+        assert!(app.is_plugin_added::<CameraPlugin>());
+
+        let world = app.world();
+        assert!(world.contains_resource::<Time>());
+        assert!(world.contains_resource::<Camera>());
+        assert!(world.contains_resource::<FrameStats>());
+        assert!(world.contains_resource::<DeferredDestroyQueue>());
+
+        // The storages are created and their destroy order verified at startup.
+        assert_eq!(app.run(), AppExit::Success);
+    }
+}
@@ -0,0 +1,56 @@
+use ash::{Device, Instance, vk};
+use bevy_ecs::{
+    change_detection::DetectChangesMut,
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
+
+use super::VulkanApp;
+
+pub fn create_allocator(
+    instance: &Instance,
+    device: &Device,
+    physical_device: vk::PhysicalDevice,
+) -> Allocator {
+    Allocator::new(&AllocatorCreateDesc {
+        instance: instance.clone(),
+        device: device.clone(),
+        physical_device,
+        debug_settings: Default::default(),
+        buffer_device_address: false,
+        allocation_sizes: Default::default(),
+    })
+    .expect("Failed to create the GPU allocator")
+}
+
+/// Device memory usage of the [`Allocator`], updated every frame.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuMemoryStats {
+    /// Bytes handed out to live allocations.
+    pub used_bytes: u64,
+    /// Bytes of device memory blocks reserved by the allocator.
+    pub reserved_bytes: u64,
+    pub allocation_count: usize,
+    /// Number of `vkAllocateMemory` allocations backing the blocks.
+    pub block_count: usize,
+}
+
+impl GpuMemoryStats {
+    pub fn from_allocator(allocator: &Allocator) -> Self {
+        let report = allocator.generate_report();
+        Self {
+            used_bytes: report.total_allocated_bytes,
+            reserved_bytes: report.total_reserved_bytes,
+            allocation_count: report.allocations.len(),
+            block_count: report.blocks.len(),
+        }
+    }
+}
+
+pub fn update_gpu_memory_stats_system(
+    vulkan_app: Res<VulkanApp>,
+    mut stats: ResMut<GpuMemoryStats>,
+) {
+    stats.set_if_neq(GpuMemoryStats::from_allocator(&vulkan_app.allocator));
+}
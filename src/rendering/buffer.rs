@@ -0,0 +1,147 @@
+use ash::{Device, vk};
+use bytemuck::Pod;
+use gpu_allocator::{
+    MemoryLocation,
+    vulkan::{Allocation, AllocationCreateDesc, AllocationScheme, Allocator},
+};
+
+use super::{
+    debug_utils::set_debug_name,
+    transfer::{UploadQueues, begin_single_time_commands, end_single_time_commands},
+};
+
+/// A buffer bound to memory sub-allocated from the [`Allocator`].
+pub struct Buffer {
+    pub buffer: vk::Buffer,
+    pub allocation: Allocation,
+}
+
+impl Buffer {
+    /// Destroys the buffer and returns its memory to the allocator.
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        unsafe { device.destroy_buffer(self.buffer, None) };
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .expect("Failed to free buffer memory");
+    }
+}
+
+pub fn create_buffer(
+    device: &Device,
+    allocator: &mut Allocator,
+    name: &str,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    location: MemoryLocation,
+) -> Buffer {
+    let buffer_info = vk::BufferCreateInfo::default()
+        .size(size)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let buffer = unsafe { device.create_buffer(&buffer_info, None).unwrap() };
+    set_debug_name(device, buffer, name);
+
+    let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+    let allocation = allocator
+        .allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location,
+            linear: true,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })
+        .expect("Failed to allocate buffer memory");
+
+    unsafe {
+        device
+            .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+            .unwrap()
+    };
+
+    Buffer { buffer, allocation }
+}
+
+/// Copies `size` bytes from `src` to `dst` on the transfer queue and waits
+/// for the copy to finish. `dst` ends up owned by the graphics queue family.
+pub fn copy_buffer(
+    device: &Device,
+    queues: &UploadQueues,
+    src: vk::Buffer,
+    dst: vk::Buffer,
+    size: vk::DeviceSize,
+) {
+    let acquire_command_buffer = queues
+        .needs_ownership_transfer()
+        .then(|| begin_single_time_commands(device, queues.graphics.command_pool));
+
+    // Only wait for this copy, other work may be running on the transfer queue.
+    queues
+        .transfer
+        .submit_once_async(device, |command_buffer| {
+            let region = vk::BufferCopy::default().size(size);
+            unsafe { device.cmd_copy_buffer(command_buffer, src, dst, &[region]) };
+
+            if let Some(acquire_command_buffer) = acquire_command_buffer {
+                queues.transfer_buffer_ownership(
+                    device,
+                    command_buffer,
+                    acquire_command_buffer,
+                    dst,
+                );
+            }
+        })
+        .wait(device, queues.transfer.command_pool);
+
+    if let Some(acquire_command_buffer) = acquire_command_buffer {
+        end_single_time_commands(
+            device,
+            queues.graphics.queue,
+            queues.graphics.command_pool,
+            acquire_command_buffer,
+        );
+    }
+}
+
+/// Creates a GPU-only buffer filled with `data` through a staging buffer.
+pub fn create_device_local_buffer<T: Pod>(
+    device: &Device,
+    allocator: &mut Allocator,
+    queues: &UploadQueues,
+    name: &str,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+) -> Buffer {
+    let bytes: &[u8] = bytemuck::cast_slice(data);
+    let size = bytes.len() as vk::DeviceSize;
+
+    let mut staging = create_buffer(
+        device,
+        allocator,
+        "staging",
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::CpuToGpu,
+    );
+
+    staging
+        .allocation
+        .mapped_slice_mut()
+        .expect("Staging memory must be host visible")[..bytes.len()]
+        .copy_from_slice(bytes);
+
+    let buffer = create_buffer(
+        device,
+        allocator,
+        name,
+        size,
+        vk::BufferUsageFlags::TRANSFER_DST | usage,
+        MemoryLocation::GpuOnly,
+    );
+
+    copy_buffer(device, queues, staging.buffer, buffer.buffer, size);
+
+    staging.destroy(device, allocator);
+
+    buffer
+}
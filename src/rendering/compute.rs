@@ -0,0 +1,267 @@
+#[cfg(feature = "compute")]
+use ash::Device;
+use ash::vk;
+
+#[cfg(feature = "compute")]
+use super::{create_shader_module, transfer::QueueContext};
+
+/// A compute pipeline together with the layouts it was created with.
+#[cfg(feature = "compute")]
+pub struct ComputePipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+}
+
+#[cfg(feature = "compute")]
+impl ComputePipeline {
+    pub fn destroy(&self, device: &Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+/// Creates a compute pipeline from SPIR-V `shader` whose only descriptor set
+/// has the given `bindings`.
+#[cfg(feature = "compute")]
+pub fn create_compute_pipeline(
+    device: &Device,
+    shader: &[u8],
+    bindings: &[vk::DescriptorSetLayoutBinding],
+) -> ComputePipeline {
+    let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings);
+    let descriptor_set_layout = unsafe {
+        device
+            .create_descriptor_set_layout(&set_layout_info, None)
+            .unwrap()
+    };
+
+    let set_layouts = &[descriptor_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::default().set_layouts(set_layouts);
+    let layout = unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() };
+
+    let shader_module = create_shader_module(device, shader).expect("Invalid compute shader");
+
+    let stage = vk::PipelineShaderStageCreateInfo::default()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .name(c"main");
+
+    let pipeline_info = vk::ComputePipelineCreateInfo::default()
+        .stage(stage)
+        .layout(layout);
+
+    let pipeline = unsafe {
+        device
+            .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+            .unwrap()[0]
+    };
+
+    unsafe { device.destroy_shader_module(shader_module, None) };
+
+    ComputePipeline {
+        pipeline,
+        layout,
+        descriptor_set_layout,
+    }
+}
+
+/// Runs `pipeline` with `group_counts` workgroups on the compute queue and
+/// waits for it to finish.
+///
+/// A barrier makes the shader writes visible to the host and to later
+/// transfers once this returns.
+#[cfg(feature = "compute")]
+pub fn dispatch(
+    device: &Device,
+    compute: &QueueContext,
+    pipeline: &ComputePipeline,
+    descriptor_set: vk::DescriptorSet,
+    group_counts: [u32; 3],
+) {
+    compute.submit_once(device, |command_buffer| unsafe {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline.layout,
+            0,
+            &[descriptor_set],
+            &[],
+        );
+
+        let [x, y, z] = group_counts;
+        device.cmd_dispatch(command_buffer, x, y, z);
+
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ | vk::AccessFlags::TRANSFER_READ);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+    });
+}
+
+/// Picks the queue family compute work runs on.
+///
+/// Prefers an async compute family without graphics support and falls back to
+/// `graphics_family`, which Vulkan guarantees to support compute if any family does.
+pub fn pick_compute_family(properties: &[vk::QueueFamilyProperties], graphics_family: u32) -> u32 {
+    properties
+        .iter()
+        .position(|family| {
+            family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map_or(graphics_family, |index| index as u32)
+}
+
+#[cfg(all(test, feature = "compute"))]
+mod tests {
+    use ash::Entry;
+    use gpu_allocator::{
+        MemoryLocation,
+        vulkan::{Allocator, AllocatorCreateDesc},
+    };
+
+    use super::*;
+    use crate::rendering::buffer::create_buffer;
+
+    /// Threads per workgroup of `shaders/fill.comp`.
+    const FILL_WORKGROUP_SIZE: u32 = 64;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn fill_shader_writes_buffer() {
+        const COUNT: u32 = 1000;
+
+        let entry = unsafe { Entry::load().unwrap() };
+        let app_info = vk::ApplicationInfo::default().api_version(vk::API_VERSION_1_0);
+        let instance_info = vk::InstanceCreateInfo::default().application_info(&app_info);
+        let instance = unsafe { entry.create_instance(&instance_info, None).unwrap() };
+
+        let physical_device = unsafe { instance.enumerate_physical_devices().unwrap()[0] };
+        let properties =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let graphics_family = properties
+            .iter()
+            .position(|family| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .unwrap() as u32;
+        let compute_family = pick_compute_family(&properties, graphics_family);
+
+        let queue_infos = &[vk::DeviceQueueCreateInfo::default()
+            .queue_family_index(compute_family)
+            .queue_priorities(&[1.0])];
+        let device_info = vk::DeviceCreateInfo::default().queue_create_infos(queue_infos);
+        let device = unsafe {
+            instance
+                .create_device(physical_device, &device_info, None)
+                .unwrap()
+        };
+
+        let mut allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: false,
+            allocation_sizes: Default::default(),
+        })
+        .unwrap();
+
+        let pool_info = vk::CommandPoolCreateInfo::default().queue_family_index(compute_family);
+        let compute = QueueContext {
+            family: compute_family,
+            queue: unsafe { device.get_device_queue(compute_family, 0) },
+            command_pool: unsafe { device.create_command_pool(&pool_info, None).unwrap() },
+        };
+
+        let size = (COUNT as usize * size_of::<u32>()) as vk::DeviceSize;
+        let mut buffer = create_buffer(
+            &device,
+            &mut allocator,
+            "fill output",
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuToCpu,
+        );
+
+        let pipeline = create_compute_pipeline(
+            &device,
+            include_bytes!("../../shaders/out/fill.comp.spv"),
+            &[vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)],
+        );
+
+        let pool_sizes = &[vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(pool_sizes)
+            .max_sets(1);
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_info, None)
+                .unwrap()
+        };
+        let set_layouts = &[pipeline.descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(set_layouts);
+        let descriptor_set = unsafe { device.allocate_descriptor_sets(&allocate_info).unwrap()[0] };
+
+        let buffer_infos = &[vk::DescriptorBufferInfo::default()
+            .buffer(buffer.buffer)
+            .offset(0)
+            .range(size)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(buffer_infos);
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+        dispatch(
+            &device,
+            &compute,
+            &pipeline,
+            descriptor_set,
+            [COUNT.div_ceil(FILL_WORKGROUP_SIZE), 1, 1],
+        );
+
+        let bytes = &buffer.allocation.mapped_slice().unwrap()[..size as usize];
+        let values: &[u32] = bytemuck::cast_slice(bytes);
+        assert!(
+            values
+                .iter()
+                .enumerate()
+                .all(|(i, value)| *value == i as u32 * 2)
+        );
+
+        unsafe {
+            device.destroy_descriptor_pool(descriptor_pool, None);
+            pipeline.destroy(&device);
+            buffer.destroy(&device, &mut allocator);
+            drop(allocator);
+            device.destroy_command_pool(compute.command_pool, None);
+            device.destroy_device(None);
+            instance.destroy_instance(None);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use bevy_ecs::{
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use glam::{IVec3, Mat4, Vec3, Vec4};
+
+use super::{VulkanApp, mesh::chunk_offset};
+use crate::world::chunk::CHUNK_SIZE;
+
+/// Skips drawing chunks outside the camera frustum. Enabled by default,
+/// disabling it helps to debug pop-in.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrustumCulling(pub bool);
+
+impl Default for FrustumCulling {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// Axis-aligned bounding box in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn of_chunk(coord: IVec3) -> Self {
+        let min = chunk_offset(coord);
+        Self {
+            min,
+            max: min + Vec3::splat(CHUNK_SIZE as f32),
+        }
+    }
+}
+
+/// The six planes bounding what a view-projection matrix maps into Vulkan clip space.
+///
+/// Every plane is stored as `(normal, distance)` with the normal pointing inside.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Left, right, top, bottom, near and far. The top plane comes first as
+    /// Y points down in Vulkan clip space.
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub fn from_view_projection(view_proj: Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+
+        // Depth is in `[0, 1]`, so the near plane is `z >= 0` rather than `z >= -w`.
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncate().length();
+            plane / length
+        });
+
+        Self { planes }
+    }
+
+    /// Whether `aabb` is at least partially inside.
+    ///
+    /// Boxes near a corner of the frustum may be reported inside while they
+    /// aren't, which only costs an unneeded draw.
+    pub fn contains_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            // The corner furthest along the normal.
+            let positive = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(positive) + plane.w >= 0.0
+        })
+    }
+}
+
+pub fn update_frustum_culling_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    culling: Res<FrustumCulling>,
+) {
+    if vulkan_app.frustum_culling != culling.0 {
+        vulkan_app.frustum_culling = culling.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    /// Camera at the origin looking along `-Z` with a 90° field of view.
+    fn frustum() -> Frustum {
+        let camera = Camera {
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_y: 90f32.to_radians(),
+            near: 0.1,
+            far: 100.0,
+        };
+        Frustum::from_view_projection(camera.view_projection(1.0))
+    }
+
+    fn cube(center: Vec3, half_size: f32) -> Aabb {
+        Aabb {
+            min: center - half_size,
+            max: center + half_size,
+        }
+    }
+
+    #[test]
+    fn extracts_planes() {
+        let [left, right, top, bottom, near, far] = frustum().planes;
+
+        for plane in [left, right, top, bottom, near, far] {
+            assert!((plane.truncate().length() - 1.0).abs() < 1e-5);
+        }
+
+        assert!(near.truncate().abs_diff_eq(Vec3::NEG_Z, 1e-5));
+        assert!((near.w - -0.1).abs() < 1e-4);
+        assert!(far.truncate().abs_diff_eq(Vec3::Z, 1e-5));
+        assert!((far.w - 100.0).abs() < 1e-2);
+
+        // With a 90° field of view the side planes are at 45°.
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(
+            left.truncate()
+                .abs_diff_eq(Vec3::new(diagonal, 0.0, -diagonal), 1e-5)
+        );
+        assert!(
+            right
+                .truncate()
+                .abs_diff_eq(Vec3::new(-diagonal, 0.0, -diagonal), 1e-5)
+        );
+        assert!(top.y < 0.0 && bottom.y > 0.0);
+    }
+
+    #[test]
+    fn aabb_inside() {
+        assert!(frustum().contains_aabb(&cube(Vec3::new(0.0, 0.0, -10.0), 1.0)));
+    }
+
+    #[test]
+    fn aabb_outside() {
+        let frustum = frustum();
+        // Behind the camera.
+        assert!(!frustum.contains_aabb(&cube(Vec3::new(0.0, 0.0, 10.0), 1.0)));
+        // Beyond the far plane.
+        assert!(!frustum.contains_aabb(&cube(Vec3::new(0.0, 0.0, -200.0), 1.0)));
+        // Left of the left plane.
+        assert!(!frustum.contains_aabb(&cube(Vec3::new(-20.0, 0.0, -10.0), 1.0)));
+        // Above the top plane.
+        assert!(!frustum.contains_aabb(&cube(Vec3::new(0.0, 20.0, -10.0), 1.0)));
+    }
+
+    #[test]
+    fn aabb_straddling() {
+        let frustum = frustum();
+        // Crosses the left plane.
+        assert!(frustum.contains_aabb(&cube(Vec3::new(-10.0, 0.0, -10.0), 1.0)));
+        // Contains the camera.
+        assert!(frustum.contains_aabb(&cube(Vec3::ZERO, 1.0)));
+        // Crosses the far plane.
+        assert!(frustum.contains_aabb(&cube(Vec3::new(0.0, 0.0, -100.0), 1.0)));
+    }
+
+    #[test]
+    fn chunk_aabb() {
+        let aabb = Aabb::of_chunk(IVec3::new(1, -1, 0));
+        let size = CHUNK_SIZE as f32;
+        assert_eq!(aabb.min, Vec3::new(size, -size, 0.0));
+        assert_eq!(aabb.max, Vec3::new(2.0 * size, 0.0, size));
+    }
+}
@@ -0,0 +1,268 @@
+use std::ops::RangeInclusive;
+
+use ash::{Device, vk};
+use bevy_ecs::{
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use glam::Vec3;
+use gpu_allocator::vulkan::Allocator;
+
+use super::{
+    GraphicsPipelineDesc, PipelineTarget, VulkanApp,
+    buffer::{Buffer, create_device_local_buffer},
+    create_graphics_pipeline,
+    debug_utils::set_debug_name,
+    mesh::{ChunkUniform, DrawItem},
+    raster::RasterConfig,
+    recording::{ChunkDrawState, ThreadLocalCommandPools},
+    transfer::UploadQueues,
+    uniform_ring::DynamicUniformRing,
+};
+use crate::world::meshing::Vertex;
+
+/// Ground grid and axis lines drawn around the world origin.
+///
+/// Read once when the [`VulkanApp`] is created, see [`ShowDebugGrid`] to toggle it.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct DebugGrid {
+    /// Number of cells from the origin to the edge of the grid along X and Z.
+    pub half_extent: u32,
+    /// Size of a grid cell in world units.
+    pub spacing: f32,
+    /// Length of the X, Y and Z axis lines.
+    pub axis_length: f32,
+    /// Requested line width in pixels, see [`line_width`].
+    pub line_width: f32,
+}
+
+impl Default for DebugGrid {
+    fn default() -> Self {
+        Self {
+            half_extent: 32,
+            spacing: 16.0,
+            axis_length: 64.0,
+            line_width: 1.0,
+        }
+    }
+}
+
+/// Whether the [`DebugGrid`] is drawn. Hidden by default.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShowDebugGrid(pub bool);
+
+const GRID_COLOR: [f32; 3] = [0.4, 0.4, 0.4];
+const AXIS_COLORS: [(Vec3, [f32; 3]); 3] = [
+    (Vec3::X, [1.0, 0.0, 0.0]),
+    (Vec3::Y, [0.0, 1.0, 0.0]),
+    (Vec3::Z, [0.0, 0.0, 1.0]),
+];
+
+/// Line list vertices of the grid on the `y = 0` plane followed by the axes.
+pub fn grid_vertices(grid: &DebugGrid) -> Vec<Vertex> {
+    let vertex = |position: Vec3, color| Vertex {
+        position: position.to_array(),
+        color,
+        uv: [0.0; 2],
+        ao: 1.0,
+        normal: [0.0; 3],
+        layer: 0,
+    };
+
+    let half = grid.half_extent as i32;
+    let edge = grid.half_extent as f32 * grid.spacing;
+
+    let mut vertices = Vec::with_capacity((half as usize * 2 + 1) * 4 + AXIS_COLORS.len() * 2);
+    for i in -half..=half {
+        let offset = i as f32 * grid.spacing;
+        vertices.extend([
+            vertex(Vec3::new(offset, 0.0, -edge), GRID_COLOR),
+            vertex(Vec3::new(offset, 0.0, edge), GRID_COLOR),
+            vertex(Vec3::new(-edge, 0.0, offset), GRID_COLOR),
+            vertex(Vec3::new(edge, 0.0, offset), GRID_COLOR),
+        ]);
+    }
+
+    for (axis, color) in AXIS_COLORS {
+        vertices.extend([
+            vertex(Vec3::ZERO, color),
+            vertex(axis * grid.axis_length, color),
+        ]);
+    }
+
+    vertices
+}
+
+/// Clamps `requested` to the supported line widths, which is only ever 1 pixel
+/// without the `wideLines` feature.
+pub fn line_width(requested: f32, wide_lines: bool, range: RangeInclusive<f32>) -> f32 {
+    if wide_lines {
+        requested.clamp(*range.start(), *range.end())
+    } else {
+        1.0
+    }
+}
+
+/// Pipeline and vertex buffer of the debug grid.
+pub struct DebugLines {
+    pub visible: bool,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    /// Kept to recreate the pipeline, see [`DebugLines::rebuild_pipeline`].
+    desc: GraphicsPipelineDesc,
+    vertex_buffer: Buffer,
+    /// Draws go through the indexed chunk recording path.
+    index_buffer: Buffer,
+    index_count: u32,
+    /// Single recording thread, the grid is one draw.
+    pools: ThreadLocalCommandPools,
+}
+
+impl DebugLines {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        queues: &UploadQueues,
+        target: PipelineTarget,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        grid: &DebugGrid,
+        desc: GraphicsPipelineDesc,
+    ) -> Self {
+        let vertices = grid_vertices(grid);
+        let indices = (0..vertices.len() as u32).collect::<Vec<_>>();
+
+        let vertex_buffer = create_device_local_buffer(
+            device,
+            allocator,
+            queues,
+            "debug grid vertices",
+            &vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        let index_buffer = create_device_local_buffer(
+            device,
+            allocator,
+            queues,
+            "debug grid indices",
+            &indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        );
+
+        let (pipeline, pipeline_layout) =
+            create_graphics_pipeline::<Vertex>(device, target, descriptor_set_layout, &desc);
+        set_debug_name(device, pipeline, "debug grid pipeline");
+
+        Self {
+            visible: false,
+            pipeline,
+            pipeline_layout,
+            desc,
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            pools: ThreadLocalCommandPools::new(device, queues.graphics.family, 1),
+        }
+    }
+
+    /// Records the grid into a secondary command buffer of `frame`, using the
+    /// target and camera of the chunk draws.
+    pub fn record(
+        &self,
+        device: &Device,
+        frame: usize,
+        chunk_state: &ChunkDrawState,
+        chunk_uniforms: &mut DynamicUniformRing,
+    ) -> vk::CommandBuffer {
+        let state = ChunkDrawState {
+            pipeline: self.pipeline,
+            pipeline_layout: self.pipeline_layout,
+            label: "DebugGrid",
+            ..*chunk_state
+        };
+        let draw = chunk_uniforms
+            .push(&ChunkUniform::new(Vec3::ZERO))
+            .map(|uniform_offset| DrawItem {
+                vertex_buffer: self.vertex_buffer.buffer,
+                index_buffer: self.index_buffer.buffer,
+                index_count: self.index_count,
+                uniform_offset,
+                query: None,
+            });
+
+        self.pools
+            .record_on_current_thread(device, frame, &state, draw.as_slice())
+    }
+
+    /// Recreates the pipeline with the options of `raster`. The device must be
+    /// idle.
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &Device,
+        target: PipelineTarget,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        raster: &RasterConfig,
+    ) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+
+        self.desc = self.desc.with_raster(raster);
+        (self.pipeline, self.pipeline_layout) =
+            create_graphics_pipeline::<Vertex>(device, target, descriptor_set_layout, &self.desc);
+        set_debug_name(device, self.pipeline, "debug grid pipeline");
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        self.pools.destroy(device);
+        self.vertex_buffer.destroy(device, allocator);
+        self.index_buffer.destroy(device, allocator);
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+pub fn update_debug_grid_visibility_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    show: Res<ShowDebugGrid>,
+) {
+    if vulkan_app.debug_lines.visible != show.0 {
+        vulkan_app.debug_lines.visible = show.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_lines_span_the_extent() {
+        let grid = DebugGrid {
+            half_extent: 2,
+            spacing: 4.0,
+            axis_length: 10.0,
+            line_width: 1.0,
+        };
+        let vertices = grid_vertices(&grid);
+
+        // 5 lines along each of X and Z, then 3 axes.
+        assert_eq!(vertices.len(), 5 * 4 + 3 * 2);
+        assert!(vertices[..20].iter().all(|vertex| vertex.position[1] == 0.0
+            && vertex.position[0].abs() <= 8.0
+            && vertex.position[2].abs() <= 8.0));
+
+        let y_axis = &vertices[22..24];
+        assert_eq!(y_axis[0].position, [0.0; 3]);
+        assert_eq!(y_axis[1].position, [0.0, 10.0, 0.0]);
+        assert_eq!(y_axis[1].color, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn wide_lines_fall_back_to_one_pixel() {
+        assert_eq!(line_width(3.0, false, 1.0..=8.0), 1.0);
+        assert_eq!(line_width(3.0, true, 1.0..=8.0), 3.0);
+        assert_eq!(line_width(16.0, true, 1.0..=8.0), 8.0);
+    }
+}
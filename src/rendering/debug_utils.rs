@@ -0,0 +1,167 @@
+use std::{ffi::CString, sync::RwLock};
+
+use ash::{
+    Device, Instance, ext,
+    vk::{self, Handle},
+};
+use glam::IVec3;
+use tracing::warn;
+
+/// Loader of the device whose objects are named and command buffers
+/// labeled, `None` when `VK_EXT_debug_utils` isn't enabled.
+///
+/// Objects are created in many places that only get the [`Device`], so the
+/// loader is kept here rather than passed around.
+static DEBUG_UTILS: RwLock<Option<ext::debug_utils::Device>> = RwLock::new(None);
+
+/// Makes [`set_debug_name`] and [`DebugLabel`] work for `device`. The
+/// instance must have been created with `VK_EXT_debug_utils`.
+pub fn enable_debug_utils(instance: &Instance, device: &Device) {
+    *DEBUG_UTILS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+        Some(ext::debug_utils::Device::new(instance, device));
+}
+
+/// Stops naming and labeling for `device`, called before it is destroyed.
+pub fn disable_debug_utils(device: &Device) {
+    let mut debug_utils = DEBUG_UTILS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if debug_utils
+        .as_ref()
+        .is_some_and(|debug_utils| debug_utils.device() == device.handle())
+    {
+        *debug_utils = None;
+    }
+}
+
+/// Names `handle` in validation messages and graphics debuggers.
+///
+/// Does nothing unless [`enable_debug_utils`] was called for `device`.
+pub fn set_debug_name<H: Handle>(device: &Device, handle: H, name: &str) {
+    let Some(debug_utils) = debug_utils_of(device) else {
+        return;
+    };
+
+    let Ok(name) = CString::new(name) else {
+        warn!("Debug name {name:?} contains a nul byte");
+        return;
+    };
+    let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+        .object_handle(handle)
+        .object_name(&name);
+    if let Err(err) = unsafe { debug_utils.set_debug_utils_object_name(&name_info) } {
+        warn!("Failed to name {:?} {name:?}: {err}", H::TYPE);
+    }
+}
+
+fn debug_utils_of(device: &Device) -> Option<ext::debug_utils::Device> {
+    DEBUG_UTILS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .filter(|debug_utils| debug_utils.device() == device.handle())
+        .cloned()
+}
+
+/// Labels the commands recorded into a command buffer until it is dropped,
+/// which shows them as a region in RenderDoc and validation messages.
+///
+/// Does nothing unless [`enable_debug_utils`] was called for the device.
+#[must_use = "The label ends when dropped"]
+pub struct DebugLabel {
+    debug_utils: Option<ext::debug_utils::Device>,
+    command_buffer: vk::CommandBuffer,
+}
+
+impl DebugLabel {
+    /// Begins the label in `command_buffer`, which must be recording until the
+    /// label is dropped.
+    ///
+    /// Inside a render pass recorded with secondary command buffers, labels
+    /// can only be used in the secondary ones.
+    pub fn begin(device: &Device, command_buffer: vk::CommandBuffer, name: &str) -> Self {
+        let debug_utils = debug_utils_of(device).and_then(|debug_utils| {
+            let name = CString::new(name).ok()?;
+            let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+            unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+            Some(debug_utils)
+        });
+
+        Self {
+            debug_utils,
+            command_buffer,
+        }
+    }
+}
+
+impl Drop for DebugLabel {
+    fn drop(&mut self) {
+        if let Some(debug_utils) = &self.debug_utils {
+            unsafe { debug_utils.cmd_end_debug_utils_label(self.command_buffer) };
+        }
+    }
+}
+
+/// Name of an object of the chunk at `coord`, e.g. `chunk(3,0,-2).vertex_buffer`.
+pub fn chunk_object_name(coord: IVec3, object: &str) -> String {
+    format!("chunk({},{},{}).{object}", coord.x, coord.y, coord.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::VulkanApp;
+
+    #[test]
+    fn chunk_names() {
+        assert_eq!(
+            chunk_object_name(IVec3::new(3, 0, -2), "vertex_buffer"),
+            "chunk(3,0,-2).vertex_buffer"
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn naming_objects_succeeds() {
+        let app = VulkanApp::new_headless(vk::Extent2D {
+            width: 4,
+            height: 4,
+        })
+        .unwrap();
+
+        set_debug_name(app.device(), app.pipeline, "test pipeline");
+        set_debug_name(app.device(), app.pipeline, "name\0with nul");
+        app.destroy();
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn labels_end_when_dropped() {
+        let app = VulkanApp::new_headless(vk::Extent2D {
+            width: 4,
+            height: 4,
+        })
+        .unwrap();
+        let device = app.device();
+
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(app.transient_command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        unsafe {
+            let command_buffer = device.allocate_command_buffers(&allocate_info).unwrap()[0];
+            device
+                .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default())
+                .unwrap();
+            {
+                let _outer = DebugLabel::begin(device, command_buffer, "Outer");
+                let _inner = DebugLabel::begin(device, command_buffer, "Inner");
+            }
+            device.end_command_buffer(command_buffer).unwrap();
+            device.free_command_buffers(app.transient_command_pool, &[command_buffer]);
+        }
+        app.destroy();
+    }
+}
@@ -0,0 +1,245 @@
+use ash::{Device, vk};
+use hashbrown::HashMap;
+
+use super::{
+    MAX_FRAMES_IN_FLIGHT,
+    lighting::{LightBuffers, LightUniform},
+    mesh::ChunkUniform,
+    texture::Texture,
+    uniform_ring::{DynamicUniformRing, dynamic_uniform_binding},
+};
+
+/// Bindings of the voxel pipeline's only set: the block texture at binding 0,
+/// the directional light at binding 1 and the [`ChunkUniform`] of each draw
+/// at binding 2.
+pub fn chunk_set_bindings() -> [vk::DescriptorSetLayoutBinding<'static>; 3] {
+    [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        dynamic_uniform_binding(2, vk::ShaderStageFlags::VERTEX),
+    ]
+}
+
+/// Binding, type, count and stages of every binding of a set, sorted by binding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct LayoutKey(Vec<(u32, vk::DescriptorType, u32, vk::ShaderStageFlags)>);
+
+impl LayoutKey {
+    fn new(bindings: &[vk::DescriptorSetLayoutBinding]) -> Self {
+        let mut key = bindings
+            .iter()
+            .map(|binding| {
+                (
+                    binding.binding,
+                    binding.descriptor_type,
+                    binding.descriptor_count,
+                    binding.stage_flags,
+                )
+            })
+            .collect::<Vec<_>>();
+        key.sort_unstable_by_key(|(binding, ..)| *binding);
+        Self(key)
+    }
+}
+
+/// Descriptor set layouts shared by every pipeline asking for the same
+/// bindings, each created once and destroyed once with the cache.
+///
+/// Bindings with immutable samplers aren't told apart, their layouts must not
+/// be cached.
+#[derive(Default)]
+pub struct DescriptorSetLayoutCache {
+    layouts: HashMap<LayoutKey, vk::DescriptorSetLayout>,
+}
+
+impl DescriptorSetLayoutCache {
+    /// The layout of `bindings`, created if no pipeline asked for them yet.
+    pub fn get_or_create(
+        &mut self,
+        device: &Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> vk::DescriptorSetLayout {
+        self.get_or_insert_with(bindings, |bindings| {
+            let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings);
+            unsafe {
+                device
+                    .create_descriptor_set_layout(&create_info, None)
+                    .unwrap()
+            }
+        })
+    }
+
+    fn get_or_insert_with(
+        &mut self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        create: impl FnOnce(&[vk::DescriptorSetLayoutBinding]) -> vk::DescriptorSetLayout,
+    ) -> vk::DescriptorSetLayout {
+        *self
+            .layouts
+            .entry(LayoutKey::new(bindings))
+            .or_insert_with(|| create(bindings))
+    }
+
+    /// Destroys every cached layout. The pipelines created with them must be
+    /// destroyed already.
+    pub fn destroy(&mut self, device: &Device) {
+        for (_, layout) in self.layouts.drain() {
+            unsafe { device.destroy_descriptor_set_layout(layout, None) };
+        }
+    }
+}
+
+/// Pool holding one set per frame in flight.
+pub fn create_descriptor_pool(device: &Device) -> vk::DescriptorPool {
+    let frames = MAX_FRAMES_IN_FLIGHT as u32;
+    let pool_sizes = &[
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(frames),
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(frames),
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(frames),
+    ];
+
+    let create_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(pool_sizes)
+        .max_sets(frames);
+
+    unsafe { device.create_descriptor_pool(&create_info, None).unwrap() }
+}
+
+/// Allocates a descriptor set per frame in flight binding `texture` and the
+/// light buffer of that frame to the fragment shader, and the buffer of that
+/// frame in `chunk_uniforms` to the vertex shader.
+pub fn create_descriptor_sets(
+    device: &Device,
+    descriptor_pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+    texture: &Texture,
+    light_buffers: &LightBuffers,
+    chunk_uniforms: &DynamicUniformRing,
+) -> Vec<vk::DescriptorSet> {
+    let layouts = [layout; MAX_FRAMES_IN_FLIGHT];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(descriptor_pool)
+        .set_layouts(&layouts);
+
+    let descriptor_sets = unsafe { device.allocate_descriptor_sets(&allocate_info).unwrap() };
+
+    for (frame, descriptor_set) in descriptor_sets.iter().enumerate() {
+        write_texture_descriptor(device, *descriptor_set, texture);
+
+        let buffer_infos = &[vk::DescriptorBufferInfo::default()
+            .buffer(light_buffers.buffer(frame))
+            .offset(0)
+            .range(size_of::<LightUniform>() as vk::DeviceSize)];
+
+        let chunk_buffer_infos =
+            &[chunk_uniforms.buffer_info(frame, size_of::<ChunkUniform>() as vk::DeviceSize)];
+
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(*descriptor_set)
+                .dst_binding(1)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(buffer_infos),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*descriptor_set)
+                .dst_binding(2)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                .buffer_info(chunk_buffer_infos),
+        ];
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    descriptor_sets
+}
+
+/// Binds the view and sampler of `texture` to binding 0 of `descriptor_set`,
+/// which must not be in use by a pending frame.
+pub fn write_texture_descriptor(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    texture: &Texture,
+) {
+    let image_infos = &[vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(texture.view)
+        .sampler(texture.sampler)];
+
+    let write = vk::WriteDescriptorSet::default()
+        .dst_set(descriptor_set)
+        .dst_binding(0)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(image_infos);
+
+    unsafe { device.update_descriptor_sets(&[write], &[]) };
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+
+    use super::*;
+
+    fn binding(
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+    ) -> vk::DescriptorSetLayoutBinding<'static> {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+    }
+
+    #[test]
+    fn identical_bindings_share_a_layout() {
+        let mut cache = DescriptorSetLayoutCache::default();
+        let mut created = 0;
+        let mut create = |_: &[vk::DescriptorSetLayoutBinding]| {
+            created += 1;
+            vk::DescriptorSetLayout::from_raw(created)
+        };
+
+        let camera = [binding(0, vk::DescriptorType::UNIFORM_BUFFER)];
+        let first = cache.get_or_insert_with(&camera, &mut create);
+        let second = cache.get_or_insert_with(&camera, &mut create);
+        assert_eq!(first, second);
+
+        let storage = [binding(0, vk::DescriptorType::STORAGE_BUFFER)];
+        let other = cache.get_or_insert_with(&storage, &mut create);
+        assert_ne!(first, other);
+
+        assert_eq!(created, 2);
+        assert_eq!(cache.layouts.len(), 2);
+    }
+
+    #[test]
+    fn binding_order_does_not_matter() {
+        let texture = binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        let light = binding(1, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(
+            LayoutKey::new(&[texture, light]),
+            LayoutKey::new(&[light, texture])
+        );
+
+        let fragment = light.stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        assert_ne!(LayoutKey::new(&[light]), LayoutKey::new(&[fragment]));
+    }
+}
@@ -0,0 +1,29 @@
+use ash::vk;
+use bevy_ecs::resource::Resource;
+
+/// The Vulkan handles of [`VulkanApp`](super::VulkanApp), for systems that
+/// only need the device and shouldn't lock the whole app.
+///
+/// The handles stay owned by `VulkanApp`, which destroys them on drop.
+#[derive(Resource, Clone)]
+pub struct RenderDevice {
+    pub instance: ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: ash::Device,
+    pub graphics: RenderQueue,
+    pub present: RenderQueue,
+    /// Same as `graphics` without a dedicated transfer family.
+    pub transfer: RenderQueue,
+    /// Same as `graphics` without an async compute family.
+    pub compute: RenderQueue,
+}
+
+/// A queue and the family it belongs to.
+///
+/// Submitting to a queue must be externally synchronized, queues may be
+/// shared between roles and with `VulkanApp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderQueue {
+    pub family: u32,
+    pub queue: vk::Queue,
+}
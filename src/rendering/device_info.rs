@@ -0,0 +1,348 @@
+use std::fmt;
+
+use ash::{Entry, Instance, ext, khr, vk};
+use bevy_ecs::resource::Resource;
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Limits of the selected physical device that matter for sizing resources.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct DeviceLimits {
+    pub device_name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub max_image_dimension_2d: u32,
+    pub max_image_array_layers: u32,
+    pub max_push_constants_size: u32,
+    pub max_memory_allocation_count: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub max_sampler_anisotropy: f32,
+    pub max_compute_work_group_invocations: u32,
+    pub max_compute_shared_memory_size: u32,
+    /// Nanoseconds per timestamp query tick.
+    pub timestamp_period: f32,
+}
+
+impl DeviceLimits {
+    pub fn from_properties(properties: &vk::PhysicalDeviceProperties) -> Self {
+        let limits = &properties.limits;
+        Self {
+            device_name: properties
+                .device_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            device_type: properties.device_type,
+            max_image_dimension_2d: limits.max_image_dimension2_d,
+            max_image_array_layers: limits.max_image_array_layers,
+            max_push_constants_size: limits.max_push_constants_size,
+            max_memory_allocation_count: limits.max_memory_allocation_count,
+            max_bound_descriptor_sets: limits.max_bound_descriptor_sets,
+            max_sampler_anisotropy: limits.max_sampler_anisotropy,
+            max_compute_work_group_invocations: limits.max_compute_work_group_invocations,
+            max_compute_shared_memory_size: limits.max_compute_shared_memory_size,
+            timestamp_period: limits.timestamp_period,
+        }
+    }
+
+    pub fn log_summary(&self) {
+        info!(
+            "Device `{}` ({:?}): max image 2D {}, max array layers {}, push constants {} bytes, \
+             max allocations {}, max descriptor sets {}, max anisotropy {}",
+            self.device_name,
+            self.device_type,
+            self.max_image_dimension_2d,
+            self.max_image_array_layers,
+            self.max_push_constants_size,
+            self.max_memory_allocation_count,
+            self.max_bound_descriptor_sets,
+            self.max_sampler_anisotropy,
+        );
+    }
+}
+
+/// Budget and usage of every memory heap reported by `VK_EXT_memory_budget`.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub heaps: Vec<HeapBudget>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapBudget {
+    pub size: u64,
+    /// Bytes this process can allocate from the heap without failures or
+    /// degraded performance.
+    pub budget: u64,
+    /// Bytes of the heap this process currently uses.
+    pub usage: u64,
+    pub device_local: bool,
+}
+
+impl MemoryBudget {
+    pub fn from_properties(
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        budget_properties: &vk::PhysicalDeviceMemoryBudgetPropertiesEXT<'_>,
+    ) -> Self {
+        let heaps = memory_properties
+            .memory_heaps_as_slice()
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| HeapBudget {
+                size: heap.size,
+                budget: budget_properties.heap_budget[index],
+                usage: budget_properties.heap_usage[index],
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+            })
+            .collect();
+
+        Self { heaps }
+    }
+
+    pub fn log_summary(&self) {
+        for (index, heap) in self.heaps.iter().enumerate() {
+            info!(
+                "Memory heap {index}{}: {} MiB, budget {} MiB, used {} MiB",
+                if heap.device_local {
+                    " (device local)"
+                } else {
+                    ""
+                },
+                heap.size >> 20,
+                heap.budget >> 20,
+                heap.usage >> 20,
+            );
+        }
+    }
+}
+
+/// Why a physical device can't be rendered with.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Unsuitable {
+    #[error("no graphics queue family")]
+    NoGraphicsFamily,
+    #[error("no queue family can present to the surface")]
+    NoPresentSupport,
+    #[error("missing device extension {0}")]
+    MissingExtension(String),
+    #[error("no surface formats or present modes")]
+    NoSwapchainFormats,
+}
+
+/// A physical device offered by the system, see [`DeviceCandidates`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceCandidate {
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub api_version: u32,
+    /// Why the device can't be used, `Ok` if it can.
+    pub suitability: Result<(), Unsuitable>,
+}
+
+impl DeviceCandidate {
+    pub fn new(
+        properties: &vk::PhysicalDeviceProperties,
+        suitability: Result<(), Unsuitable>,
+    ) -> Self {
+        Self {
+            name: properties
+                .device_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            device_type: properties.device_type,
+            api_version: properties.api_version,
+            suitability,
+        }
+    }
+
+    pub fn is_suitable(&self) -> bool {
+        self.suitability.is_ok()
+    }
+}
+
+impl fmt::Display for DeviceCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` ({:?}, Vulkan {}.{}.{}): ",
+            self.name,
+            self.device_type,
+            vk::api_version_major(self.api_version),
+            vk::api_version_minor(self.api_version),
+            vk::api_version_patch(self.api_version),
+        )?;
+        match &self.suitability {
+            Ok(()) => write!(f, "suitable"),
+            Err(reason) => write!(f, "unsuitable, {reason}"),
+        }
+    }
+}
+
+/// Every physical device of the system, in the order they were considered.
+///
+/// The first suitable one is selected.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceCandidates {
+    pub candidates: Vec<DeviceCandidate>,
+}
+
+impl DeviceCandidates {
+    pub fn selected(&self) -> Option<&DeviceCandidate> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.is_suitable())
+    }
+
+    pub fn log_summary(&self) {
+        for (index, candidate) in self.candidates.iter().enumerate() {
+            if candidate.is_suitable() {
+                info!("Physical device {index}: {candidate}");
+            } else {
+                warn!("Physical device {index}: {candidate}");
+            }
+        }
+    }
+}
+
+/// Whether `VK_KHR_get_physical_device_properties2` can be enabled, which is
+/// needed to query the memory budget on Vulkan 1.0 instances.
+pub fn supports_physical_device_properties2(entry: &Entry) -> bool {
+    let extension_properties =
+        unsafe { entry.enumerate_instance_extension_properties(None).unwrap() };
+
+    extension_properties.iter().any(|properties| {
+        properties.extension_name_as_c_str() == Ok(khr::get_physical_device_properties2::NAME)
+    })
+}
+
+pub fn supports_memory_budget(instance: &Instance, physical_device: vk::PhysicalDevice) -> bool {
+    let extension_properties = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .unwrap()
+    };
+
+    extension_properties
+        .iter()
+        .any(|properties| properties.extension_name_as_c_str() == Ok(ext::memory_budget::NAME))
+}
+
+pub fn query_memory_budget(
+    properties2: &khr::get_physical_device_properties2::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> MemoryBudget {
+    let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let mut memory_properties =
+        vk::PhysicalDeviceMemoryProperties2::default().push_next(&mut budget_properties);
+    unsafe {
+        properties2.get_physical_device_memory_properties2(physical_device, &mut memory_properties)
+    };
+    let memory_properties = memory_properties.memory_properties;
+
+    MemoryBudget::from_properties(&memory_properties, &budget_properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_from_properties() {
+        let mut properties = vk::PhysicalDeviceProperties {
+            device_type: vk::PhysicalDeviceType::DISCRETE_GPU,
+            limits: vk::PhysicalDeviceLimits {
+                max_image_dimension2_d: 16384,
+                max_push_constants_size: 128,
+                max_memory_allocation_count: 4096,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (dst, src) in properties.device_name.iter_mut().zip(b"Test GPU\0") {
+            *dst = *src as _;
+        }
+
+        let limits = DeviceLimits::from_properties(&properties);
+
+        assert_eq!(limits.device_name, "Test GPU");
+        assert_eq!(limits.device_type, vk::PhysicalDeviceType::DISCRETE_GPU);
+        assert_eq!(limits.max_image_dimension_2d, 16384);
+        assert_eq!(limits.max_push_constants_size, 128);
+        assert_eq!(limits.max_memory_allocation_count, 4096);
+    }
+
+    fn candidate(name: &str, suitability: Result<(), Unsuitable>) -> DeviceCandidate {
+        DeviceCandidate {
+            name: name.to_owned(),
+            device_type: vk::PhysicalDeviceType::DISCRETE_GPU,
+            api_version: vk::make_api_version(0, 1, 3, 250),
+            suitability,
+        }
+    }
+
+    #[test]
+    fn first_suitable_candidate_is_selected() {
+        let candidates = DeviceCandidates {
+            candidates: vec![
+                candidate("A", Err(Unsuitable::NoPresentSupport)),
+                candidate("B", Ok(())),
+                candidate("C", Ok(())),
+            ],
+        };
+        assert_eq!(candidates.selected().map(|c| c.name.as_str()), Some("B"));
+        assert_eq!(DeviceCandidates::default().selected(), None);
+    }
+
+    #[test]
+    fn candidates_explain_why_they_are_unsuitable() {
+        assert_eq!(
+            candidate("Test GPU", Ok(())).to_string(),
+            "`Test GPU` (DISCRETE_GPU, Vulkan 1.3.250): suitable"
+        );
+        assert_eq!(
+            candidate(
+                "Test GPU",
+                Err(Unsuitable::MissingExtension("VK_KHR_swapchain".to_owned()))
+            )
+            .to_string(),
+            "`Test GPU` (DISCRETE_GPU, Vulkan 1.3.250): unsuitable, missing device extension VK_KHR_swapchain"
+        );
+    }
+
+    #[test]
+    fn budget_per_heap() {
+        let mut memory_properties = vk::PhysicalDeviceMemoryProperties {
+            memory_heap_count: 2,
+            ..Default::default()
+        };
+        memory_properties.memory_heaps[0] = vk::MemoryHeap {
+            size: 8 << 30,
+            flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+        };
+        memory_properties.memory_heaps[1] = vk::MemoryHeap {
+            size: 16 << 30,
+            flags: vk::MemoryHeapFlags::empty(),
+        };
+
+        let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        budget_properties.heap_budget[..2].copy_from_slice(&[7 << 30, 12 << 30]);
+        budget_properties.heap_usage[..2].copy_from_slice(&[1 << 30, 1 << 20]);
+
+        let budget = MemoryBudget::from_properties(&memory_properties, &budget_properties);
+
+        assert_eq!(
+            budget.heaps,
+            [
+                HeapBudget {
+                    size: 8 << 30,
+                    budget: 7 << 30,
+                    usage: 1 << 30,
+                    device_local: true,
+                },
+                HeapBudget {
+                    size: 16 << 30,
+                    budget: 12 << 30,
+                    usage: 1 << 20,
+                    device_local: false,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use bevy_ecs::{
+    event::Event,
+    resource::Resource,
+    system::{Res, ResMut},
+};
+
+use super::VulkanApp;
+
+/// How many times in a row the device is rebuilt before giving up.
+pub const MAX_DEVICE_REBUILDS: u32 = 3;
+
+/// How long to wait for the fence of a frame in flight before treating the
+/// GPU as hung and the device as lost.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FenceTimeout(pub Duration);
+
+impl Default for FenceTimeout {
+    fn default() -> Self {
+        Self(Duration::from_secs(5))
+    }
+}
+
+impl FenceTimeout {
+    /// Timeout passed to `vkWaitForFences`, saturating at `u64::MAX`.
+    pub fn as_nanos(&self) -> u64 {
+        self.0.as_nanos().try_into().unwrap_or(u64::MAX)
+    }
+}
+
+pub fn update_fence_timeout_system(mut vulkan_app: ResMut<VulkanApp>, timeout: Res<FenceTimeout>) {
+    if vulkan_app.fence_timeout != timeout.as_nanos() {
+        vulkan_app.fence_timeout = timeout.as_nanos();
+    }
+}
+
+/// Sent after the device was lost and rebuilt, e.g. after a driver reset.
+///
+/// Everything created on the old device is gone. Chunk meshes are meshed and
+/// uploaded again on their own, other GPU data has to be recreated.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceLost {
+    /// Consecutive rebuilds so far, starting at 1.
+    pub attempt: u32,
+}
+
+/// Counts consecutive device losses, reset by every frame that renders.
+#[derive(Debug, Default)]
+pub struct DeviceRebuilds {
+    attempts: u32,
+}
+
+impl DeviceRebuilds {
+    /// Registers a lost device and returns the attempt to rebuild it, or
+    /// `None` once [`MAX_DEVICE_REBUILDS`] attempts have failed.
+    pub fn next_attempt(&mut self) -> Option<u32> {
+        if self.attempts >= MAX_DEVICE_REBUILDS {
+            return None;
+        }
+
+        self.attempts += 1;
+        Some(self.attempts)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fence_timeout_in_nanoseconds() {
+        assert_eq!(FenceTimeout::default().as_nanos(), 5_000_000_000);
+        assert_eq!(
+            FenceTimeout(Duration::from_millis(250)).as_nanos(),
+            250_000_000
+        );
+        assert_eq!(FenceTimeout(Duration::MAX).as_nanos(), u64::MAX);
+    }
+
+    #[test]
+    fn gives_up_after_max_rebuilds() {
+        let mut rebuilds = DeviceRebuilds::default();
+
+        for attempt in 1..=MAX_DEVICE_REBUILDS {
+            assert_eq!(rebuilds.next_attempt(), Some(attempt));
+        }
+        assert_eq!(rebuilds.next_attempt(), None);
+    }
+
+    #[test]
+    fn rendered_frame_resets_attempts() {
+        let mut rebuilds = DeviceRebuilds::default();
+
+        rebuilds.next_attempt();
+        rebuilds.next_attempt();
+        rebuilds.reset();
+
+        assert_eq!(rebuilds.next_attempt(), Some(1));
+    }
+}
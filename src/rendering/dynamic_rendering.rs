@@ -0,0 +1,267 @@
+use ash::{Device, Entry, Instance, vk};
+
+/// Formats of the attachments rendered to without a render pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentFormats {
+    pub color: vk::Format,
+    pub depth: vk::Format,
+    /// Shared by both attachments, mismatched sample counts are invalid.
+    pub samples: vk::SampleCountFlags,
+    /// Mode the multisampled depth is resolved with, `None` when it isn't.
+    pub depth_resolve: Option<vk::ResolveModeFlags>,
+}
+
+/// Images a frame is rendered to with `vkCmdBeginRendering`.
+#[derive(Debug, Clone, Copy)]
+pub struct DynamicTarget {
+    pub color_image: vk::Image,
+    pub color_view: vk::ImageView,
+    pub depth_image: vk::Image,
+    pub depth_view: vk::ImageView,
+    pub depth_format: vk::Format,
+    /// Multisampled image rendered to instead of `color_image`, which it is
+    /// resolved into.
+    pub msaa_color: Option<(vk::Image, vk::ImageView)>,
+    /// Mode and single sampled image the depth is resolved into.
+    pub depth_resolve: Option<(vk::ResolveModeFlags, vk::Image, vk::ImageView)>,
+    /// Layout the color image is left in, `PRESENT_SRC_KHR` for swapchain images.
+    pub final_layout: vk::ImageLayout,
+}
+
+/// Highest API version up to 1.3 the loader supports.
+pub fn instance_api_version(entry: &Entry) -> u32 {
+    let loader_version = unsafe { entry.try_enumerate_instance_version() }
+        .ok()
+        .flatten()
+        .unwrap_or(vk::API_VERSION_1_0);
+    clamp_api_version(loader_version, vk::API_VERSION_1_3)
+}
+
+/// Whether frames can be rendered without render passes and framebuffers.
+///
+/// Requires both the instance and the device to be at least Vulkan 1.3.
+pub fn supports_dynamic_rendering(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    instance_api_version: u32,
+) -> bool {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    if clamp_api_version(properties.api_version, instance_api_version) < vk::API_VERSION_1_3 {
+        return false;
+    }
+
+    let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features::default();
+    let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut vulkan_13_features);
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+
+    vulkan_13_features.dynamic_rendering == vk::TRUE
+}
+
+/// Compares major and minor versions only, patch versions differ between
+/// drivers and loaders.
+fn clamp_api_version(version: u32, max: u32) -> u32 {
+    let major_minor = |version: u32| {
+        vk::make_api_version(
+            0,
+            vk::api_version_major(version),
+            vk::api_version_minor(version),
+            0,
+        )
+    };
+    major_minor(version).min(major_minor(max))
+}
+
+/// Transitions the target to attachment layouts and begins rendering to it.
+///
+/// The draws are expected to be recorded into secondary command buffers.
+pub fn begin_rendering(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    target: &DynamicTarget,
+    extent: vk::Extent2D,
+    clear_color: [f32; 4],
+    clear_depth: f32,
+) {
+    let depth_aspect = depth_aspect_mask(target.depth_format);
+
+    let color_barrier = |image| {
+        vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range(vk::ImageAspectFlags::COLOR))
+    };
+    let depth_barrier = |image| {
+        vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range(depth_aspect))
+    };
+    let mut barriers = vec![
+        color_barrier(target.color_image),
+        depth_barrier(target.depth_image),
+    ];
+
+    let color_attachment = match target.msaa_color {
+        Some((msaa_image, msaa_view)) => {
+            barriers.push(color_barrier(msaa_image));
+            vk::RenderingAttachmentInfo::default()
+                .image_view(msaa_view)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                .resolve_image_view(target.color_view)
+                .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        }
+        None => vk::RenderingAttachmentInfo::default()
+            .image_view(target.color_view)
+            .store_op(vk::AttachmentStoreOp::STORE),
+    };
+    let color_attachments = [color_attachment
+        .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .clear_value(vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: clear_color,
+            },
+        })];
+
+    let mut depth_attachment = vk::RenderingAttachmentInfo::default();
+    if let Some((mode, resolved_image, resolved_view)) = target.depth_resolve {
+        barriers.push(depth_barrier(resolved_image));
+        depth_attachment = depth_attachment
+            .resolve_mode(mode)
+            .resolve_image_view(resolved_view)
+            .resolve_image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    }
+    let depth_attachment = depth_attachment
+        .image_view(target.depth_view)
+        .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .clear_value(vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: clear_depth,
+                stencil: 0,
+            },
+        });
+
+    let rendering_info = vk::RenderingInfo::default()
+        .flags(vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS)
+        .render_area(vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        })
+        .layer_count(1)
+        .color_attachments(&color_attachments)
+        .depth_attachment(&depth_attachment);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers,
+        );
+
+        device.cmd_begin_rendering(command_buffer, &rendering_info);
+    }
+}
+
+/// Ends rendering and transitions the color image to its final layout.
+pub fn end_rendering(device: &Device, command_buffer: vk::CommandBuffer, target: &DynamicTarget) {
+    let barrier = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::empty())
+        .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .new_layout(target.final_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(target.color_image)
+        .subresource_range(subresource_range(vk::ImageAspectFlags::COLOR));
+
+    unsafe {
+        device.cmd_end_rendering(command_buffer);
+
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    }
+}
+
+fn subresource_range(aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange::default()
+        .aspect_mask(aspect_mask)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+}
+
+/// Layout transitions of combined depth/stencil images must cover both aspects.
+pub fn depth_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_version_is_clamped() {
+        let loader = vk::make_api_version(0, 1, 4, 303);
+        assert_eq!(
+            clamp_api_version(loader, vk::API_VERSION_1_3),
+            vk::API_VERSION_1_3
+        );
+
+        let device = vk::make_api_version(0, 1, 2, 198);
+        assert_eq!(
+            clamp_api_version(device, vk::API_VERSION_1_3),
+            vk::API_VERSION_1_2
+        );
+    }
+
+    #[test]
+    fn stencil_formats_transition_both_aspects() {
+        assert_eq!(
+            depth_aspect_mask(vk::Format::D32_SFLOAT),
+            vk::ImageAspectFlags::DEPTH
+        );
+        assert_eq!(
+            depth_aspect_mask(vk::Format::D24_UNORM_S8_UINT),
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        );
+    }
+}
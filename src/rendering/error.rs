@@ -0,0 +1,91 @@
+use ash::vk;
+use thiserror::Error;
+
+use super::texture::TextureError;
+
+/// A failed Vulkan call the frame loop can't continue past.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VulkanError {
+    #[error("Vulkan device was lost")]
+    DeviceLost,
+    #[error("Out of host memory")]
+    OutOfHostMemory,
+    #[error("Out of device memory")]
+    OutOfDeviceMemory,
+    #[error("Vulkan call failed: {0}")]
+    Other(vk::Result),
+    #[error("Unsupported image layout transition from {old:?} to {new:?}")]
+    UnsupportedLayoutTransition {
+        old: vk::ImageLayout,
+        new: vk::ImageLayout,
+    },
+    #[error("SPIR-V code of {0} bytes is not a positive multiple of 4")]
+    SpirvSize(usize),
+    #[error("SPIR-V code starts with {0:#010x} instead of the magic number")]
+    SpirvMagic(u32),
+}
+
+/// Why a [`VulkanApp`](super::VulkanApp) couldn't be created.
+#[derive(Error, Debug)]
+pub enum InitError {
+    #[error("Failed to load the Vulkan library: {0}")]
+    Loading(#[from] ash::LoadingError),
+    #[error("Validation layer `{0}` is not supported")]
+    MissingLayer(&'static str),
+    #[error("Required instance extension `{0}` is not supported")]
+    MissingInstanceExtension(String),
+    #[error("Failed to find a GPU with Vulkan support")]
+    NoVulkanDevice,
+    #[error("Failed to find a suitable GPU")]
+    NoSuitableDevice,
+    #[error(transparent)]
+    Vulkan(#[from] VulkanError),
+    #[error(transparent)]
+    Texture(#[from] TextureError),
+}
+
+impl From<vk::Result> for InitError {
+    fn from(result: vk::Result) -> Self {
+        VulkanError::from(result).into()
+    }
+}
+
+impl From<vk::Result> for VulkanError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_DEVICE_LOST => VulkanError::DeviceLost,
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY => VulkanError::OutOfHostMemory,
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => VulkanError::OutOfDeviceMemory,
+            result => VulkanError::Other(result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_known_results() {
+        assert_eq!(
+            VulkanError::from(vk::Result::ERROR_DEVICE_LOST),
+            VulkanError::DeviceLost
+        );
+        assert_eq!(
+            VulkanError::from(vk::Result::ERROR_OUT_OF_HOST_MEMORY),
+            VulkanError::OutOfHostMemory
+        );
+        assert_eq!(
+            VulkanError::from(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY),
+            VulkanError::OutOfDeviceMemory
+        );
+    }
+
+    #[test]
+    fn keeps_other_results() {
+        assert_eq!(
+            VulkanError::from(vk::Result::ERROR_INITIALIZATION_FAILED),
+            VulkanError::Other(vk::Result::ERROR_INITIALIZATION_FAILED)
+        );
+    }
+}
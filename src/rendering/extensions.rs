@@ -0,0 +1,125 @@
+use std::ffi::{CStr, CString};
+
+use ash::{Entry, Instance, vk};
+use tracing::warn;
+
+use super::InitError;
+
+/// Names of the instance extensions the Vulkan implementation supports.
+pub fn available_instance_extensions(entry: &Entry) -> Vec<CString> {
+    let extension_properties =
+        unsafe { entry.enumerate_instance_extension_properties(None).unwrap() };
+
+    extension_properties
+        .iter()
+        .filter_map(|properties| properties.extension_name_as_c_str().ok())
+        .map(CStr::to_owned)
+        .collect()
+}
+
+pub fn supports_instance_extension(entry: &Entry, name: &CStr) -> bool {
+    available_instance_extensions(entry)
+        .iter()
+        .any(|available| available.as_c_str() == name)
+}
+
+/// Whether `physical_device` advertises the device extension `name`.
+pub fn supports_device_extension(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    name: &CStr,
+) -> bool {
+    let extension_properties = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .unwrap()
+    };
+
+    extension_properties
+        .iter()
+        .any(|properties| properties.extension_name_as_c_str() == Ok(name))
+}
+
+/// Checks the extensions an instance is created with against the `available` ones.
+///
+/// Missing `optional` extensions are left out with a warning, a missing
+/// `required` one fails instance creation.
+pub fn select_instance_extensions<'a>(
+    required: &[&'a CStr],
+    optional: &[&'a CStr],
+    available: &[CString],
+) -> Result<Vec<&'a CStr>, InitError> {
+    let is_available = |name: &CStr| {
+        available
+            .iter()
+            .any(|available| available.as_c_str() == name)
+    };
+
+    if let Some(missing) = required.iter().find(|name| !is_available(name)) {
+        return Err(InitError::MissingInstanceExtension(
+            missing.to_string_lossy().into_owned(),
+        ));
+    }
+
+    let mut selected = required.to_vec();
+    for name in optional {
+        if is_available(name) {
+            selected.push(name);
+        } else {
+            warn!(
+                "Optional instance extension {} is not available",
+                name.to_string_lossy()
+            );
+        }
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::{ext, khr};
+
+    use super::*;
+
+    fn available() -> Vec<CString> {
+        [khr::surface::NAME, khr::xlib_surface::NAME]
+            .map(CStr::to_owned)
+            .to_vec()
+    }
+
+    #[test]
+    fn missing_optional_extensions_are_dropped() {
+        let selected = select_instance_extensions(
+            &[khr::surface::NAME],
+            &[ext::debug_utils::NAME],
+            &available(),
+        )
+        .unwrap();
+        assert_eq!(selected, [khr::surface::NAME]);
+    }
+
+    #[test]
+    fn missing_required_extension_is_an_error() {
+        let result = select_instance_extensions(
+            &[khr::surface::NAME, khr::wayland_surface::NAME],
+            &[],
+            &available(),
+        );
+        assert!(matches!(
+            result,
+            Err(InitError::MissingInstanceExtension(name)) if name == "VK_KHR_wayland_surface"
+        ));
+    }
+
+    #[test]
+    fn available_extensions_are_kept() {
+        let selected = select_instance_extensions(
+            &[khr::surface::NAME],
+            &[khr::xlib_surface::NAME],
+            &available(),
+        )
+        .unwrap();
+        assert_eq!(selected, [khr::surface::NAME, khr::xlib_surface::NAME]);
+    }
+}
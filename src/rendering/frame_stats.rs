@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+use bevy_ecs::resource::Resource;
+
+/// Enables [`FrameStats`] collection. Disabled by default.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectFrameStats(pub bool);
+
+/// CPU time spent in each stage of the last presented frame.
+///
+/// Only updated while [`CollectFrameStats`] is enabled.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Waiting on the in-flight fence, i.e. for the GPU to finish an older frame.
+    pub fence_wait: Duration,
+    /// Acquiring the next swapchain image.
+    pub acquire: Duration,
+    /// Recording and submitting the command buffers.
+    pub record: Duration,
+    /// Queueing the image for presentation.
+    pub present: Duration,
+}
+
+impl FrameStats {
+    pub fn total(&self) -> Duration {
+        self.fence_wait + self.acquire + self.record + self.present
+    }
+}
+
+/// Measures consecutive stages of a frame. Does nothing when disabled.
+pub(super) struct StageTimer {
+    last: Option<Instant>,
+}
+
+impl StageTimer {
+    pub fn start(enabled: bool) -> Self {
+        Self {
+            last: enabled.then(Instant::now),
+        }
+    }
+
+    /// Time since the previous lap, or [`Duration::ZERO`] when disabled.
+    pub fn lap(&mut self) -> Duration {
+        let Some(last) = self.last.as_mut() else {
+            return Duration::ZERO;
+        };
+
+        let now = Instant::now();
+        let elapsed = now - *last;
+        *last = now;
+        elapsed
+    }
+
+    /// Starts a new lap without measuring the work done since the previous one.
+    pub fn skip(&mut self) {
+        self.lap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_timer_measures_nothing() {
+        let mut timer = StageTimer::start(false);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(timer.lap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn laps_are_consecutive() {
+        let mut timer = StageTimer::start(true);
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(timer.lap() >= Duration::from_millis(2));
+
+        // The second lap starts where the first one ended.
+        std::thread::sleep(Duration::from_millis(1));
+        let second = timer.lap();
+        assert!(second >= Duration::from_millis(1));
+        assert!(second < Duration::from_secs(1));
+    }
+}
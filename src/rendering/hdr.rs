@@ -0,0 +1,101 @@
+use ash::{Entry, ext, vk};
+use bevy_ecs::resource::Resource;
+use tracing::warn;
+
+/// Whether to present to an HDR surface when one is available.
+///
+/// Read once when the [`VulkanApp`](super::VulkanApp) is created. Falls back
+/// to sRGB output when the instance or the surface lacks HDR support.
+///
+/// Only linear extended sRGB is presented. HDR10 output, which needs the PQ
+/// transfer function in the shaders, and AMD's native HDR display mode are
+/// not supported.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HdrMode(pub bool);
+
+/// Format of HDR swapchains. In the linear extended sRGB color space, the
+/// linear colors written by the fragment shader are presented as they are,
+/// with values above 1 brighter than SDR white.
+const HDR_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::R16G16B16A16_SFLOAT,
+    color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+};
+
+/// Whether the loader exposes `VK_EXT_swapchain_colorspace`, which HDR color
+/// spaces are part of.
+pub fn supports_swapchain_colorspace(entry: &Entry) -> bool {
+    let extension_properties =
+        unsafe { entry.enumerate_instance_extension_properties(None).unwrap() };
+
+    let supported = extension_properties.iter().any(|properties| {
+        properties.extension_name_as_c_str() == Ok(ext::swapchain_colorspace::NAME)
+    });
+    if !supported {
+        warn!("HDR output requires VK_EXT_swapchain_colorspace, presenting in sRGB");
+    }
+
+    supported
+}
+
+/// The HDR surface format if it is among `available_formats`.
+///
+/// HDR10 surfaces are not used, they expect colors encoded with the PQ
+/// transfer function which the fragment shader doesn't apply.
+pub fn find_hdr_surface_format(
+    available_formats: &[vk::SurfaceFormatKHR],
+) -> Option<vk::SurfaceFormatKHR> {
+    available_formats
+        .iter()
+        .copied()
+        .find(|available| *available == HDR_SURFACE_FORMAT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    #[test]
+    fn finds_linear_extended_srgb_format() {
+        let hdr = surface_format(
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        );
+        let available = [
+            surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            hdr,
+        ];
+
+        assert_eq!(find_hdr_surface_format(&available), Some(hdr));
+    }
+
+    #[test]
+    fn float_formats_need_an_hdr_color_space() {
+        let available = [
+            surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            surface_format(
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            ),
+        ];
+
+        assert_eq!(find_hdr_surface_format(&available), None);
+    }
+
+    #[test]
+    fn hdr10_surfaces_are_not_used() {
+        // Presenting to them would need PQ encoded colors.
+        let available = [surface_format(
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        )];
+
+        assert_eq!(find_hdr_surface_format(&available), None);
+    }
+}
@@ -0,0 +1,364 @@
+use ash::{Device, Instance, vk};
+use gpu_allocator::{
+    MemoryLocation,
+    vulkan::{Allocation, AllocationCreateDesc, AllocationScheme, Allocator},
+};
+
+use super::debug_utils::set_debug_name;
+
+/// An image bound to memory sub-allocated from the [`Allocator`].
+#[derive(Default)]
+pub struct Image {
+    pub image: vk::Image,
+    pub allocation: Allocation,
+}
+
+impl Image {
+    /// Destroys the image and returns its memory to the allocator.
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        unsafe { device.destroy_image(self.image, None) };
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .expect("Failed to free image memory");
+    }
+}
+
+/// A 2D image created by [`create_image`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageDesc {
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub tiling: vk::ImageTiling,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl ImageDesc {
+    /// An optimally tiled image with a single level, layer and sample.
+    pub fn new(extent: vk::Extent2D, format: vk::Format, usage: vk::ImageUsageFlags) -> Self {
+        Self {
+            extent,
+            format,
+            usage,
+            mip_levels: 1,
+            array_layers: 1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    fn create_info(&self) -> vk::ImageCreateInfo<'static> {
+        vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(self.extent.into())
+            .mip_levels(self.mip_levels)
+            .array_layers(self.array_layers)
+            .format(self.format)
+            .tiling(self.tiling)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(self.usage)
+            .samples(self.samples)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+    }
+}
+
+/// Creates the image described by `desc` in device local memory.
+pub fn create_image(
+    device: &Device,
+    allocator: &mut Allocator,
+    name: &str,
+    desc: &ImageDesc,
+) -> Image {
+    let image = unsafe { device.create_image(&desc.create_info(), None).unwrap() };
+    set_debug_name(device, image, name);
+
+    let requirements = unsafe { device.get_image_memory_requirements(image) };
+    let allocation = allocator
+        .allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: desc.tiling == vk::ImageTiling::LINEAR,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })
+        .expect("Failed to allocate image memory");
+
+    unsafe {
+        device
+            .bind_image_memory(image, allocation.memory(), allocation.offset())
+            .unwrap()
+    };
+
+    Image { image, allocation }
+}
+
+pub fn create_image_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect_mask: vk::ImageAspectFlags,
+    mip_levels: u32,
+) -> vk::ImageView {
+    let create_info = vk::ImageViewCreateInfo::default()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect_mask)
+                .base_mip_level(0)
+                .level_count(mip_levels)
+                .base_array_layer(0)
+                .layer_count(1),
+        );
+
+    unsafe { device.create_image_view(&create_info, None).unwrap() }
+}
+
+/// Number of levels in a full mip chain: `floor(log2(max(width, height))) + 1`.
+pub fn mip_levels(extent: vk::Extent2D) -> u32 {
+    let size = extent.width.max(extent.height).max(1);
+    u32::BITS - size.leading_zeros()
+}
+
+fn find_supported_format(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    candidates: &[vk::Format],
+    tiling: vk::ImageTiling,
+    features: vk::FormatFeatureFlags,
+) -> Option<vk::Format> {
+    candidates.iter().copied().find(|format| {
+        let properties =
+            unsafe { instance.get_physical_device_format_properties(physical_device, *format) };
+        match tiling {
+            vk::ImageTiling::LINEAR => properties.linear_tiling_features.contains(features),
+            vk::ImageTiling::OPTIMAL => properties.optimal_tiling_features.contains(features),
+            _ => false,
+        }
+    })
+}
+
+pub fn find_depth_format(instance: &Instance, physical_device: vk::PhysicalDevice) -> vk::Format {
+    find_supported_format(
+        instance,
+        physical_device,
+        &[
+            vk::Format::D32_SFLOAT,
+            vk::Format::D32_SFLOAT_S8_UINT,
+            vk::Format::D24_UNORM_S8_UINT,
+        ],
+        vk::ImageTiling::OPTIMAL,
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+    )
+    .expect("Failed to find a supported depth format")
+}
+
+/// Depth attachment matching the swapchain extent.
+pub struct DepthResources {
+    pub image: Image,
+    pub view: vk::ImageView,
+    /// Single sampled image the depth is resolved into, see
+    /// [`depth_resolve_mode`].
+    pub resolved: Option<ResolvedDepth>,
+}
+
+/// Depth of a multisampled attachment, resolved for the passes reading it
+/// after the frame.
+pub struct ResolvedDepth {
+    pub image: Image,
+    pub view: vk::ImageView,
+    pub mode: vk::ResolveModeFlags,
+}
+
+impl DepthResources {
+    /// `samples` must be the sample count of the color attachment rendered
+    /// to along with it. The depth is resolved with `resolve_mode` if set.
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        samples: vk::SampleCountFlags,
+        resolve_mode: Option<vk::ResolveModeFlags>,
+    ) -> Self {
+        let desc = ImageDesc {
+            samples,
+            ..ImageDesc::new(
+                extent,
+                format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            )
+        };
+        let image = create_image(device, allocator, "depth", &desc);
+        let view = create_image_view(device, image.image, format, vk::ImageAspectFlags::DEPTH, 1);
+
+        let resolved = resolve_mode.map(|mode| {
+            let desc = ImageDesc::new(
+                extent,
+                format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            );
+            let image = create_image(device, allocator, "resolved depth", &desc);
+            let view =
+                create_image_view(device, image.image, format, vk::ImageAspectFlags::DEPTH, 1);
+            ResolvedDepth { image, view, mode }
+        });
+
+        Self {
+            image,
+            view,
+            resolved,
+        }
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        if let Some(mut resolved) = self.resolved.take() {
+            unsafe { device.destroy_image_view(resolved.view, None) };
+            resolved.image.destroy(device, allocator);
+        }
+        unsafe { device.destroy_image_view(self.view, None) };
+        self.image.destroy(device, allocator);
+    }
+}
+
+/// Multisampled color attachment, resolved into the single sampled image a
+/// frame ends up in.
+pub struct MsaaColor {
+    pub image: Image,
+    pub view: vk::ImageView,
+}
+
+impl MsaaColor {
+    /// `None` for a single sample, which is rendered into the resolve target
+    /// directly.
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Option<Self> {
+        if samples == vk::SampleCountFlags::TYPE_1 {
+            return None;
+        }
+
+        // Only the resolved image is read after the render pass.
+        let desc = ImageDesc {
+            samples,
+            ..ImageDesc::new(
+                extent,
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+            )
+        };
+        let image = create_image(device, allocator, "msaa color", &desc);
+        let view = create_image_view(device, image.image, format, vk::ImageAspectFlags::COLOR, 1);
+
+        Some(Self { image, view })
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        unsafe { device.destroy_image_view(self.view, None) };
+        self.image.destroy(device, allocator);
+    }
+}
+
+/// Mode a multisampled depth attachment is resolved with, `None` for a single
+/// sample or when none of the `supported` modes of
+/// `VK_KHR_depth_stencil_resolve` fits.
+///
+/// `SAMPLE_ZERO` is preferred, the extension guarantees it and it keeps a
+/// depth value that was actually rendered.
+pub fn depth_resolve_mode(
+    samples: vk::SampleCountFlags,
+    supported: vk::ResolveModeFlags,
+) -> Option<vk::ResolveModeFlags> {
+    if samples == vk::SampleCountFlags::TYPE_1 {
+        return None;
+    }
+
+    [
+        vk::ResolveModeFlags::SAMPLE_ZERO,
+        vk::ResolveModeFlags::MIN,
+        vk::ResolveModeFlags::MAX,
+    ]
+    .into_iter()
+    .find(|mode| supported.contains(*mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    #[test]
+    fn mip_levels_of_full_chain() {
+        assert_eq!(mip_levels(extent(1, 1)), 1);
+        assert_eq!(mip_levels(extent(2, 2)), 2);
+        assert_eq!(mip_levels(extent(16, 16)), 5);
+        assert_eq!(mip_levels(extent(17, 16)), 5);
+        assert_eq!(mip_levels(extent(512, 64)), 10);
+        assert_eq!(mip_levels(extent(300, 1000)), 10);
+        assert_eq!(mip_levels(extent(1024, 1024)), 11);
+    }
+
+    #[test]
+    fn image_desc_fills_the_create_info() {
+        let desc = ImageDesc {
+            samples: vk::SampleCountFlags::TYPE_4,
+            ..ImageDesc::new(
+                extent(64, 32),
+                vk::Format::D32_SFLOAT,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            )
+        };
+
+        let info = desc.create_info();
+        assert_eq!(
+            info.extent,
+            vk::Extent3D {
+                width: 64,
+                height: 32,
+                depth: 1
+            }
+        );
+        assert_eq!((info.mip_levels, info.array_layers), (1, 1));
+        assert_eq!(info.tiling, vk::ImageTiling::OPTIMAL);
+        assert_eq!(info.samples, vk::SampleCountFlags::TYPE_4);
+        assert_eq!(info.usage, desc.usage);
+    }
+
+    #[test]
+    fn mip_levels_match_log2() {
+        for size in 1..=4096u32 {
+            let expected = (size as f64).log2().floor() as u32 + 1;
+            assert_eq!(mip_levels(extent(size, 1)), expected, "size {size}");
+        }
+    }
+
+    #[test]
+    fn depth_is_resolved_only_when_multisampled() {
+        let all = vk::ResolveModeFlags::SAMPLE_ZERO
+            | vk::ResolveModeFlags::MIN
+            | vk::ResolveModeFlags::MAX;
+        assert_eq!(depth_resolve_mode(vk::SampleCountFlags::TYPE_1, all), None);
+        assert_eq!(
+            depth_resolve_mode(vk::SampleCountFlags::TYPE_4, all),
+            Some(vk::ResolveModeFlags::SAMPLE_ZERO)
+        );
+        assert_eq!(
+            depth_resolve_mode(vk::SampleCountFlags::TYPE_4, vk::ResolveModeFlags::MAX),
+            Some(vk::ResolveModeFlags::MAX)
+        );
+        assert_eq!(
+            depth_resolve_mode(vk::SampleCountFlags::TYPE_4, vk::ResolveModeFlags::NONE),
+            None
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::ops::Range;
+
+use ash::{Device, vk};
+
+use super::VulkanError;
+
+/// Access masks and pipeline stages a barrier between two image layouts synchronizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutTransition {
+    pub src_access_mask: vk::AccessFlags,
+    pub dst_access_mask: vk::AccessFlags,
+    pub src_stage: vk::PipelineStageFlags,
+    pub dst_stage: vk::PipelineStageFlags,
+}
+
+impl LayoutTransition {
+    /// Looks up the masks for one of the transitions the renderer performs.
+    pub fn between(
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) -> Result<Self, VulkanError> {
+        use vk::{AccessFlags as A, ImageLayout as L, PipelineStageFlags as S};
+
+        let (src_access_mask, dst_access_mask, src_stage, dst_stage) =
+            match (old_layout, new_layout) {
+                (L::UNDEFINED, L::TRANSFER_DST_OPTIMAL) => {
+                    (A::empty(), A::TRANSFER_WRITE, S::TOP_OF_PIPE, S::TRANSFER)
+                }
+                (L::UNDEFINED, L::COLOR_ATTACHMENT_OPTIMAL) => (
+                    A::empty(),
+                    A::COLOR_ATTACHMENT_WRITE,
+                    S::TOP_OF_PIPE,
+                    S::COLOR_ATTACHMENT_OUTPUT,
+                ),
+                (L::UNDEFINED, L::DEPTH_STENCIL_ATTACHMENT_OPTIMAL) => (
+                    A::empty(),
+                    A::DEPTH_STENCIL_ATTACHMENT_READ | A::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    S::TOP_OF_PIPE,
+                    S::EARLY_FRAGMENT_TESTS,
+                ),
+                (L::TRANSFER_DST_OPTIMAL, L::TRANSFER_SRC_OPTIMAL) => (
+                    A::TRANSFER_WRITE,
+                    A::TRANSFER_READ,
+                    S::TRANSFER,
+                    S::TRANSFER,
+                ),
+                (L::TRANSFER_DST_OPTIMAL, L::SHADER_READ_ONLY_OPTIMAL) => (
+                    A::TRANSFER_WRITE,
+                    A::SHADER_READ,
+                    S::TRANSFER,
+                    S::FRAGMENT_SHADER,
+                ),
+                (L::TRANSFER_SRC_OPTIMAL, L::SHADER_READ_ONLY_OPTIMAL) => (
+                    A::TRANSFER_READ,
+                    A::SHADER_READ,
+                    S::TRANSFER,
+                    S::FRAGMENT_SHADER,
+                ),
+                (L::PRESENT_SRC_KHR, L::TRANSFER_SRC_OPTIMAL) => (
+                    A::COLOR_ATTACHMENT_WRITE,
+                    A::TRANSFER_READ,
+                    S::COLOR_ATTACHMENT_OUTPUT,
+                    S::TRANSFER,
+                ),
+                (L::TRANSFER_SRC_OPTIMAL, L::PRESENT_SRC_KHR) => {
+                    (A::TRANSFER_READ, A::empty(), S::TRANSFER, S::BOTTOM_OF_PIPE)
+                }
+                (old, new) => return Err(VulkanError::UnsupportedLayoutTransition { old, new }),
+            };
+
+        Ok(Self {
+            src_access_mask,
+            dst_access_mask,
+            src_stage,
+            dst_stage,
+        })
+    }
+}
+
+/// Records a barrier moving the `levels` of every layer of `image` from
+/// `old_layout` to `new_layout`.
+pub fn transition_image_layout(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    aspect: vk::ImageAspectFlags,
+    levels: Range<u32>,
+) -> Result<(), VulkanError> {
+    let transition = LayoutTransition::between(old_layout, new_layout)?;
+
+    let barrier = vk::ImageMemoryBarrier::default()
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect)
+                .base_mip_level(levels.start)
+                .level_count(levels.len() as u32)
+                .base_array_layer(0)
+                .layer_count(vk::REMAINING_ARRAY_LAYERS),
+        )
+        .src_access_mask(transition.src_access_mask)
+        .dst_access_mask(transition.dst_access_mask);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            transition.src_stage,
+            transition.dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_transitions() {
+        let to_transfer = LayoutTransition::between(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        )
+        .unwrap();
+        assert_eq!(to_transfer.src_access_mask, vk::AccessFlags::empty());
+        assert_eq!(to_transfer.dst_access_mask, vk::AccessFlags::TRANSFER_WRITE);
+        assert_eq!(to_transfer.src_stage, vk::PipelineStageFlags::TOP_OF_PIPE);
+        assert_eq!(to_transfer.dst_stage, vk::PipelineStageFlags::TRANSFER);
+
+        let to_sampled = LayoutTransition::between(
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+        .unwrap();
+        assert_eq!(to_sampled.src_access_mask, vk::AccessFlags::TRANSFER_WRITE);
+        assert_eq!(to_sampled.dst_access_mask, vk::AccessFlags::SHADER_READ);
+        assert_eq!(
+            to_sampled.dst_stage,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+    }
+
+    #[test]
+    fn depth_attachment_waits_for_fragment_tests() {
+        let transition = LayoutTransition::between(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        )
+        .unwrap();
+        assert_eq!(
+            transition.dst_stage,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+        );
+        assert!(
+            transition
+                .dst_access_mask
+                .contains(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        );
+    }
+
+    #[test]
+    fn unsupported_transition() {
+        assert_eq!(
+            LayoutTransition::between(
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Err(VulkanError::UnsupportedLayoutTransition {
+                old: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                new: vk::ImageLayout::UNDEFINED,
+            })
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use ash::{Device, vk};
+use bevy_ecs::{
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use gpu_allocator::{MemoryLocation, vulkan::Allocator};
+
+use super::{
+    MAX_FRAMES_IN_FLIGHT, VulkanApp,
+    buffer::{Buffer, create_buffer},
+};
+
+/// Sun-like light shining on the whole world, read every frame.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction the light travels in, doesn't need to be normalized.
+    pub direction: Vec3,
+    pub color: Vec3,
+    /// Light reaching faces turned away from the light.
+    pub ambient: Vec3,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::new(-0.4, -1.0, -0.3),
+            color: Vec3::ONE,
+            ambient: Vec3::splat(0.3),
+        }
+    }
+}
+
+/// Uniform block at binding 1 of the voxel pipeline, mirrored in `shaders/voxel.frag`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct LightUniform {
+    /// Normalized direction towards the light, `w` is unused.
+    pub to_light: Vec4,
+    /// `w` is unused.
+    pub color: Vec4,
+    /// `w` is unused.
+    pub ambient: Vec4,
+}
+
+impl From<&DirectionalLight> for LightUniform {
+    fn from(light: &DirectionalLight) -> Self {
+        Self {
+            to_light: (-light.direction).normalize_or(Vec3::Y).extend(0.0),
+            color: light.color.extend(0.0),
+            ambient: light.ambient.extend(0.0),
+        }
+    }
+}
+
+/// Host-visible uniform buffer of the light for every frame in flight, so a
+/// frame can be updated while the previous one is still rendered.
+pub struct LightBuffers {
+    buffers: Vec<Buffer>,
+}
+
+impl LightBuffers {
+    pub fn new(device: &Device, allocator: &mut Allocator) -> Self {
+        let buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                create_buffer(
+                    device,
+                    allocator,
+                    "directional light",
+                    size_of::<LightUniform>() as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    MemoryLocation::CpuToGpu,
+                )
+            })
+            .collect();
+
+        Self { buffers }
+    }
+
+    pub fn buffer(&self, frame: usize) -> vk::Buffer {
+        self.buffers[frame].buffer
+    }
+
+    /// Writes `light` to the buffer of `frame`, whose previous submission must have completed.
+    pub fn write(&mut self, frame: usize, light: &LightUniform) {
+        let bytes = bytemuck::bytes_of(light);
+        self.buffers[frame]
+            .allocation
+            .mapped_slice_mut()
+            .expect("Uniform memory must be host visible")[..bytes.len()]
+            .copy_from_slice(bytes);
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        for buffer in &mut self.buffers {
+            buffer.destroy(device, allocator);
+        }
+    }
+}
+
+pub fn update_directional_light_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    light: Res<DirectionalLight>,
+) {
+    let uniform = LightUniform::from(&*light);
+    if vulkan_app.light != uniform {
+        vulkan_app.light = uniform;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Light reaching a face with the given `normal`, as computed by `shaders/voxel.frag`.
+    fn shade(light: &LightUniform, normal: Vec3) -> Vec3 {
+        let diffuse = normal.dot(light.to_light.truncate()).max(0.0);
+        light.ambient.truncate() + light.color.truncate() * diffuse
+    }
+
+    #[test]
+    fn uniform_points_towards_the_light() {
+        let light = DirectionalLight {
+            direction: Vec3::new(0.0, -2.0, 0.0),
+            ..Default::default()
+        };
+        let uniform = LightUniform::from(&light);
+        assert_eq!(uniform.to_light, Vec4::new(0.0, 1.0, 0.0, 0.0));
+        assert_eq!(size_of::<LightUniform>(), 48);
+    }
+
+    #[test]
+    fn faces_away_from_the_light_get_ambient() {
+        let light = DirectionalLight {
+            direction: Vec3::NEG_Y,
+            color: Vec3::splat(0.5),
+            ambient: Vec3::splat(0.25),
+        };
+        let uniform = LightUniform::from(&light);
+
+        assert_eq!(shade(&uniform, Vec3::Y), Vec3::splat(0.75));
+        assert_eq!(shade(&uniform, Vec3::X), Vec3::splat(0.25));
+        assert_eq!(shade(&uniform, Vec3::NEG_Y), Vec3::splat(0.25));
+    }
+}
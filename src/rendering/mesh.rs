@@ -0,0 +1,280 @@
+use std::mem::offset_of;
+
+use ash::{Device, vk};
+use bevy_ecs::{event::EventReader, system::ResMut};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use gpu_allocator::vulkan::Allocator;
+
+use super::{
+    buffer::Buffer,
+    renderer::Renderer,
+    storage::{
+        DeferredDestroyQueue,
+        dense::{DenseHandle, DenseHandled},
+    },
+};
+use crate::world::{
+    chunk::CHUNK_SIZE, mesh_queue::MeshQueue, meshing::Vertex, store::ChunkStore,
+    streaming::ChunkUnloaded,
+};
+
+/// Vertex input of the pipelines drawing a vertex struct, see
+/// [`create_graphics_pipeline`](super::create_graphics_pipeline).
+pub trait VertexFormat {
+    fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription>;
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription>;
+}
+
+impl VertexFormat for Vertex {
+    fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        vec![
+            vk::VertexInputBindingDescription::default()
+                .binding(0)
+                .stride(size_of::<Vertex>() as u32)
+                .input_rate(vk::VertexInputRate::VERTEX),
+        ]
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Vertex, position) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Vertex, color) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(2)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Vertex, uv) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(3)
+                .format(vk::Format::R32_SFLOAT)
+                .offset(offset_of!(Vertex, ao) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(4)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Vertex, normal) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(5)
+                .format(vk::Format::R32_UINT)
+                .offset(offset_of!(Vertex, layer) as u32),
+        ]
+    }
+}
+
+/// Push constants of the chunk pipeline, mirrored in `shaders/voxel.vert`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct ChunkPushConstants {
+    pub view_proj: Mat4,
+}
+
+/// Uniform block of a single draw at binding 2 of the voxel pipeline,
+/// mirrored in `shaders/voxel.vert`. Written to a
+/// [`DynamicUniformRing`](super::uniform_ring::DynamicUniformRing).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct ChunkUniform {
+    /// World-space origin of the chunk, `w` is unused.
+    pub offset: Vec4,
+}
+
+impl ChunkUniform {
+    pub fn new(offset: Vec3) -> Self {
+        Self {
+            offset: offset.extend(0.0),
+        }
+    }
+}
+
+/// Bytes of the [`ChunkUniform`]s of a frame, 16384 draws at the common
+/// `minUniformBufferOffsetAlignment` of 256 bytes.
+pub const CHUNK_UNIFORM_CAPACITY: vk::DeviceSize = 4 * 1024 * 1024;
+
+/// Vertex and index buffers of a meshed chunk.
+pub struct GpuMesh {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub index_count: u32,
+}
+
+impl GpuMesh {
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        self.vertex_buffer.destroy(device, allocator);
+        self.index_buffer.destroy(device, allocator);
+    }
+
+    /// Moves the buffers into `buffers`, which the returned mesh refers to.
+    pub fn store(self, buffers: &mut DenseHandled<Buffer>) -> ChunkMesh {
+        ChunkMesh {
+            vertex_buffer: buffers.insert(self.vertex_buffer),
+            index_buffer: buffers.insert(self.index_buffer),
+            index_count: self.index_count,
+        }
+    }
+}
+
+/// A [`GpuMesh`] whose buffers were moved into a [`DenseHandled`] storage.
+pub struct ChunkMesh {
+    pub vertex_buffer: DenseHandle<Buffer>,
+    pub index_buffer: DenseHandle<Buffer>,
+    pub index_count: u32,
+}
+
+impl ChunkMesh {
+    /// Removes the buffers from the storage they were [stored](GpuMesh::store) in.
+    pub fn take(self, buffers: &mut DenseHandled<Buffer>) -> GpuMesh {
+        let mut remove = |handle| {
+            buffers
+                .remove(&handle)
+                .expect("Chunk mesh buffers are stored until the mesh is taken")
+        };
+        GpuMesh {
+            vertex_buffer: remove(self.vertex_buffer),
+            index_buffer: remove(self.index_buffer),
+            index_count: self.index_count,
+        }
+    }
+}
+
+/// A single chunk draw recorded into the command buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct DrawItem {
+    pub vertex_buffer: vk::Buffer,
+    pub index_buffer: vk::Buffer,
+    pub index_count: u32,
+    /// Dynamic offset of the [`ChunkUniform`] of the draw.
+    pub uniform_offset: u32,
+    /// Occlusion query around the draw, in the pool of the [`ChunkDrawState`](super::recording::ChunkDrawState).
+    pub query: Option<u32>,
+}
+
+/// Queues the loaded chunks without a mesh for meshing and uploads the meshes
+/// finished by the workers.
+///
+/// Only up to the queue's upload budget is uploaded each frame, and nothing
+/// here waits for the workers or the uploads.
+pub fn upload_chunk_meshes_system<R: Renderer>(
+    mut renderer: ResMut<R>,
+    mut mesh_queue: ResMut<MeshQueue>,
+    mut store: ResMut<ChunkStore>,
+    mut destroy_queue: ResMut<DeferredDestroyQueue>,
+) {
+    renderer.finish_chunk_uploads(&mut destroy_queue);
+
+    for coord in store.take_dirty().drain() {
+        mesh_queue.enqueue(coord);
+    }
+
+    for (coord, _) in store.iter() {
+        if !renderer.has_chunk_mesh(coord) && !mesh_queue.contains(coord) {
+            mesh_queue.enqueue(coord);
+        }
+    }
+
+    mesh_queue.dispatch(&store);
+    mesh_queue.collect();
+
+    for mesh in mesh_queue.drain_ready() {
+        // The chunk may have been unloaded while it was meshed.
+        // The previous mesh is replaced once the upload finished.
+        if store.is_loaded(mesh.coord) {
+            renderer.upload_chunk_mesh(mesh.coord, &mesh.vertices, &mesh.indices);
+        }
+    }
+}
+
+/// Drops the meshes of unloaded chunks.
+pub fn unload_chunk_meshes_system<R: Renderer>(
+    mut renderer: ResMut<R>,
+    mut unloaded: EventReader<ChunkUnloaded>,
+    mut destroy_queue: ResMut<DeferredDestroyQueue>,
+) {
+    for ChunkUnloaded { coord } in unloaded.read() {
+        renderer.retire_chunk_mesh(&mut destroy_queue, *coord);
+    }
+}
+
+/// World-space origin of the chunk at `coord`.
+pub fn chunk_offset(coord: glam::IVec3) -> Vec3 {
+    (coord * CHUNK_SIZE as i32).as_vec3()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct PosColorVertex {
+        position: [f32; 3],
+        color: [f32; 4],
+    }
+
+    impl VertexFormat for PosColorVertex {
+        fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+            vec![
+                vk::VertexInputBindingDescription::default()
+                    .binding(0)
+                    .stride(size_of::<PosColorVertex>() as u32)
+                    .input_rate(vk::VertexInputRate::VERTEX),
+            ]
+        }
+
+        fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+            vec![
+                vk::VertexInputAttributeDescription::default()
+                    .binding(0)
+                    .location(0)
+                    .format(vk::Format::R32G32B32_SFLOAT)
+                    .offset(offset_of!(PosColorVertex, position) as u32),
+                vk::VertexInputAttributeDescription::default()
+                    .binding(0)
+                    .location(1)
+                    .format(vk::Format::R32G32B32A32_SFLOAT)
+                    .offset(offset_of!(PosColorVertex, color) as u32),
+            ]
+        }
+    }
+
+    #[test]
+    fn descriptions_match_the_struct() {
+        let bindings = PosColorVertex::binding_descriptions();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].stride, 28);
+
+        let attributes = PosColorVertex::attribute_descriptions();
+        let layout = attributes
+            .iter()
+            .map(|attribute| (attribute.location, attribute.format, attribute.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            layout,
+            [
+                (0, vk::Format::R32G32B32_SFLOAT, 0),
+                (1, vk::Format::R32G32B32A32_SFLOAT, 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn chunk_vertex_attributes_fit_the_stride() {
+        let stride = Vertex::binding_descriptions()[0].stride;
+        let attributes = Vertex::attribute_descriptions();
+
+        for (location, attribute) in attributes.iter().enumerate() {
+            assert_eq!(attribute.location, location as u32);
+            assert!(attribute.offset < stride);
+        }
+    }
+}
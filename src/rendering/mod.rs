@@ -1,27 +1,111 @@
 use std::{
     collections::HashSet,
-    ffi::{CStr, CString, c_char, c_void},
+    ffi::{CStr, CString, c_char},
+    mem::ManuallyDrop,
+    path::PathBuf,
     sync::Arc,
 };
 
+use allocator::{GpuMemoryStats, create_allocator, update_gpu_memory_stats_system};
 use ash::{
     Device, Entry, Instance,
     ext::{self},
     khr,
     vk::{
         self, API_VERSION_1_0, API_VERSION_1_3, DebugReportCallbackEXT, DebugUtilsMessengerEXT,
-        Extent2D, Queue, SwapchainDisplayNativeHdrCreateInfoAMD,
+        Extent2D, Queue,
     },
 };
-use bevy_app::{Last, MainScheduleOrder, Plugin, Startup};
-use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
+use bevy_app::{AppExit, Last, MainScheduleOrder, Plugin, Startup};
+use bevy_ecs::{prelude::*, schedule::ScheduleLabel, system::SystemParam};
+use buffer::Buffer;
+pub use culling::FrustumCulling;
+use culling::{Aabb, Frustum, update_frustum_culling_system};
+pub use debug_lines::{DebugGrid, ShowDebugGrid};
+use debug_lines::{DebugLines, line_width, update_debug_grid_visibility_system};
+use debug_utils::{DebugLabel, disable_debug_utils, enable_debug_utils, set_debug_name};
+use descriptor::{
+    DescriptorSetLayoutCache, chunk_set_bindings, create_descriptor_pool, create_descriptor_sets,
+    write_texture_descriptor,
+};
+pub use device::{RenderDevice, RenderQueue};
+pub use device_info::{DeviceCandidate, DeviceCandidates, DeviceLimits, MemoryBudget, Unsuitable};
+use device_info::{
+    query_memory_budget, supports_memory_budget, supports_physical_device_properties2,
+};
+pub use device_lost::{DeviceLost, FenceTimeout};
+use device_lost::{DeviceRebuilds, update_fence_timeout_system};
+use dynamic_rendering::{
+    AttachmentFormats, DynamicTarget, begin_rendering, depth_aspect_mask, end_rendering,
+    instance_api_version, supports_dynamic_rendering,
+};
+pub use error::{InitError, VulkanError};
+use extensions::{
+    available_instance_extensions, select_instance_extensions, supports_device_extension,
+    supports_instance_extension,
+};
+use frame_stats::StageTimer;
+pub use frame_stats::{CollectFrameStats, FrameStats};
+use glam::{IVec3, Mat4};
+use gpu_allocator::vulkan::Allocator;
+pub use hdr::HdrMode;
+use hdr::{find_hdr_surface_format, supports_swapchain_colorspace};
+use image::{DepthResources, MsaaColor, depth_resolve_mode, find_depth_format};
 use itertools::Itertools;
+pub use lighting::DirectionalLight;
+use lighting::{LightBuffers, LightUniform, update_directional_light_system};
+use mesh::{
+    CHUNK_UNIFORM_CAPACITY, ChunkMesh, ChunkPushConstants, ChunkUniform, DrawItem, GpuMesh,
+    VertexFormat, chunk_offset, unload_chunk_meshes_system, upload_chunk_meshes_system,
+};
+pub use msaa::SampleCount;
+use msaa::{DEPTH_RESOLVE_EXTENSIONS, attachment_sample_counts, query_depth_resolve_modes};
+pub use occlusion::OcclusionCulling;
+use occlusion::{OcclusionQueries, is_queryable, update_occlusion_culling_system};
+use offscreen::{OFFSCREEN_FORMAT, OffscreenTarget};
+use portability::{
+    device_extensions, instance_create_flags, portability_instance_extensions,
+    supports_portability_subset,
+};
+use queue_family::{QueueFamilyIndices, find_queue_families, search_queue_families};
+pub use raster::{CullConfig, DepthMode, FlipViewportY};
+use raster::{RasterConfig, unflip_projection, update_flip_viewport_y_system};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle};
+pub use recording::CommandPoolResetMode;
+use recording::{
+    ChunkDrawState, FrameCommandPools, InheritedTarget, MAX_RECORDING_THREADS,
+    ThreadLocalCommandPools,
+};
+pub use render_target::{AutoRenderScale, RenderScale, RenderTargetSize};
+use render_target::{
+    SceneTarget, auto_render_scale_system, scene_extent, update_render_target_system,
+};
+use renderer::update_primary_window_state_system;
+pub use renderer::{PrimaryWindowState, Renderer};
+use screenshot::{ChannelOrder, PendingCapture, ScreenshotError};
+pub use storage::{DeferredDestroyQueue, Destroy, StoragePlugin, common::CommonStoragesPlugin};
 use storage::{
-    Handle, InsertStorageCommandsExt, RawStorage, Storage, StorageHandledMut,
-    common::{DeviceStorage, SurfacePack},
+    DestroySet, Handle, InsertStorageCommandsExt, KeyedHandled, RawStorage, Storage,
+    StorageHandledMut, StorageSingle,
+    common::{DeviceStorage, DeviceStorageExt, SurfacePack, add_common_destroy_storages},
+    dense::DenseHandled,
+    destroy_storages,
+    order::DestroyGraph,
+};
+pub use swapchain::{
+    CompositeAlpha, PresentMode, SurfaceFormatPreference, SwapchainClipped, SwapchainImageCount,
+};
+use swapchain::{SuboptimalTracker, SwapchainConfig, pre_rotated_extent, pre_rotation};
+pub use texture::{AnisotropyLevel, SamplerConfig};
+use texture::{
+    SamplerDesc, Texture, TextureSource, load_texture, update_sampler_config_system, usable_layers,
 };
-use tracing::{debug, error, info, info_span, trace, warn};
+use tracing::{debug, error, info, info_span, warn};
+use transfer::{QueueContext, UploadQueues};
+use uniform_ring::DynamicUniformRing;
+use upload::UploadTracker;
+use validation::check_validation_errors;
+pub use validation::{VALIDATION_TARGET, ValidationConfig};
 use winit::{
     dpi::PhysicalSize,
     event::WindowEvent,
@@ -29,24 +113,307 @@ use winit::{
     window::Window,
 };
 
-use crate::utils::FirstRun;
-use crate::windowing::{AppWindows, RawWnitWindowEvent, WinitOwnedDisplayHandle};
-
+use crate::camera::Camera;
+use crate::utils::{FirstRun, OnChange};
+use crate::windowing::{AppWindows, RawWnitWindowEvent, Screenshot, WinitOwnedDisplayHandle};
+use crate::world::chunk::BLOCK_TEXTURES;
+use crate::world::meshing::Vertex;
+
+mod allocator;
+mod buffer;
+mod compute;
+mod culling;
+mod debug_lines;
+mod debug_utils;
+mod descriptor;
+mod device;
+mod device_info;
+mod device_lost;
+mod dynamic_rendering;
+mod error;
+mod extensions;
+mod frame_stats;
+mod hdr;
+mod image;
+mod layout;
+mod lighting;
+mod mesh;
+mod msaa;
+mod occlusion;
+mod offscreen;
+mod portability;
+mod queue_family;
+mod raster;
+mod recording;
+mod render_target;
+mod renderer;
+mod screenshot;
 mod storage;
+mod swapchain;
+mod texture;
+mod transfer;
 mod triangle;
+mod uniform_ring;
+mod upload;
+mod validation;
+
+/// Renders the world into the primary window.
+///
+/// Configured through its `with_*` options, which insert their resource when
+/// the plugin is built:
+///
+/// ```ignore
+/// RenderingPlugin::default()
+///     .with_present_mode(PresentMode::Fifo)
+///     .with_msaa(SampleCount::X4)
+///     .with_clear_color([0.5, 0.7, 1.0, 1.0])
+/// ```
+///
+/// Resources of unset options are kept if they were inserted before adding
+/// the plugin, and initialized to their defaults otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct RenderingPlugin {
+    anisotropy: Option<AnisotropyLevel>,
+    sampler: Option<SamplerConfig>,
+    hdr: Option<HdrMode>,
+    validation: Option<ValidationConfig>,
+    surface_formats: Option<SurfaceFormatPreference>,
+    image_count: Option<SwapchainImageCount>,
+    clipped: Option<SwapchainClipped>,
+    composite_alpha: Option<CompositeAlpha>,
+    present_mode: Option<PresentMode>,
+    msaa: Option<SampleCount>,
+    clear_color: Option<ClearColor>,
+    depth_mode: Option<DepthMode>,
+    cull: Option<CullConfig>,
+    flip_viewport_y: Option<FlipViewportY>,
+    debug_grid: Option<DebugGrid>,
+    frustum_culling: Option<FrustumCulling>,
+    occlusion_culling: Option<OcclusionCulling>,
+    light: Option<DirectionalLight>,
+    collect_frame_stats: Option<CollectFrameStats>,
+    render_target_size: Option<RenderTargetSize>,
+    render_scale: Option<RenderScale>,
+    auto_render_scale: Option<AutoRenderScale>,
+    fence_timeout: Option<FenceTimeout>,
+    command_pool_reset_mode: Option<CommandPoolResetMode>,
+}
+
+impl RenderingPlugin {
+    pub fn with_anisotropy(mut self, anisotropy: AnisotropyLevel) -> Self {
+        self.anisotropy = Some(anisotropy);
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerConfig) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    pub fn with_hdr(mut self, enabled: bool) -> Self {
+        self.hdr = Some(HdrMode(enabled));
+        self
+    }
+
+    pub fn with_validation(mut self, validation: ValidationConfig) -> Self {
+        self.validation = Some(validation);
+        self
+    }
+
+    /// Acceptable swapchain formats, most preferred first.
+    pub fn with_surface_formats(mut self, formats: Vec<vk::SurfaceFormatKHR>) -> Self {
+        self.surface_formats = Some(SurfaceFormatPreference(formats));
+        self
+    }
+
+    pub fn with_swapchain_image_count(mut self, count: u32) -> Self {
+        self.image_count = Some(SwapchainImageCount(Some(count)));
+        self
+    }
+
+    pub fn with_swapchain_clipped(mut self, clipped: bool) -> Self {
+        self.clipped = Some(SwapchainClipped(clipped));
+        self
+    }
+
+    pub fn with_composite_alpha(mut self, composite_alpha: CompositeAlpha) -> Self {
+        self.composite_alpha = Some(composite_alpha);
+        self
+    }
+
+    pub fn with_present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.present_mode = Some(present_mode);
+        self
+    }
+
+    pub fn with_msaa(mut self, samples: SampleCount) -> Self {
+        self.msaa = Some(samples);
+        self
+    }
+
+    /// Linear RGBA color the frames are cleared to.
+    pub fn with_clear_color(mut self, color: [f32; 4]) -> Self {
+        self.clear_color = Some(ClearColor(color));
+        self
+    }
+
+    pub fn with_depth_mode(mut self, depth_mode: DepthMode) -> Self {
+        self.depth_mode = Some(depth_mode);
+        self
+    }
+
+    pub fn with_cull(mut self, cull: CullConfig) -> Self {
+        self.cull = Some(cull);
+        self
+    }
+
+    pub fn with_flip_viewport_y(mut self, enabled: bool) -> Self {
+        self.flip_viewport_y = Some(FlipViewportY(enabled));
+        self
+    }
+
+    pub fn with_debug_grid(mut self, grid: DebugGrid) -> Self {
+        self.debug_grid = Some(grid);
+        self
+    }
+
+    pub fn with_frustum_culling(mut self, enabled: bool) -> Self {
+        self.frustum_culling = Some(FrustumCulling(enabled));
+        self
+    }
+
+    pub fn with_occlusion_culling(mut self, enabled: bool) -> Self {
+        self.occlusion_culling = Some(OcclusionCulling(enabled));
+        self
+    }
+
+    pub fn with_light(mut self, light: DirectionalLight) -> Self {
+        self.light = Some(light);
+        self
+    }
+
+    pub fn with_frame_stats(mut self, enabled: bool) -> Self {
+        self.collect_frame_stats = Some(CollectFrameStats(enabled));
+        self
+    }
+
+    pub fn with_render_target_size(mut self, size: RenderTargetSize) -> Self {
+        self.render_target_size = Some(size);
+        self
+    }
 
-pub struct RenderingPlugin;
+    pub fn with_render_scale(mut self, scale: f32) -> Self {
+        self.render_scale = Some(RenderScale(scale));
+        self
+    }
+
+    pub fn with_auto_render_scale(mut self, enabled: bool) -> Self {
+        self.auto_render_scale = Some(AutoRenderScale(enabled));
+        self
+    }
+
+    pub fn with_fence_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.fence_timeout = Some(FenceTimeout(timeout));
+        self
+    }
+
+    pub fn with_command_pool_reset_mode(mut self, mode: CommandPoolResetMode) -> Self {
+        self.command_pool_reset_mode = Some(mode);
+        self
+    }
+}
+
+/// Inserts `value` if it is set, otherwise the default unless `R` already exists.
+fn insert_or_init<R: Resource + Default + Clone>(app: &mut bevy_app::App, value: &Option<R>) {
+    match value {
+        Some(value) => app.insert_resource(value.clone()),
+        None => app.init_resource::<R>(),
+    };
+}
 
 impl Plugin for RenderingPlugin {
     fn build(&self, app: &mut bevy_app::App) {
         app.add_schedule(Schedule::new(Render));
 
+        // Also adds the `DeferredDestroyPlugin`.
+        if !app.is_plugin_added::<StoragePlugin>() {
+            app.add_plugins(StoragePlugin);
+        }
+        if !app.is_plugin_added::<CommonStoragesPlugin>() {
+            app.add_plugins(CommonStoragesPlugin);
+        }
+
         let mut order = app.world_mut().resource_mut::<MainScheduleOrder>();
         order.insert_after(Last, Render);
 
-        // app.add_systems(Startup, init_vulkan_app);
+        insert_or_init(app, &self.anisotropy);
+        insert_or_init(app, &self.sampler);
+        insert_or_init(app, &self.hdr);
+        insert_or_init(app, &self.validation);
+        insert_or_init(app, &self.surface_formats);
+        insert_or_init(app, &self.image_count);
+        insert_or_init(app, &self.clipped);
+        insert_or_init(app, &self.composite_alpha);
+        insert_or_init(app, &self.present_mode);
+        insert_or_init(app, &self.msaa);
+        insert_or_init(app, &self.clear_color);
+        insert_or_init(app, &self.depth_mode);
+        insert_or_init(app, &self.cull);
+        insert_or_init(app, &self.flip_viewport_y);
+        insert_or_init(app, &self.debug_grid);
+        insert_or_init(app, &self.frustum_culling);
+        insert_or_init(app, &self.occlusion_culling);
+        insert_or_init(app, &self.light);
+        insert_or_init(app, &self.collect_frame_stats);
+        insert_or_init(app, &self.render_target_size);
+        insert_or_init(app, &self.render_scale);
+        insert_or_init(app, &self.auto_render_scale);
+        insert_or_init(app, &self.fence_timeout);
+        insert_or_init(app, &self.command_pool_reset_mode);
+
+        app.init_resource::<GpuMemoryStats>()
+            .init_resource::<FrameStats>()
+            .init_resource::<ShowDebugGrid>()
+            .add_event::<DeviceLost>();
+
+        // Headless apps have no window to render to.
+        app.add_systems(
+            Startup,
+            init_vulkan_app.run_if(resource_exists::<AppWindows>),
+        );
+
+        app.add_systems(
+            Destroy,
+            release_vulkan_app_system.in_set(DestroySet::Release),
+        );
 
-        app.add_systems(Render, render_frame);
+        // Nothing is rendered if `init_vulkan_app` failed, the app exits at the end of this update.
+        app.add_systems(
+            Render,
+            (
+                unload_chunk_meshes_system::<VulkanApp>,
+                upload_chunk_meshes_system::<VulkanApp>,
+                update_swapchain_config_system,
+                update_raster_config_system,
+                update_sampler_config_system,
+                update_debug_grid_visibility_system,
+                update_frustum_culling_system,
+                update_occlusion_culling_system,
+                update_flip_viewport_y_system,
+                update_fence_timeout_system,
+                update_command_pool_reset_mode_system,
+                auto_render_scale_system,
+                update_render_target_system,
+                update_directional_light_system,
+                update_clear_color_system,
+                capture_screenshots_system,
+                update_primary_window_state_system,
+                render_frame::<VulkanApp>,
+                update_gpu_memory_stats_system,
+            )
+                .chain()
+                .run_if(resource_exists::<VulkanApp>),
+        );
     }
 }
 
@@ -59,35 +426,48 @@ pub const REQUIRED_DEVICE_EXTENSIONS: &[*const i8] = &[khr::swapchain::NAME.as_p
 pub const ENABLE_VALIDATION_LAYERS: bool = true;
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
-unsafe extern "system" fn vulkan_debug_callback(
-    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
-    message_types: vk::DebugUtilsMessageTypeFlagsEXT,
-    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
-    _p_user_data: *mut c_void,
-) -> u32 {
-    let message = unsafe {
-        let p_message = (*p_callback_data).p_message;
-        CStr::from_ptr(p_message)
-    };
-    let message = message.to_string_lossy();
-
-    if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
-        error!("{:?} - {}", message_types, message);
-    } else if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
-        warn!("{:?} - {}", message_types, message);
-    } else if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::INFO {
-        info!("{:?} - {}", message_types, message);
-    } else {
-        trace!("{:?} - {}", message_types, message)
-    };
-
-    vk::FALSE
-}
-
 /// The window represented by `window` must be associated with the display connection in `display_handle`.
 pub struct VulkanAppCreateInfo {
     pub display_handle: OwnedDisplayHandle,
     pub window: Arc<winit::window::Window>,
+    pub anisotropy: AnisotropyLevel,
+    pub sampler: SamplerConfig,
+    pub validation: ValidationConfig,
+    pub swapchain: SwapchainConfig,
+    pub raster: RasterConfig,
+    pub msaa: SampleCount,
+    pub debug_grid: DebugGrid,
+}
+
+/// Where a [`VulkanApp`] presents its frames.
+enum Output {
+    Window {
+        display_handle: OwnedDisplayHandle,
+        window: Arc<winit::window::Window>,
+    },
+    /// An offscreen image of the given size, without a surface.
+    Offscreen(vk::Extent2D),
+}
+
+/// The objects of a [`VulkanApp`] that outlive its device, see
+/// [`VulkanApp::rebuild_device`].
+struct InstanceContext {
+    entry: ash::Entry,
+    instance: ash::Instance,
+    debug_utils_instance_messenger: Option<(ext::debug_utils::Instance, DebugUtilsMessengerEXT)>,
+    /// `None` for headless apps.
+    surface: Option<SurfacePack>,
+    /// Size of the window, or of the offscreen image without a surface.
+    size: PhysicalSize<u32>,
+    api_version: u32,
+    /// Whether `VK_KHR_get_physical_device_properties2` is enabled.
+    properties2: bool,
+    /// Physical devices stay valid when a device created from them is lost.
+    physical_device: vk::PhysicalDevice,
+    queue_family_indices: QueueFamilyIndices,
+    device_candidates: DeviceCandidates,
+    /// One per layer of the block texture array.
+    block_textures: Vec<TextureSource>,
 }
 
 #[derive(Resource)]
@@ -97,14 +477,35 @@ pub struct VulkanApp {
 
     debug_utils_instance_messenger: Option<(ext::debug_utils::Instance, DebugUtilsMessengerEXT)>,
 
-    surface_instance: khr::surface::Instance,
-    surface: vk::SurfaceKHR,
+    /// `None` for headless apps.
+    surface: Option<SurfacePack>,
+
+    api_version: u32,
+    /// Whether `VK_KHR_get_physical_device_properties2` is enabled.
+    properties2: bool,
+    anisotropy: AnisotropyLevel,
+    /// Kept for device rebuilds, the texture sampler is created with it.
+    sampler_config: SamplerConfig,
+    /// One per layer of the block texture array.
+    block_textures: Vec<TextureSource>,
+    debug_grid: DebugGrid,
 
     physical_device: vk::PhysicalDevice,
+    /// Every physical device considered when `physical_device` was selected.
+    device_candidates: DeviceCandidates,
+    /// `None` when `VK_EXT_memory_budget` isn't supported.
+    memory_budget_instance: Option<khr::get_physical_device_properties2::Instance>,
     pub device: Device,
+    /// Sub-allocates all buffer and image memory. Dropped right before the device.
+    allocator: ManuallyDrop<Allocator>,
 
+    queue_family_indices: QueueFamilyIndices,
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
+    /// Queue uploads run on. Same as `graphics_queue` without a dedicated transfer family.
+    transfer_queue: vk::Queue,
+    /// Same as `graphics_queue` without an async compute family.
+    compute_queue: vk::Queue,
 
     swapchain_device: khr::swapchain::Device,
     swapchain: vk::SwapchainKHR,
@@ -112,146 +513,813 @@ pub struct VulkanApp {
     swapchain_image_views: Vec<vk::ImageView>,
     swapchain_image_format: vk::Format,
     swapchain_extent: vk::Extent2D,
-
-    render_pass: vk::RenderPass,
+    /// Rotation the presentation engine applies to the swapchain images,
+    /// compensated by [`pre_rotation`].
+    surface_transform: vk::SurfaceTransformFlagsKHR,
+    swapchain_config: SwapchainConfig,
+    suboptimal: SuboptimalTracker,
+    /// Set after presenting to a suboptimal swapchain, taken by [`render_frame`].
+    recreate_requested: bool,
+    /// Where to save the next presented frame, see [`VulkanApp::capture_frame`].
+    pending_screenshot: Option<PathBuf>,
+
+    depth_format: vk::Format,
+    depth: DepthResources,
+    /// Samples of the color and depth attachments, supported by the device.
+    msaa: SampleCount,
+    /// Mode the multisampled depth is resolved with, `None` without
+    /// `VK_KHR_depth_stencil_resolve`.
+    depth_resolve: Option<vk::ResolveModeFlags>,
+    /// Rendered to and resolved into the swapchain image when multisampled.
+    msaa_color: Option<MsaaColor>,
+
+    /// Rendered to instead of the swapchain by headless apps. Its view and
+    /// framebuffer are the only entries of `swapchain_image_views` and
+    /// `swapchain_framebuffers`.
+    offscreen: Option<OffscreenTarget>,
+
+    /// Rendered to instead of the swapchain images when `render_target_size`
+    /// isn't [`RenderTargetSize::Swapchain`], then blitted onto them.
+    scene_target: Option<SceneTarget>,
+    /// Mirrors [`RenderTargetSize`].
+    render_target_size: RenderTargetSize,
+    /// Mirrors [`RenderScale`], clamped when the extent is computed.
+    render_scale: f32,
+
+    /// `None` when frames are rendered with dynamic rendering.
+    render_pass: Option<vk::RenderPass>,
+    /// Creates the render passes resolving depth, `None` when it isn't
+    /// resolved or with dynamic rendering.
+    render_pass2: Option<khr::create_renderpass2::Device>,
+    /// Owns `descriptor_set_layout` and the layouts of future pipelines.
+    descriptor_set_layouts: DescriptorSetLayoutCache,
+    descriptor_set_layout: vk::DescriptorSetLayout,
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
+    /// Fixed-function state the pipelines are created with.
+    raster_config: RasterConfig,
+    debug_lines: DebugLines,
+    /// `None` when the query pools couldn't be created.
+    occlusion_queries: Option<OcclusionQueries>,
 
+    /// Empty when frames are rendered with dynamic rendering.
     swapchain_framebuffers: Vec<vk::Framebuffer>,
 
-    command_pool: vk::CommandPool,
-    command_buffers: Vec<vk::CommandBuffer>,
+    texture: Texture,
+    light_buffers: LightBuffers,
+    /// [`ChunkUniform`] of every draw of the frame being recorded.
+    chunk_uniforms: DynamicUniformRing,
+    /// Mirrors [`DirectionalLight`], written to the light buffer of each frame.
+    light: LightUniform,
+    /// Mirrors [`ClearColor`].
+    clear_color: [f32; 4],
+    descriptor_pool: vk::DescriptorPool,
+    /// One per frame in flight.
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    /// Sampler bound by each of `descriptor_sets`, which is rewritten before
+    /// its frame when the sampler of `texture` was replaced.
+    descriptor_set_samplers: Vec<vk::Sampler>,
+
+    /// Pool of the one-shot command buffers of the graphics queue, e.g. uploads
+    /// and layout transitions.
+    transient_command_pool: vk::CommandPool,
+    /// Primary command buffers of the frames, see [`CommandPoolResetMode`].
+    frame_commands: FrameCommandPools,
+    transfer_command_pool: vk::CommandPool,
+    compute_command_pool: vk::CommandPool,
+    /// Pools the chunk draws are recorded from in parallel.
+    recording_pools: ThreadLocalCommandPools,
 
     image_available_semaphores: Vec<vk::Semaphore>,
     render_finished_semaphores: Vec<vk::Semaphore>,
     in_flight_fences: Vec<vk::Fence>,
 
+    /// Uploaded chunk meshes. `None` marks chunks without any visible faces.
+    chunk_meshes: KeyedHandled<IVec3, Option<ChunkMesh>>,
+    /// Buffers of `chunk_meshes`, which hold their handles.
+    chunk_buffers: DenseHandled<Buffer>,
+    /// Chunk meshes still being copied, moved to `chunk_meshes` once finished.
+    uploads: UploadTracker,
+    /// Meshes replaced in `chunk_meshes` that frames in flight may still draw,
+    /// see [`Renderer::finish_chunk_uploads`].
+    replaced_meshes: Vec<GpuMesh>,
+    /// Mirrors [`FrustumCulling`].
+    frustum_culling: bool,
+    /// Mirrors [`OcclusionCulling`].
+    occlusion_culling: bool,
+    /// Mirrors [`FenceTimeout`], in nanoseconds.
+    fence_timeout: u64,
+    /// Whether `VK_KHR_maintenance1` is enabled, which allows negative viewport heights.
+    maintenance1: bool,
+    /// Mirrors [`FlipViewportY`] when `maintenance1` is enabled.
+    flip_viewport_y: bool,
+    /// Incremented every time the device is rebuilt, objects of an older
+    /// generation were destroyed with their device.
+    device_generation: u64,
+
     current_frame: usize,
+    /// Set once the objects were handed over to the storages, see
+    /// [`VulkanApp::release_into_storages`].
+    released: bool,
 }
 
 impl Drop for VulkanApp {
     fn drop(&mut self) {
-        unsafe {
-            self.cleanup_swapchain();
+        // A panic may unwind through an app that would have been released.
+        if !std::thread::panicking() {
+            debug_assert!(
+                self.released,
+                "VulkanApp was dropped without releasing its Vulkan objects"
+            );
+        }
+    }
+}
 
-            for semaphore in &self.image_available_semaphores {
-                self.device.destroy_semaphore(*semaphore, None);
-            }
+impl VulkanApp {
+    /// Hands the device and everything created on it over to the storages,
+    /// which destroy them in order in the [`Destroy`] schedule. The instance,
+    /// debug messenger and surface are left to the app.
+    ///
+    /// Objects that are only part of a larger one without a storage of its
+    /// own, like the depth image or the recording pools, are destroyed right
+    /// away.
+    ///
+    /// # Safety
+    /// The app must not be used afterwards except for releasing its instance
+    /// objects or passing them to [`VulkanApp::create_on_instance`].
+    unsafe fn release_device_objects(&mut self, commands: &mut Commands) {
+        // The last submitted frames may still be using the objects below.
+        if let Err(err) = unsafe { self.device.device_wait_idle() } {
+            error!("Failed to wait for the device to become idle: {err}");
+        }
 
-            for semaphore in &self.render_finished_semaphores {
-                self.device.destroy_semaphore(*semaphore, None);
-            }
+        let device = &self.device;
+        let allocator = &mut *self.allocator;
+        if let Some(mut scene_target) = self.scene_target.take() {
+            scene_target.destroy(device, allocator);
+        }
+        self.depth.destroy(device, allocator);
+        if let Some(mut msaa_color) = self.msaa_color.take() {
+            msaa_color.destroy(device, allocator);
+        }
+        if let Some(offscreen) = &mut self.offscreen {
+            offscreen.destroy(device, allocator);
+        }
+        self.uploads.destroy(device, allocator);
+        self.recording_pools.destroy(device);
+        self.debug_lines.destroy(device, allocator);
+        if let Some(occlusion_queries) = &mut self.occlusion_queries {
+            occlusion_queries.destroy(device, allocator);
+        }
+        self.light_buffers.destroy(device, allocator);
+        self.chunk_uniforms.destroy(device, allocator);
 
-            for fence in &self.in_flight_fences {
-                self.device.destroy_fence(*fence, None);
-            }
+        debug!(
+            "Releasing the {} buffers of the chunk meshes",
+            self.chunk_buffers.len()
+        );
+        for mesh in self
+            .chunk_meshes
+            .drain()
+            .filter_map(|(_, mesh)| mesh)
+            .map(|mesh| mesh.take(&mut self.chunk_buffers))
+            .chain(self.replaced_meshes.drain(..))
+        {
+            commands.track_dense(mesh.vertex_buffer);
+            commands.track_dense(mesh.index_buffer);
+        }
+        commands.track(std::mem::take(&mut self.texture));
 
-            self.device.destroy_command_pool(self.command_pool, None);
+        for framebuffer in self.swapchain_framebuffers.drain(..) {
+            commands.track(framebuffer);
+        }
+        for image_view in self.swapchain_image_views.drain(..) {
+            commands.track(image_view);
+        }
+        for semaphore in self
+            .image_available_semaphores
+            .drain(..)
+            .chain(self.render_finished_semaphores.drain(..))
+        {
+            commands.track(semaphore);
+        }
+        for fence in self.in_flight_fences.drain(..) {
+            commands.track(fence);
+        }
+        for command_pool in [
+            self.transient_command_pool,
+            self.transfer_command_pool,
+            self.compute_command_pool,
+        ]
+        .into_iter()
+        .chain(self.frame_commands.pools())
+        {
+            commands.track(command_pool);
+        }
+        commands.track(self.descriptor_pool);
+        commands.track(self.pipeline);
+        commands.track(self.pipeline_layout);
+        if let Some(render_pass) = self.render_pass.take() {
+            commands.track(render_pass);
+        }
+        commands.insert_single_storage(std::mem::take(&mut self.descriptor_set_layouts));
 
-            self.device.destroy_pipeline(self.pipeline, None);
+        // Headless apps render to `offscreen` instead of a swapchain.
+        if self.offscreen.is_none() {
+            commands.insert_single_storage((self.swapchain_device.clone(), self.swapchain));
+        }
+        commands.insert_single_storage(unsafe { ManuallyDrop::take(&mut self.allocator) });
+        disable_debug_utils(&self.device);
+        commands.insert_single_storage(self.device.clone());
+    }
 
-            self.device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
+    /// Hands all objects of the app over to the storages, see
+    /// [`release_device_objects`](Self::release_device_objects).
+    fn release_into_storages(mut self, commands: &mut Commands) {
+        unsafe { self.release_device_objects(commands) };
 
-            self.device.destroy_render_pass(self.render_pass, None);
+        if let Some(debug_utils) = self.debug_utils_instance_messenger.take() {
+            commands.insert_single_storage(debug_utils);
+        }
+        if let Some(surface) = self.surface.take() {
+            commands.insert_single_storage(surface);
+        }
+        commands.insert_single_storage(self.instance.clone());
+        self.released = true;
+    }
 
-            self.device.destroy_device(None);
+    /// Destroys what `release` hands over to the storages of a temporary
+    /// world, in the order of the common storages.
+    fn destroy_released(release: impl FnOnce(&mut Commands)) {
+        let mut graph = DestroyGraph::default();
+        add_common_destroy_storages(&mut graph);
+        let mut world = World::new();
+        world.insert_resource(graph);
+
+        release(&mut world.commands());
+        world.flush();
+        if let Err(err) = destroy_storages(&mut world) {
+            error!("Failed to destroy the released Vulkan objects: {err}");
+        }
+    }
 
-            if let Some((instance, messenger)) = self.debug_utils_instance_messenger.take() {
-                instance.destroy_debug_utils_messenger(messenger, None);
-            }
+    /// Destroys an app that isn't a resource of an [`App`](bevy_app::App),
+    /// like the ones created by tests.
+    pub fn destroy(self) {
+        Self::destroy_released(|commands| self.release_into_storages(commands));
+    }
 
-            self.surface_instance.destroy_surface(self.surface, None);
+    /// Destroys the device and everything created on it, leaving the
+    /// instance, debug messenger and surface alive.
+    ///
+    /// # Safety
+    /// Same as [`release_device_objects`](Self::release_device_objects).
+    unsafe fn destroy_device_objects(&mut self) {
+        Self::destroy_released(|commands| unsafe { self.release_device_objects(commands) });
+    }
 
-            self.instance.destroy_instance(None);
-        }
+    /// Recreates the device and everything created on it after it was lost,
+    /// keeping the instance and surface. The swapchain is created at `size`.
+    ///
+    /// Uploaded chunk meshes are dropped with the old device and uploaded
+    /// again by [`upload_chunk_meshes_system`].
+    pub fn rebuild_device(&mut self, size: PhysicalSize<u32>) {
+        unsafe { self.destroy_device_objects() };
+
+        let context = InstanceContext {
+            entry: self._entry.clone(),
+            instance: self.instance.clone(),
+            debug_utils_instance_messenger: self.debug_utils_instance_messenger.take(),
+            surface: self.surface.take(),
+            size,
+            api_version: self.api_version,
+            properties2: self.properties2,
+            physical_device: self.physical_device,
+            queue_family_indices: self.queue_family_indices,
+            device_candidates: std::mem::take(&mut self.device_candidates),
+            block_textures: std::mem::take(&mut self.block_textures),
+        };
+        // The old device objects are gone and the instance objects moved into
+        // `context`, there is no app left to keep rendering with.
+        let mut rebuilt = Self::create_on_instance(
+            context,
+            (self.anisotropy, self.sampler_config),
+            self.swapchain_config.clone(),
+            self.raster_config,
+            self.msaa,
+            self.debug_grid,
+        )
+        .expect("Failed to rebuild the Vulkan device");
+        rebuilt.device_generation = self.device_generation + 1;
+        rebuilt.light = self.light;
+
+        // The device objects of the old app are destroyed and its instance
+        // objects moved into `rebuilt`, so it must not be dropped.
+        std::mem::forget(std::mem::replace(self, rebuilt));
     }
 }
 
 impl VulkanApp {
-    fn new(create_info: VulkanAppCreateInfo) -> Self {
-        let entry = unsafe { ash::Entry::load().expect("Failed to load entry") };
+    fn new(create_info: VulkanAppCreateInfo) -> Result<Self, InitError> {
+        let VulkanAppCreateInfo {
+            display_handle,
+            window,
+            anisotropy,
+            sampler,
+            validation,
+            swapchain,
+            raster,
+            msaa,
+            debug_grid,
+        } = create_info;
+
+        Self::create(
+            Output::Window {
+                display_handle,
+                window,
+            },
+            (anisotropy, sampler),
+            validation,
+            swapchain,
+            raster,
+            msaa,
+            debug_grid,
+        )
+    }
 
-        let handle = create_info.display_handle.display_handle().unwrap();
-        let raw_display_handle = handle.as_raw();
-        let required_extensions =
-            ash_window::enumerate_required_extensions(raw_display_handle).unwrap();
-        let instance = create_instance(&entry, required_extensions);
+    /// Creates an app without a surface that renders into an offscreen image
+    /// of `extent`, read back with [`VulkanApp::read_framebuffer`].
+    pub fn new_headless(extent: vk::Extent2D) -> Result<Self, InitError> {
+        Self::create(
+            Output::Offscreen(extent),
+            (AnisotropyLevel::default(), SamplerConfig::default()),
+            ValidationConfig::default(),
+            SwapchainConfig::default(),
+            RasterConfig::default(),
+            SampleCount::default(),
+            DebugGrid::default(),
+        )
+    }
 
-        let debug_utils_instance_messenger = setup_debug_messenger(&entry, &instance);
+    fn create(
+        output: Output,
+        (anisotropy, sampler_config): (AnisotropyLevel, SamplerConfig),
+        validation: ValidationConfig,
+        mut swapchain_config: SwapchainConfig,
+        raster_config: RasterConfig,
+        msaa: SampleCount,
+        debug_grid: DebugGrid,
+    ) -> Result<Self, InitError> {
+        let entry = unsafe { ash::Entry::load()? };
+
+        let mut required_extensions = match &output {
+            Output::Window { display_handle, .. } => {
+                let handle = display_handle.display_handle().unwrap();
+                ash_window::enumerate_required_extensions(handle.as_raw())
+                    .unwrap()
+                    .to_vec()
+            }
+            Output::Offscreen(_) => Vec::new(),
+        };
+        // HDR color spaces are only reported by surfaces with the extension enabled.
+        swapchain_config.hdr = swapchain_config.hdr
+            && matches!(output, Output::Window { .. })
+            && supports_swapchain_colorspace(&entry);
+        if swapchain_config.hdr {
+            required_extensions.push(ext::swapchain_colorspace::NAME.as_ptr());
+        }
+        let properties2 = supports_physical_device_properties2(&entry);
+        if properties2 {
+            required_extensions.push(khr::get_physical_device_properties2::NAME.as_ptr());
+        }
+        let api_version = if cfg!(feature = "dynamic-rendering") {
+            instance_api_version(&entry)
+        } else {
+            API_VERSION_1_0
+        };
+        let instance = create_instance(&entry, &required_extensions, api_version, &validation)?;
+
+        let debug_utils_instance_messenger = setup_debug_messenger(&entry, &instance, &validation);
+
+        let (surface, size) = match &output {
+            Output::Window {
+                display_handle,
+                window,
+                ..
+            } => {
+                let raw_display_handle = display_handle.display_handle().unwrap().as_raw();
+                let raw_window_handle = window.window_handle().unwrap().as_raw();
+                let surface =
+                    create_surface(&entry, &instance, raw_display_handle, raw_window_handle);
+                (Some(surface), window.inner_size())
+            }
+            Output::Offscreen(extent) => (None, PhysicalSize::new(extent.width, extent.height)),
+        };
 
-        let window_handle = create_info.window.window_handle().unwrap();
-        let raw_window_handle = window_handle.as_raw();
-        let (surface_instance, surface) =
-            create_surface(&entry, &instance, raw_display_handle, raw_window_handle);
+        let (physical_device, queue_family_indices, device_candidates) =
+            select_physical_device(&instance, surface.as_ref())?;
+        let block_textures = TextureSource::load_layers(BLOCK_TEXTURES.map(|(_, path)| path))?;
+
+        let context = InstanceContext {
+            entry,
+            instance,
+            debug_utils_instance_messenger,
+            surface,
+            size,
+            api_version,
+            properties2,
+            physical_device,
+            queue_family_indices,
+            device_candidates,
+            block_textures,
+        };
+        Ok(Self::create_on_instance(
+            context,
+            (anisotropy, sampler_config),
+            swapchain_config,
+            raster_config,
+            msaa,
+            debug_grid,
+        )?)
+    }
+
+    /// Creates the device and everything rendered with it.
+    fn create_on_instance(
+        context: InstanceContext,
+        (anisotropy, sampler_config): (AnisotropyLevel, SamplerConfig),
+        swapchain_config: SwapchainConfig,
+        raster_config: RasterConfig,
+        msaa: SampleCount,
+        debug_grid: DebugGrid,
+    ) -> Result<Self, VulkanError> {
+        let InstanceContext {
+            entry,
+            instance,
+            debug_utils_instance_messenger,
+            surface,
+            size: window_size,
+            api_version,
+            properties2,
+            physical_device,
+            queue_family_indices,
+            device_candidates,
+            block_textures,
+        } = context;
+
+        let memory_budget_instance = (properties2
+            && supports_memory_budget(&instance, physical_device))
+        .then(|| khr::get_physical_device_properties2::Instance::new(&entry, &instance));
+        info!(
+            "Queue families: graphics {}, present {}, transfer {}, compute {}",
+            queue_family_indices.graphics_family,
+            queue_family_indices.present_family,
+            queue_family_indices.transfer_family,
+            queue_family_indices.compute_family
+        );
+        // Headless apps keep render passes, which leave the image ready to be read back.
+        let dynamic_rendering = cfg!(feature = "dynamic-rendering")
+            && surface.is_some()
+            && supports_dynamic_rendering(&instance, physical_device, api_version);
+        if cfg!(feature = "dynamic-rendering") && surface.is_some() && !dynamic_rendering {
+            warn!("Dynamic rendering requires Vulkan 1.3, falling back to render passes");
+        }
+        let portability_subset = supports_portability_subset(&instance, physical_device);
+        if portability_subset {
+            info!("Device is a portability implementation, enabling VK_KHR_portability_subset");
+        }
+        // Promoted to Vulkan 1.1 but still advertised, enabling it works on any version.
+        let maintenance1 =
+            supports_device_extension(&instance, physical_device, khr::maintenance1::NAME);
+        let mut extensions = device_extensions(surface.is_some(), portability_subset);
+        if maintenance1 {
+            extensions.push(khr::maintenance1::NAME.as_ptr());
+        }
 
-        let (physical_device, queue_family_indices) =
-            select_physical_device(&instance, &surface_instance, surface);
-        let device = create_logical_device(&instance, physical_device, queue_family_indices);
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+        let requested_msaa = msaa;
+        let msaa = requested_msaa.supported(attachment_sample_counts(&limits));
+        if msaa != requested_msaa {
+            warn!("{requested_msaa:?} MSAA is not supported, using {msaa:?}");
+        }
+        let depth_format = find_depth_format(&instance, physical_device);
+        let depth_resolve_modes = if properties2 {
+            let properties2 =
+                khr::get_physical_device_properties2::Instance::new(&entry, &instance);
+            query_depth_resolve_modes(&instance, &properties2, physical_device, depth_format)
+        } else {
+            vk::ResolveModeFlags::NONE
+        };
+        let depth_resolve = depth_resolve_mode(msaa.flags(), depth_resolve_modes);
+        if let Some(mode) = depth_resolve {
+            info!("Resolving the multisampled depth with {mode:?}");
+            extensions.extend(DEPTH_RESOLVE_EXTENSIONS.map(CStr::as_ptr));
+        }
+        let device = create_logical_device(
+            &instance,
+            physical_device,
+            queue_family_indices,
+            &extensions,
+            dynamic_rendering,
+        );
+        if debug_utils_instance_messenger.is_some() {
+            enable_debug_utils(&instance, &device);
+        }
+        let mut allocator = create_allocator(&instance, &device, physical_device);
+        let render_pass2 = (depth_resolve.is_some() && !dynamic_rendering)
+            .then(|| khr::create_renderpass2::Device::new(&instance, &device));
 
         let graphics_queue =
             unsafe { device.get_device_queue(queue_family_indices.graphics_family, 0) };
         let present_queue =
             unsafe { device.get_device_queue(queue_family_indices.present_family, 0) };
+        let transfer_queue =
+            unsafe { device.get_device_queue(queue_family_indices.transfer_family, 0) };
+        let compute_queue =
+            unsafe { device.get_device_queue(queue_family_indices.compute_family, 0) };
 
-        let (swapchain_device, swapchain, swapchain_image_format, swapchain_extent) =
-            create_swapchain(
+        let SwapchainParts {
+            device: swapchain_device,
+            swapchain,
+            format: swapchain_image_format,
+            extent: swapchain_extent,
+            transform: surface_transform,
+        } = match &surface {
+            Some((surface_instance, surface)) => create_swapchain(
                 &instance,
                 &device,
                 physical_device,
-                &surface_instance,
-                surface,
-                create_info.window.inner_size(),
+                (surface_instance, *surface),
+                window_size,
                 queue_family_indices,
+                &swapchain_config,
+            ),
+            // The loader is never called without a swapchain.
+            None => SwapchainParts {
+                device: khr::swapchain::Device::new(&instance, &device),
+                swapchain: vk::SwapchainKHR::null(),
+                format: OFFSCREEN_FORMAT,
+                extent: vk::Extent2D {
+                    width: window_size.width,
+                    height: window_size.height,
+                },
+                transform: vk::SurfaceTransformFlagsKHR::IDENTITY,
+            },
+        };
+        let offscreen = surface
+            .is_none()
+            .then(|| OffscreenTarget::new(&device, &mut allocator, swapchain_extent));
+        let (swapchain_images, swapchain_image_views) = match &offscreen {
+            Some(offscreen) => (
+                vec![offscreen.image.image],
+                vec![offscreen.create_view(&device)],
+            ),
+            None => {
+                let images = unsafe { swapchain_device.get_swapchain_images(swapchain).unwrap() };
+                let views = create_image_views(&device, &images, swapchain_image_format);
+                (images, views)
+            }
+        };
+
+        let attachment_formats = AttachmentFormats {
+            color: swapchain_image_format,
+            depth: depth_format,
+            samples: msaa.flags(),
+            depth_resolve,
+        };
+        let depth = DepthResources::new(
+            &device,
+            &mut allocator,
+            swapchain_extent,
+            depth_format,
+            attachment_formats.samples,
+            depth_resolve,
+        );
+        let msaa_color = MsaaColor::new(
+            &device,
+            &mut allocator,
+            swapchain_extent,
+            swapchain_image_format,
+            attachment_formats.samples,
+        );
+
+        let final_layout = if offscreen.is_some() {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        };
+        let render_pass = (!dynamic_rendering).then(|| {
+            let render_pass = create_render_pass(
+                &device,
+                render_pass2.as_ref(),
+                attachment_formats,
+                final_layout,
             );
-        let swapchain_images = unsafe { swapchain_device.get_swapchain_images(swapchain).unwrap() };
-        let swapchain_image_views =
-            create_image_views(&device, &swapchain_images, swapchain_image_format);
+            set_debug_name(&device, render_pass, "main render pass");
+            render_pass
+        });
 
-        let render_pass = create_render_pass(&device, swapchain_image_format);
+        let mut descriptor_set_layouts = DescriptorSetLayoutCache::default();
+        let descriptor_set_layout =
+            descriptor_set_layouts.get_or_create(&device, &chunk_set_bindings());
 
-        let (pipeline, pipeline_layout) = create_graphics_pipeline(&device, render_pass);
+        let pipeline_target = PipelineTarget::new(render_pass, attachment_formats);
+        let (pipeline, pipeline_layout) = create_graphics_pipeline::<Vertex>(
+            &device,
+            pipeline_target,
+            descriptor_set_layout,
+            &GraphicsPipelineDesc::CHUNKS.with_raster(&raster_config),
+        );
+        set_debug_name(&device, pipeline, "chunk pipeline");
 
         let swapchain_framebuffers = create_framebuffers(
             &device,
             render_pass,
             &swapchain_image_views,
+            &depth,
+            msaa_color.as_ref().map(|msaa_color| msaa_color.view),
             swapchain_extent,
         );
 
-        let command_pool = create_command_pool(&device, queue_family_indices);
-        let command_buffers = create_command_buffers(&device, command_pool);
+        let transient_command_pool =
+            create_transient_command_pool(&device, queue_family_indices.graphics_family);
+        let frame_commands = FrameCommandPools::new(
+            &device,
+            queue_family_indices.graphics_family,
+            CommandPoolResetMode::default(),
+        );
+        let transfer_command_pool =
+            create_transient_command_pool(&device, queue_family_indices.transfer_family);
+        let compute_command_pool =
+            create_transient_command_pool(&device, queue_family_indices.compute_family);
+        let recording_threads = std::thread::available_parallelism()
+            .map_or(1, |count| count.get().min(MAX_RECORDING_THREADS));
+        let recording_pools = ThreadLocalCommandPools::new(
+            &device,
+            queue_family_indices.graphics_family,
+            recording_threads,
+        );
+
+        let upload_queues = UploadQueues {
+            transfer: QueueContext {
+                family: queue_family_indices.transfer_family,
+                queue: transfer_queue,
+                command_pool: transfer_command_pool,
+            },
+            graphics: QueueContext {
+                family: queue_family_indices.graphics_family,
+                queue: graphics_queue,
+                command_pool: transient_command_pool,
+            },
+        };
+
+        let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let max_anisotropy = anisotropy.max_anisotropy(
+            supported_features.sampler_anisotropy == vk::TRUE,
+            limits.max_sampler_anisotropy,
+        );
+
+        let texture = load_texture(
+            &instance,
+            physical_device,
+            &device,
+            &mut allocator,
+            &upload_queues,
+            usable_layers(&block_textures, limits.max_image_array_layers),
+            &SamplerDesc {
+                config: sampler_config,
+                max_anisotropy,
+            },
+        )?;
+        let debug_lines = DebugLines::new(
+            &device,
+            &mut allocator,
+            &upload_queues,
+            pipeline_target,
+            descriptor_set_layout,
+            &debug_grid,
+            GraphicsPipelineDesc {
+                line_width: line_width(
+                    debug_grid.line_width,
+                    supported_features.wide_lines == vk::TRUE,
+                    limits.line_width_range[0]..=limits.line_width_range[1],
+                ),
+                ..GraphicsPipelineDesc::LINES
+            }
+            .with_raster(&raster_config),
+        );
+        let occlusion_queries = OcclusionQueries::new(
+            &device,
+            &mut allocator,
+            &upload_queues,
+            pipeline_target,
+            descriptor_set_layout,
+            &raster_config,
+        )
+        .inspect_err(|err| warn!("Failed to create the occlusion queries: {err}"))
+        .ok();
+        let light_buffers = LightBuffers::new(&device, &mut allocator);
+        let chunk_uniforms = DynamicUniformRing::new(
+            &device,
+            &mut allocator,
+            CHUNK_UNIFORM_CAPACITY,
+            limits.min_uniform_buffer_offset_alignment,
+        );
+        let descriptor_pool = create_descriptor_pool(&device);
+        let descriptor_sets = create_descriptor_sets(
+            &device,
+            descriptor_pool,
+            descriptor_set_layout,
+            &texture,
+            &light_buffers,
+            &chunk_uniforms,
+        );
+        let texture_sampler = texture.sampler;
 
         let (image_available_semaphores, render_finished_semaphores, in_flight_fences) =
             create_sync_objects(&device);
 
-        Self {
+        check_validation_errors();
+
+        Ok(Self {
             _entry: entry,
             instance,
             debug_utils_instance_messenger,
-            surface_instance,
             surface,
+            api_version,
+            properties2,
+            anisotropy,
+            sampler_config,
+            block_textures,
+            debug_grid,
             physical_device,
+            device_candidates,
+            memory_budget_instance,
             device,
+            allocator: ManuallyDrop::new(allocator),
+            queue_family_indices,
             graphics_queue,
             present_queue,
+            transfer_queue,
+            compute_queue,
             swapchain_device,
             swapchain,
             swapchain_images,
             swapchain_image_views,
             swapchain_image_format,
             swapchain_extent,
+            surface_transform,
+            swapchain_config,
+            suboptimal: SuboptimalTracker::default(),
+            recreate_requested: false,
+            pending_screenshot: None,
+            depth_format,
+            depth,
+            msaa,
+            depth_resolve,
+            msaa_color,
+            offscreen,
+            scene_target: None,
+            render_target_size: RenderTargetSize::default(),
+            render_scale: RenderScale::default().0,
             render_pass,
+            render_pass2,
+            descriptor_set_layouts,
+            descriptor_set_layout,
             pipeline_layout,
             pipeline,
+            raster_config,
+            debug_lines,
+            occlusion_queries,
             swapchain_framebuffers,
-            command_pool,
-            command_buffers,
+            texture,
+            light_buffers,
+            chunk_uniforms,
+            light: LightUniform::from(&DirectionalLight::default()),
+            clear_color: ClearColor::default().0,
+            descriptor_pool,
+            descriptor_set_samplers: vec![texture_sampler; MAX_FRAMES_IN_FLIGHT],
+            descriptor_sets,
+            transient_command_pool,
+            frame_commands,
+            transfer_command_pool,
+            compute_command_pool,
+            recording_pools,
             image_available_semaphores,
             render_finished_semaphores,
             in_flight_fences,
+            chunk_meshes: KeyedHandled::default(),
+            chunk_buffers: DenseHandled::default(),
+            uploads: UploadTracker::default(),
+            replaced_meshes: Vec::new(),
+            frustum_culling: FrustumCulling::default().0,
+            occlusion_culling: OcclusionCulling::default().0,
+            fence_timeout: FenceTimeout::default().as_nanos(),
+            maintenance1,
+            flip_viewport_y: false,
+            device_generation: 0,
             current_frame: 0,
-        }
+            released: false,
+        })
     }
     // TODO: Handle minimization/maximization
     fn recreate_swapchain(&mut self, window: &Window) {
@@ -261,46 +1329,83 @@ impl VulkanApp {
 
         info!("Swapchain is cleaned and is ready to be recreated");
 
+        let (surface_instance, surface) = self
+            .surface
+            .as_ref()
+            .expect("Headless apps have no swapchain to recreate");
+
         let queue_family_indices = find_queue_families(
             &self.instance,
             self.physical_device,
-            &self.surface_instance,
-            self.surface,
+            Some((surface_instance, *surface)),
         )
         .unwrap();
 
-        let (swapchain_device, swapchain, swapchain_image_format, swapchain_extent) =
-            create_swapchain(
-                &self.instance,
-                &self.device,
-                self.physical_device,
-                &self.surface_instance,
-                self.surface,
-                window.inner_size(),
-                queue_family_indices,
-            );
+        let SwapchainParts {
+            device: swapchain_device,
+            swapchain,
+            format: swapchain_image_format,
+            extent: swapchain_extent,
+            transform: surface_transform,
+        } = create_swapchain(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            (surface_instance, *surface),
+            window.inner_size(),
+            queue_family_indices,
+            &self.swapchain_config,
+        );
 
         let swapchain_images = unsafe { swapchain_device.get_swapchain_images(swapchain).unwrap() };
         let swapchain_image_views =
             create_image_views(&self.device, &swapchain_images, swapchain_image_format);
 
+        let depth = DepthResources::new(
+            &self.device,
+            &mut self.allocator,
+            swapchain_extent,
+            self.depth_format,
+            self.msaa.flags(),
+            self.depth_resolve,
+        );
+        let msaa_color = MsaaColor::new(
+            &self.device,
+            &mut self.allocator,
+            swapchain_extent,
+            swapchain_image_format,
+            self.msaa.flags(),
+        );
+
         let swapchain_framebuffers = create_framebuffers(
             &self.device,
             self.render_pass,
             &swapchain_image_views,
+            &depth,
+            msaa_color.as_ref().map(|msaa_color| msaa_color.view),
             swapchain_extent,
         );
 
+        self.depth = depth;
+        self.msaa_color = msaa_color;
         self.swapchain_device = swapchain_device;
         self.swapchain = swapchain;
         self.swapchain_extent = swapchain_extent;
+        self.surface_transform = surface_transform;
         self.swapchain_images = swapchain_images;
         self.swapchain_image_views = swapchain_image_views;
         self.swapchain_framebuffers = swapchain_framebuffers;
+        // The scene target was destroyed with the old swapchain.
+        let replaced = self.update_scene_target();
+        debug_assert!(replaced.is_none());
     }
 
     fn cleanup_swapchain(&mut self) {
         unsafe {
+            if let Some(mut scene_target) = self.scene_target.take() {
+                scene_target.destroy(&self.device, &mut self.allocator);
+            }
+
             for framebuffer in &self.swapchain_framebuffers {
                 self.device.destroy_framebuffer(*framebuffer, None);
             }
@@ -309,113 +1414,605 @@ impl VulkanApp {
                 self.device.destroy_image_view(*image_view, None);
             }
 
-            self.swapchain_device
-                .destroy_swapchain(self.swapchain, None);
+            self.depth.destroy(&self.device, &mut self.allocator);
+            if let Some(mut msaa_color) = self.msaa_color.take() {
+                msaa_color.destroy(&self.device, &mut self.allocator);
+            }
+
+            match &mut self.offscreen {
+                Some(offscreen) => offscreen.destroy(&self.device, &mut self.allocator),
+                None => self
+                    .swapchain_device
+                    .destroy_swapchain(self.swapchain, None),
+            }
         }
     }
 
-    fn resize(&mut self, swapchain_ok: &mut bool, size: PhysicalSize<u32>) {
+    fn resize(
+        &mut self,
+        swapchain_ok: &mut bool,
+        size: PhysicalSize<u32>,
+    ) -> Result<(), VulkanError> {
         unsafe {
-            self.device.device_wait_idle();
+            self.device.device_wait_idle()?;
 
             let old_swapchain = self.swapchain;
 
             self.cleanup_swapchain();
 
+            let (surface_instance, surface) = self
+                .surface
+                .as_ref()
+                .expect("Headless apps have no swapchain to resize");
+
             let queue_family_indices = find_queue_families(
                 &self.instance,
                 self.physical_device,
-                &self.surface_instance,
-                self.surface,
+                Some((surface_instance, *surface)),
             )
             .unwrap();
 
-            let (swapchain_device, swapchain, swapchain_image_format, swapchain_extent) =
-                create_swapchain(
-                    &self.instance,
-                    &self.device,
-                    self.physical_device,
-                    &self.surface_instance,
-                    self.surface,
-                    size,
-                    queue_family_indices,
-                );
+            let SwapchainParts {
+                device: swapchain_device,
+                swapchain,
+                format: swapchain_image_format,
+                extent: swapchain_extent,
+                transform: surface_transform,
+            } = create_swapchain(
+                &self.instance,
+                &self.device,
+                self.physical_device,
+                (surface_instance, *surface),
+                size,
+                queue_family_indices,
+                &self.swapchain_config,
+            );
 
             let swapchain_images = swapchain_device.get_swapchain_images(swapchain).unwrap();
             let swapchain_image_views =
                 create_image_views(&self.device, &swapchain_images, swapchain_image_format);
 
-            let swapchain_framebuffers = create_framebuffers(
-                &self.device,
-                self.render_pass,
-                &swapchain_image_views,
-                swapchain_extent,
-            );
+            let depth = DepthResources::new(
+                &self.device,
+                &mut self.allocator,
+                swapchain_extent,
+                self.depth_format,
+                self.msaa.flags(),
+                self.depth_resolve,
+            );
+            let msaa_color = MsaaColor::new(
+                &self.device,
+                &mut self.allocator,
+                swapchain_extent,
+                swapchain_image_format,
+                self.msaa.flags(),
+            );
+
+            let swapchain_framebuffers = create_framebuffers(
+                &self.device,
+                self.render_pass,
+                &swapchain_image_views,
+                &depth,
+                msaa_color.as_ref().map(|msaa_color| msaa_color.view),
+                swapchain_extent,
+            );
+
+            self.depth = depth;
+            self.msaa_color = msaa_color;
+            self.swapchain_device = swapchain_device;
+            self.swapchain = swapchain;
+            self.swapchain_extent = swapchain_extent;
+            self.surface_transform = surface_transform;
+            self.swapchain_images = swapchain_images;
+            self.swapchain_image_views = swapchain_image_views;
+            self.swapchain_framebuffers = swapchain_framebuffers;
+
+            *swapchain_ok = true;
+        }
+
+        // The scene target was destroyed with the old swapchain.
+        let replaced = self.update_scene_target();
+        debug_assert!(replaced.is_none());
+
+        Ok(())
+    }
+
+    /// Creates, resizes or destroys the scene target to match the swapchain
+    /// extent and `render_target_size`, and returns the one it replaced.
+    ///
+    /// Frames in flight may still render into the replaced target, see
+    /// [`VulkanApp::retire_scene_target`].
+    fn update_scene_target(&mut self) -> Option<SceneTarget> {
+        let mut extent = scene_extent(
+            self.render_target_size,
+            self.render_scale,
+            self.swapchain_extent,
+            self.device_limits().max_image_dimension_2d,
+        );
+        if extent.is_some() && !self.swapchain_supports(vk::ImageUsageFlags::TRANSFER_DST) {
+            warn!("The swapchain images can't be blitted to, rendering into them directly");
+            extent = None;
+        }
+        if self.scene_target.as_ref().map(|target| target.extent) == extent {
+            return None;
+        }
+
+        let replaced = self.scene_target.take();
+        let formats = self.attachment_formats();
+        let dynamic = self.render_pass.is_none();
+        self.scene_target = extent.map(|extent| {
+            info!("Rendering the scene at {}x{}", extent.width, extent.height);
+            SceneTarget::new(
+                &self.device,
+                &mut self.allocator,
+                extent,
+                formats,
+                dynamic,
+                self.render_pass2.as_ref(),
+            )
+        });
+
+        replaced
+    }
+
+    /// Destroys `scene_target` once the frames in flight that may render into
+    /// it have completed.
+    fn retire_scene_target(
+        &self,
+        destroy_queue: &mut DeferredDestroyQueue,
+        mut scene_target: SceneTarget,
+    ) {
+        let device_generation = self.device_generation;
+        destroy_queue.push_with(move |world| {
+            if let Some(mut vulkan_app) = world.get_resource_mut::<VulkanApp>()
+                && vulkan_app.device_generation == device_generation
+            {
+                let vulkan_app = &mut *vulkan_app;
+                scene_target.destroy(&vulkan_app.device, &mut vulkan_app.allocator);
+            }
+        });
+    }
+
+    /// Whether the images of the swapchain can be created with `usage`.
+    fn swapchain_supports(&self, usage: vk::ImageUsageFlags) -> bool {
+        let Some((surface_instance, surface)) = &self.surface else {
+            return false;
+        };
+
+        unsafe {
+            surface_instance
+                .get_physical_device_surface_capabilities(self.physical_device, *surface)
+        }
+        .is_ok_and(|capabilities| capabilities.supported_usage_flags.contains(usage))
+    }
+
+    /// The image the scene is rendered into when [`RenderTargetSize`] isn't
+    /// [`RenderTargetSize::Swapchain`], see [`SceneTarget`].
+    pub fn scene_target(&self) -> Option<&SceneTarget> {
+        self.scene_target.as_ref()
+    }
+
+    /// Records the chunk draws of the current frame into its command buffer,
+    /// rendering to the swapchain image at `image_index`.
+    ///
+    /// `capture` copies the rendered image for a screenshot.
+    fn record_frame(
+        &mut self,
+        image_index: u32,
+        view_proj: Mat4,
+        capture: Option<&PendingCapture>,
+    ) -> Result<(), VulkanError> {
+        let command_buffer = self
+            .frame_commands
+            .reset(&self.device, self.current_frame)?;
+        // The previous submission of this frame has completed.
+        self.chunk_uniforms.begin_frame(self.current_frame);
+        let frustum = Frustum::from_view_projection(view_proj);
+        let visible = self
+            .chunk_meshes
+            .iter()
+            .filter_map(|(coord, mesh)| Some((*coord, mesh.as_ref()?)))
+            .filter(|(coord, _)| {
+                !self.frustum_culling || frustum.contains_aabb(&Aabb::of_chunk(*coord))
+            })
+            .collect_vec();
+
+        let attachment_formats = self.attachment_formats();
+        let mut occlusion_queries = self
+            .occlusion_queries
+            .as_mut()
+            .filter(|_| self.occlusion_culling);
+        if let Some(occlusion_queries) = &mut occlusion_queries {
+            // The previous submission of this frame has completed.
+            occlusion_queries.read_results(&self.device, self.current_frame);
+            let queried = visible
+                .iter()
+                .map(|(coord, _)| *coord)
+                .filter(|coord| is_queryable(&frustum, *coord))
+                .collect_vec();
+            occlusion_queries.prepare(&self.device, self.current_frame, queried)?;
+        }
+
+        let chunk_buffer = |handle| {
+            self.chunk_buffers
+                .get(&handle)
+                .expect("Chunk mesh buffers are stored until the mesh is taken")
+                .buffer
+        };
+        let mut ring_full = false;
+        let draws = visible
+            .iter()
+            .filter(|(coord, _)| {
+                !occlusion_queries.as_ref().is_some_and(|occlusion_queries| {
+                    occlusion_queries.is_hidden(*coord) && is_queryable(&frustum, *coord)
+                })
+            })
+            .map_while(|(coord, mesh)| {
+                let uniform = ChunkUniform::new(chunk_offset(*coord));
+                let Some(uniform_offset) = self.chunk_uniforms.push(&uniform) else {
+                    ring_full = true;
+                    return None;
+                };
+                Some(DrawItem {
+                    vertex_buffer: chunk_buffer(mesh.vertex_buffer),
+                    index_buffer: chunk_buffer(mesh.index_buffer),
+                    index_count: mesh.index_count,
+                    uniform_offset,
+                    query: None,
+                })
+            })
+            .collect_vec();
+        if ring_full {
+            warn!("The chunk uniform ring is full, some chunks are not drawn this frame");
+        }
+        // The frustum is extracted from the standard depth range.
+        let mut view_proj = self.raster_config.depth_mode.apply(view_proj);
+        if self.flip_viewport_y {
+            view_proj = unflip_projection(view_proj);
+        }
+
+        let swapchain_image = self.swapchain_images[image_index as usize];
+        let frame_target = match (&self.scene_target, self.render_pass) {
+            (Some(scene_target), _) => scene_target.frame_target(),
+            (None, Some(render_pass)) => FrameTarget::RenderPass {
+                render_pass,
+                framebuffer: self.swapchain_framebuffers[image_index as usize],
+            },
+            (None, None) => FrameTarget::Dynamic(DynamicTarget {
+                color_image: swapchain_image,
+                color_view: self.swapchain_image_views[image_index as usize],
+                depth_image: self.depth.image.image,
+                depth_view: self.depth.view,
+                depth_format: self.depth_format,
+                depth_resolve: self
+                    .depth
+                    .resolved
+                    .as_ref()
+                    .map(|resolved| (resolved.mode, resolved.image.image, resolved.view)),
+                msaa_color: self
+                    .msaa_color
+                    .as_ref()
+                    .map(|msaa_color| (msaa_color.image.image, msaa_color.view)),
+                final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            }),
+        };
+        let extent = self
+            .scene_target
+            .as_ref()
+            .map_or(self.swapchain_extent, |scene_target| scene_target.extent);
+        let draw_state = ChunkDrawState {
+            target: frame_target.inherited(attachment_formats),
+            extent,
+            pipeline: self.pipeline,
+            pipeline_layout: self.pipeline_layout,
+            descriptor_set: self.descriptor_sets[self.current_frame],
+            query_pool: vk::QueryPool::null(),
+            view_proj,
+            flip_viewport_y: self.flip_viewport_y,
+            label: "ChunkDraws",
+        };
+        let mut secondary_command_buffers =
+            self.recording_pools
+                .record(&self.device, self.current_frame, &draw_state, &draws);
+        if let Some(occlusion_queries) = &occlusion_queries {
+            secondary_command_buffers.push(occlusion_queries.record(
+                &self.device,
+                self.current_frame,
+                &draw_state,
+                &mut self.chunk_uniforms,
+            ));
+        }
+        if self.debug_lines.visible {
+            secondary_command_buffers.push(self.debug_lines.record(
+                &self.device,
+                self.current_frame,
+                &draw_state,
+                &mut self.chunk_uniforms,
+            ));
+        }
+
+        record_command_buffer(
+            &self.device,
+            command_buffer,
+            &frame_target,
+            extent,
+            &secondary_command_buffers,
+            &FrameCommands {
+                clear: ClearValues {
+                    color: self.clear_color,
+                    depth: self.raster_config.depth_mode.clear_depth(),
+                },
+                query_reset: occlusion_queries.and_then(|occlusion_queries| {
+                    occlusion_queries.reset_range(self.current_frame)
+                }),
+                present_blit: self
+                    .scene_target
+                    .as_ref()
+                    .map(|scene_target| (scene_target, swapchain_image, self.swapchain_extent)),
+                capture: capture.map(|capture| (capture, swapchain_image)),
+            },
+        )?;
+
+        Ok(())
+    }
+
+    fn attachment_formats(&self) -> AttachmentFormats {
+        AttachmentFormats {
+            color: self.swapchain_image_format,
+            depth: self.depth_format,
+            samples: self.msaa.flags(),
+            depth_resolve: self.depth_resolve,
+        }
+    }
+
+    fn pipeline_target(&self) -> PipelineTarget {
+        PipelineTarget::new(self.render_pass, self.attachment_formats())
+    }
+
+    /// Recreates the command pools of the frames to reset them as set by `mode`.
+    fn set_command_pool_reset_mode(&mut self, mode: CommandPoolResetMode) {
+        // Frames in flight may still be executing the old command buffers.
+        if let Err(err) = unsafe { self.device.device_wait_idle() } {
+            error!("Failed to wait for the device to become idle: {err}");
+        }
+        self.frame_commands.destroy(&self.device);
+        self.frame_commands = FrameCommandPools::new(
+            &self.device,
+            self.queue_family_indices.graphics_family,
+            mode,
+        );
+        info!("Resetting command buffers {mode:?}");
+    }
+
+    /// Recreates the graphics pipelines after [`VulkanApp::raster_config`] changed.
+    fn rebuild_pipelines(&mut self) {
+        unsafe {
+            // Frames in flight may still be drawn with the old pipelines.
+            if let Err(err) = self.device.device_wait_idle() {
+                error!("Failed to wait for the device to become idle: {err}");
+            }
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+
+        let target = self.pipeline_target();
+        let (pipeline, pipeline_layout) = create_graphics_pipeline::<Vertex>(
+            &self.device,
+            target,
+            self.descriptor_set_layout,
+            &GraphicsPipelineDesc::CHUNKS.with_raster(&self.raster_config),
+        );
+        set_debug_name(&self.device, pipeline, "chunk pipeline");
+        self.pipeline = pipeline;
+        self.pipeline_layout = pipeline_layout;
+
+        self.debug_lines.rebuild_pipeline(
+            &self.device,
+            target,
+            self.descriptor_set_layout,
+            &self.raster_config,
+        );
+        if let Some(occlusion_queries) = &mut self.occlusion_queries {
+            occlusion_queries.rebuild_pipeline(
+                &self.device,
+                target,
+                self.descriptor_set_layout,
+                &self.raster_config,
+            );
+        }
+    }
+
+    pub fn instance(&self) -> &Instance {
+        &self.instance
+    }
+
+    pub fn physical_device(&self) -> vk::PhysicalDevice {
+        self.physical_device
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Handles for systems that don't need the whole app, see [`RenderDevice`].
+    pub fn render_device(&self) -> RenderDevice {
+        let queue = |family, queue| RenderQueue { family, queue };
+        let families = &self.queue_family_indices;
+
+        RenderDevice {
+            instance: self.instance().clone(),
+            physical_device: self.physical_device(),
+            device: self.device().clone(),
+            graphics: queue(families.graphics_family, self.graphics_queue),
+            present: queue(families.present_family, self.present_queue),
+            transfer: queue(families.transfer_family, self.transfer_queue),
+            compute: queue(families.compute_family, self.compute_queue),
+        }
+    }
+
+    pub fn device_limits(&self) -> DeviceLimits {
+        let properties = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+        };
+        DeviceLimits::from_properties(&properties)
+    }
+
+    /// Current memory budget, `None` without `VK_EXT_memory_budget`.
+    pub fn memory_budget(&self) -> Option<MemoryBudget> {
+        self.memory_budget_instance
+            .as_ref()
+            .map(|properties2| query_memory_budget(properties2, self.physical_device))
+    }
+
+    pub fn draw_offscreen(&mut self, view_proj: Mat4) -> Result<(), VulkanError> {
+        assert!(
+            self.offscreen.is_some(),
+            "Only headless apps render offscreen"
+        );
+
+        let fence = self.in_flight_fences[self.current_frame];
+        self.wait_for_in_flight_fence(fence)?;
+        unsafe {
+            self.device.reset_fences(&[fence])?;
+
+            self.record_frame(0, view_proj, None)?;
+
+            let command_buffers = &[self.frame_commands.buffer(self.current_frame)];
+            let submit_info = vk::SubmitInfo::default().command_buffers(command_buffers);
+            self.device
+                .queue_submit(self.graphics_queue, &[submit_info], fence)?;
+        }
+        self.wait_for_in_flight_fence(fence)?;
+
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        check_validation_errors();
+
+        Ok(())
+    }
+
+    /// Saves the next presented frame as a PNG at `path`.
+    pub fn capture_frame(&mut self, path: impl Into<PathBuf>) -> Result<(), ScreenshotError> {
+        let (surface_instance, surface) =
+            self.surface.as_ref().ok_or(ScreenshotError::NoSwapchain)?;
+
+        let capabilities = unsafe {
+            surface_instance
+                .get_physical_device_surface_capabilities(self.physical_device, *surface)
+                .unwrap()
+        };
+        if !capabilities
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            return Err(ScreenshotError::TransferUnsupported);
+        }
+
+        if ChannelOrder::of(self.swapchain_image_format).is_none() {
+            return Err(ScreenshotError::UnsupportedFormat(
+                self.swapchain_image_format,
+            ));
+        }
+
+        self.pending_screenshot = Some(path.into());
+        Ok(())
+    }
 
-            self.swapchain_device = swapchain_device;
-            self.swapchain = swapchain;
-            self.swapchain_extent = swapchain_extent;
-            self.swapchain_images = swapchain_images;
-            self.swapchain_image_views = swapchain_image_views;
-            self.swapchain_framebuffers = swapchain_framebuffers;
+    /// RGBA bytes of the last frame rendered by a headless app, row by row.
+    pub fn read_framebuffer(&self) -> Vec<u8> {
+        let offscreen = self
+            .offscreen
+            .as_ref()
+            .expect("Only headless apps can read back their framebuffer");
 
-            *swapchain_ok = true;
+        let pixels = offscreen.read(&self.device, &self.upload_queues().graphics);
+        check_validation_errors();
+        pixels
+    }
 
-            self.draw_frame(swapchain_ok);
+    /// Waits for `fence` up to the [`FenceTimeout`], after which the GPU is
+    /// assumed to hang and the device to be lost.
+    fn wait_for_in_flight_fence(&self, fence: vk::Fence) -> Result<(), VulkanError> {
+        match unsafe {
+            self.device
+                .wait_for_fences(&[fence], true, self.fence_timeout)
+        } {
+            Err(vk::Result::TIMEOUT) => {
+                warn!(
+                    "Frame fence not signaled after {} ms, the GPU may hang or the device be lost",
+                    self.fence_timeout / 1_000_000
+                );
+                Err(VulkanError::DeviceLost)
+            }
+            result => Ok(result?),
         }
     }
 
     // TODO: Replace bool with custom error type
-    fn draw_frame(&mut self, swapchain_ok: &mut bool) {
-        unsafe {
-            self.device
-                .wait_for_fences(&[self.in_flight_fences[self.current_frame]], true, u64::MAX)
-                .unwrap();
+    fn draw_frame(
+        &mut self,
+        swapchain_ok: &mut bool,
+        view_proj: Mat4,
+        stats: Option<&mut FrameStats>,
+    ) -> Result<(), VulkanError> {
+        let view_proj = pre_rotation(self.surface_transform) * view_proj;
+        let mut timer = StageTimer::start(stats.is_some());
+        let mut frame_stats = FrameStats::default();
+
+        self.wait_for_in_flight_fence(self.in_flight_fences[self.current_frame])?;
+        frame_stats.fence_wait = timer.lap();
 
+        unsafe {
             // FIXME: nesting
-            let image_index = if *swapchain_ok {
+            let (image_index, acquire_suboptimal) = if *swapchain_ok {
                 match self.swapchain_device.acquire_next_image(
                     self.swapchain,
                     u64::MAX,
                     self.image_available_semaphores[self.current_frame],
                     vk::Fence::null(),
                 ) {
-                    Ok((index, _)) => index,
+                    Ok(acquired) => acquired,
                     Err(err) if err == vk::Result::ERROR_OUT_OF_DATE_KHR => {
                         // self.recreate_swapchain(window);
                         *swapchain_ok = false;
-                        return;
+                        return Ok(());
                     }
-                    Err(err) => panic!("{}", err),
+                    Err(err) => return Err(err.into()),
                 }
             } else {
-                return;
+                return Ok(());
             };
+            frame_stats.acquire = timer.lap();
 
             self.device
-                .reset_fences(&[self.in_flight_fences[self.current_frame]])
-                .unwrap();
-
-            self.device
-                .reset_command_buffer(
-                    self.command_buffers[self.current_frame],
-                    vk::CommandBufferResetFlags::empty(),
-                )
-                .unwrap();
+                .reset_fences(&[self.in_flight_fences[self.current_frame]])?;
 
-            record_command_buffer(
-                &self.device,
-                self.command_buffers[self.current_frame],
-                self.render_pass,
-                &self.swapchain_framebuffers,
-                image_index as usize,
-                self.swapchain_extent,
-                self.pipeline,
-            );
+            let capture = self.pending_screenshot.take().and_then(|path| {
+                let channel_order = ChannelOrder::of(self.swapchain_image_format)?;
+                Some(PendingCapture::new(
+                    &self.device,
+                    &mut self.allocator,
+                    path,
+                    self.swapchain_extent,
+                    channel_order,
+                ))
+            });
+
+            self.light_buffers.write(self.current_frame, &self.light);
+            if self.descriptor_set_samplers[self.current_frame] != self.texture.sampler {
+                write_texture_descriptor(
+                    &self.device,
+                    self.descriptor_sets[self.current_frame],
+                    &self.texture,
+                );
+                self.descriptor_set_samplers[self.current_frame] = self.texture.sampler;
+            }
+            self.record_frame(image_index, view_proj, capture.as_ref())?;
 
             let wait_semaphores = &[self.image_available_semaphores[self.current_frame]];
             let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-            let command_buffers = &[self.command_buffers[self.current_frame]];
+            let command_buffers = &[self.frame_commands.buffer(self.current_frame)];
             let signal_semaphores = &[self.render_finished_semaphores[self.current_frame]];
 
             let submit_info = vk::SubmitInfo::default()
@@ -428,7 +2025,17 @@ impl VulkanApp {
                 self.graphics_queue,
                 &[submit_info],
                 self.in_flight_fences[self.current_frame],
-            );
+            )?;
+            frame_stats.record = timer.lap();
+
+            if let Some(capture) = capture {
+                self.wait_for_in_flight_fence(self.in_flight_fences[self.current_frame])?;
+                match capture.save(&self.device, &mut self.allocator) {
+                    Ok(path) => info!("Saved screenshot to `{}`", path.display()),
+                    Err(err) => error!("{err}"),
+                }
+                timer.skip();
+            }
 
             let swapchains = &[self.swapchain];
             let image_indices = &[image_index];
@@ -437,28 +2044,147 @@ impl VulkanApp {
                 .swapchains(swapchains)
                 .image_indices(image_indices);
 
-            match self
+            let present_result = self
                 .swapchain_device
-                .queue_present(self.present_queue, &present_info)
-            {
-                /* Ok(true) | */
+                .queue_present(self.present_queue, &present_info);
+            frame_stats.present = timer.lap();
+
+            match present_result {
                 Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
                     // self.recreate_swapchain(window);
                     *swapchain_ok = false;
                 }
-                // Err(_) | Ok(_) if was_resized => {
-                //     self.recreate_swapchain(window);
-                // }
-                Ok(_) => {}
-                Err(_) => panic!("Failed to present swapchain image"),
+                Ok(present_suboptimal) => {
+                    if self
+                        .suboptimal
+                        .observe(acquire_suboptimal || present_suboptimal)
+                    {
+                        info!("Swapchain is suboptimal, recreating it");
+                        self.recreate_requested = true;
+                    }
+                }
+                Err(err) => return Err(err.into()),
             };
         };
 
         self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+
+        if let Some(stats) = stats {
+            *stats = frame_stats;
+        }
+
+        Ok(())
+    }
+
+    fn upload_queues(&self) -> UploadQueues {
+        UploadQueues {
+            transfer: QueueContext {
+                family: self.queue_family_indices.transfer_family,
+                queue: self.transfer_queue,
+                command_pool: self.transfer_command_pool,
+            },
+            graphics: QueueContext {
+                family: self.queue_family_indices.graphics_family,
+                queue: self.graphics_queue,
+                command_pool: self.transient_command_pool,
+            },
+        }
+    }
+
+    /// Queue to [`dispatch`](compute::dispatch) compute pipelines on.
+    #[cfg(feature = "compute")]
+    pub fn compute_queue(&self) -> QueueContext {
+        QueueContext {
+            family: self.queue_family_indices.compute_family,
+            queue: self.compute_queue,
+            command_pool: self.compute_command_pool,
+        }
+    }
+
+    /// Recreates the texture sampler with `config`. The old one is destroyed by
+    /// `destroy_queue` once no frame in flight uses it.
+    fn set_sampler_config(
+        &mut self,
+        config: SamplerConfig,
+        destroy_queue: &mut DeferredDestroyQueue,
+    ) {
+        let old = match self.texture.replace_sampler(&self.device, &config) {
+            Ok(old) => old,
+            Err(err) => {
+                error!("Failed to recreate the texture sampler: {err}");
+                return;
+            }
+        };
+        self.sampler_config = config;
+
+        let device_generation = self.device_generation;
+        destroy_queue.push_with(move |world| {
+            if let Some(vulkan_app) = world.get_resource::<VulkanApp>()
+                && vulkan_app.device_generation == device_generation
+            {
+                unsafe { vulkan_app.device.destroy_sampler(old, None) };
+            }
+        });
+    }
+
+    /// Destroys a mesh removed from `chunk_meshes`, unless the device it was
+    /// created on was rebuilt since.
+    fn destroy_chunk_mesh(&mut self, mut mesh: GpuMesh, device_generation: u64) {
+        if device_generation == self.device_generation {
+            mesh.destroy(&self.device, &mut self.allocator);
+        } else {
+            std::mem::forget(mesh);
+        }
+    }
+
+    /// Starts uploading the mesh of the chunk at `coord`, which replaces the
+    /// current one once finished. Empty meshes are only recorded.
+    fn upload_chunk_mesh(&mut self, coord: IVec3, vertices: &[Vertex], indices: &[u32]) {
+        if indices.is_empty() {
+            self.uploads.discard(coord);
+            self.replace_chunk_mesh(coord, None);
+            return;
+        }
+
+        let upload_queues = self.upload_queues();
+        if let Err(err) = self.uploads.upload(
+            &self.device,
+            &mut self.allocator,
+            &upload_queues,
+            coord,
+            vertices,
+            indices,
+        ) {
+            error!("Failed to upload the mesh of chunk {coord}: {err}");
+        }
+    }
+
+    /// Stores `mesh` as the one of the chunk at `coord`. The mesh it replaces is
+    /// retired by the next [`Renderer::finish_chunk_uploads`].
+    fn replace_chunk_mesh(&mut self, coord: IVec3, mesh: Option<GpuMesh>) {
+        let mesh = mesh.map(|mesh| mesh.store(&mut self.chunk_buffers));
+        if let Some(Some(old)) = self.chunk_meshes.insert(coord, mesh) {
+            self.replaced_meshes.push(old.take(&mut self.chunk_buffers));
+        }
+    }
+
+    /// Destroys `mesh` once the frames in flight that may draw it have completed.
+    fn retire_mesh(&self, destroy_queue: &mut DeferredDestroyQueue, mesh: GpuMesh) {
+        let device_generation = self.device_generation;
+        destroy_queue.push_with(move |world| {
+            if let Some(mut vulkan_app) = world.get_resource_mut::<VulkanApp>() {
+                vulkan_app.destroy_chunk_mesh(mesh, device_generation);
+            }
+        });
     }
 }
 
-fn create_instance(entry: &Entry, required_extensions: &[*const c_char]) -> Instance {
+fn create_instance(
+    entry: &Entry,
+    required_extensions: &[*const c_char],
+    api_version: u32,
+    validation: &ValidationConfig,
+) -> Result<Instance, InitError> {
     let app_name = CString::new("Vulkan Application").unwrap();
     let engine_name = CString::new("No Engine").unwrap();
     let app_info = vk::ApplicationInfo::default()
@@ -466,29 +2192,31 @@ fn create_instance(entry: &Entry, required_extensions: &[*const c_char]) -> Inst
         .application_version(vk::make_api_version(0, 0, 1, 0))
         .engine_name(engine_name.as_c_str())
         .engine_version(vk::make_api_version(0, 0, 1, 0))
-        .api_version(API_VERSION_1_0);
+        .api_version(api_version);
 
-    let extension_properties =
-        unsafe { entry.enumerate_instance_extension_properties(None).unwrap() };
-    let names = extension_properties
+    let available = available_instance_extensions(entry);
+    let names = available
         .iter()
-        .flat_map(|prop| {
-            prop.extension_name_as_c_str().inspect_err(|_| {
-                error!("Filed to convert extension name to CStr. No null byte was present.")
-            })
-        })
-        .map(|cstr| cstr.to_string_lossy())
+        .map(|name| name.to_string_lossy())
         .join("\n\t");
 
     info!("Available extensions:\n\t{}", names);
 
-    let mut extension_names = Vec::from_iter(required_extensions.into_iter().map(|x| *x));
-
+    // The required names come from `ash` and `ash_window` constants.
+    let required = required_extensions
+        .iter()
+        .map(|name| unsafe { CStr::from_ptr(*name) })
+        .collect_vec();
+    let mut optional = portability_instance_extensions().to_vec();
     if ENABLE_VALIDATION_LAYERS {
-        extension_names.push(ext::debug_utils::NAME.as_ptr());
+        optional.push(ext::debug_utils::NAME);
     }
+    let selected = select_instance_extensions(&required, &optional, &available)?;
+    let debug_utils = selected.contains(&ext::debug_utils::NAME);
+    let extension_names = selected.iter().map(|name| name.as_ptr()).collect_vec();
 
     let mut create_info = vk::InstanceCreateInfo::default()
+        .flags(instance_create_flags(&selected))
         .application_info(&app_info)
         .enabled_extension_names(&extension_names);
 
@@ -501,19 +2229,20 @@ fn create_instance(entry: &Entry, required_extensions: &[*const c_char]) -> Inst
         .collect_vec();
     let layer_name_ptrs = layer_names.iter().map(|s| s.as_ptr()).collect_vec();
 
-    let mut debug_create_info = get_debug_utils_messenger_create_info();
+    let mut debug_create_info = validation.messenger_create_info();
 
     if ENABLE_VALIDATION_LAYERS {
-        check_validation_layer_support(entry);
-        create_info = create_info
-            .enabled_layer_names(&layer_name_ptrs)
-            .push_next(&mut debug_create_info);
+        check_validation_layer_support(entry)?;
+        create_info = create_info.enabled_layer_names(&layer_name_ptrs);
+        if debug_utils {
+            create_info = create_info.push_next(&mut debug_create_info);
+        }
     }
 
-    unsafe { entry.create_instance(&create_info, None).unwrap() }
+    Ok(unsafe { entry.create_instance(&create_info, None)? })
 }
 
-fn check_validation_layer_support(entry: &Entry) {
+fn check_validation_layer_support(entry: &Entry) -> Result<(), InitError> {
     let layer_properties = unsafe { entry.enumerate_instance_layer_properties().unwrap() };
     for required in REQUIRED_LAYERS {
         let found = layer_properties.iter().any(|layer| {
@@ -523,20 +2252,24 @@ fn check_validation_layer_support(entry: &Entry) {
         });
 
         if !found {
-            panic!("Validation layer not supported: {}", required);
+            return Err(InitError::MissingLayer(required));
         }
     }
+
+    Ok(())
 }
 
 fn setup_debug_messenger(
     entry: &Entry,
     instance: &Instance,
+    validation: &ValidationConfig,
 ) -> Option<(ext::debug_utils::Instance, DebugUtilsMessengerEXT)> {
-    if !ENABLE_VALIDATION_LAYERS {
+    // Validation messages aren't reported without `VK_EXT_debug_utils`, see `create_instance`.
+    if !ENABLE_VALIDATION_LAYERS || !supports_instance_extension(entry, ext::debug_utils::NAME) {
         return None;
     }
 
-    let create_info = get_debug_utils_messenger_create_info();
+    let create_info = validation.messenger_create_info();
 
     let debug_utils_instance = ext::debug_utils::Instance::new(entry, instance);
 
@@ -549,165 +2282,135 @@ fn setup_debug_messenger(
     Some((debug_utils_instance, messenger))
 }
 
-fn get_debug_utils_messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
-    vk::DebugUtilsMessengerCreateInfoEXT::default()
-        .message_severity(
-            vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-                | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-        )
-        .message_type(
-            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
-        )
-        .pfn_user_callback(Some(vulkan_debug_callback))
-}
-
 // TODO: select gpu from all available
+/// Without a `surface`, present support and the swapchain extension aren't required.
+///
+/// Every physical device is checked and logged, see [`DeviceCandidates`].
 fn select_physical_device(
     instance: &Instance,
-    surface_instance: &khr::surface::Instance,
-    surface: vk::SurfaceKHR,
-) -> (vk::PhysicalDevice, QueueFamilyIndices) {
-    let physical_devices = unsafe { instance.enumerate_physical_devices().unwrap() };
+    surface: Option<&SurfacePack>,
+) -> Result<(vk::PhysicalDevice, QueueFamilyIndices, DeviceCandidates), InitError> {
+    let physical_devices = unsafe { instance.enumerate_physical_devices()? };
 
     if physical_devices.is_empty() {
-        panic!("Failed to find GPUs with Vulkan support");
+        return Err(InitError::NoVulkanDevice);
     }
 
+    let mut candidates = DeviceCandidates::default();
+    let mut selected = None;
     for physical_device in physical_devices {
         let properties = unsafe { instance.get_physical_device_properties(physical_device) };
-        let features = unsafe { instance.get_physical_device_features(physical_device) };
 
-        if let Some(queue_families_data) = is_device_suitable(
-            instance,
-            physical_device,
-            properties,
-            features,
-            surface_instance,
-            surface,
-        ) {
-            info!(
-                "Selected physical device: {}",
-                properties.device_name_as_c_str().unwrap().to_string_lossy()
-            );
-            return (physical_device, queue_families_data);
+        let suitability = check_device_suitability(instance, physical_device, surface);
+        if let Ok(queue_family_indices) = suitability
+            && selected.is_none()
+        {
+            selected = Some((physical_device, queue_family_indices));
         }
+        candidates
+            .candidates
+            .push(DeviceCandidate::new(&properties, suitability.map(|_| ())));
+    }
+    candidates.log_summary();
+
+    let (physical_device, queue_family_indices) = selected.ok_or(InitError::NoSuitableDevice)?;
+    if let Some(candidate) = candidates.selected() {
+        info!("Selected physical device: {}", candidate.name);
     }
+    info!("Queue families: {queue_family_indices}");
 
-    panic!("Failed to find a suitable GPU")
+    Ok((physical_device, queue_family_indices, candidates))
 }
 
-fn is_device_suitable(
+fn check_device_suitability(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
-    properties: vk::PhysicalDeviceProperties,
-    features: vk::PhysicalDeviceFeatures,
-    surface_instance: &khr::surface::Instance,
-    surface: vk::SurfaceKHR,
-) -> Option<QueueFamilyIndices> {
-    let queue_family_indices =
-        find_queue_families(instance, physical_device, surface_instance, surface);
-
-    let extensions_supported = check_device_extension_support(instance, physical_device);
-    if !extensions_supported {
-        return None;
+    surface: Option<&SurfacePack>,
+) -> Result<QueueFamilyIndices, Unsuitable> {
+    let (search, families) = search_queue_families(
+        instance,
+        physical_device,
+        surface.map(|(surface_instance, surface)| (surface_instance, *surface)),
+    );
+    if search.graphics_family.is_none() {
+        return Err(Unsuitable::NoGraphicsFamily);
     }
+    if !search.is_complete() {
+        return Err(Unsuitable::NoPresentSupport);
+    }
+    let queue_family_indices = search
+        .finish(&families)
+        .expect("A complete search has a graphics and a present family");
+
+    let Some((surface_instance, surface)) = surface else {
+        return Ok(queue_family_indices);
+    };
 
-    let mut swapchain_support = false;
-    if extensions_supported {
-        let swapchain_support_details =
-            query_swapchain_support(physical_device, surface_instance, surface);
-        swapchain_support = !swapchain_support_details.formats.is_empty()
-            && !swapchain_support_details.present_modes.is_empty();
+    if let Some(extension) = missing_device_extension(instance, physical_device) {
+        return Err(Unsuitable::MissingExtension(extension));
     }
 
-    if !swapchain_support {
-        return None;
+    let swapchain_support_details =
+        query_swapchain_support(physical_device, surface_instance, *surface);
+    if swapchain_support_details.formats.is_empty()
+        || swapchain_support_details.present_modes.is_empty()
+    {
+        return Err(Unsuitable::NoSwapchainFormats);
     }
 
-    queue_family_indices
+    Ok(queue_family_indices)
 }
 
-fn check_device_extension_support(
+/// The first of [`REQUIRED_DEVICE_EXTENSIONS`] `physical_device` doesn't support.
+fn missing_device_extension(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
-) -> bool {
+) -> Option<String> {
     let extension_properties = unsafe {
         instance
             .enumerate_device_extension_properties(physical_device)
             .unwrap()
     };
 
-    for name in REQUIRED_DEVICE_EXTENSIONS {
-        let found = extension_properties.iter().any(|ext_prop| {
-            ext_prop.extension_name_as_c_str().unwrap() == unsafe { CStr::from_ptr(*name) }
-        });
-
-        if !found {
-            return false;
-        }
-    }
-
-    true
-}
-
-fn find_queue_families(
-    instance: &Instance,
-    physical_device: vk::PhysicalDevice,
-    surface_instance: &khr::surface::Instance,
-    surface: vk::SurfaceKHR,
-) -> Option<QueueFamilyIndices> {
-    let properties =
-        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
-
-    let mut graphics_family_index = None;
-    let mut present_family_index = None;
-
-    for (i, queue_family) in properties.iter().enumerate() {
-        let i = i as u32;
-
-        if graphics_family_index.is_none()
-            && queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
-        {
-            graphics_family_index = Some(i)
-        };
-
-        let surface_support = unsafe {
-            surface_instance
-                .get_physical_device_surface_support(physical_device, i, surface)
-                .unwrap()
-        };
-        if present_family_index.is_none() && surface_support {
-            present_family_index = Some(i)
-        }
-
-        if graphics_family_index.is_some() && present_family_index.is_some() {
-            break;
-        }
-    }
-
-    graphics_family_index.and_then(|graphics| {
-        present_family_index.map(|present| QueueFamilyIndices {
-            graphics_family: graphics,
-            present_family: present,
+    REQUIRED_DEVICE_EXTENSIONS
+        .iter()
+        .map(|name| unsafe { CStr::from_ptr(*name) })
+        .find(|name| {
+            !extension_properties
+                .iter()
+                .any(|ext_prop| ext_prop.extension_name_as_c_str() == Ok(*name))
         })
-    })
+        .map(|name| name.to_string_lossy().into_owned())
 }
 
 fn create_logical_device(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
     queue_families_data: QueueFamilyIndices,
+    extensions: &[*const c_char],
+    dynamic_rendering: bool,
 ) -> Device {
     let mut queue_create_infos = vec![];
 
     let unique_queue_families = HashSet::from([
         queue_families_data.graphics_family,
         queue_families_data.present_family,
+        queue_families_data.transfer_family,
+        queue_families_data.compute_family,
     ]);
 
+    if queue_families_data.shares_graphics_and_present() {
+        info!(
+            "Graphics and present use the same queue family {}",
+            queue_families_data.graphics_family
+        );
+    } else {
+        info!(
+            "Graphics and present use different queue families {} and {}, swapchain images are shared concurrently",
+            queue_families_data.graphics_family, queue_families_data.present_family
+        );
+    }
+
     let queue_priorities = &[1.0];
     for queue_family in unique_queue_families {
         let queue_create_info = vk::DeviceQueueCreateInfo::default()
@@ -716,11 +2419,19 @@ fn create_logical_device(
         queue_create_infos.push(queue_create_info);
     }
 
-    let features = vk::PhysicalDeviceFeatures::default();
-    let device_create_info = vk::DeviceCreateInfo::default()
+    let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+    let features = vk::PhysicalDeviceFeatures::default()
+        .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE)
+        .wide_lines(supported_features.wide_lines == vk::TRUE);
+    let mut vulkan_13_features =
+        vk::PhysicalDeviceVulkan13Features::default().dynamic_rendering(true);
+    let mut device_create_info = vk::DeviceCreateInfo::default()
         .queue_create_infos(&queue_create_infos)
         .enabled_features(&features)
-        .enabled_extension_names(REQUIRED_DEVICE_EXTENSIONS);
+        .enabled_extension_names(extensions);
+    if dynamic_rendering {
+        device_create_info = device_create_info.push_next(&mut vulkan_13_features);
+    }
 
     unsafe {
         instance
@@ -772,30 +2483,24 @@ fn query_swapchain_support(
     }
 }
 
+/// Picks the HDR format when `hdr` is set and the surface supports one,
+/// otherwise the most preferred available format.
 fn choose_swapchain_surface_format(
     available_formats: &[vk::SurfaceFormatKHR],
+    hdr: bool,
+    preference: &SurfaceFormatPreference,
 ) -> vk::SurfaceFormatKHR {
-    for available_format in available_formats {
-        if available_format.format == vk::Format::B8G8R8A8_SRGB
-            && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-        {
-            return *available_format;
-        }
-    }
-
-    available_formats[0]
-}
-
-fn choose_swapchain_present_mode(
-    available_present_modes: &[vk::PresentModeKHR],
-) -> vk::PresentModeKHR {
-    for available_present_mode in available_present_modes {
-        if *available_present_mode == vk::PresentModeKHR::MAILBOX {
-            return *available_present_mode;
+    if hdr {
+        match find_hdr_surface_format(available_formats) {
+            Some(format) => return format,
+            None => warn!("Surface doesn't support linear extended sRGB, presenting in sRGB"),
         }
     }
 
-    vk::PresentModeKHR::FIFO
+    preference.choose(available_formats).unwrap_or_else(|| {
+        warn!("No preferred surface format is available, using the first supported one");
+        available_formats[0]
+    })
 }
 
 fn choose_swapchain_extent(
@@ -803,7 +2508,11 @@ fn choose_swapchain_extent(
     size: PhysicalSize<u32>,
 ) -> vk::Extent2D {
     if capabilities.current_extent.width != u32::MAX {
-        dbg!(capabilities.current_extent)
+        debug!(
+            "Swapchain extent {:?} set by the surface",
+            capabilities.current_extent
+        );
+        capabilities.current_extent
     } else {
         let width = size.width.clamp(
             capabilities.min_image_extent.width,
@@ -814,35 +2523,89 @@ fn choose_swapchain_extent(
             capabilities.max_image_extent.height,
         );
 
-        Extent2D::default().width(width).height(height)
+        let extent = Extent2D::default().width(width).height(height);
+        debug!("Swapchain extent {extent:?} clamped from the window size {size:?}");
+        extent
     }
 }
 
+/// A created swapchain and what its images were created with.
+struct SwapchainParts {
+    device: khr::swapchain::Device,
+    swapchain: vk::SwapchainKHR,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    /// Rotation the presentation engine applies to the images.
+    transform: vk::SurfaceTransformFlagsKHR,
+}
+
 fn create_swapchain(
     instance: &Instance,
     device: &Device,
     physical_device: vk::PhysicalDevice,
-    surface_instance: &khr::surface::Instance,
-    surface: vk::SurfaceKHR,
+    (surface_instance, surface): (&khr::surface::Instance, vk::SurfaceKHR),
     size: PhysicalSize<u32>,
     queue_family_indices: QueueFamilyIndices,
-) -> (
-    khr::swapchain::Device,
-    vk::SwapchainKHR,
-    vk::Format,
-    vk::Extent2D,
-) {
+    config: &SwapchainConfig,
+) -> SwapchainParts {
     let swapchain_support = query_swapchain_support(physical_device, surface_instance, surface);
 
-    let surface_format = choose_swapchain_surface_format(&swapchain_support.formats);
-    let present_mode = choose_swapchain_present_mode(&swapchain_support.present_modes);
-    let extent = choose_swapchain_extent(swapchain_support.capabilities, size);
+    let surface_format = choose_swapchain_surface_format(
+        &swapchain_support.formats,
+        config.hdr,
+        &config.surface_formats,
+    );
+    info!(
+        "Swapchain format {:?} in {:?} color space",
+        surface_format.format, surface_format.color_space
+    );
+    let present_mode = config.present_mode.choose(&swapchain_support.present_modes);
+    if present_mode != config.present_mode.vk() {
+        warn!(
+            "Present mode {:?} is not supported, using {present_mode:?}",
+            config.present_mode
+        );
+    }
+    info!("Swapchain present mode {present_mode:?}");
+    let supported_composite_alpha = swapchain_support.capabilities.supported_composite_alpha;
+    let composite_alpha = config.composite_alpha.choose(supported_composite_alpha);
+    if composite_alpha != config.composite_alpha.flags() {
+        warn!(
+            "Composite alpha {:?} is not supported, using {composite_alpha:?} (supported: {supported_composite_alpha:?})",
+            config.composite_alpha
+        );
+    }
+    info!("Swapchain composite alpha {composite_alpha:?}");
+    // The images are created in the orientation of the display, so they look
+    // upright once the presentation engine rotated them.
+    let transform = swapchain_support.capabilities.current_transform;
+    let extent = pre_rotated_extent(
+        choose_swapchain_extent(swapchain_support.capabilities, size),
+        transform,
+    );
+    if transform != vk::SurfaceTransformFlagsKHR::IDENTITY {
+        info!("Surface transform {transform:?}, pre-rotating the swapchain images");
+    }
+
+    let image_count = config.image_count.clamp(
+        swapchain_support.capabilities.min_image_count,
+        swapchain_support.capabilities.max_image_count,
+    );
 
-    let mut image_count = swapchain_support.capabilities.min_image_count + 1;
-    if swapchain_support.capabilities.max_image_count > 0
-        && image_count > swapchain_support.capabilities.max_image_count
-    {
-        image_count = swapchain_support.capabilities.max_image_count;
+    // Copying from swapchain images is only needed for screenshots, and
+    // blitting to them for a scene target.
+    let mut image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+    for usage in [
+        vk::ImageUsageFlags::TRANSFER_SRC,
+        vk::ImageUsageFlags::TRANSFER_DST,
+    ] {
+        if swapchain_support
+            .capabilities
+            .supported_usage_flags
+            .contains(usage)
+        {
+            image_usage |= usage;
+        }
     }
 
     let mut create_info = vk::SwapchainCreateInfoKHR::default()
@@ -852,26 +2615,34 @@ fn create_swapchain(
         .image_color_space(surface_format.color_space)
         .image_extent(extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
+        .image_usage(image_usage);
 
     let indices = &[
         queue_family_indices.graphics_family,
         queue_family_indices.present_family,
     ];
 
-    if queue_family_indices.graphics_family != queue_family_indices.present_family {
+    if queue_family_indices.shares_graphics_and_present() {
+        debug!(
+            "Swapchain images are used exclusively by queue family {}",
+            queue_family_indices.graphics_family
+        );
+        create_info = create_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+    } else {
+        debug!(
+            "Swapchain images are shared concurrently by the graphics family {} and the present family {}",
+            queue_family_indices.graphics_family, queue_family_indices.present_family
+        );
         create_info = create_info
             .image_sharing_mode(vk::SharingMode::CONCURRENT)
             .queue_family_indices(indices)
-    } else {
-        create_info = create_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE)
     };
 
     create_info = create_info
-        .pre_transform(swapchain_support.capabilities.current_transform)
-        .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+        .pre_transform(transform)
+        .composite_alpha(composite_alpha)
         .present_mode(present_mode)
-        .clipped(false);
+        .clipped(config.clipped.0);
 
     let swapchain_device = khr::swapchain::Device::new(instance, device);
     let swapchain = unsafe {
@@ -880,7 +2651,20 @@ fn create_swapchain(
             .unwrap()
     };
 
-    (swapchain_device, swapchain, surface_format.format, extent)
+    // Drivers may create more images than requested.
+    let granted = unsafe { swapchain_device.get_swapchain_images(swapchain).unwrap() }.len();
+    info!(
+        "Swapchain has {granted} images, requested {image_count}, clipped: {}",
+        config.clipped.0
+    );
+
+    SwapchainParts {
+        device: swapchain_device,
+        swapchain,
+        format: surface_format.format,
+        extent,
+        transform,
+    }
 }
 
 fn create_image_views(
@@ -909,58 +2693,361 @@ fn create_image_views(
     image_views
 }
 
-fn create_render_pass(device: &Device, swapchain_image_format: vk::Format) -> vk::RenderPass {
+/// Color and depth attachments of the render passes, with the same sample
+/// count. A multisampled color attachment is resolved into a third, single
+/// sampled one.
+///
+/// `final_layout` is the layout the color attachment the frame ends up in is
+/// left in.
+fn render_pass_attachments(
+    formats: AttachmentFormats,
+    final_layout: vk::ImageLayout,
+) -> Vec<vk::AttachmentDescription> {
+    let multisampled = formats.samples != vk::SampleCountFlags::TYPE_1;
     let color_attachment = vk::AttachmentDescription::default()
-        .format(swapchain_image_format)
-        .samples(vk::SampleCountFlags::TYPE_1)
+        .format(formats.color)
+        .samples(formats.samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(if multisampled {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        })
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(if multisampled {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            final_layout
+        });
+
+    let depth_attachment = vk::AttachmentDescription::default()
+        .format(formats.depth)
+        .samples(formats.samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let mut attachments = vec![color_attachment, depth_attachment];
+    if multisampled {
+        attachments.push(
+            vk::AttachmentDescription::default()
+                .format(formats.color)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(final_layout),
+        );
+    }
+    attachments
+}
+
+/// `final_layout` is the layout the color attachment is left in.
+/// A render pass resolving depth is created with `render_pass2`, which must
+/// be set when `formats` has a `depth_resolve` mode.
+fn create_render_pass(
+    device: &Device,
+    render_pass2: Option<&khr::create_renderpass2::Device>,
+    formats: AttachmentFormats,
+    final_layout: vk::ImageLayout,
+) -> vk::RenderPass {
+    if let Some(mode) = formats.depth_resolve {
+        let render_pass2 =
+            render_pass2.expect("Resolving depth requires VK_KHR_create_renderpass2");
+        return create_depth_resolve_render_pass(render_pass2, formats, mode, final_layout);
+    }
 
     let color_attachment_ref = vk::AttachmentReference::default()
         .attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
 
+    let depth_attachment_ref = vk::AttachmentReference::default()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let resolve_attachment_ref = vk::AttachmentReference::default()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
     let color_attachments = &[color_attachment_ref];
-    let subpass = vk::SubpassDescription::default()
+    let resolve_attachments = &[resolve_attachment_ref];
+    let mut subpass = vk::SubpassDescription::default()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .color_attachments(color_attachments);
+        .color_attachments(color_attachments)
+        .depth_stencil_attachment(&depth_attachment_ref);
+    if formats.samples != vk::SampleCountFlags::TYPE_1 {
+        subpass = subpass.resolve_attachments(resolve_attachments);
+    }
 
     let dependency = vk::SubpassDependency::default()
         .src_subpass(vk::SUBPASS_EXTERNAL)
         .dst_subpass(0)
-        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )
+        .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        );
+
+    let attachments = &render_pass_attachments(formats, final_layout);
+    let subpasses = &[subpass];
+    let dependencies = &[dependency];
+    let render_pass_create_info = vk::RenderPassCreateInfo::default()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+
+    unsafe {
+        device
+            .create_render_pass(&render_pass_create_info, None)
+            .unwrap()
+    }
+}
+
+/// Attachments of [`render_pass_attachments`], followed by the single sampled
+/// depth attachment resolved into.
+fn depth_resolve_attachments(
+    formats: AttachmentFormats,
+    final_layout: vk::ImageLayout,
+) -> Vec<vk::AttachmentDescription2<'static>> {
+    let mut attachments = render_pass_attachments(formats, final_layout)
+        .iter()
+        .map(|attachment| {
+            vk::AttachmentDescription2::default()
+                .flags(attachment.flags)
+                .format(attachment.format)
+                .samples(attachment.samples)
+                .load_op(attachment.load_op)
+                .store_op(attachment.store_op)
+                .stencil_load_op(attachment.stencil_load_op)
+                .stencil_store_op(attachment.stencil_store_op)
+                .initial_layout(attachment.initial_layout)
+                .final_layout(attachment.final_layout)
+        })
+        .collect_vec();
+    attachments.push(
+        vk::AttachmentDescription2::default()
+            .format(formats.depth)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::STORE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+    );
+    attachments
+}
+
+/// [`create_render_pass`] with the multisampled depth resolved with `mode`,
+/// which `VkRenderPassCreateInfo` can't express.
+fn create_depth_resolve_render_pass(
+    render_pass2: &khr::create_renderpass2::Device,
+    formats: AttachmentFormats,
+    mode: vk::ResolveModeFlags,
+    final_layout: vk::ImageLayout,
+) -> vk::RenderPass {
+    let attachments = &depth_resolve_attachments(formats, final_layout);
+    let depth_aspect = depth_aspect_mask(formats.depth);
+    let reference = |attachment, layout, aspect_mask| {
+        vk::AttachmentReference2::default()
+            .attachment(attachment)
+            .layout(layout)
+            .aspect_mask(aspect_mask)
+    };
+    let color_attachments = &[reference(
+        0,
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        vk::ImageAspectFlags::COLOR,
+    )];
+    let depth_attachment_ref = reference(
+        1,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        depth_aspect,
+    );
+    let resolve_attachments = &[reference(
+        2,
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        vk::ImageAspectFlags::COLOR,
+    )];
+    let depth_resolve_attachment_ref = reference(
+        3,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        depth_aspect,
+    );
+
+    // The stencil of combined formats is resolved with the same mode, which
+    // `query_depth_resolve_modes` made sure it supports.
+    let mut depth_stencil_resolve = vk::SubpassDescriptionDepthStencilResolve::default()
+        .depth_resolve_mode(mode)
+        .stencil_resolve_mode(mode)
+        .depth_stencil_resolve_attachment(&depth_resolve_attachment_ref);
+    let subpass = vk::SubpassDescription2::default()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments)
+        .resolve_attachments(resolve_attachments)
+        .depth_stencil_attachment(&depth_attachment_ref)
+        .push_next(&mut depth_stencil_resolve);
+
+    // Depth is resolved in the late fragment tests.
+    let stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+    let dependency = vk::SubpassDependency2::default()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(stages)
         .src_access_mask(vk::AccessFlags::empty())
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        .dst_stage_mask(stages)
+        .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        );
+
+    let subpasses = &[subpass];
+    let dependencies = &[dependency];
+    let render_pass_create_info = vk::RenderPassCreateInfo2::default()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+
+    unsafe {
+        render_pass2
+            .create_render_pass2(&render_pass_create_info, None)
+            .unwrap()
+    }
+}
+
+/// Shaders and fixed-function state that differ between the graphics pipelines.
+#[derive(Debug, Clone, Copy)]
+struct GraphicsPipelineDesc {
+    vertex_shader: &'static [u8],
+    fragment_shader: &'static [u8],
+    topology: vk::PrimitiveTopology,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    line_width: f32,
+    depth_compare_op: vk::CompareOp,
+    depth_write: bool,
+    color_write: bool,
+}
+
+impl GraphicsPipelineDesc {
+    const CHUNKS: Self = Self {
+        vertex_shader: include_bytes!("../../shaders/out/voxel.vert.spv"),
+        fragment_shader: include_bytes!("../../shaders/out/voxel.frag.spv"),
+        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+        cull_mode: vk::CullModeFlags::BACK,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        line_width: 1.0,
+        depth_compare_op: vk::CompareOp::LESS,
+        depth_write: true,
+        color_write: true,
+    };
+
+    /// Untextured lines. The voxel vertex shader is reused with the fragment
+    /// shader of the triangle, which outputs the vertex color as is.
+    const LINES: Self = Self {
+        vertex_shader: include_bytes!("../../shaders/out/voxel.vert.spv"),
+        fragment_shader: include_bytes!("../../shaders/out/triangle.frag.spv"),
+        topology: vk::PrimitiveTopology::LINE_LIST,
+        cull_mode: vk::CullModeFlags::NONE,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        line_width: 1.0,
+        depth_compare_op: vk::CompareOp::LESS,
+        depth_write: true,
+        color_write: true,
+    };
+
+    /// Boxes of the occlusion queries, only depth tested.
+    const OCCLUSION_BOXES: Self = Self {
+        depth_write: false,
+        color_write: false,
+        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+        ..Self::LINES
+    };
+
+    /// Applies the options of `raster`, replacing any set before.
+    fn with_raster(self, raster: &RasterConfig) -> Self {
+        Self {
+            cull_mode: raster.cull.mode.flags(),
+            front_face: raster.cull.front_face.vk(),
+            depth_compare_op: raster.depth_mode.compare_op(),
+            ..self
+        }
+    }
+}
+
+/// What a graphics pipeline is compatible with.
+#[derive(Debug, Clone, Copy)]
+enum PipelineTarget {
+    RenderPass {
+        render_pass: vk::RenderPass,
+        samples: vk::SampleCountFlags,
+    },
+    Dynamic(AttachmentFormats),
+}
 
-    let attachments = &[color_attachment];
-    let subpasses = &[subpass];
-    let dependencies = &[dependency];
-    let render_pass_create_info = vk::RenderPassCreateInfo::default()
-        .attachments(attachments)
-        .subpasses(subpasses)
-        .dependencies(dependencies);
+impl PipelineTarget {
+    /// `render_pass` is `None` when frames are rendered with dynamic rendering.
+    fn new(render_pass: Option<vk::RenderPass>, formats: AttachmentFormats) -> Self {
+        match render_pass {
+            Some(render_pass) => Self::RenderPass {
+                render_pass,
+                samples: formats.samples,
+            },
+            None => Self::Dynamic(formats),
+        }
+    }
 
-    unsafe {
-        device
-            .create_render_pass(&render_pass_create_info, None)
-            .unwrap()
+    /// Samples the pipeline rasterizes with, the ones of its attachments.
+    fn samples(&self) -> vk::SampleCountFlags {
+        match self {
+            Self::RenderPass { samples, .. } => *samples,
+            Self::Dynamic(formats) => formats.samples,
+        }
     }
 }
 
-fn create_graphics_pipeline(
+fn rasterization_state(
+    desc: &GraphicsPipelineDesc,
+) -> vk::PipelineRasterizationStateCreateInfo<'static> {
+    vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(desc.line_width)
+        .cull_mode(desc.cull_mode)
+        // The projection flips Y, which turns counter-clockwise meshes clockwise on screen.
+        .front_face(desc.front_face)
+        .depth_bias_enable(false)
+}
+
+/// Pipeline drawing vertices laid out as `V`.
+fn create_graphics_pipeline<V: VertexFormat>(
     device: &Device,
-    render_pass: vk::RenderPass,
+    target: PipelineTarget,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    desc: &GraphicsPipelineDesc,
 ) -> (vk::Pipeline, vk::PipelineLayout) {
-    let vertex = include_bytes!("../../shaders/out/triangle.vert.spv");
-    let fragment = include_bytes!("../../shaders/out/triangle.frag.spv");
-
-    let vertex_shader_module = create_shader_module(device, vertex);
-    let fragment_shader_module = create_shader_module(device, fragment);
+    let vertex_shader_module =
+        create_shader_module(device, desc.vertex_shader).expect("Invalid vertex shader");
+    let fragment_shader_module =
+        create_shader_module(device, desc.fragment_shader).expect("Invalid fragment shader");
 
     let vertex_stage_info = vk::PipelineShaderStageCreateInfo::default()
         .stage(vk::ShaderStageFlags::VERTEX)
@@ -972,12 +3059,14 @@ fn create_graphics_pipeline(
         .name(c"main");
     let shader_stages = &[vertex_stage_info, fragment_stage_info];
 
+    let binding_descriptions = V::binding_descriptions();
+    let attribute_descriptions = V::attribute_descriptions();
     let vertex_input_create_info = vk::PipelineVertexInputStateCreateInfo::default()
-        .vertex_attribute_descriptions(&[])
-        .vertex_binding_descriptions(&[]);
+        .vertex_attribute_descriptions(&attribute_descriptions)
+        .vertex_binding_descriptions(&binding_descriptions);
 
     let input_assembly_create_info = vk::PipelineInputAssemblyStateCreateInfo::default()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .topology(desc.topology)
         .primitive_restart_enable(false);
 
     // let viewport = vk::Viewport::default()
@@ -1000,27 +3089,27 @@ fn create_graphics_pipeline(
         .viewport_count(1)
         .scissor_count(1);
 
-    let rasterizer_create_info = vk::PipelineRasterizationStateCreateInfo::default()
-        .depth_clamp_enable(false)
-        .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
-        .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::CLOCKWISE)
-        .depth_bias_enable(false);
+    let rasterizer_create_info = rasterization_state(desc);
 
     let multisampling_create_info = vk::PipelineMultisampleStateCreateInfo::default()
         .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        .rasterization_samples(target.samples())
         .min_sample_shading(1.0);
 
+    let depth_stencil_create_info = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(desc.depth_write)
+        .depth_compare_op(desc.depth_compare_op)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    let color_write_mask = if desc.color_write {
+        vk::ColorComponentFlags::RGBA
+    } else {
+        vk::ColorComponentFlags::empty()
+    };
     let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
-        .color_write_mask(
-            vk::ColorComponentFlags::R
-                | vk::ColorComponentFlags::G
-                | vk::ColorComponentFlags::B
-                | vk::ColorComponentFlags::A,
-        )
+        .color_write_mask(color_write_mask)
         .blend_enable(false);
 
     let attachments = &[color_blend_attachment];
@@ -1029,7 +3118,14 @@ fn create_graphics_pipeline(
         .logic_op(vk::LogicOp::COPY)
         .attachments(attachments);
 
-    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default();
+    let push_constant_ranges = &[vk::PushConstantRange::default()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<ChunkPushConstants>() as u32)];
+    let set_layouts = &[descriptor_set_layout];
+    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
 
     let pipeline_layout = unsafe {
         device
@@ -1037,18 +3133,32 @@ fn create_graphics_pipeline(
             .unwrap()
     };
 
-    let pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
+    let mut pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
         .stages(shader_stages)
         .vertex_input_state(&vertex_input_create_info)
         .input_assembly_state(&input_assembly_create_info)
         .viewport_state(&viewport_state_create_info)
         .rasterization_state(&rasterizer_create_info)
         .multisample_state(&multisampling_create_info)
+        .depth_stencil_state(&depth_stencil_create_info)
         .color_blend_state(&color_blending_create_info)
         .dynamic_state(&dynamic_state_create_info)
-        .layout(pipeline_layout)
-        .render_pass(render_pass)
-        .subpass(0);
+        .layout(pipeline_layout);
+
+    let color_formats;
+    let mut rendering_create_info;
+    match target {
+        PipelineTarget::RenderPass { render_pass, .. } => {
+            pipeline_create_info = pipeline_create_info.render_pass(render_pass).subpass(0);
+        }
+        PipelineTarget::Dynamic(formats) => {
+            color_formats = [formats.color];
+            rendering_create_info = vk::PipelineRenderingCreateInfo::default()
+                .color_attachment_formats(&color_formats)
+                .depth_attachment_format(formats.depth);
+            pipeline_create_info = pipeline_create_info.push_next(&mut rendering_create_info);
+        }
+    }
 
     let pipeline = unsafe {
         device
@@ -1064,21 +3174,73 @@ fn create_graphics_pipeline(
     (pipeline, pipeline_layout)
 }
 
-fn create_shader_module(device: &Device, buf: &[u8]) -> vk::ShaderModule {
-    let create_info = vk::ShaderModuleCreateInfo::default().code(bytemuck::cast_slice(buf));
-    unsafe { device.create_shader_module(&create_info, None).unwrap() }
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Words of the SPIR-V code in `bytes`, checking its size and magic number.
+///
+/// Copies the words since `bytes`, e.g. from `include_bytes!`, may not be
+/// aligned to 4 bytes.
+fn spirv_words(bytes: &[u8]) -> Result<Vec<u32>, VulkanError> {
+    if bytes.is_empty() || !bytes.len().is_multiple_of(4) {
+        return Err(VulkanError::SpirvSize(bytes.len()));
+    }
+
+    let words = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    if words[0] != SPIRV_MAGIC {
+        return Err(VulkanError::SpirvMagic(words[0]));
+    }
+
+    Ok(words)
+}
+
+fn create_shader_module(device: &Device, buf: &[u8]) -> Result<vk::ShaderModule, VulkanError> {
+    let code = spirv_words(buf)?;
+    let create_info = vk::ShaderModuleCreateInfo::default().code(&code);
+    Ok(unsafe { device.create_shader_module(&create_info, None)? })
+}
+
+/// Creates a framebuffer per swapchain image, or none without a render pass.
+/// Attachments of a framebuffer of [`create_render_pass`] the frame ends up
+/// in `image_view` of, rendered into `msaa_color_view` first when multisampled.
+/// The depth is resolved into `resolved_depth_view` last, if set.
+fn framebuffer_attachments(
+    image_view: vk::ImageView,
+    depth_image_view: vk::ImageView,
+    resolved_depth_view: Option<vk::ImageView>,
+    msaa_color_view: Option<vk::ImageView>,
+) -> Vec<vk::ImageView> {
+    let mut attachments = match msaa_color_view {
+        Some(msaa_color_view) => vec![msaa_color_view, depth_image_view, image_view],
+        None => vec![image_view, depth_image_view],
+    };
+    attachments.extend(resolved_depth_view);
+    attachments
 }
 
 fn create_framebuffers(
     device: &Device,
-    render_pass: vk::RenderPass,
+    render_pass: Option<vk::RenderPass>,
     swapchain_image_views: &[vk::ImageView],
+    depth: &DepthResources,
+    msaa_color_view: Option<vk::ImageView>,
     swapchain_extent: Extent2D,
 ) -> Vec<vk::Framebuffer> {
+    let Some(render_pass) = render_pass else {
+        return Vec::new();
+    };
+
     let mut swapchain_framebuffers = Vec::with_capacity(swapchain_image_views.len());
 
     for image_view in swapchain_image_views {
-        let attachments = &[*image_view];
+        let attachments = &framebuffer_attachments(
+            *image_view,
+            depth.view,
+            depth.resolved.as_ref().map(|resolved| resolved.view),
+            msaa_color_view,
+        );
 
         let framebuffer_create_info = vk::FramebufferCreateInfo::default()
             .render_pass(render_pass)
@@ -1099,13 +3261,15 @@ fn create_framebuffers(
     swapchain_framebuffers
 }
 
-fn create_command_pool(
-    device: &Device,
-    queue_family_indices: QueueFamilyIndices,
-) -> vk::CommandPool {
+/// Pool of short-lived command buffers, see
+/// [`begin_single_time_commands`](transfer::begin_single_time_commands).
+///
+/// They are freed after their submission instead of being reset, so the pool
+/// only needs the `TRANSIENT` hint.
+fn create_transient_command_pool(device: &Device, queue_family_index: u32) -> vk::CommandPool {
     let command_pool_info = vk::CommandPoolCreateInfo::default()
-        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
-        .queue_family_index(queue_family_indices.graphics_family);
+        .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+        .queue_family_index(queue_family_index);
 
     unsafe {
         device
@@ -1114,78 +3278,156 @@ fn create_command_pool(
     }
 }
 
-fn create_command_buffers(
-    device: &Device,
-    command_pool: vk::CommandPool,
-) -> Vec<vk::CommandBuffer> {
-    let allocate_info = vk::CommandBufferAllocateInfo::default()
-        .command_pool(command_pool)
-        .level(vk::CommandBufferLevel::PRIMARY)
-        .command_buffer_count(2);
+/// Attachments a frame is rendered into.
+#[derive(Debug, Clone, Copy)]
+enum FrameTarget {
+    RenderPass {
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+    },
+    Dynamic(DynamicTarget),
+}
+
+impl FrameTarget {
+    /// `formats` are the ones of the target.
+    fn inherited(&self, formats: AttachmentFormats) -> InheritedTarget {
+        match *self {
+            FrameTarget::RenderPass {
+                render_pass,
+                framebuffer,
+            } => InheritedTarget::RenderPass {
+                render_pass,
+                framebuffer,
+            },
+            FrameTarget::Dynamic(_) => InheritedTarget::Dynamic(formats),
+        }
+    }
+}
+
+/// Linear RGBA color the frames are cleared to before the world is drawn.
+///
+/// Its alpha shows through transparent windows, see [`CompositeAlpha`].
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct ClearColor(pub [f32; 4]);
+
+impl Default for ClearColor {
+    fn default() -> Self {
+        Self([1.0, 1.0, 1.0, 1.0])
+    }
+}
+
+fn update_clear_color_system(mut vulkan_app: ResMut<VulkanApp>, clear_color: Res<ClearColor>) {
+    if vulkan_app.clear_color != clear_color.0 {
+        vulkan_app.clear_color = clear_color.0;
+    }
+}
 
-    unsafe { device.allocate_command_buffers(&allocate_info).unwrap() }
+/// Values the attachments of a frame are cleared to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ClearValues {
+    color: [f32; 4],
+    depth: f32,
+}
+
+/// Per-frame inputs of [`record_command_buffer`] besides the draws.
+struct FrameCommands<'a> {
+    clear: ClearValues,
+    /// Occlusion query pool and query count, reset before rendering.
+    query_reset: Option<(vk::QueryPool, u32)>,
+    /// Blit of the scene target onto the swapchain image of the given extent.
+    present_blit: Option<(&'a SceneTarget, vk::Image, Extent2D)>,
+    /// Copy of the rendered swapchain image for a screenshot.
+    capture: Option<(&'a PendingCapture, vk::Image)>,
 }
 
 fn record_command_buffer(
     device: &Device,
     command_buffer: vk::CommandBuffer,
-    render_pass: vk::RenderPass,
-    swapchain_framebuffers: &[vk::Framebuffer],
-    image_index: usize,
-    swapchain_extent: Extent2D,
-    graphics_pipeline: vk::Pipeline,
-) {
+    target: &FrameTarget,
+    extent: Extent2D,
+    secondary_command_buffers: &[vk::CommandBuffer],
+    frame: &FrameCommands,
+) -> Result<(), VulkanError> {
     let begin_info = vk::CommandBufferBeginInfo::default();
 
     unsafe {
-        device.begin_command_buffer(command_buffer, &begin_info);
+        device.begin_command_buffer(command_buffer, &begin_info)?;
 
-        let render_pass_info = vk::RenderPassBeginInfo::default()
-            .render_pass(render_pass)
-            .framebuffer(swapchain_framebuffers[image_index])
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: swapchain_extent,
-            })
-            .clear_values(&[vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [1.0, 1.0, 1.0, 1.0],
-                },
-            }]);
+        // Queries can't be reset inside a render pass.
+        if let Some((query_pool, query_count)) = frame.query_reset {
+            device.cmd_reset_query_pool(command_buffer, query_pool, 0, query_count);
+        }
 
-        device.cmd_begin_render_pass(
-            command_buffer,
-            &render_pass_info,
-            vk::SubpassContents::INLINE,
-        );
+        let frame_label = DebugLabel::begin(device, command_buffer, "Frame");
+        match target {
+            FrameTarget::RenderPass {
+                render_pass,
+                framebuffer,
+            } => {
+                let clear_values = [
+                    vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: frame.clear.color,
+                        },
+                    },
+                    vk::ClearValue {
+                        depth_stencil: vk::ClearDepthStencilValue {
+                            depth: frame.clear.depth,
+                            stencil: 0,
+                        },
+                    },
+                ];
+                let render_pass_info = vk::RenderPassBeginInfo::default()
+                    .render_pass(*render_pass)
+                    .framebuffer(*framebuffer)
+                    .render_area(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .clear_values(&clear_values);
+
+                device.cmd_begin_render_pass(
+                    command_buffer,
+                    &render_pass_info,
+                    vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+                );
+            }
+            FrameTarget::Dynamic(target) => {
+                begin_rendering(
+                    device,
+                    command_buffer,
+                    target,
+                    extent,
+                    frame.clear.color,
+                    frame.clear.depth,
+                );
+            }
+        }
 
-        device.cmd_bind_pipeline(
-            command_buffer,
-            vk::PipelineBindPoint::GRAPHICS,
-            graphics_pipeline,
-        );
+        if !secondary_command_buffers.is_empty() {
+            device.cmd_execute_commands(command_buffer, secondary_command_buffers);
+        }
 
-        let viewport = vk::Viewport::default()
-            .x(0.0)
-            .y(0.0)
-            .width(swapchain_extent.width as f32)
-            .height(swapchain_extent.height as f32)
-            .min_depth(0.0)
-            .max_depth(1.0);
-        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
-
-        let scissor = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain_extent,
-        };
-        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+        match target {
+            FrameTarget::RenderPass { .. } => device.cmd_end_render_pass(command_buffer),
+            FrameTarget::Dynamic(target) => end_rendering(device, command_buffer, target),
+        }
+        drop(frame_label);
+
+        if let Some((scene_target, swapchain_image, swapchain_extent)) = frame.present_blit {
+            let _label = DebugLabel::begin(device, command_buffer, "PresentBlit");
+            scene_target.record_blit(device, command_buffer, swapchain_image, swapchain_extent);
+        }
 
-        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        if let Some((capture, image)) = frame.capture {
+            let _label = DebugLabel::begin(device, command_buffer, "Screenshot");
+            capture.record_copy(device, command_buffer, image)?;
+        }
 
-        device.cmd_end_render_pass(command_buffer);
+        device.end_command_buffer(command_buffer)?;
+    }
 
-        device.end_command_buffer(command_buffer)
-    };
+    Ok(())
 }
 
 fn create_sync_objects(
@@ -1211,12 +3453,6 @@ fn create_sync_objects(
     objects.into_iter().multiunzip()
 }
 
-#[derive(Resource, Clone, Copy, Default)]
-struct QueueFamilyIndices {
-    graphics_family: u32,
-    present_family: u32,
-}
-
 #[derive(Default)]
 struct SwapchainSupportDetails {
     capabilities: vk::SurfaceCapabilitiesKHR,
@@ -1224,24 +3460,100 @@ struct SwapchainSupportDetails {
     present_modes: Vec<vk::PresentModeKHR>,
 }
 
-// fn init_vulkan_app(
-//     mut commands: Commands,
-//     windows: Res<AppWindows>,
-//     display_handle: Res<WinitOwnedDisplayHandle>,
-// ) {
-//     // let create_info = VulkanAppCreateInfo {
-//     //     display_handle: display_handle.0.clone(),
-//     //     window: windows.primary.clone(),
-//     // };
+/// The config resources read once when the [`VulkanApp`] is created.
+#[derive(SystemParam)]
+struct VulkanAppConfig<'w> {
+    anisotropy: Res<'w, AnisotropyLevel>,
+    sampler: Res<'w, SamplerConfig>,
+    hdr: Res<'w, HdrMode>,
+    validation: Res<'w, ValidationConfig>,
+    surface_formats: Res<'w, SurfaceFormatPreference>,
+    image_count: Res<'w, SwapchainImageCount>,
+    clipped: Res<'w, SwapchainClipped>,
+    composite_alpha: Res<'w, CompositeAlpha>,
+    present_mode: Res<'w, PresentMode>,
+    msaa: Res<'w, SampleCount>,
+    depth_mode: Res<'w, DepthMode>,
+    cull: Res<'w, CullConfig>,
+    debug_grid: Res<'w, DebugGrid>,
+}
+
+impl VulkanAppConfig<'_> {
+    fn create_info(
+        &self,
+        display_handle: OwnedDisplayHandle,
+        window: Arc<winit::window::Window>,
+    ) -> VulkanAppCreateInfo {
+        VulkanAppCreateInfo {
+            display_handle,
+            window,
+            anisotropy: *self.anisotropy,
+            sampler: *self.sampler,
+            validation: *self.validation,
+            swapchain: SwapchainConfig {
+                hdr: self.hdr.0,
+                surface_formats: self.surface_formats.clone(),
+                image_count: *self.image_count,
+                clipped: *self.clipped,
+                composite_alpha: *self.composite_alpha,
+                present_mode: *self.present_mode,
+            },
+            raster: RasterConfig {
+                depth_mode: *self.depth_mode,
+                cull: *self.cull,
+            },
+            msaa: *self.msaa,
+            debug_grid: *self.debug_grid,
+        }
+    }
+}
+
+fn init_vulkan_app(
+    mut commands: Commands,
+    windows: Res<AppWindows>,
+    display_handle: Res<WinitOwnedDisplayHandle>,
+    config: VulkanAppConfig,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    let create_info = config.create_info(display_handle.0.clone(), windows.primary.clone());
+    let vulkan_app = match VulkanApp::new(create_info) {
+        Ok(vulkan_app) => vulkan_app,
+        Err(err) => {
+            error!("Failed to initialize Vulkan: {err}");
+            app_exit.write(AppExit::error());
+            return;
+        }
+    };
+
+    let limits = vulkan_app.device_limits();
+    limits.log_summary();
+    commands.insert_resource(limits);
+    commands.insert_resource(vulkan_app.device_candidates.clone());
+
+    match vulkan_app.memory_budget() {
+        Some(budget) => {
+            budget.log_summary();
+            commands.insert_resource(budget);
+        }
+        None => info!("VK_EXT_memory_budget is not supported, no memory budget available"),
+    }
 
-//     // let vulkan_app = VulkanApp::new(create_info);
-//     // commands.insert_resource(vulkan_app);
+    commands.insert_resource(vulkan_app.render_device());
+    commands.insert_resource(vulkan_app);
+}
 
-// }
+/// Hands the objects of the [`VulkanApp`] over to the storages once the app exits.
+fn release_vulkan_app_system(world: &mut World) {
+    if let Some(vulkan_app) = world.remove_resource::<VulkanApp>() {
+        vulkan_app.release_into_storages(&mut world.commands());
+        world.flush();
+    }
+}
 
 fn load_entry_and_create_instance(
     mut commands: Commands,
     owned_display_handle: Res<WinitOwnedDisplayHandle>,
+    validation: Res<ValidationConfig>,
 ) -> Result<(), BevyError> {
     let entry = unsafe { ash::Entry::load()? };
 
@@ -1249,10 +3561,10 @@ fn load_entry_and_create_instance(
     let raw_handle = handle.as_raw();
     let required_extensions = ash_window::enumerate_required_extensions(raw_handle)?;
 
-    let instance = create_instance(&entry, required_extensions);
+    let instance = create_instance(&entry, required_extensions, API_VERSION_1_0, &validation)?;
 
     commands.insert_resource(RawStorage { data: entry });
-    commands.insert_resource(RawStorage { data: instance });
+    commands.insert_single_storage(instance);
 
     Ok(())
 }
@@ -1260,14 +3572,15 @@ fn load_entry_and_create_instance(
 fn setup_debug_messanger_system(
     mut commands: Commands,
     entry: Storage<ash::Entry>,
-    instance: Storage<ash::Instance>,
-) {
-    let debug_messanger_pack = setup_debug_messenger(&entry, &instance);
+    instance: StorageSingle<ash::Instance>,
+    validation: Res<ValidationConfig>,
+) -> Result<(), BevyError> {
+    let debug_messanger_pack = setup_debug_messenger(&entry, instance.try_get()?, &validation);
     if let Some(debug_messanger_pack) = debug_messanger_pack {
-        commands.insert_resource(RawStorage {
-            data: debug_messanger_pack,
-        });
+        commands.insert_single_storage(debug_messanger_pack);
     }
+
+    Ok(())
 }
 
 fn create_surface_system(
@@ -1275,7 +3588,7 @@ fn create_surface_system(
     windows: Res<AppWindows>,
     owned_display_handle: Res<WinitOwnedDisplayHandle>,
     entry: Storage<ash::Entry>,
-    instance: Storage<ash::Instance>,
+    instance: StorageSingle<ash::Instance>,
 ) -> Result<(), BevyError> {
     let handle = owned_display_handle.0.display_handle()?;
     let raw_display_handle = handle.as_raw();
@@ -1283,34 +3596,49 @@ fn create_surface_system(
     let handle = windows.primary.window_handle()?;
     let raw_window_handle = handle.as_raw();
 
-    let (surface_instance, surface) =
-        create_surface(&entry, &instance, raw_display_handle, raw_window_handle);
+    let (surface_instance, surface) = create_surface(
+        &entry,
+        instance.try_get()?,
+        raw_display_handle,
+        raw_window_handle,
+    );
 
-    commands.insert_resource(RawStorage {
-        data: (surface_instance, surface),
-    });
+    commands.insert_single_storage((surface_instance, surface));
 
     Ok(())
 }
 
 fn select_physical_device_system(
     mut commands: Commands,
-    instance: Storage<ash::Instance>,
-    surface_pack: Storage<SurfacePack>,
-) {
-    let (physical_device, queue_family_indices) =
-        select_physical_device(&instance, &surface_pack.0, surface_pack.1);
-    let device = create_logical_device(&instance, physical_device, queue_family_indices);
+    instance: StorageSingle<ash::Instance>,
+    surface_pack: StorageSingle<SurfacePack>,
+) -> Result<(), BevyError> {
+    let instance = instance.try_get()?;
+    let (surface_instance, surface) = surface_pack.try_get()?;
+
+    let (physical_device, queue_family_indices, _) =
+        select_physical_device(instance, Some(surface_pack.try_get()?))?;
+    let device = create_logical_device(
+        instance,
+        physical_device,
+        queue_family_indices,
+        REQUIRED_DEVICE_EXTENSIONS,
+        false,
+    );
 
     commands.insert_storage(physical_device);
-    commands.insert_storage(device);
+    commands.insert_single_storage(device);
     commands.insert_storage(queue_family_indices);
+
+    Ok(())
 }
 
 #[derive(Resource)]
 pub struct Queues {
     pub graphics: vk::Queue,
     pub present: vk::Queue,
+    pub transfer: vk::Queue,
+    pub compute: vk::Queue,
 }
 
 fn create_queues_system(
@@ -1318,13 +3646,19 @@ fn create_queues_system(
     device: DeviceStorage,
     queue_family_indices: Res<QueueFamilyIndices>,
 ) {
+    let device = device.device();
     let graphics_queue =
         unsafe { device.get_device_queue(queue_family_indices.graphics_family, 0) };
     let present_queue = unsafe { device.get_device_queue(queue_family_indices.present_family, 0) };
+    let transfer_queue =
+        unsafe { device.get_device_queue(queue_family_indices.transfer_family, 0) };
+    let compute_queue = unsafe { device.get_device_queue(queue_family_indices.compute_family, 0) };
 
     commands.insert_resource(Queues {
         graphics: graphics_queue,
         present: present_queue,
+        transfer: transfer_queue,
+        compute: compute_queue,
     });
 }
 
@@ -1339,39 +3673,72 @@ pub struct SwapchainData {
 fn create_swapchain_system(
     mut commands: Commands,
     windows: Res<AppWindows>,
-    instance: Storage<ash::Instance>,
+    instance: StorageSingle<ash::Instance>,
     device: DeviceStorage,
     physical_device: Storage<vk::PhysicalDevice>,
-    surface_pack: Storage<SurfacePack>,
+    surface_pack: StorageSingle<SurfacePack>,
     queue_family_indices: Res<QueueFamilyIndices>,
     images: StorageHandledMut<vk::Image>,
     image_views: StorageHandledMut<vk::ImageView>,
-) {
-    let (swapchain_device, swapchain, swapchain_image_format, swapchain_extent) = create_swapchain(
-        &instance,
-        &device,
+) -> Result<(), BevyError> {
+    let device = device.device();
+    let (surface_instance, surface) = surface_pack.try_get()?;
+
+    let SwapchainParts {
+        device: swapchain_device,
+        swapchain,
+        format: swapchain_image_format,
+        extent: swapchain_extent,
+        ..
+    } = create_swapchain(
+        instance.try_get()?,
+        device,
         **physical_device,
-        &surface_pack.0,
-        surface_pack.1,
+        (surface_instance, *surface),
         windows.primary.inner_size(),
         *queue_family_indices,
+        &SwapchainConfig::default(),
     );
     let swapchain_images = unsafe { swapchain_device.get_swapchain_images(swapchain).unwrap() };
     let swapchain_image_views =
-        create_image_views(&device, &swapchain_images, swapchain_image_format);
+        create_image_views(device, &swapchain_images, swapchain_image_format);
+
+    Ok(())
 }
 
-fn render_frame(
-    mut vulkan_app: ResMut<VulkanApp>,
-    windows: Res<AppWindows>,
-    mut raw_winit_events: EventReader<RawWnitWindowEvent>,
-    mut maximization_state: Local<Option<bool>>,
+/// The primary window and its events, read by [`render_frame`].
+#[derive(SystemParam)]
+struct PrimaryWindowEvents<'w, 's> {
+    state: Res<'w, PrimaryWindowState>,
+    raw_events: EventReader<'w, 's, RawWnitWindowEvent>,
+    maximization_state: Local<'s, Option<bool>>,
+}
+
+/// [`FrameStats`] filled by [`render_frame`] when [`CollectFrameStats`] is enabled.
+#[derive(SystemParam)]
+struct FrameStatsParam<'w> {
+    collect: Res<'w, CollectFrameStats>,
+    stats: ResMut<'w, FrameStats>,
+}
+
+impl FrameStatsParam<'_> {
+    fn collected(&mut self) -> Option<&mut FrameStats> {
+        self.collect.0.then(|| self.stats.as_mut())
+    }
+}
+
+fn render_frame<R: Renderer>(
+    mut renderer: ResMut<R>,
+    mut window_events: PrimaryWindowEvents,
+    camera: Res<Camera>,
     mut swapchain_ok: Local<Option<bool>>,
     mut first_run: FirstRun,
+    mut errors: RenderErrors,
+    mut frame_stats: FrameStatsParam,
 ) {
+    let window = &*window_events.state;
     let swapchain_ok = swapchain_ok.get_or_insert(true);
 
-    let primary_window = &windows.primary;
     // let was_resized = raw_winit_events
     //     .read()
     //     .any(|RawWnitWindowEvent { event, window_id }| {
@@ -1384,16 +3751,31 @@ fn render_frame(
     //     });
 
     if !first_run.is_first_run() {
-        for event in raw_winit_events.read() {
-            let WindowEvent::Resized(size) = event.event else {
+        // Only the last size matters when several resizes arrive in one frame.
+        let mut new_size = None;
+        for event in window_events.raw_events.read() {
+            if event.window_id != window.id {
                 continue;
-            };
+            }
+            // A scale factor change resizes the window in physical pixels,
+            // which isn't always followed by a `Resized` event.
+            match event.event {
+                WindowEvent::Resized(size) => new_size = Some(size),
+                WindowEvent::ScaleFactorChanged { .. } => new_size = Some(window.inner_size),
+                _ => {}
+            }
+        }
 
-            vulkan_app.resize(swapchain_ok, size);
+        if let Some(size) = new_size
+            && let Err(err) = renderer.resize(swapchain_ok, size)
+        {
+            errors.handle(err, &mut *renderer, swapchain_ok, window.inner_size);
+            return;
         }
     }
 
-    let is_maximized = primary_window.is_maximized();
+    let is_maximized = window.maximized;
+    let maximization_state = &mut *window_events.maximization_state;
     let was_maximized = match *maximization_state {
         Some(previous_state) => previous_state ^ is_maximized,
         None => is_maximized,
@@ -1404,5 +3786,445 @@ fn render_frame(
         info!("Maximized");
     }
 
-    vulkan_app.draw_frame(swapchain_ok);
+    let extent = renderer.swapchain_extent();
+    let aspect_ratio = extent.width as f32 / extent.height.max(1) as f32;
+    let stats = frame_stats.collected();
+    if let Err(err) = renderer.draw_frame(swapchain_ok, camera.view_projection(aspect_ratio), stats)
+    {
+        errors.handle(err, &mut *renderer, swapchain_ok, window.inner_size);
+        return;
+    }
+    errors.frame_rendered();
+    check_validation_errors();
+
+    if renderer.take_recreate_request() {
+        let size = window.inner_size;
+        if let Err(err) = renderer.resize(swapchain_ok, size) {
+            errors.handle(err, &mut *renderer, swapchain_ok, size);
+        }
+    }
+}
+
+/// Recreates the swapchain when one of its options changed.
+fn update_swapchain_config_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    (image_count, mut image_count_change): (
+        Res<SwapchainImageCount>,
+        OnChange<SwapchainImageCount>,
+    ),
+    (clipped, mut clipped_change): (Res<SwapchainClipped>, OnChange<SwapchainClipped>),
+    (composite_alpha, mut composite_alpha_change): (Res<CompositeAlpha>, OnChange<CompositeAlpha>),
+    (present_mode, mut present_mode_change): (Res<PresentMode>, OnChange<PresentMode>),
+) {
+    if vulkan_app.surface.is_none() {
+        return;
+    }
+
+    if let Some(image_count) = image_count_change.changed(&image_count) {
+        vulkan_app.swapchain_config.image_count = *image_count;
+        vulkan_app.recreate_requested = true;
+    }
+    if let Some(clipped) = clipped_change.changed(&clipped) {
+        vulkan_app.swapchain_config.clipped = *clipped;
+        vulkan_app.recreate_requested = true;
+    }
+    if let Some(composite_alpha) = composite_alpha_change.changed(&composite_alpha) {
+        vulkan_app.swapchain_config.composite_alpha = *composite_alpha;
+        vulkan_app.recreate_requested = true;
+    }
+    if let Some(present_mode) = present_mode_change.changed(&present_mode) {
+        vulkan_app.swapchain_config.present_mode = *present_mode;
+        vulkan_app.recreate_requested = true;
+    }
+}
+
+/// Rebuilds the graphics pipelines when one of their options changed.
+fn update_raster_config_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    (depth_mode, mut depth_mode_change): (Res<DepthMode>, OnChange<DepthMode>),
+    (cull, mut cull_change): (Res<CullConfig>, OnChange<CullConfig>),
+) {
+    let mut rebuild = false;
+    if let Some(depth_mode) = depth_mode_change.changed(&depth_mode) {
+        vulkan_app.raster_config.depth_mode = *depth_mode;
+        rebuild = true;
+    }
+    if let Some(cull) = cull_change.changed(&cull) {
+        vulkan_app.raster_config.cull = *cull;
+        rebuild = true;
+    }
+
+    if rebuild {
+        vulkan_app.rebuild_pipelines();
+    }
+}
+
+fn update_command_pool_reset_mode_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    mode: Res<CommandPoolResetMode>,
+) {
+    if vulkan_app.frame_commands.mode() != *mode {
+        vulkan_app.set_command_pool_reset_mode(*mode);
+    }
+}
+
+fn capture_screenshots_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    mut screenshots: EventReader<Screenshot>,
+) {
+    for Screenshot { path } in screenshots.read() {
+        if let Err(err) = vulkan_app.capture_frame(path.clone()) {
+            error!("Failed to capture `{}`: {err}", path.display());
+        }
+    }
+}
+
+/// Recovers [`render_frame`] from a lost device and exits on other errors.
+#[derive(SystemParam)]
+struct RenderErrors<'w, 's> {
+    commands: Commands<'w, 's>,
+    rebuilds: Local<'s, DeviceRebuilds>,
+    device_lost: EventWriter<'w, DeviceLost>,
+    app_exit: EventWriter<'w, AppExit>,
+}
+
+impl RenderErrors<'_, '_> {
+    /// Rebuilds the device when it was lost, up to [`device_lost::MAX_DEVICE_REBUILDS`]
+    /// times in a row, otherwise exits.
+    fn handle(
+        &mut self,
+        err: VulkanError,
+        renderer: &mut impl Renderer,
+        swapchain_ok: &mut bool,
+        size: PhysicalSize<u32>,
+    ) {
+        if err != VulkanError::DeviceLost {
+            error!("Rendering failed, exiting: {err}");
+            self.app_exit.write(AppExit::error());
+            return;
+        }
+
+        let Some(attempt) = self.rebuilds.next_attempt() else {
+            error!("Vulkan device was lost again after rebuilding it, exiting");
+            self.app_exit.write(AppExit::error());
+            return;
+        };
+
+        warn!("Vulkan device was lost, rebuilding it (attempt {attempt})");
+        let render_device = renderer.rebuild_device(size);
+        *swapchain_ok = true;
+
+        if let Some(render_device) = render_device {
+            self.commands.insert_resource(render_device);
+        }
+        self.device_lost.write(DeviceLost { attempt });
+    }
+
+    fn frame_rendered(&mut self) {
+        self.rebuilds.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy_app::App;
+
+    use super::*;
+    use crate::rendering::raster::{CullMode, FrontFace};
+
+    #[test]
+    fn attachments_share_the_sample_count() {
+        let formats = AttachmentFormats {
+            color: vk::Format::B8G8R8A8_SRGB,
+            depth: vk::Format::D32_SFLOAT,
+            samples: vk::SampleCountFlags::TYPE_4,
+            depth_resolve: None,
+        };
+
+        let [color, depth, resolve] =
+            render_pass_attachments(formats, vk::ImageLayout::PRESENT_SRC_KHR)[..]
+        else {
+            panic!("a multisampled color attachment is resolved");
+        };
+        assert_eq!(color.samples, vk::SampleCountFlags::TYPE_4);
+        assert_eq!(depth.samples, color.samples);
+        assert_eq!(color.store_op, vk::AttachmentStoreOp::DONT_CARE);
+        assert_eq!(resolve.samples, vk::SampleCountFlags::TYPE_1);
+        assert_eq!(resolve.final_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+
+        let single_sampled = AttachmentFormats {
+            samples: vk::SampleCountFlags::TYPE_1,
+            ..formats
+        };
+        let attachments = render_pass_attachments(single_sampled, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(
+            attachments[0].final_layout,
+            vk::ImageLayout::PRESENT_SRC_KHR
+        );
+
+        let render_pass = PipelineTarget::new(Some(vk::RenderPass::null()), formats);
+        assert_eq!(render_pass.samples(), formats.samples);
+        assert_eq!(
+            PipelineTarget::new(None, formats).samples(),
+            formats.samples
+        );
+    }
+
+    #[test]
+    fn multisampled_framebuffers_resolve_into_the_image() {
+        let image = vk::Handle::from_raw(1);
+        let depth = vk::Handle::from_raw(2);
+        let msaa_color = vk::Handle::from_raw(3);
+
+        let resolved_depth = vk::Handle::from_raw(4);
+
+        assert_eq!(
+            framebuffer_attachments(image, depth, None, None),
+            [image, depth]
+        );
+        assert_eq!(
+            framebuffer_attachments(image, depth, None, Some(msaa_color)),
+            [msaa_color, depth, image]
+        );
+        assert_eq!(
+            framebuffer_attachments(image, depth, Some(resolved_depth), Some(msaa_color)),
+            [msaa_color, depth, image, resolved_depth]
+        );
+    }
+
+    #[test]
+    fn depth_is_resolved_when_the_sample_count_changes() {
+        let supported = vk::ResolveModeFlags::SAMPLE_ZERO | vk::ResolveModeFlags::MAX;
+        let formats = |samples| AttachmentFormats {
+            color: vk::Format::B8G8R8A8_SRGB,
+            depth: vk::Format::D32_SFLOAT,
+            samples,
+            depth_resolve: depth_resolve_mode(samples, supported),
+        };
+
+        let single_sampled = formats(SampleCount::X1.flags());
+        assert_eq!(single_sampled.depth_resolve, None);
+
+        for samples in [SampleCount::X2, SampleCount::X4, SampleCount::X8] {
+            let formats = formats(samples.flags());
+            assert_eq!(
+                formats.depth_resolve,
+                Some(vk::ResolveModeFlags::SAMPLE_ZERO)
+            );
+
+            let attachments = depth_resolve_attachments(formats, vk::ImageLayout::PRESENT_SRC_KHR);
+            let [color, depth, resolve, resolved_depth] = attachments[..] else {
+                panic!("{samples:?} resolves both color and depth");
+            };
+            assert_eq!(color.samples, samples.flags());
+            assert_eq!(depth.samples, color.samples);
+            assert_eq!(resolve.samples, vk::SampleCountFlags::TYPE_1);
+            assert_eq!(resolved_depth.samples, vk::SampleCountFlags::TYPE_1);
+            assert_eq!(resolved_depth.format, formats.depth);
+            assert_eq!(resolved_depth.store_op, vk::AttachmentStoreOp::STORE);
+        }
+    }
+
+    #[test]
+    fn spirv_size_must_be_a_multiple_of_4() {
+        let mut code = SPIRV_MAGIC.to_le_bytes().to_vec();
+        code.push(0);
+        assert_eq!(spirv_words(&code), Err(VulkanError::SpirvSize(5)));
+        assert_eq!(spirv_words(&[]), Err(VulkanError::SpirvSize(0)));
+    }
+
+    #[test]
+    fn spirv_must_start_with_the_magic_number() {
+        let code = SPIRV_MAGIC.to_be_bytes();
+        assert_eq!(
+            spirv_words(&code),
+            Err(VulkanError::SpirvMagic(SPIRV_MAGIC.swap_bytes()))
+        );
+
+        let mut code = SPIRV_MAGIC.to_le_bytes().to_vec();
+        code.extend(0x0001_0000u32.to_le_bytes());
+        assert_eq!(spirv_words(&code), Ok(vec![SPIRV_MAGIC, 0x0001_0000]));
+    }
+
+    #[test]
+    fn embedded_shaders_are_spirv() {
+        for code in [
+            GraphicsPipelineDesc::CHUNKS.vertex_shader,
+            GraphicsPipelineDesc::CHUNKS.fragment_shader,
+        ] {
+            assert!(spirv_words(code).is_ok());
+        }
+    }
+
+    #[test]
+    fn builder_inserts_resources() {
+        let mut app = App::new();
+        app.insert_resource(DebugGrid {
+            half_extent: 4,
+            ..Default::default()
+        });
+        app.add_plugins(
+            RenderingPlugin::default()
+                .with_anisotropy(AnisotropyLevel::X16)
+                .with_hdr(true)
+                .with_swapchain_image_count(3)
+                .with_frustum_culling(false)
+                .with_occlusion_culling(true)
+                .with_render_target_size(RenderTargetSize::Scaled)
+                .with_render_scale(0.5)
+                .with_auto_render_scale(true)
+                .with_fence_timeout(Duration::from_secs(1))
+                .with_command_pool_reset_mode(CommandPoolResetMode::PerPool),
+        );
+
+        let world = app.world();
+        assert_eq!(*world.resource::<AnisotropyLevel>(), AnisotropyLevel::X16);
+        assert_eq!(*world.resource::<HdrMode>(), HdrMode(true));
+        assert_eq!(
+            *world.resource::<SwapchainImageCount>(),
+            SwapchainImageCount(Some(3))
+        );
+        assert_eq!(*world.resource::<FrustumCulling>(), FrustumCulling(false));
+        assert_eq!(
+            *world.resource::<OcclusionCulling>(),
+            OcclusionCulling(true)
+        );
+        assert_eq!(
+            *world.resource::<RenderTargetSize>(),
+            RenderTargetSize::Scaled
+        );
+        assert_eq!(*world.resource::<RenderScale>(), RenderScale(0.5));
+        assert_eq!(*world.resource::<AutoRenderScale>(), AutoRenderScale(true));
+        assert_eq!(
+            *world.resource::<FenceTimeout>(),
+            FenceTimeout(Duration::from_secs(1))
+        );
+        assert_eq!(
+            *world.resource::<CommandPoolResetMode>(),
+            CommandPoolResetMode::PerPool
+        );
+
+        // Unset options keep the resources inserted before, or their defaults.
+        assert_eq!(world.resource::<DebugGrid>().half_extent, 4);
+        assert_eq!(
+            *world.resource::<DirectionalLight>(),
+            DirectionalLight::default()
+        );
+        assert_eq!(
+            *world.resource::<CollectFrameStats>(),
+            CollectFrameStats(false)
+        );
+    }
+
+    #[test]
+    fn every_builder_option_is_inserted() {
+        let sampler = SamplerConfig {
+            mag: vk::Filter::LINEAR,
+            ..SamplerConfig::NEAREST
+        };
+        let validation = ValidationConfig {
+            severities: vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            ..Default::default()
+        };
+        let formats = vec![vk::SurfaceFormatKHR {
+            format: vk::Format::R8G8B8A8_UNORM,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }];
+        let cull = CullConfig {
+            mode: CullMode::None,
+            front_face: FrontFace::Clockwise,
+        };
+        let grid = DebugGrid {
+            half_extent: 2,
+            ..Default::default()
+        };
+        let light = DirectionalLight {
+            color: glam::Vec3::X,
+            ..Default::default()
+        };
+
+        let mut app = App::new();
+        app.add_plugins(
+            RenderingPlugin::default()
+                .with_sampler(sampler)
+                .with_validation(validation)
+                .with_surface_formats(formats.clone())
+                .with_swapchain_clipped(false)
+                .with_composite_alpha(CompositeAlpha::PreMultiplied)
+                .with_present_mode(PresentMode::Fifo)
+                .with_msaa(SampleCount::X4)
+                .with_clear_color([0.0, 0.0, 0.0, 1.0])
+                .with_depth_mode(DepthMode::ReverseZ)
+                .with_cull(cull)
+                .with_flip_viewport_y(true)
+                .with_debug_grid(grid)
+                .with_light(light)
+                .with_frame_stats(true),
+        );
+
+        let world = app.world();
+        assert_eq!(*world.resource::<SamplerConfig>(), sampler);
+        assert_eq!(*world.resource::<ValidationConfig>(), validation);
+        assert_eq!(world.resource::<SurfaceFormatPreference>().0, formats);
+        assert_eq!(
+            *world.resource::<SwapchainClipped>(),
+            SwapchainClipped(false)
+        );
+        assert_eq!(
+            *world.resource::<CompositeAlpha>(),
+            CompositeAlpha::PreMultiplied
+        );
+        assert_eq!(*world.resource::<PresentMode>(), PresentMode::Fifo);
+        assert_eq!(*world.resource::<SampleCount>(), SampleCount::X4);
+        assert_eq!(
+            *world.resource::<ClearColor>(),
+            ClearColor([0.0, 0.0, 0.0, 1.0])
+        );
+        assert_eq!(*world.resource::<DepthMode>(), DepthMode::ReverseZ);
+        assert_eq!(*world.resource::<CullConfig>(), cull);
+        assert_eq!(*world.resource::<FlipViewportY>(), FlipViewportY(true));
+        assert_eq!(*world.resource::<DebugGrid>(), grid);
+        assert_eq!(*world.resource::<DirectionalLight>(), light);
+        assert_eq!(
+            *world.resource::<CollectFrameStats>(),
+            CollectFrameStats(true)
+        );
+    }
+
+    #[test]
+    fn cull_config_reaches_rasterization_state() {
+        let default = rasterization_state(
+            &GraphicsPipelineDesc::CHUNKS.with_raster(&RasterConfig::default()),
+        );
+        assert_eq!(default.cull_mode, vk::CullModeFlags::BACK);
+        assert_eq!(default.front_face, vk::FrontFace::COUNTER_CLOCKWISE);
+
+        let raster = RasterConfig {
+            cull: CullConfig {
+                mode: CullMode::None,
+                front_face: FrontFace::Clockwise,
+            },
+            ..Default::default()
+        };
+        let state = rasterization_state(&GraphicsPipelineDesc::CHUNKS.with_raster(&raster));
+        assert_eq!(state.cull_mode, vk::CullModeFlags::NONE);
+        assert_eq!(state.front_face, vk::FrontFace::CLOCKWISE);
+
+        let front = RasterConfig {
+            cull: CullConfig {
+                mode: CullMode::Front,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = rasterization_state(&GraphicsPipelineDesc::CHUNKS.with_raster(&front));
+        assert_eq!(state.cull_mode, vk::CullModeFlags::FRONT);
+        assert_eq!(
+            CullMode::FrontAndBack.flags(),
+            vk::CullModeFlags::FRONT_AND_BACK
+        );
+    }
 }
@@ -0,0 +1,123 @@
+use std::ffi::CStr;
+
+use ash::{Instance, khr, vk};
+use bevy_ecs::resource::Resource;
+
+use super::{dynamic_rendering::depth_aspect_mask, extensions::supports_device_extension};
+
+/// Device extensions resolving a multisampled depth attachment needs,
+/// `VK_KHR_depth_stencil_resolve` and the ones it depends on before Vulkan 1.2.
+pub const DEPTH_RESOLVE_EXTENSIONS: [&CStr; 4] = [
+    khr::depth_stencil_resolve::NAME,
+    khr::create_renderpass2::NAME,
+    khr::multiview::NAME,
+    khr::maintenance2::NAME,
+];
+
+/// Samples per pixel of the color and depth attachments frames are rendered
+/// to. Multisampled frames are resolved into the swapchain image, or into the
+/// scene target.
+///
+/// Read once when the [`VulkanApp`](super::VulkanApp) is created, and lowered
+/// to the highest count the device supports for both attachments.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SampleCount {
+    #[default]
+    X1,
+    X2,
+    X4,
+    X8,
+}
+
+impl SampleCount {
+    pub fn flags(self) -> vk::SampleCountFlags {
+        match self {
+            Self::X1 => vk::SampleCountFlags::TYPE_1,
+            Self::X2 => vk::SampleCountFlags::TYPE_2,
+            Self::X4 => vk::SampleCountFlags::TYPE_4,
+            Self::X8 => vk::SampleCountFlags::TYPE_8,
+        }
+    }
+
+    /// The highest count up to this one among `supported`. A single sample is
+    /// always supported.
+    pub fn supported(self, supported: vk::SampleCountFlags) -> Self {
+        [Self::X8, Self::X4, Self::X2]
+            .into_iter()
+            .find(|count| *count <= self && supported.contains(count.flags()))
+            .unwrap_or(Self::X1)
+    }
+}
+
+/// Sample counts both the color and the depth attachments can be created with.
+pub fn attachment_sample_counts(limits: &vk::PhysicalDeviceLimits) -> vk::SampleCountFlags {
+    limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts
+}
+
+/// Modes a depth attachment of `depth_format` can be resolved with, empty
+/// when the device lacks any of the [`DEPTH_RESOLVE_EXTENSIONS`].
+///
+/// The stencil of combined formats is resolved with the same mode, which its
+/// supported modes are intersected for.
+pub fn query_depth_resolve_modes(
+    instance: &Instance,
+    properties2: &khr::get_physical_device_properties2::Instance,
+    physical_device: vk::PhysicalDevice,
+    depth_format: vk::Format,
+) -> vk::ResolveModeFlags {
+    let supported = DEPTH_RESOLVE_EXTENSIONS
+        .iter()
+        .all(|name| supports_device_extension(instance, physical_device, name));
+    if !supported {
+        return vk::ResolveModeFlags::NONE;
+    }
+
+    let mut resolve_properties = vk::PhysicalDeviceDepthStencilResolveProperties::default();
+    let mut properties =
+        vk::PhysicalDeviceProperties2::default().push_next(&mut resolve_properties);
+    unsafe { properties2.get_physical_device_properties2(physical_device, &mut properties) };
+
+    if depth_aspect_mask(depth_format).contains(vk::ImageAspectFlags::STENCIL) {
+        resolve_properties.supported_depth_resolve_modes
+            & resolve_properties.supported_stencil_resolve_modes
+    } else {
+        resolve_properties.supported_depth_resolve_modes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_count_is_lowered_to_a_supported_one() {
+        let supported = vk::SampleCountFlags::TYPE_1
+            | vk::SampleCountFlags::TYPE_2
+            | vk::SampleCountFlags::TYPE_4;
+
+        assert_eq!(SampleCount::X4.supported(supported), SampleCount::X4);
+        assert_eq!(SampleCount::X8.supported(supported), SampleCount::X4);
+        assert_eq!(SampleCount::X2.supported(supported), SampleCount::X2);
+        assert_eq!(
+            SampleCount::X8.supported(vk::SampleCountFlags::TYPE_1),
+            SampleCount::X1
+        );
+    }
+
+    #[test]
+    fn both_attachments_must_support_the_count() {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_4
+                | vk::SampleCountFlags::TYPE_8,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_4,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            SampleCount::X8.supported(attachment_sample_counts(&limits)),
+            SampleCount::X4
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use ash::{Device, vk};
+use bevy_ecs::{
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use glam::{IVec3, Vec3};
+use gpu_allocator::vulkan::Allocator;
+use hashbrown::HashSet;
+use tracing::warn;
+
+use super::{
+    GraphicsPipelineDesc, MAX_FRAMES_IN_FLIGHT, PipelineTarget, VulkanApp, VulkanError,
+    buffer::{Buffer, create_device_local_buffer},
+    create_graphics_pipeline,
+    culling::{Aabb, Frustum},
+    debug_utils::set_debug_name,
+    mesh::{ChunkUniform, DrawItem, chunk_offset},
+    raster::RasterConfig,
+    recording::{ChunkDrawState, ThreadLocalCommandPools},
+    transfer::UploadQueues,
+    uniform_ring::DynamicUniformRing,
+};
+use crate::world::{chunk::CHUNK_SIZE, meshing::Vertex};
+
+/// Skips drawing chunks hidden behind other chunks, tested with occlusion
+/// queries on their bounding boxes. Disabled by default.
+///
+/// The boxes are drawn without writing anything after the chunks of a frame,
+/// and counted against their depth. Their results are only read once the
+/// frame in flight is reused, so chunks are drawn or skipped by how visible
+/// they were [`MAX_FRAMES_IN_FLIGHT`] frames ago. Chunks coming into view,
+/// e.g. when turning around a corner, may therefore be missing for as many
+/// frames.
+///
+/// Falls back to [`FrustumCulling`](super::FrustumCulling) only when the query
+/// pools can't be created.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OcclusionCulling(pub bool);
+
+/// How far the boxes reach past their chunk, so that the faces on the edge of
+/// a chunk don't hide its own box.
+const BOX_MARGIN: f32 = 0.5;
+
+/// Fewest queries a pool is created with.
+const MIN_QUERY_CAPACITY: u32 = 256;
+
+/// World-space box tested for the chunk at `coord`.
+pub fn query_box(coord: IVec3) -> Aabb {
+    let aabb = Aabb::of_chunk(coord);
+    Aabb {
+        min: aabb.min - BOX_MARGIN,
+        max: aabb.max + BOX_MARGIN,
+    }
+}
+
+/// Whether the chunk at `coord` can be queried. The box of a chunk reaching
+/// behind the near plane is clipped, possibly entirely while the camera is
+/// inside it, so such chunks are always drawn.
+pub fn is_queryable(frustum: &Frustum, coord: IVec3) -> bool {
+    let aabb = query_box(coord);
+    let [.., near, _] = frustum.planes;
+    let normal = near.truncate();
+    // The corner furthest against the normal.
+    let negative = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.min, aabb.max);
+    normal.dot(negative) + near.w >= 0.0
+}
+
+/// Triangle list of the [`query_box`] of a chunk, relative to the chunk origin.
+pub fn box_mesh() -> (Vec<Vertex>, Vec<u32>) {
+    let (min, max) = (-BOX_MARGIN, CHUNK_SIZE as f32 + BOX_MARGIN);
+    let vertices = (0..8)
+        .map(|corner| Vertex {
+            position: [
+                if corner & 1 == 0 { min } else { max },
+                if corner & 2 == 0 { min } else { max },
+                if corner & 4 == 0 { min } else { max },
+            ],
+            color: [0.0; 3],
+            uv: [0.0; 2],
+            ao: 1.0,
+            normal: [0.0; 3],
+            layer: 0,
+        })
+        .collect();
+
+    // Two triangles per face, the boxes aren't culled so winding doesn't matter.
+    #[rustfmt::skip]
+    let indices = vec![
+        0, 1, 3, 0, 3, 2, // -Z
+        4, 5, 7, 4, 7, 6, // +Z
+        0, 1, 5, 0, 5, 4, // -Y
+        2, 3, 7, 2, 7, 6, // +Y
+        0, 2, 6, 0, 6, 4, // -X
+        1, 3, 7, 1, 7, 5, // +X
+    ];
+
+    (vertices, indices)
+}
+
+/// Chunks whose query passed no samples, `results` being in the order of `coords`.
+pub fn hidden_chunks(coords: &[IVec3], results: &[u32]) -> HashSet<IVec3> {
+    coords
+        .iter()
+        .zip(results)
+        .filter(|&(_, samples)| *samples == 0)
+        .map(|(coord, _)| *coord)
+        .collect()
+}
+
+/// Number of queries a pool is created with to fit `count` of them.
+pub fn query_capacity(count: usize) -> u32 {
+    (count as u32).next_power_of_two().max(MIN_QUERY_CAPACITY)
+}
+
+/// Queries of one frame in flight.
+struct FrameQueries {
+    pool: vk::QueryPool,
+    capacity: u32,
+    /// Chunk of every query recorded, by query index.
+    coords: Vec<IVec3>,
+}
+
+fn create_query_pool(device: &Device, capacity: u32) -> Result<vk::QueryPool, VulkanError> {
+    let create_info = vk::QueryPoolCreateInfo::default()
+        .query_type(vk::QueryType::OCCLUSION)
+        .query_count(capacity);
+    let pool = unsafe { device.create_query_pool(&create_info, None)? };
+    set_debug_name(device, pool, "chunk occlusion queries");
+    Ok(pool)
+}
+
+/// Query pools, box mesh and depth-only pipeline of [`OcclusionCulling`].
+pub struct OcclusionQueries {
+    frames: Vec<FrameQueries>,
+    /// Chunks hidden according to the last results read.
+    hidden: HashSet<IVec3>,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    index_count: u32,
+    /// Single recording thread, the boxes are cheap to record.
+    pools: ThreadLocalCommandPools,
+}
+
+impl OcclusionQueries {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        queues: &UploadQueues,
+        target: PipelineTarget,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        raster: &RasterConfig,
+    ) -> Result<Self, VulkanError> {
+        let mut frames = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            match create_query_pool(device, MIN_QUERY_CAPACITY) {
+                Ok(pool) => frames.push(FrameQueries {
+                    pool,
+                    capacity: MIN_QUERY_CAPACITY,
+                    coords: Vec::new(),
+                }),
+                Err(err) => {
+                    for frame in &frames {
+                        unsafe { device.destroy_query_pool(frame.pool, None) };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let (vertices, indices) = box_mesh();
+        let vertex_buffer = create_device_local_buffer(
+            device,
+            allocator,
+            queues,
+            "occlusion box vertices",
+            &vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        let index_buffer = create_device_local_buffer(
+            device,
+            allocator,
+            queues,
+            "occlusion box indices",
+            &indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        );
+
+        let (pipeline, pipeline_layout) = create_graphics_pipeline::<Vertex>(
+            device,
+            target,
+            descriptor_set_layout,
+            &box_desc(raster),
+        );
+        set_debug_name(device, pipeline, "occlusion box pipeline");
+
+        Ok(Self {
+            frames,
+            hidden: HashSet::new(),
+            pipeline,
+            pipeline_layout,
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            pools: ThreadLocalCommandPools::new(device, queues.graphics.family, 1),
+        })
+    }
+
+    /// Replaces the hidden chunks with the results of the queries last
+    /// recorded for `frame`, whose submission must have completed.
+    pub fn read_results(&mut self, device: &Device, frame: usize) {
+        let queries = &mut self.frames[frame];
+        let coords = std::mem::take(&mut queries.coords);
+        if coords.is_empty() {
+            self.hidden.clear();
+            return;
+        }
+
+        let mut results = vec![0u32; coords.len()];
+        let read = unsafe {
+            device.get_query_pool_results(
+                queries.pool,
+                0,
+                &mut results,
+                vk::QueryResultFlags::empty(),
+            )
+        };
+        self.hidden = match read {
+            Ok(()) => hidden_chunks(&coords, &results),
+            // E.g. the frame wasn't submitted, draw everything until the next results.
+            Err(_) => HashSet::new(),
+        };
+    }
+
+    pub fn is_hidden(&self, coord: IVec3) -> bool {
+        self.hidden.contains(&coord)
+    }
+
+    /// Sets the chunks queried by `frame`, growing its pool to fit them. The
+    /// previous submission of `frame` must have completed.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        frame: usize,
+        coords: Vec<IVec3>,
+    ) -> Result<(), VulkanError> {
+        let queries = &mut self.frames[frame];
+        if coords.len() > queries.capacity as usize {
+            let capacity = query_capacity(coords.len());
+            let pool = create_query_pool(device, capacity)?;
+            unsafe { device.destroy_query_pool(queries.pool, None) };
+            queries.pool = pool;
+            queries.capacity = capacity;
+        }
+
+        queries.coords = coords;
+        Ok(())
+    }
+
+    /// Queries of `frame` to reset before its render pass, if there are any.
+    pub fn reset_range(&self, frame: usize) -> Option<(vk::QueryPool, u32)> {
+        let queries = &self.frames[frame];
+        (!queries.coords.is_empty()).then_some((queries.pool, queries.coords.len() as u32))
+    }
+
+    /// Records the boxes of the chunks prepared for `frame` into a secondary
+    /// command buffer, using the target and camera of the chunk draws.
+    pub fn record(
+        &self,
+        device: &Device,
+        frame: usize,
+        chunk_state: &ChunkDrawState,
+        chunk_uniforms: &mut DynamicUniformRing,
+    ) -> vk::CommandBuffer {
+        let queries = &self.frames[frame];
+        let state = ChunkDrawState {
+            pipeline: self.pipeline,
+            pipeline_layout: self.pipeline_layout,
+            query_pool: queries.pool,
+            label: "OcclusionBoxes",
+            ..*chunk_state
+        };
+        // Boxes that don't fit in the ring aren't drawn, their queries have
+        // no results and every chunk is drawn until the next ones.
+        let draws = queries
+            .coords
+            .iter()
+            .enumerate()
+            .map_while(|(query, coord)| {
+                Some(DrawItem {
+                    vertex_buffer: self.vertex_buffer.buffer,
+                    index_buffer: self.index_buffer.buffer,
+                    index_count: self.index_count,
+                    uniform_offset: chunk_uniforms
+                        .push(&ChunkUniform::new(chunk_offset(*coord)))?,
+                    query: Some(query as u32),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.pools
+            .record_on_current_thread(device, frame, &state, &draws)
+    }
+
+    /// Recreates the pipeline with the options of `raster`. The device must be
+    /// idle.
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &Device,
+        target: PipelineTarget,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        raster: &RasterConfig,
+    ) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+
+        (self.pipeline, self.pipeline_layout) = create_graphics_pipeline::<Vertex>(
+            device,
+            target,
+            descriptor_set_layout,
+            &box_desc(raster),
+        );
+        set_debug_name(device, self.pipeline, "occlusion box pipeline");
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        self.pools.destroy(device);
+        self.vertex_buffer.destroy(device, allocator);
+        self.index_buffer.destroy(device, allocator);
+        unsafe {
+            for frame in &self.frames {
+                device.destroy_query_pool(frame.pool, None);
+            }
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Both sides of the boxes are tested, in the depth mode of the chunks.
+fn box_desc(raster: &RasterConfig) -> GraphicsPipelineDesc {
+    GraphicsPipelineDesc {
+        cull_mode: vk::CullModeFlags::NONE,
+        ..GraphicsPipelineDesc::OCCLUSION_BOXES.with_raster(raster)
+    }
+}
+
+pub fn update_occlusion_culling_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    culling: Res<OcclusionCulling>,
+) {
+    if vulkan_app.occlusion_culling != culling.0 {
+        if culling.0 && vulkan_app.occlusion_queries.is_none() {
+            warn!("Occlusion queries are unavailable, chunks are only frustum culled");
+        }
+        vulkan_app.occlusion_culling = culling.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    #[test]
+    fn boxes_enclose_their_chunk() {
+        let (vertices, indices) = box_mesh();
+        let size = CHUNK_SIZE as f32;
+        assert_eq!(indices.len(), 36);
+        assert!(
+            indices
+                .iter()
+                .all(|index| (*index as usize) < vertices.len())
+        );
+        assert!(
+            vertices
+                .iter()
+                .flat_map(|vertex| vertex.position)
+                .all(|coordinate| coordinate == -BOX_MARGIN || coordinate == size + BOX_MARGIN)
+        );
+
+        let aabb = query_box(IVec3::new(1, 0, -1));
+        assert_eq!(aabb.min, Vec3::new(size, 0.0, -size) - BOX_MARGIN);
+        assert_eq!(aabb.max, Vec3::new(2.0 * size, size, 0.0) + BOX_MARGIN);
+    }
+
+    #[test]
+    fn chunks_around_the_camera_are_not_queried() {
+        let camera = Camera {
+            position: Vec3::new(CHUNK_SIZE as f32 / 2.0, CHUNK_SIZE as f32 / 2.0, 0.25),
+            yaw: 0.0,
+            pitch: 0.0,
+            ..Default::default()
+        };
+        let frustum = Frustum::from_view_projection(camera.view_projection(1.0));
+
+        assert!(!is_queryable(&frustum, IVec3::ZERO));
+        // Contains the camera through the margin of its box.
+        assert!(!is_queryable(&frustum, IVec3::new(0, 0, -1)));
+        assert!(is_queryable(&frustum, IVec3::new(0, 0, -2)));
+    }
+
+    #[test]
+    fn chunks_without_samples_are_hidden() {
+        let coords = [IVec3::ZERO, IVec3::X, IVec3::Y];
+        let hidden = hidden_chunks(&coords, &[0, 12, 0]);
+        assert_eq!(hidden, HashSet::from([IVec3::ZERO, IVec3::Y]));
+    }
+
+    #[test]
+    fn pools_grow_in_powers_of_two() {
+        assert_eq!(query_capacity(0), MIN_QUERY_CAPACITY);
+        assert_eq!(query_capacity(300), 512);
+        assert_eq!(query_capacity(1024), 1024);
+    }
+}
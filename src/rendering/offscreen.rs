@@ -0,0 +1,242 @@
+use ash::{Device, vk};
+use gpu_allocator::{MemoryLocation, vulkan::Allocator};
+
+use super::{
+    buffer::{Buffer, create_buffer},
+    image::{Image, ImageDesc, create_image, create_image_view},
+    transfer::QueueContext,
+};
+
+/// Format of the offscreen color image, read back as RGBA bytes.
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// Color image rendered to instead of a swapchain, with a host-visible buffer
+/// it is copied into for reading.
+pub struct OffscreenTarget {
+    pub image: Image,
+    pub readback: Buffer,
+    pub extent: vk::Extent2D,
+}
+
+impl OffscreenTarget {
+    pub fn new(device: &Device, allocator: &mut Allocator, extent: vk::Extent2D) -> Self {
+        let desc = ImageDesc::new(
+            extent,
+            OFFSCREEN_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        );
+        let image = create_image(device, allocator, "offscreen color", &desc);
+
+        let readback = create_buffer(
+            device,
+            allocator,
+            "offscreen readback",
+            byte_size(extent),
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        );
+
+        Self {
+            image,
+            readback,
+            extent,
+        }
+    }
+
+    pub fn create_view(&self, device: &Device) -> vk::ImageView {
+        create_image_view(
+            device,
+            self.image.image,
+            OFFSCREEN_FORMAT,
+            vk::ImageAspectFlags::COLOR,
+            1,
+        )
+    }
+
+    /// Copies the image into the readback buffer and returns its RGBA bytes,
+    /// row by row.
+    ///
+    /// The image must be in `TRANSFER_SRC_OPTIMAL`, which the render pass
+    /// leaves it in.
+    pub fn read(&self, device: &Device, graphics: &QueueContext) -> Vec<u8> {
+        graphics.submit_once(device, |command_buffer| {
+            let region = vk::BufferImageCopy::default()
+                .buffer_offset(0)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1),
+                )
+                .image_extent(self.extent.into());
+
+            // Order the copy after the color writes of the rendered frame.
+            let image_barrier = vk::ImageMemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image.image)
+                .subresource_range(
+                    vk::ImageSubresourceRange::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .base_mip_level(0)
+                        .level_count(1)
+                        .base_array_layer(0)
+                        .layer_count(1),
+                );
+
+            let buffer_barrier = vk::BufferMemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(self.readback.buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE);
+
+            unsafe {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[image_barrier],
+                );
+
+                device.cmd_copy_image_to_buffer(
+                    command_buffer,
+                    self.image.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    self.readback.buffer,
+                    &[region],
+                );
+
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[buffer_barrier],
+                    &[],
+                );
+            }
+        });
+
+        self.readback
+            .allocation
+            .mapped_slice()
+            .expect("Readback memory must be host visible")[..byte_size(self.extent) as usize]
+            .to_vec()
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        self.readback.destroy(device, allocator);
+        self.image.destroy(device, allocator);
+    }
+}
+
+fn byte_size(extent: vk::Extent2D) -> vk::DeviceSize {
+    extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::{VulkanApp, triangle::create_triangle_pipeline};
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn renders_triangle_headless() {
+        // An odd size puts the center of the middle pixel exactly at the origin.
+        let extent = vk::Extent2D {
+            width: 65,
+            height: 65,
+        };
+        let app = VulkanApp::new_headless(extent).unwrap();
+
+        let render_pass = app.render_pass.unwrap();
+        let (pipeline, pipeline_layout) = create_triangle_pipeline(&app.device, render_pass);
+
+        app.upload_queues()
+            .graphics
+            .submit_once(&app.device, |command_buffer| {
+                let clear_values = [
+                    vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: [0.0, 0.0, 0.0, 1.0],
+                        },
+                    },
+                    vk::ClearValue {
+                        depth_stencil: vk::ClearDepthStencilValue {
+                            depth: 1.0,
+                            stencil: 0,
+                        },
+                    },
+                ];
+                let render_pass_info = vk::RenderPassBeginInfo::default()
+                    .render_pass(render_pass)
+                    .framebuffer(app.swapchain_framebuffers[0])
+                    .render_area(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .clear_values(&clear_values);
+                let viewport = vk::Viewport::default()
+                    .width(extent.width as f32)
+                    .height(extent.height as f32)
+                    .max_depth(1.0);
+                let scissor = vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                };
+
+                unsafe {
+                    app.device.cmd_begin_render_pass(
+                        command_buffer,
+                        &render_pass_info,
+                        vk::SubpassContents::INLINE,
+                    );
+                    app.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    app.device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+                    app.device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+                    app.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+                    app.device.cmd_end_render_pass(command_buffer);
+                }
+            });
+
+        let pixels = app.read_framebuffer();
+        assert_eq!(pixels.len(), 65 * 65 * 4);
+
+        // The origin interpolates the red top vertex at one half and the
+        // green and blue bottom vertices at one quarter each.
+        let center = ((32 * extent.width + 32) * 4) as usize;
+        let expected = [128, 64, 64, 255];
+        for (channel, (actual, expected)) in
+            pixels[center..center + 4].iter().zip(expected).enumerate()
+        {
+            assert!(
+                actual.abs_diff(expected) <= 2,
+                "channel {channel} is {actual}, expected {expected}"
+            );
+        }
+
+        unsafe {
+            app.device.destroy_pipeline(pipeline, None);
+            app.device.destroy_pipeline_layout(pipeline_layout, None);
+        }
+        app.destroy();
+    }
+}
@@ -0,0 +1,99 @@
+use std::ffi::{CStr, c_char};
+
+use ash::{Instance, khr, vk};
+
+use super::REQUIRED_DEVICE_EXTENSIONS;
+
+/// Instance extensions enabled when available to run on top of a
+/// non-conformant implementation like MoltenVK.
+///
+/// Only requested on macOS, elsewhere portability devices aren't wanted.
+pub fn portability_instance_extensions() -> &'static [&'static CStr] {
+    if cfg!(target_os = "macos") {
+        &[khr::portability_enumeration::NAME]
+    } else {
+        &[]
+    }
+}
+
+/// Flags an instance with the `enabled` extensions is created with.
+///
+/// Portability devices are only listed with `ENUMERATE_PORTABILITY_KHR`.
+pub fn instance_create_flags(enabled: &[&CStr]) -> vk::InstanceCreateFlags {
+    if enabled.contains(&khr::portability_enumeration::NAME) {
+        vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
+    } else {
+        vk::InstanceCreateFlags::empty()
+    }
+}
+
+/// Whether the device advertises `VK_KHR_portability_subset`, which must then be enabled.
+pub fn supports_portability_subset(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> bool {
+    let extension_properties = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .unwrap()
+    };
+
+    extension_properties
+        .iter()
+        .any(|properties| properties.extension_name_as_c_str() == Ok(khr::portability_subset::NAME))
+}
+
+/// Extensions the logical device is created with.
+///
+/// Without a surface the swapchain extension isn't needed.
+pub fn device_extensions(surface: bool, portability_subset: bool) -> Vec<*const c_char> {
+    let mut extensions = if surface {
+        REQUIRED_DEVICE_EXTENSIONS.to_vec()
+    } else {
+        Vec::new()
+    };
+    if portability_subset {
+        extensions.push(khr::portability_subset::NAME.as_ptr());
+    }
+
+    extensions
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::ext;
+
+    use super::*;
+
+    #[test]
+    fn portability_enumeration_sets_the_flag() {
+        assert_eq!(
+            instance_create_flags(&[ext::debug_utils::NAME]),
+            vk::InstanceCreateFlags::empty()
+        );
+        assert_eq!(
+            instance_create_flags(&[ext::debug_utils::NAME, khr::portability_enumeration::NAME]),
+            vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
+        );
+    }
+
+    #[test]
+    fn portability_subset_is_appended() {
+        let names = |extensions: Vec<*const c_char>| {
+            extensions
+                .into_iter()
+                .map(|name| unsafe { CStr::from_ptr(name) })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(names(device_extensions(false, false)).is_empty());
+        assert_eq!(
+            names(device_extensions(true, true)),
+            [khr::swapchain::NAME, khr::portability_subset::NAME]
+        );
+        assert_eq!(
+            names(device_extensions(false, true)),
+            [khr::portability_subset::NAME]
+        );
+    }
+}
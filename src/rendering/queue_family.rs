@@ -0,0 +1,297 @@
+use std::fmt;
+
+use ash::{Instance, khr, vk};
+use bevy_ecs::resource::Resource;
+
+use super::{compute::pick_compute_family, transfer::pick_transfer_family};
+
+/// Queue families the device is created with.
+///
+/// The transfer and compute families are the graphics family when the
+/// device has no dedicated one, see [`pick_transfer_family`] and
+/// [`pick_compute_family`].
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFamilyIndices {
+    pub graphics_family: u32,
+    pub present_family: u32,
+    /// Family uploads run on.
+    pub transfer_family: u32,
+    /// Family compute work runs on.
+    pub compute_family: u32,
+}
+
+impl QueueFamilyIndices {
+    /// Transfer family other than the graphics family, if the device has one.
+    pub fn dedicated_transfer_family(&self) -> Option<u32> {
+        (self.transfer_family != self.graphics_family).then_some(self.transfer_family)
+    }
+
+    /// Whether the graphics family also presents, which lets the swapchain
+    /// images be used exclusively by one family.
+    pub fn shares_graphics_and_present(&self) -> bool {
+        self.graphics_family == self.present_family
+    }
+
+    /// Compute family other than the graphics family, if the device has one.
+    pub fn dedicated_compute_family(&self) -> Option<u32> {
+        (self.compute_family != self.graphics_family).then_some(self.compute_family)
+    }
+}
+
+impl fmt::Display for QueueFamilyIndices {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shared = |dedicated: Option<u32>| match dedicated {
+            Some(family) => family.to_string(),
+            None => "graphics".to_owned(),
+        };
+        write!(
+            f,
+            "graphics {}, present {}, transfer {}, compute {}",
+            self.graphics_family,
+            self.present_family,
+            shared(self.dedicated_transfer_family()),
+            shared(self.dedicated_compute_family()),
+        )
+    }
+}
+
+/// Families found so far while looking through the queue families of a device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueFamilySearch {
+    pub graphics_family: Option<u32>,
+    pub present_family: Option<u32>,
+}
+
+impl QueueFamilySearch {
+    /// Looks for a family that supports both graphics and presenting,
+    /// according to `supports_present`. Without one, the first graphics
+    /// family and the first family that can present are used.
+    pub fn run(
+        properties: &[vk::QueueFamilyProperties],
+        mut supports_present: impl FnMut(u32) -> bool,
+    ) -> Self {
+        let mut search = Self::default();
+
+        for (i, queue_family) in properties.iter().enumerate() {
+            let i = i as u32;
+            let graphics = queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
+            let present = supports_present(i);
+
+            if graphics && present {
+                return Self {
+                    graphics_family: Some(i),
+                    present_family: Some(i),
+                };
+            }
+            if graphics && search.graphics_family.is_none() {
+                search.graphics_family = Some(i);
+            }
+            if present && search.present_family.is_none() {
+                search.present_family = Some(i);
+            }
+        }
+
+        search
+    }
+
+    /// Whether both a graphics and a present family were found.
+    pub fn is_complete(&self) -> bool {
+        self.graphics_family.is_some() && self.present_family.is_some()
+    }
+
+    /// The families of a complete search, with the transfer and compute
+    /// families picked from `properties`.
+    pub fn finish(self, properties: &[vk::QueueFamilyProperties]) -> Option<QueueFamilyIndices> {
+        let (Some(graphics_family), Some(present_family)) =
+            (self.graphics_family, self.present_family)
+        else {
+            return None;
+        };
+
+        Some(QueueFamilyIndices {
+            graphics_family,
+            present_family,
+            transfer_family: pick_transfer_family(properties, graphics_family),
+            compute_family: pick_compute_family(properties, graphics_family),
+        })
+    }
+}
+
+/// Queue families of `physical_device`, or `None` if it can't render or
+/// present. Without a surface, the graphics family is used to present.
+pub fn find_queue_families(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    surface: Option<(&khr::surface::Instance, vk::SurfaceKHR)>,
+) -> Option<QueueFamilyIndices> {
+    let (search, properties) = search_queue_families(instance, physical_device, surface);
+    search.finish(&properties)
+}
+
+/// Same as [`find_queue_families`], but keeps the partial result and the
+/// properties of the families.
+pub fn search_queue_families(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    surface: Option<(&khr::surface::Instance, vk::SurfaceKHR)>,
+) -> (QueueFamilySearch, Vec<vk::QueueFamilyProperties>) {
+    let properties =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+
+    let search = QueueFamilySearch::run(&properties, |i| match surface {
+        Some((surface_instance, surface)) => unsafe {
+            surface_instance
+                .get_physical_device_surface_support(physical_device, i, surface)
+                .unwrap()
+        },
+        None => properties[i as usize]
+            .queue_flags
+            .contains(vk::QueueFlags::GRAPHICS),
+    });
+
+    (search, properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family(queue_flags: vk::QueueFlags) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties {
+            queue_flags,
+            queue_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn search_needs_graphics_and_present() {
+        let properties = [
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE),
+        ];
+
+        let no_present = QueueFamilySearch::run(&properties, |_| false);
+        assert_eq!(no_present.graphics_family, Some(1));
+        assert!(!no_present.is_complete());
+        assert_eq!(no_present.finish(&properties), None);
+
+        let compute_only = [family(vk::QueueFlags::COMPUTE)];
+        let no_graphics = QueueFamilySearch::run(&compute_only, |_| true);
+        assert_eq!(no_graphics.present_family, Some(0));
+        assert!(!no_graphics.is_complete());
+
+        let complete = QueueFamilySearch::run(&properties, |i| i == 0);
+        assert!(complete.is_complete());
+        assert_eq!(
+            complete.finish(&properties),
+            Some(QueueFamilyIndices {
+                graphics_family: 1,
+                present_family: 0,
+                transfer_family: 0,
+                compute_family: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn unified_family_is_preferred() {
+        let properties = [
+            family(vk::QueueFlags::GRAPHICS),
+            family(vk::QueueFlags::COMPUTE),
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE),
+        ];
+
+        // Family 0 renders and family 1 presents, but family 2 does both.
+        let search = QueueFamilySearch::run(&properties, |i| i != 0);
+        assert_eq!(search.graphics_family, Some(2));
+        assert_eq!(search.present_family, Some(2));
+        assert!(
+            search
+                .finish(&properties)
+                .unwrap()
+                .shares_graphics_and_present()
+        );
+
+        // Without a unified family, two different ones are used.
+        let search = QueueFamilySearch::run(&properties, |i| i == 1);
+        assert_eq!(search.graphics_family, Some(0));
+        assert_eq!(search.present_family, Some(1));
+        assert!(
+            !search
+                .finish(&properties)
+                .unwrap()
+                .shares_graphics_and_present()
+        );
+    }
+
+    #[test]
+    fn transfer_prefers_a_transfer_only_family() {
+        let properties = [
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::TRANSFER | vk::QueueFlags::SPARSE_BINDING),
+        ];
+        assert_eq!(pick_transfer_family(&properties, 0), 2);
+    }
+
+    #[test]
+    fn transfer_accepts_an_async_compute_family() {
+        let properties = [
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+        ];
+        assert_eq!(pick_transfer_family(&properties, 0), 1);
+    }
+
+    #[test]
+    fn transfer_falls_back_to_the_graphics_family() {
+        let properties = [
+            family(vk::QueueFlags::COMPUTE),
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+        ];
+        assert_eq!(pick_transfer_family(&properties, 1), 1);
+    }
+
+    #[test]
+    fn compute_prefers_an_async_compute_family() {
+        let properties = [
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+        ];
+        assert_eq!(pick_compute_family(&properties, 0), 2);
+    }
+
+    #[test]
+    fn compute_falls_back_to_the_graphics_family() {
+        let properties = [
+            family(vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE),
+        ];
+        assert_eq!(pick_compute_family(&properties, 1), 1);
+    }
+
+    #[test]
+    fn empty_device_has_no_families() {
+        let search = QueueFamilySearch::run(&[], |_| true);
+        assert_eq!(search, QueueFamilySearch::default());
+        assert!(!search.is_complete());
+    }
+
+    #[test]
+    fn display_names_shared_families() {
+        let indices = QueueFamilyIndices {
+            graphics_family: 0,
+            present_family: 0,
+            transfer_family: 2,
+            compute_family: 0,
+        };
+        assert_eq!(indices.dedicated_transfer_family(), Some(2));
+        assert_eq!(indices.dedicated_compute_family(), None);
+        assert_eq!(
+            indices.to_string(),
+            "graphics 0, present 0, transfer 2, compute graphics"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use ash::vk;
+use bevy_ecs::{
+    change_detection::DetectChanges,
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use glam::{Mat4, Vec3, Vec4};
+use tracing::warn;
+
+use super::VulkanApp;
+
+/// Fixed-function options of the graphics pipelines kept by the app for every
+/// pipeline rebuild, collected from their resources.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RasterConfig {
+    pub depth_mode: DepthMode,
+    pub cull: CullConfig,
+}
+
+/// Which triangles of the chunk meshes are culled, and which winding faces the
+/// camera. Lines are never culled.
+///
+/// Defaults to culling back faces of counter-clockwise meshes, which is how
+/// the mesher winds them. With the Y flip of [`Camera::projection`](crate::camera::Camera::projection)
+/// they are clockwise on screen, so flipping Y again, e.g. with a negative
+/// viewport height, needs the other [`FrontFace`]. [`FlipViewportY`] replaces
+/// the flip of the projection instead, which keeps the winding.
+///
+/// Changing it rebuilds the graphics pipelines. Disabling culling is useful
+/// when a mesh is invisible because its winding is backwards.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullConfig {
+    pub mode: CullMode,
+    pub front_face: FrontFace,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Front,
+    #[default]
+    Back,
+    FrontAndBack,
+}
+
+impl CullMode {
+    pub fn flags(self) -> vk::CullModeFlags {
+        match self {
+            Self::None => vk::CullModeFlags::NONE,
+            Self::Front => vk::CullModeFlags::FRONT,
+            Self::Back => vk::CullModeFlags::BACK,
+            Self::FrontAndBack => vk::CullModeFlags::FRONT_AND_BACK,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrontFace {
+    #[default]
+    CounterClockwise,
+    Clockwise,
+}
+
+impl FrontFace {
+    pub fn vk(self) -> vk::FrontFace {
+        match self {
+            Self::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+            Self::Clockwise => vk::FrontFace::CLOCKWISE,
+        }
+    }
+}
+
+/// How depth is mapped into the depth buffer.
+///
+/// Changing it rebuilds the graphics pipelines.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DepthMode {
+    /// The near plane at depth 0 and the far plane at 1.
+    #[default]
+    Standard,
+    /// The near plane at depth 1 and the far plane at 0.
+    ///
+    /// Floating point depth is the most precise close to 0, which this spends
+    /// on distant faces so they don't z-fight with large view distances. Needs
+    /// a floating point depth buffer, `D32_SFLOAT` is preferred when available.
+    ReverseZ,
+}
+
+impl DepthMode {
+    /// Passes fragments closer to the camera than the stored depth.
+    pub fn compare_op(self) -> vk::CompareOp {
+        match self {
+            Self::Standard => vk::CompareOp::LESS,
+            Self::ReverseZ => vk::CompareOp::GREATER,
+        }
+    }
+
+    /// Depth of the far plane, which the depth buffer is cleared to.
+    pub fn clear_depth(self) -> f32 {
+        match self {
+            Self::Standard => 1.0,
+            Self::ReverseZ => 0.0,
+        }
+    }
+
+    /// Maps the depth of a `[0, 1]` projection, like [`Camera::projection`](crate::camera::Camera::projection), to this mode.
+    pub fn apply(self, projection: Mat4) -> Mat4 {
+        match self {
+            Self::Standard => projection,
+            // `z' = w - z`, which is `1 - depth` after the perspective divide.
+            Self::ReverseZ => {
+                let reverse = Mat4::from_cols(
+                    Vec4::X,
+                    Vec4::Y,
+                    Vec4::new(0.0, 0.0, -1.0, 0.0),
+                    Vec4::new(0.0, 0.0, 1.0, 1.0),
+                );
+                reverse * projection
+            }
+        }
+    }
+}
+
+/// Flips Y with a negative viewport height instead of in the projection, so
+/// clip space is Y-up like glam's.
+///
+/// Needs `VK_KHR_maintenance1`, core since Vulkan 1.1, which is enabled when
+/// the device supports it. Ignored with a warning otherwise.
+///
+/// The flip moves every fragment to where the Y flip of [`Camera::projection`](crate::camera::Camera::projection)
+/// put it, so the on-screen winding and [`FrontFace`] stay the same.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlipViewportY(pub bool);
+
+/// Viewport covering `extent`, starting at the bottom edge when `flip_y` is set.
+pub fn viewport(extent: vk::Extent2D, flip_y: bool) -> vk::Viewport {
+    let (y, height) = if flip_y {
+        (extent.height as f32, -(extent.height as f32))
+    } else {
+        (0.0, extent.height as f32)
+    };
+
+    vk::Viewport::default()
+        .x(0.0)
+        .y(y)
+        .width(extent.width as f32)
+        .height(height)
+        .min_depth(0.0)
+        .max_depth(1.0)
+}
+
+/// Undoes the Y flip of the camera projection for a flipped viewport.
+pub fn unflip_projection(view_proj: Mat4) -> Mat4 {
+    Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0)) * view_proj
+}
+
+pub fn update_flip_viewport_y_system(mut vulkan_app: ResMut<VulkanApp>, flip: Res<FlipViewportY>) {
+    if flip.is_changed() && flip.0 && !vulkan_app.maintenance1 {
+        warn!("Flipping the viewport needs VK_KHR_maintenance1, which isn't supported");
+    }
+
+    let flip_viewport_y = flip.0 && vulkan_app.maintenance1;
+    if vulkan_app.flip_viewport_y != flip_viewport_y {
+        vulkan_app.flip_viewport_y = flip_viewport_y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    fn depth(mode: DepthMode, view_space: Vec3) -> f32 {
+        let camera = Camera {
+            near: 0.1,
+            far: 100.0,
+            ..Default::default()
+        };
+        mode.apply(camera.projection(1.0))
+            .project_point3(view_space)
+            .z
+    }
+
+    #[test]
+    fn standard_depth_range() {
+        let mode = DepthMode::Standard;
+        assert!(depth(mode, Vec3::new(0.0, 0.0, -0.1)).abs() < 1e-6);
+        assert!((depth(mode, Vec3::new(0.0, 0.0, -100.0)) - 1.0).abs() < 1e-6);
+        assert_eq!(mode.clear_depth(), 1.0);
+    }
+
+    #[test]
+    fn reverse_z_depth_range() {
+        let mode = DepthMode::ReverseZ;
+        assert!((depth(mode, Vec3::new(0.0, 0.0, -0.1)) - 1.0).abs() < 1e-6);
+        assert!(depth(mode, Vec3::new(0.0, 0.0, -100.0)).abs() < 1e-6);
+        assert_eq!(mode.clear_depth(), 0.0);
+
+        // Closer fragments have a greater depth.
+        let near = depth(mode, Vec3::new(0.0, 0.0, -10.0));
+        let far = depth(mode, Vec3::new(0.0, 0.0, -20.0));
+        assert!(near > far);
+        assert_eq!(mode.compare_op(), vk::CompareOp::GREATER);
+    }
+
+    #[test]
+    fn flipped_viewport_starts_at_the_bottom() {
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+
+        let viewport = viewport(extent, false);
+        assert_eq!((viewport.y, viewport.height), (0.0, 600.0));
+
+        let flipped = super::viewport(extent, true);
+        assert_eq!((flipped.x, flipped.width), (0.0, 800.0));
+        assert_eq!((flipped.y, flipped.height), (600.0, -600.0));
+    }
+
+    #[test]
+    fn flipped_viewport_matches_flipped_projection() {
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        // Framebuffer Y of a clip space point, as in the viewport transform.
+        let framebuffer_y = |viewport: vk::Viewport, view_proj: Mat4, point: Vec3| {
+            let ndc = view_proj.project_point3(point);
+            viewport.y + (ndc.y + 1.0) / 2.0 * viewport.height
+        };
+
+        let view_proj = Camera::default().view_projection(4.0 / 3.0);
+        let point = Camera::default().position + Vec3::new(1.0, 2.0, -10.0);
+        let flipped = framebuffer_y(viewport(extent, true), unflip_projection(view_proj), point);
+        assert!((framebuffer_y(viewport(extent, false), view_proj, point) - flipped).abs() < 1e-3);
+        // Above the camera is the upper half of the image.
+        assert!(flipped < 300.0);
+    }
+}
@@ -0,0 +1,398 @@
+use std::{ops::Range, thread};
+
+use ash::{Device, vk};
+use bevy_ecs::resource::Resource;
+use glam::Mat4;
+
+use super::{
+    MAX_FRAMES_IN_FLIGHT, VulkanError,
+    debug_utils::DebugLabel,
+    dynamic_rendering::AttachmentFormats,
+    mesh::{ChunkPushConstants, DrawItem},
+    raster::viewport,
+    uniform_ring::bind_with_dynamic_offset,
+};
+
+/// Maximum number of threads chunk draws are recorded on.
+pub const MAX_RECORDING_THREADS: usize = 4;
+
+/// What the secondary command buffers render into.
+#[derive(Debug, Clone, Copy)]
+pub enum InheritedTarget {
+    RenderPass {
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+    },
+    /// Rendering begun with `vkCmdBeginRendering`.
+    Dynamic(AttachmentFormats),
+}
+
+/// State shared by every chunk draw of a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkDrawState {
+    pub target: InheritedTarget,
+    pub extent: vk::Extent2D,
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub descriptor_set: vk::DescriptorSet,
+    /// Pool of the queries of the draws, null if they have none.
+    pub query_pool: vk::QueryPool,
+    pub view_proj: Mat4,
+    /// Whether the viewport has a negative height, see [`FlipViewportY`](super::FlipViewportY).
+    pub flip_viewport_y: bool,
+    /// Debug label around the draws, e.g. in RenderDoc captures.
+    pub label: &'static str,
+}
+
+/// How the primary command buffers of the frames are reset before recording.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommandPoolResetMode {
+    /// Every command buffer on its own with `vkResetCommandBuffer`.
+    #[default]
+    PerBuffer,
+    /// The whole pool of a frame at once with `vkResetCommandPool`, cheaper
+    /// when many command buffers are recorded per frame.
+    PerPool,
+}
+
+impl CommandPoolResetMode {
+    /// Flags of the pools, individual resets need `RESET_COMMAND_BUFFER`.
+    pub fn pool_flags(self) -> vk::CommandPoolCreateFlags {
+        match self {
+            Self::PerBuffer => vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            Self::PerPool => vk::CommandPoolCreateFlags::empty(),
+        }
+    }
+}
+
+/// One command pool with a primary command buffer per frame in flight, reset
+/// as set by its [`CommandPoolResetMode`].
+pub struct FrameCommandPools {
+    mode: CommandPoolResetMode,
+    pools: [vk::CommandPool; MAX_FRAMES_IN_FLIGHT],
+    buffers: [vk::CommandBuffer; MAX_FRAMES_IN_FLIGHT],
+}
+
+impl FrameCommandPools {
+    pub fn new(device: &Device, queue_family_index: u32, mode: CommandPoolResetMode) -> Self {
+        let pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(mode.pool_flags())
+            .queue_family_index(queue_family_index);
+
+        let pools = std::array::from_fn(|_| unsafe {
+            device.create_command_pool(&pool_info, None).unwrap()
+        });
+        let buffers = pools.map(|pool| {
+            let allocate_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+            unsafe { device.allocate_command_buffers(&allocate_info).unwrap()[0] }
+        });
+
+        Self {
+            mode,
+            pools,
+            buffers,
+        }
+    }
+
+    pub fn mode(&self) -> CommandPoolResetMode {
+        self.mode
+    }
+
+    pub fn buffer(&self, frame: usize) -> vk::CommandBuffer {
+        self.buffers[frame]
+    }
+
+    /// Resets the command buffer of `frame` for recording. The previous
+    /// submission of `frame` must have completed.
+    pub fn reset(&self, device: &Device, frame: usize) -> Result<vk::CommandBuffer, VulkanError> {
+        unsafe {
+            match self.mode {
+                CommandPoolResetMode::PerBuffer => device.reset_command_buffer(
+                    self.buffers[frame],
+                    vk::CommandBufferResetFlags::empty(),
+                )?,
+                CommandPoolResetMode::PerPool => device
+                    .reset_command_pool(self.pools[frame], vk::CommandPoolResetFlags::empty())?,
+            }
+        }
+        Ok(self.buffers[frame])
+    }
+
+    /// The pools, destroying them frees their command buffers.
+    pub fn pools(&self) -> [vk::CommandPool; MAX_FRAMES_IN_FLIGHT] {
+        self.pools
+    }
+
+    pub fn destroy(&self, device: &Device) {
+        for pool in self.pools {
+            unsafe { device.destroy_command_pool(pool, None) };
+        }
+    }
+}
+
+/// Command pools of the recording threads.
+///
+/// Command pools can't be used from several threads at once, so every
+/// recording thread owns one pool, and one secondary command buffer, per frame in flight.
+pub struct ThreadLocalCommandPools {
+    /// Indexed as `pools[thread][frame]`.
+    pools: Vec<[vk::CommandPool; MAX_FRAMES_IN_FLIGHT]>,
+    /// Indexed as `buffers[thread][frame]`.
+    buffers: Vec<[vk::CommandBuffer; MAX_FRAMES_IN_FLIGHT]>,
+}
+
+impl ThreadLocalCommandPools {
+    pub fn new(device: &Device, queue_family_index: u32, thread_count: usize) -> Self {
+        let pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue_family_index);
+
+        let pools = (0..thread_count.max(1))
+            .map(|_| {
+                std::array::from_fn(|_| unsafe {
+                    device.create_command_pool(&pool_info, None).unwrap()
+                })
+            })
+            .collect::<Vec<[vk::CommandPool; MAX_FRAMES_IN_FLIGHT]>>();
+
+        let buffers = pools
+            .iter()
+            .map(|frame_pools| {
+                frame_pools.map(|pool| {
+                    let allocate_info = vk::CommandBufferAllocateInfo::default()
+                        .command_pool(pool)
+                        .level(vk::CommandBufferLevel::SECONDARY)
+                        .command_buffer_count(1);
+                    unsafe { device.allocate_command_buffers(&allocate_info).unwrap()[0] }
+                })
+            })
+            .collect();
+
+        Self { pools, buffers }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Records `draws` into secondary command buffers of `frame`, split evenly
+    /// across the recording threads.
+    ///
+    /// The previous submission of `frame` must have completed.
+    pub fn record(
+        &self,
+        device: &Device,
+        frame: usize,
+        state: &ChunkDrawState,
+        draws: &[DrawItem],
+    ) -> Vec<vk::CommandBuffer> {
+        let ranges = split_draws(draws.len(), self.thread_count());
+
+        thread::scope(|scope| {
+            let handles = ranges
+                .into_iter()
+                .enumerate()
+                .map(|(thread_index, range)| {
+                    let pool = self.pools[thread_index][frame];
+                    let command_buffer = self.buffers[thread_index][frame];
+                    let draws = &draws[range];
+                    scope.spawn(move || {
+                        record_secondary(device, pool, command_buffer, state, draws);
+                        command_buffer
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Chunk draw recording panicked"))
+                .collect()
+        })
+    }
+
+    /// Records `draws` into the secondary command buffer of the first thread
+    /// for `frame` without spawning any thread.
+    pub fn record_on_current_thread(
+        &self,
+        device: &Device,
+        frame: usize,
+        state: &ChunkDrawState,
+        draws: &[DrawItem],
+    ) -> vk::CommandBuffer {
+        let command_buffer = self.buffers[0][frame];
+        record_secondary(device, self.pools[0][frame], command_buffer, state, draws);
+        command_buffer
+    }
+
+    pub fn destroy(&self, device: &Device) {
+        for pool in self.pools.iter().flatten() {
+            unsafe { device.destroy_command_pool(*pool, None) };
+        }
+    }
+}
+
+fn record_secondary(
+    device: &Device,
+    pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    state: &ChunkDrawState,
+    draws: &[DrawItem],
+) {
+    let mut inheritance_info = vk::CommandBufferInheritanceInfo::default();
+    let color_formats;
+    let mut rendering_info;
+    match state.target {
+        InheritedTarget::RenderPass {
+            render_pass,
+            framebuffer,
+        } => {
+            inheritance_info = inheritance_info
+                .render_pass(render_pass)
+                .subpass(0)
+                .framebuffer(framebuffer);
+        }
+        InheritedTarget::Dynamic(formats) => {
+            color_formats = [formats.color];
+            rendering_info = vk::CommandBufferInheritanceRenderingInfo::default()
+                .color_attachment_formats(&color_formats)
+                .depth_attachment_format(formats.depth)
+                .rasterization_samples(formats.samples);
+            inheritance_info = inheritance_info.push_next(&mut rendering_info);
+        }
+    }
+    let begin_info = vk::CommandBufferBeginInfo::default()
+        .flags(
+            vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+        )
+        .inheritance_info(&inheritance_info);
+
+    unsafe {
+        device
+            .reset_command_pool(pool, vk::CommandPoolResetFlags::empty())
+            .unwrap();
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .unwrap();
+
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            state.pipeline,
+        );
+
+        let push_constants = ChunkPushConstants {
+            view_proj: state.view_proj,
+        };
+        device.cmd_push_constants(
+            command_buffer,
+            state.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            bytemuck::bytes_of(&push_constants),
+        );
+
+        // Dynamic state isn't inherited from the primary command buffer.
+        let viewport = viewport(state.extent, state.flip_viewport_y);
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: state.extent,
+        };
+        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+
+        let label = DebugLabel::begin(device, command_buffer, state.label);
+        for draw in draws {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[draw.vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                draw.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            bind_with_dynamic_offset(
+                device,
+                command_buffer,
+                state.pipeline_layout,
+                0,
+                state.descriptor_set,
+                draw.uniform_offset,
+            );
+
+            if let Some(query) = draw.query {
+                device.cmd_begin_query(
+                    command_buffer,
+                    state.query_pool,
+                    query,
+                    vk::QueryControlFlags::empty(),
+                );
+            }
+            device.cmd_draw_indexed(command_buffer, draw.index_count, 1, 0, 0, 0);
+            if let Some(query) = draw.query {
+                device.cmd_end_query(command_buffer, state.query_pool, query);
+            }
+        }
+        drop(label);
+
+        device.end_command_buffer(command_buffer).unwrap();
+    }
+}
+
+/// Splits `len` draws into at most `threads` contiguous non-empty ranges of
+/// nearly equal size.
+fn split_draws(len: usize, threads: usize) -> Vec<Range<usize>> {
+    let threads = threads.clamp(1, len.max(1));
+    let base = len / threads;
+    let extra = len % threads;
+
+    let mut start = 0;
+    (0..threads)
+        .map(|thread| {
+            let end = start + base + usize::from(thread < extra);
+            let range = start..end;
+            start = end;
+            range
+        })
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_flags_match_the_reset_mode() {
+        assert_eq!(
+            CommandPoolResetMode::default(),
+            CommandPoolResetMode::PerBuffer
+        );
+        assert!(
+            CommandPoolResetMode::PerBuffer
+                .pool_flags()
+                .contains(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+        );
+        assert!(
+            !CommandPoolResetMode::PerPool
+                .pool_flags()
+                .contains(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+        );
+    }
+
+    #[test]
+    fn splits_draws_evenly() {
+        assert_eq!(split_draws(10, 4), vec![0..3, 3..6, 6..8, 8..10]);
+        assert_eq!(split_draws(8, 4), vec![0..2, 2..4, 4..6, 6..8]);
+    }
+
+    #[test]
+    fn fewer_draws_than_threads() {
+        assert_eq!(split_draws(2, 4), vec![0..1, 1..2]);
+        assert!(split_draws(0, 4).is_empty());
+    }
+}
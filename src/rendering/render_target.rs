@@ -0,0 +1,503 @@
+use std::time::Duration;
+
+use ash::{Device, khr, vk};
+use bevy_ecs::{
+    resource::Resource,
+    system::{Local, Res, ResMut},
+};
+use gpu_allocator::vulkan::Allocator;
+
+use super::{
+    CollectFrameStats, FrameStats, FrameTarget, VulkanApp, create_framebuffers, create_render_pass,
+    debug_utils::set_debug_name,
+    dynamic_rendering::{AttachmentFormats, DynamicTarget},
+    image::{DepthResources, Image, ImageDesc, MsaaColor, create_image, create_image_view},
+    storage::DeferredDestroyQueue,
+};
+use crate::windowing::FpsCap;
+
+/// Size of the image the scene is rendered into.
+///
+/// Anything but [`RenderTargetSize::Swapchain`] renders into a [`SceneTarget`],
+/// which is then blitted over the whole swapchain image. Its view can also be
+/// sampled instead, e.g. to show the world inside an editor viewport.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderTargetSize {
+    /// Render straight into the swapchain images.
+    #[default]
+    Swapchain,
+    /// The swapchain extent multiplied by [`RenderScale`].
+    Scaled,
+    /// A size independent of the window, stretched over the swapchain image.
+    Fixed(vk::Extent2D),
+}
+
+/// Multiplies the swapchain extent to get the size of the [`SceneTarget`]
+/// with [`RenderTargetSize::Scaled`]. Below 1 renders fewer pixels than the
+/// window has, trading sharpness for frame rate, and above 1 supersamples it.
+///
+/// Clamped to [`RenderScale::MIN`] and [`RenderScale::MAX`]. Changing it
+/// resizes the scene target, see [`AutoRenderScale`] to adjust it to the
+/// frame rate.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct RenderScale(pub f32);
+
+impl RenderScale {
+    pub const MIN: f32 = 0.25;
+    pub const MAX: f32 = 2.0;
+
+    pub fn clamped(self) -> f32 {
+        self.0.clamp(Self::MIN, Self::MAX)
+    }
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Extent of the [`SceneTarget`], `None` to render into the swapchain images.
+///
+/// Neither side exceeds `max_dimension`, the `maxImageDimension2D` limit.
+pub fn scene_extent(
+    size: RenderTargetSize,
+    scale: f32,
+    swapchain_extent: vk::Extent2D,
+    max_dimension: u32,
+) -> Option<vk::Extent2D> {
+    let scale = RenderScale(scale).clamped();
+    let extent = match size {
+        RenderTargetSize::Swapchain => return None,
+        RenderTargetSize::Scaled => {
+            let scale = |side: u32| (side as f32 * scale).round() as u32;
+            vk::Extent2D {
+                width: scale(swapchain_extent.width),
+                height: scale(swapchain_extent.height),
+            }
+        }
+        RenderTargetSize::Fixed(extent) => extent,
+    };
+
+    Some(vk::Extent2D {
+        width: extent.width.clamp(1, max_dimension),
+        height: extent.height.clamp(1, max_dimension),
+    })
+}
+
+/// Lowers [`RenderScale`] while frames take longer than [`FpsCap`] allows,
+/// and raises it back up to 1 once they are well within. Disabled by default.
+///
+/// Frames are timed by [`FrameStats`], so [`CollectFrameStats`] must be
+/// enabled as well. Only has an effect with [`RenderTargetSize::Scaled`] and a
+/// cap set.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AutoRenderScale(pub bool);
+
+const AUTO_SCALE_STEP: f32 = 0.05;
+/// Frames averaged between adjustments, each of which resizes the scene target.
+const AUTO_SCALE_INTERVAL: u32 = 30;
+
+/// Render scale for frames that took `frame_time` on average, against a
+/// budget of `target` per frame.
+pub fn adjust_render_scale(scale: f32, frame_time: Duration, target: Duration) -> f32 {
+    let adjusted = if frame_time > target.mul_f32(1.05) {
+        scale - AUTO_SCALE_STEP
+    } else if frame_time < target.mul_f32(0.8) {
+        scale + AUTO_SCALE_STEP
+    } else {
+        scale
+    };
+    adjusted.clamp(RenderScale::MIN, 1.0)
+}
+
+/// Color and depth images the scene is rendered into instead of the
+/// swapchain image, see [`RenderTargetSize`].
+///
+/// The color image has the format of the swapchain, so the pipelines render
+/// into both. After every frame it is left in `TRANSFER_SRC_OPTIMAL` for the
+/// blit onto the swapchain image, sampling it needs a transition first.
+pub struct SceneTarget {
+    pub color: Image,
+    pub color_view: vk::ImageView,
+    pub extent: vk::Extent2D,
+    depth: DepthResources,
+    depth_format: vk::Format,
+    /// Resolved into `color` when multisampled.
+    msaa_color: Option<MsaaColor>,
+    /// `None` when frames are rendered with dynamic rendering.
+    render_pass: Option<(vk::RenderPass, vk::Framebuffer)>,
+}
+
+impl SceneTarget {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        formats: AttachmentFormats,
+        dynamic_rendering: bool,
+        render_pass2: Option<&khr::create_renderpass2::Device>,
+    ) -> Self {
+        let desc = ImageDesc::new(
+            extent,
+            formats.color,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::SAMPLED,
+        );
+        let color = create_image(device, allocator, "scene color", &desc);
+        let color_view = create_image_view(
+            device,
+            color.image,
+            formats.color,
+            vk::ImageAspectFlags::COLOR,
+            1,
+        );
+        let depth = DepthResources::new(
+            device,
+            allocator,
+            extent,
+            formats.depth,
+            formats.samples,
+            formats.depth_resolve,
+        );
+        let msaa_color = MsaaColor::new(device, allocator, extent, formats.color, formats.samples);
+
+        // Compatible with the main render pass, which the pipelines are created for.
+        let render_pass = (!dynamic_rendering).then(|| {
+            let render_pass = create_render_pass(
+                device,
+                render_pass2,
+                formats,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+            set_debug_name(device, render_pass, "scene render pass");
+            let framebuffer = create_framebuffers(
+                device,
+                Some(render_pass),
+                &[color_view],
+                &depth,
+                msaa_color.as_ref().map(|msaa_color| msaa_color.view),
+                extent,
+            )[0];
+            (render_pass, framebuffer)
+        });
+
+        Self {
+            color,
+            color_view,
+            extent,
+            depth,
+            depth_format: formats.depth,
+            msaa_color,
+            render_pass,
+        }
+    }
+
+    pub(super) fn frame_target(&self) -> FrameTarget {
+        match self.render_pass {
+            Some((render_pass, framebuffer)) => FrameTarget::RenderPass {
+                render_pass,
+                framebuffer,
+            },
+            None => FrameTarget::Dynamic(DynamicTarget {
+                color_image: self.color.image,
+                color_view: self.color_view,
+                depth_image: self.depth.image.image,
+                depth_view: self.depth.view,
+                depth_format: self.depth_format,
+                depth_resolve: self
+                    .depth
+                    .resolved
+                    .as_ref()
+                    .map(|resolved| (resolved.mode, resolved.image.image, resolved.view)),
+                msaa_color: self
+                    .msaa_color
+                    .as_ref()
+                    .map(|msaa_color| (msaa_color.image.image, msaa_color.view)),
+                final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            }),
+        }
+    }
+
+    /// Scales the rendered image over `swapchain_image` and transitions that
+    /// for presentation.
+    pub fn record_blit(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        swapchain_extent: vk::Extent2D,
+    ) {
+        let color_range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let before = [
+            // Order the blit after the color writes of the frame.
+            vk::ImageMemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.color.image)
+                .subresource_range(color_range),
+            vk::ImageMemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(swapchain_image)
+                .subresource_range(color_range),
+        ];
+        let after = vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(swapchain_image)
+            .subresource_range(color_range);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &before,
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                self.color.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit_region(self.extent, swapchain_extent)],
+                vk::Filter::LINEAR,
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[after],
+            );
+        }
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        unsafe {
+            if let Some((render_pass, framebuffer)) = self.render_pass.take() {
+                device.destroy_framebuffer(framebuffer, None);
+                device.destroy_render_pass(render_pass, None);
+            }
+            device.destroy_image_view(self.color_view, None);
+        }
+        self.depth.destroy(device, allocator);
+        if let Some(msaa_color) = &mut self.msaa_color {
+            msaa_color.destroy(device, allocator);
+        }
+        self.color.destroy(device, allocator);
+    }
+}
+
+/// Region covering the whole of both images.
+fn blit_region(src: vk::Extent2D, dst: vk::Extent2D) -> vk::ImageBlit {
+    let corner = |extent: vk::Extent2D| vk::Offset3D {
+        x: extent.width as i32,
+        y: extent.height as i32,
+        z: 1,
+    };
+    let layers = vk::ImageSubresourceLayers::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    vk::ImageBlit::default()
+        .src_subresource(layers)
+        .src_offsets([vk::Offset3D::default(), corner(src)])
+        .dst_subresource(layers)
+        .dst_offsets([vk::Offset3D::default(), corner(dst)])
+}
+
+/// Frame times summed since the last adjustment of [`AutoRenderScale`].
+#[derive(Default)]
+pub struct FrameTimes {
+    total: Duration,
+    frames: u32,
+}
+
+pub fn auto_render_scale_system(
+    auto: Res<AutoRenderScale>,
+    (collect_stats, frame_stats): (Res<CollectFrameStats>, Res<FrameStats>),
+    fps_cap: Option<Res<FpsCap>>,
+    mut scale: ResMut<RenderScale>,
+    mut frame_times: Local<FrameTimes>,
+) {
+    let Some(FpsCap(Some(target_fps))) = fps_cap.as_deref().copied() else {
+        return;
+    };
+    if !auto.0 || !collect_stats.0 || target_fps == 0 {
+        return;
+    }
+
+    frame_times.total += frame_stats.total();
+    frame_times.frames += 1;
+    if frame_times.frames < AUTO_SCALE_INTERVAL {
+        return;
+    }
+
+    let frame_time = frame_times.total / frame_times.frames;
+    *frame_times = FrameTimes::default();
+    let adjusted = adjust_render_scale(scale.0, frame_time, Duration::from_secs(1) / target_fps);
+    if scale.0 != adjusted {
+        scale.0 = adjusted;
+    }
+}
+
+/// Resizes the [`SceneTarget`] when [`RenderTargetSize`] or [`RenderScale`]
+/// changed. The old one is destroyed by `destroy_queue`.
+pub fn update_render_target_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    size: Res<RenderTargetSize>,
+    scale: Res<RenderScale>,
+    mut destroy_queue: ResMut<DeferredDestroyQueue>,
+) {
+    // Headless apps already render offscreen.
+    if vulkan_app.surface.is_none() {
+        return;
+    }
+
+    if (vulkan_app.render_target_size, vulkan_app.render_scale) != (*size, scale.0) {
+        vulkan_app.render_target_size = *size;
+        vulkan_app.render_scale = scale.0;
+        if let Some(replaced) = vulkan_app.update_scene_target() {
+            vulkan_app.retire_scene_target(&mut destroy_queue, replaced);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    const MAX_DIMENSION: u32 = 16384;
+
+    #[test]
+    fn scene_extent_follows_the_size() {
+        let swapchain = extent(1280, 720);
+        let scene_extent = |size, scale| scene_extent(size, scale, swapchain, MAX_DIMENSION);
+        assert_eq!(scene_extent(RenderTargetSize::Swapchain, 0.5), None);
+        assert_eq!(
+            scene_extent(RenderTargetSize::Scaled, 0.5),
+            Some(extent(640, 360))
+        );
+        assert_eq!(
+            scene_extent(RenderTargetSize::Scaled, 1.5),
+            Some(extent(1920, 1080))
+        );
+        // The fixed size ignores the scale and the window.
+        assert_eq!(
+            scene_extent(RenderTargetSize::Fixed(extent(300, 200)), 0.5),
+            Some(extent(300, 200))
+        );
+    }
+
+    #[test]
+    fn scale_is_clamped() {
+        let swapchain = extent(1000, 800);
+        assert_eq!(
+            scene_extent(RenderTargetSize::Scaled, 0.1, swapchain, MAX_DIMENSION),
+            Some(extent(250, 200))
+        );
+        assert_eq!(
+            scene_extent(RenderTargetSize::Scaled, 4.0, swapchain, MAX_DIMENSION),
+            Some(extent(2000, 1600))
+        );
+    }
+
+    #[test]
+    fn scene_extent_respects_the_device_limit() {
+        assert_eq!(
+            scene_extent(RenderTargetSize::Scaled, 2.0, extent(3000, 1000), 4096),
+            Some(extent(4096, 2000))
+        );
+        assert_eq!(
+            scene_extent(
+                RenderTargetSize::Fixed(extent(8192, 0)),
+                1.0,
+                extent(3, 3),
+                4096
+            ),
+            Some(extent(4096, 1))
+        );
+        assert_eq!(
+            scene_extent(RenderTargetSize::Scaled, 0.25, extent(1, 0), 4096),
+            Some(extent(1, 1))
+        );
+    }
+
+    #[test]
+    fn auto_scale_tracks_the_frame_budget() {
+        let budget = Duration::from_millis(16);
+        let slow = adjust_render_scale(1.0, Duration::from_millis(20), budget);
+        assert!((slow - 0.95).abs() < 1e-6);
+        // Close to the budget keeps the scale.
+        assert_eq!(
+            adjust_render_scale(0.7, Duration::from_millis(16), budget),
+            0.7
+        );
+        let fast = adjust_render_scale(0.7, Duration::from_millis(8), budget);
+        assert!((fast - 0.75).abs() < 1e-6);
+
+        // Never below the minimum, and never supersampled.
+        assert_eq!(
+            adjust_render_scale(RenderScale::MIN, Duration::from_millis(40), budget),
+            RenderScale::MIN
+        );
+        assert_eq!(
+            adjust_render_scale(1.0, Duration::from_millis(1), budget),
+            1.0
+        );
+    }
+
+    #[test]
+    fn blit_covers_both_images() {
+        let region = blit_region(extent(640, 360), extent(1280, 720));
+        assert_eq!(region.src_offsets[0], vk::Offset3D::default());
+        assert_eq!(
+            region.src_offsets[1],
+            vk::Offset3D {
+                x: 640,
+                y: 360,
+                z: 1
+            }
+        );
+        assert_eq!(
+            region.dst_offsets[1],
+            vk::Offset3D {
+                x: 1280,
+                y: 720,
+                z: 1
+            }
+        );
+    }
+}
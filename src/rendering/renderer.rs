@@ -0,0 +1,347 @@
+use ash::vk;
+use bevy_ecs::{
+    resource::Resource,
+    system::{Commands, Res},
+};
+use glam::{IVec3, Mat4};
+use tracing::error;
+use winit::{dpi::PhysicalSize, window::WindowId};
+
+use super::{
+    FrameStats, RenderDevice, VulkanApp, VulkanError, storage::DeferredDestroyQueue,
+    swapchain::pre_rotated_extent,
+};
+use crate::{windowing::AppWindows, world::meshing::Vertex};
+
+/// What the frame loop and the chunk mesh systems do with the GPU.
+///
+/// Implemented by [`VulkanApp`]. The systems are generic over it so that
+/// tests can run them against a `MockRenderer`, which records the calls
+/// instead of creating a device.
+pub trait Renderer: Resource {
+    /// Extent of the frames as they are presented, which the camera projection
+    /// uses for its aspect ratio.
+    fn swapchain_extent(&self) -> vk::Extent2D;
+
+    fn resize(
+        &mut self,
+        swapchain_ok: &mut bool,
+        size: PhysicalSize<u32>,
+    ) -> Result<(), VulkanError>;
+
+    fn draw_frame(
+        &mut self,
+        swapchain_ok: &mut bool,
+        view_proj: Mat4,
+        stats: Option<&mut FrameStats>,
+    ) -> Result<(), VulkanError>;
+
+    /// Whether the swapchain should be recreated, clearing the request.
+    fn take_recreate_request(&mut self) -> bool;
+
+    /// Recreates the device after it was lost and returns the handles to
+    /// publish as the [`RenderDevice`] resource, if there are any.
+    fn rebuild_device(&mut self, size: PhysicalSize<u32>) -> Option<RenderDevice>;
+
+    /// Whether the chunk at `coord` has a mesh uploaded or being uploaded,
+    /// even an empty one.
+    fn has_chunk_mesh(&self, coord: IVec3) -> bool;
+
+    /// Starts uploading the mesh of the chunk at `coord`. Until the upload
+    /// finishes, the previous mesh of the chunk is drawn, if there is one.
+    fn upload_chunk_mesh(&mut self, coord: IVec3, vertices: &[Vertex], indices: &[u32]);
+
+    /// Draws the meshes whose upload finished from now on, without waiting
+    /// for the others. The meshes they replace are destroyed by `destroy_queue`.
+    fn finish_chunk_uploads(&mut self, destroy_queue: &mut DeferredDestroyQueue);
+
+    /// Removes the mesh of the chunk at `coord`. Its buffers are destroyed by
+    /// `destroy_queue` once the frames in flight that may draw them have completed.
+    fn retire_chunk_mesh(&mut self, destroy_queue: &mut DeferredDestroyQueue, coord: IVec3);
+}
+
+impl Renderer for VulkanApp {
+    fn swapchain_extent(&self) -> vk::Extent2D {
+        // Rotating back by a quarter turn swaps the sides again.
+        pre_rotated_extent(self.swapchain_extent, self.surface_transform)
+    }
+
+    fn resize(
+        &mut self,
+        swapchain_ok: &mut bool,
+        size: PhysicalSize<u32>,
+    ) -> Result<(), VulkanError> {
+        VulkanApp::resize(self, swapchain_ok, size)
+    }
+
+    fn draw_frame(
+        &mut self,
+        swapchain_ok: &mut bool,
+        view_proj: Mat4,
+        stats: Option<&mut FrameStats>,
+    ) -> Result<(), VulkanError> {
+        VulkanApp::draw_frame(self, swapchain_ok, view_proj, stats)
+    }
+
+    fn take_recreate_request(&mut self) -> bool {
+        std::mem::take(&mut self.recreate_requested)
+    }
+
+    fn rebuild_device(&mut self, size: PhysicalSize<u32>) -> Option<RenderDevice> {
+        VulkanApp::rebuild_device(self, size);
+        Some(self.render_device())
+    }
+
+    fn has_chunk_mesh(&self, coord: IVec3) -> bool {
+        self.chunk_meshes.contains_key(&coord) || self.uploads.is_pending(coord)
+    }
+
+    fn upload_chunk_mesh(&mut self, coord: IVec3, vertices: &[Vertex], indices: &[u32]) {
+        VulkanApp::upload_chunk_mesh(self, coord, vertices, indices);
+    }
+
+    fn finish_chunk_uploads(&mut self, destroy_queue: &mut DeferredDestroyQueue) {
+        let finished = match self.uploads.poll(&self.device, &mut self.allocator) {
+            Ok(finished) => finished,
+            Err(err) => {
+                error!("Failed to check the chunk mesh uploads: {err}");
+                return;
+            }
+        };
+
+        for (coord, mesh) in finished {
+            self.replace_chunk_mesh(coord, Some(mesh));
+        }
+        for mesh in std::mem::take(&mut self.replaced_meshes) {
+            self.retire_mesh(destroy_queue, mesh);
+        }
+    }
+
+    fn retire_chunk_mesh(&mut self, destroy_queue: &mut DeferredDestroyQueue, coord: IVec3) {
+        self.uploads.discard(coord);
+        let Some(Some(mesh)) = self.chunk_meshes.remove(&coord) else {
+            return;
+        };
+
+        let mesh = mesh.take(&mut self.chunk_buffers);
+        self.retire_mesh(destroy_queue, mesh);
+    }
+}
+
+/// The state of the primary window the frame loop reads, copied from
+/// [`AppWindows`] so that it runs without a winit window in tests.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrimaryWindowState {
+    pub id: WindowId,
+    pub inner_size: PhysicalSize<u32>,
+    pub maximized: bool,
+}
+
+pub fn update_primary_window_state_system(mut commands: Commands, windows: Res<AppWindows>) {
+    let window = &windows.primary;
+    commands.insert_resource(PrimaryWindowState {
+        id: window.id(),
+        inner_size: window.inner_size(),
+        maximized: window.is_maximized(),
+    });
+}
+
+#[cfg(test)]
+pub use mock::{MockRenderer, RendererCall};
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum RendererCall {
+        Resize(PhysicalSize<u32>),
+        DrawFrame,
+        RebuildDevice(PhysicalSize<u32>),
+        UploadChunkMesh(IVec3),
+        RetireChunkMesh(IVec3),
+    }
+
+    /// Records the calls of the render systems, see [`Renderer`].
+    #[derive(Resource, Debug, Default)]
+    pub struct MockRenderer {
+        pub calls: Vec<RendererCall>,
+        pub extent: vk::Extent2D,
+        pub recreate_requested: bool,
+        /// Returned by the next `draw_frame`.
+        pub draw_error: Option<VulkanError>,
+        pub chunk_meshes: hashbrown::HashSet<IVec3>,
+    }
+
+    impl MockRenderer {
+        pub fn resizes(&self) -> Vec<PhysicalSize<u32>> {
+            self.calls
+                .iter()
+                .filter_map(|call| match call {
+                    RendererCall::Resize(size) => Some(*size),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    impl Renderer for MockRenderer {
+        fn swapchain_extent(&self) -> vk::Extent2D {
+            self.extent
+        }
+
+        fn resize(
+            &mut self,
+            _swapchain_ok: &mut bool,
+            size: PhysicalSize<u32>,
+        ) -> Result<(), VulkanError> {
+            self.extent = vk::Extent2D {
+                width: size.width,
+                height: size.height,
+            };
+            self.calls.push(RendererCall::Resize(size));
+            Ok(())
+        }
+
+        fn draw_frame(
+            &mut self,
+            _swapchain_ok: &mut bool,
+            _view_proj: Mat4,
+            _stats: Option<&mut FrameStats>,
+        ) -> Result<(), VulkanError> {
+            self.calls.push(RendererCall::DrawFrame);
+            self.draw_error.take().map_or(Ok(()), Err)
+        }
+
+        fn take_recreate_request(&mut self) -> bool {
+            std::mem::take(&mut self.recreate_requested)
+        }
+
+        fn rebuild_device(&mut self, size: PhysicalSize<u32>) -> Option<RenderDevice> {
+            self.calls.push(RendererCall::RebuildDevice(size));
+            None
+        }
+
+        fn has_chunk_mesh(&self, coord: IVec3) -> bool {
+            self.chunk_meshes.contains(&coord)
+        }
+
+        fn upload_chunk_mesh(&mut self, coord: IVec3, _vertices: &[Vertex], _indices: &[u32]) {
+            self.chunk_meshes.insert(coord);
+            self.calls.push(RendererCall::UploadChunkMesh(coord));
+        }
+
+        fn finish_chunk_uploads(&mut self, _destroy_queue: &mut DeferredDestroyQueue) {}
+
+        fn retire_chunk_mesh(&mut self, _destroy_queue: &mut DeferredDestroyQueue, coord: IVec3) {
+            if self.chunk_meshes.remove(&coord) {
+                self.calls.push(RendererCall::RetireChunkMesh(coord));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{App, Update};
+    use bevy_ecs::event::Events;
+    use winit::event::WindowEvent;
+
+    use super::*;
+    use crate::{
+        camera::Camera,
+        rendering::{CollectFrameStats, DeviceLost, render_frame},
+        windowing::RawWnitWindowEvent,
+    };
+
+    fn window() -> PrimaryWindowState {
+        PrimaryWindowState {
+            id: WindowId::from(1),
+            inner_size: PhysicalSize::new(1280, 720),
+            maximized: false,
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.insert_resource(MockRenderer::default())
+            .insert_resource(window())
+            .init_resource::<Camera>()
+            .init_resource::<CollectFrameStats>()
+            .init_resource::<FrameStats>()
+            .add_event::<RawWnitWindowEvent>()
+            .add_event::<DeviceLost>()
+            .add_systems(Update, render_frame::<MockRenderer>);
+        app
+    }
+
+    fn resized(window_id: WindowId, width: u32, height: u32) -> RawWnitWindowEvent {
+        RawWnitWindowEvent {
+            event: WindowEvent::Resized(PhysicalSize::new(width, height)),
+            window_id,
+        }
+    }
+
+    #[test]
+    fn resize_event_resizes_once() {
+        let mut app = app();
+        app.update();
+
+        let world = app.world_mut();
+        world.send_event(resized(window().id, 800, 600));
+        world.send_event(resized(window().id, 1024, 768));
+        world.send_event(resized(WindowId::from(2), 640, 480));
+        app.update();
+
+        let renderer = app.world().resource::<MockRenderer>();
+        assert_eq!(renderer.resizes(), [PhysicalSize::new(1024, 768)]);
+        assert_eq!(
+            renderer.swapchain_extent(),
+            vk::Extent2D {
+                width: 1024,
+                height: 768
+            }
+        );
+        assert_eq!(
+            renderer
+                .calls
+                .iter()
+                .filter(|call| **call == RendererCall::DrawFrame)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn lost_device_is_rebuilt_at_window_size() {
+        let mut app = app();
+        app.world_mut().resource_mut::<MockRenderer>().draw_error = Some(VulkanError::DeviceLost);
+        app.update();
+
+        assert_eq!(
+            app.world().resource::<MockRenderer>().calls,
+            [
+                RendererCall::DrawFrame,
+                RendererCall::RebuildDevice(window().inner_size)
+            ]
+        );
+        assert_eq!(app.world().resource::<Events<DeviceLost>>().len(), 1);
+    }
+
+    #[test]
+    fn recreate_request_resizes_after_drawing() {
+        let mut app = app();
+        app.world_mut()
+            .resource_mut::<MockRenderer>()
+            .recreate_requested = true;
+        app.update();
+
+        assert_eq!(
+            app.world().resource::<MockRenderer>().calls,
+            [
+                RendererCall::DrawFrame,
+                RendererCall::Resize(window().inner_size)
+            ]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::{Path, PathBuf};
+
+use ash::{Device, vk};
+use gpu_allocator::{MemoryLocation, vulkan::Allocator};
+use thiserror::Error;
+
+use super::{
+    VulkanError,
+    buffer::{Buffer, create_buffer},
+    layout::transition_image_layout,
+};
+
+#[derive(Error, Debug)]
+pub enum ScreenshotError {
+    #[error("Headless apps have no swapchain to capture")]
+    NoSwapchain,
+    #[error("Swapchain images can't be copied from on this surface")]
+    TransferUnsupported,
+    #[error("Capturing swapchain format {0:?} isn't supported")]
+    UnsupportedFormat(vk::Format),
+    #[error("Failed to create the screenshot directory of `{path}`: {source}")]
+    CreateDir {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to save screenshot `{path}`: {source}")]
+    Save {
+        path: PathBuf,
+        #[source]
+        source: ::image::ImageError,
+    },
+}
+
+/// Channel order of a swapchain format that can be saved as RGBA8.
+///
+/// Both the `SRGB` and `UNORM` variants hold the sRGB-encoded values shown on
+/// screen: `SRGB` formats encode on write, and `UNORM` images in the
+/// `SRGB_NONLINEAR` color space are presented as they are. Either way the
+/// bytes are what a PNG expects and are saved without conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+
+impl ChannelOrder {
+    pub fn of(format: vk::Format) -> Option<Self> {
+        match format {
+            vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Some(ChannelOrder::Rgba),
+            vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Some(ChannelOrder::Bgra),
+            _ => None,
+        }
+    }
+
+    /// Reorders `pixels` in place to RGBA.
+    pub fn to_rgba(self, pixels: &mut [u8]) {
+        if self == ChannelOrder::Bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+    }
+}
+
+/// A swapchain image copy recorded into a frame, saved once the frame is done.
+pub struct PendingCapture {
+    pub path: PathBuf,
+    pub readback: Buffer,
+    pub extent: vk::Extent2D,
+    pub channel_order: ChannelOrder,
+}
+
+impl PendingCapture {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        path: PathBuf,
+        extent: vk::Extent2D,
+        channel_order: ChannelOrder,
+    ) -> Self {
+        let readback = create_buffer(
+            device,
+            allocator,
+            "screenshot readback",
+            extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        );
+
+        Self {
+            path,
+            readback,
+            extent,
+            channel_order,
+        }
+    }
+
+    /// Copies the presentable `image` into the readback buffer. Recorded after
+    /// the frame is rendered, leaving the image ready to be presented.
+    pub fn record_copy(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+    ) -> Result<(), VulkanError> {
+        let subresource_range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let to_present = vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range);
+
+        let to_host = vk::BufferMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.readback.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_extent(self.extent.into());
+
+        transition_image_layout(
+            device,
+            command_buffer,
+            image,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageAspectFlags::COLOR,
+            0..1,
+        )?;
+
+        unsafe {
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.readback.buffer,
+                &[region],
+            );
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[to_host],
+                &[to_present],
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Writes the copied pixels to the PNG at `path` and frees the readback
+    /// buffer. The frame the copy was recorded into must have completed.
+    pub fn save(
+        mut self,
+        device: &Device,
+        allocator: &mut Allocator,
+    ) -> Result<PathBuf, ScreenshotError> {
+        let size = self.extent.width as usize * self.extent.height as usize * 4;
+        let mut pixels = self
+            .readback
+            .allocation
+            .mapped_slice()
+            .expect("Readback memory must be host visible")[..size]
+            .to_vec();
+        self.readback.destroy(device, allocator);
+
+        self.channel_order.to_rgba(&mut pixels);
+        write_png(&self.path, &pixels, self.extent)?;
+
+        Ok(self.path)
+    }
+}
+
+fn write_png(path: &Path, rgba: &[u8], extent: vk::Extent2D) -> Result<(), ScreenshotError> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent).map_err(|source| ScreenshotError::CreateDir {
+            path: path.to_owned(),
+            source,
+        })?;
+    }
+
+    ::image::save_buffer(
+        path,
+        rgba,
+        extent.width,
+        extent.height,
+        ::image::ColorType::Rgba8,
+    )
+    .map_err(|source| ScreenshotError::Save {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swizzles_bgra() {
+        let mut pixels = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        ChannelOrder::Bgra.to_rgba(&mut pixels);
+        assert_eq!(pixels, [3, 2, 1, 4, 7, 6, 5, 8]);
+
+        let mut pixels = vec![1, 2, 3, 4];
+        ChannelOrder::Rgba.to_rgba(&mut pixels);
+        assert_eq!(pixels, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn channel_order_of_formats() {
+        assert_eq!(
+            ChannelOrder::of(vk::Format::B8G8R8A8_SRGB),
+            Some(ChannelOrder::Bgra)
+        );
+        assert_eq!(
+            ChannelOrder::of(vk::Format::R8G8B8A8_UNORM),
+            Some(ChannelOrder::Rgba)
+        );
+        assert_eq!(ChannelOrder::of(vk::Format::A2B10G10R10_UNORM_PACK32), None);
+    }
+
+    #[test]
+    fn saved_png_round_trips() {
+        let path = std::env::temp_dir().join("wolrdgen-voxels-screenshot-test/shot.png");
+        let extent = vk::Extent2D {
+            width: 2,
+            height: 1,
+        };
+        let rgba = [255, 0, 0, 255, 0, 128, 255, 255];
+
+        write_png(&path, &rgba, extent).unwrap();
+        let decoded = ::image::open(&path).unwrap().into_rgba8();
+
+        assert_eq!(decoded.dimensions(), (2, 1));
+        assert_eq!(decoded.into_raw(), rgba);
+    }
+}
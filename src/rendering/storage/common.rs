@@ -1,10 +1,16 @@
 use ash::{ext, khr, vk};
 use bevy_app::Plugin;
-use bevy_ecs::schedule::IntoScheduleConfigs;
+use gpu_allocator::vulkan::Allocator;
 
 use super::{
-    Destroy, Destroyable, Storage, StoragesAppExt, destroy_storage, destroy_storage_handled,
-    optional,
+    Destroyable, Handled, Single, Storage, StorageSingleMut, StoragesAppExt,
+    dense::DenseHandled,
+    order::{DestroyGraph, StorageId},
+};
+#[cfg(feature = "compute")]
+use crate::rendering::compute::ComputePipeline;
+use crate::rendering::{
+    buffer::Buffer, descriptor::DescriptorSetLayoutCache, image::Image, texture::Texture,
 };
 
 pub struct CommonStoragesPlugin;
@@ -13,37 +19,71 @@ impl Plugin for CommonStoragesPlugin {
     fn build(&self, app: &mut bevy_app::App) {
         app.register_handled_storage::<vk::Framebuffer>()
             .register_handled_storage::<vk::ImageView>()
+            .register_handled_storage::<vk::Sampler>()
             .register_handled_storage::<vk::Semaphore>()
             .register_handled_storage::<vk::Fence>()
             .register_handled_storage::<vk::CommandPool>()
             .register_handled_storage::<vk::Pipeline>()
             .register_handled_storage::<vk::PipelineLayout>()
-            .register_handled_storage::<vk::RenderPass>();
-
-        app.add_systems(
-            Destroy,
-            (
-                (
-                    destroy_storage_handled::<vk::Framebuffer>(),
-                    destroy_storage_handled::<vk::ImageView>(),
-                    destroy_storage::<SwapchainPack>(),
-                ),
-                destroy_storage_handled::<vk::Semaphore>(),
-                destroy_storage_handled::<vk::Fence>(),
-                destroy_storage_handled::<vk::CommandPool>(),
-                destroy_storage_handled::<vk::Pipeline>(),
-                destroy_storage_handled::<vk::PipelineLayout>(),
-                destroy_storage_handled::<vk::RenderPass>(),
-                destroy_storage::<ash::Device>(),
-                optional(destroy_storage::<DebugUtilsPack>()),
-                destroy_storage::<SurfacePack>(),
-                destroy_storage::<ash::Instance>(),
-            )
-                .chain(),
-        );
+            .register_handled_storage::<vk::RenderPass>()
+            .register_handled_storage::<vk::DescriptorPool>()
+            .register_handled_storage::<vk::DescriptorSetLayout>()
+            .register_handled_storage::<Texture>()
+            .register_handled_storage::<Buffer>()
+            .register_dense_storage::<Buffer>()
+            .register_handled_storage::<Image>()
+            .register_single_storage::<DescriptorSetLayoutCache>()
+            .register_single_storage::<SwapchainPack>()
+            .register_single_storage::<Allocator>()
+            .register_single_storage::<ash::Device>()
+            .register_single_storage::<DebugUtilsPack>()
+            .register_single_storage::<SurfacePack>()
+            .register_single_storage::<ash::Instance>();
+
+        #[cfg(feature = "compute")]
+        app.register_handled_storage::<ComputePipeline>();
+
+        add_common_destroy_storages(&mut app.world_mut().get_resource_or_init::<DestroyGraph>());
     }
 }
 
+/// Adds the common storages to the destroy order. Also used to destroy a
+/// `VulkanApp` that isn't a resource of an app.
+pub fn add_common_destroy_storages(graph: &mut DestroyGraph) {
+    graph
+        .add_storage::<Handled<vk::Framebuffer>>()
+        .add_storage::<Handled<vk::ImageView>>()
+        .add_storage::<Handled<vk::Sampler>>()
+        .add_storage::<Handled<vk::Semaphore>>()
+        .add_storage::<Handled<vk::Fence>>()
+        .add_storage::<Handled<vk::CommandPool>>()
+        .add_storage::<Handled<vk::Pipeline>>()
+        .add_storage::<Handled<vk::PipelineLayout>>()
+        .add_storage::<Handled<vk::RenderPass>>()
+        .add_storage::<Handled<vk::DescriptorPool>>()
+        .add_storage::<Handled<vk::DescriptorSetLayout>>()
+        .add_storage::<Handled<Texture>>()
+        .add_storage::<Handled<Buffer>>()
+        .add_storage::<DenseHandled<Buffer>>()
+        .add_storage::<Handled<Image>>()
+        .add_storage::<Single<DescriptorSetLayoutCache>>()
+        .add_storage::<Single<SwapchainPack>>()
+        .add_storage::<Single<Allocator>>()
+        .add_storage::<Single<ash::Device>>()
+        .add_storage::<Single<DebugUtilsPack>>()
+        .add_storage::<Single<SurfacePack>>()
+        .add_storage::<Single<ash::Instance>>();
+    #[cfg(feature = "compute")]
+    graph.add_storage::<Handled<ComputePipeline>>();
+
+    // Framebuffers reference the swapchain image views which in turn
+    // reference the swapchain images or allocated images.
+    graph
+        .add_destroy_after::<Handled<vk::ImageView>, Handled<vk::Framebuffer>>()
+        .add_destroy_after::<Single<SwapchainPack>, Handled<vk::ImageView>>()
+        .add_destroy_after::<Handled<Image>, Handled<vk::ImageView>>();
+}
+
 impl Destroyable for ash::Instance {
     type Params<'w, 's> = ();
 
@@ -59,6 +99,10 @@ impl Destroyable for SurfacePack {
     fn destroy(&mut self, _params: &mut Self::Params<'_, '_>) {
         unsafe { self.0.destroy_surface(self.1, None) };
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Instance>>()]
+    }
 }
 
 pub type DebugUtilsPack = (ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT);
@@ -68,22 +112,58 @@ impl Destroyable for DebugUtilsPack {
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
         unsafe { self.0.destroy_debug_utils_messenger(self.1, None) };
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Instance>>()]
+    }
+}
+
+pub type DeviceStorage<'w> = Storage<'w, Single<ash::Device>>;
+
+/// Accessor for the device that the device-owned handles are destroyed with.
+pub trait DeviceStorageExt {
+    fn device(&self) -> &ash::Device;
+}
+
+impl DeviceStorageExt for DeviceStorage<'_> {
+    fn device(&self) -> &ash::Device {
+        self.try_get()
+            .unwrap_or_else(|err| panic!("{err}, it must outlive every device-owned handle"))
+    }
 }
 
-pub type DeviceStorage<'w> = Storage<'w, ash::Device>;
 impl Destroyable for ash::Device {
     type Params<'w, 's> = ();
 
     fn destroy(&mut self, _params: &mut Self::Params<'_, '_>) {
         unsafe { self.destroy_device(None) };
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Instance>>()]
+    }
+}
+
+impl Destroyable for Allocator {
+    type Params<'w, 's> = ();
+
+    /// Dropping the allocator frees its memory blocks and logs leaked allocations.
+    fn destroy(&mut self, _params: &mut Self::Params<'_, '_>) {}
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
 }
 
 impl Destroyable for vk::RenderPass {
     type Params<'w, 's> = DeviceStorage<'w>;
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
-        unsafe { params.data.destroy_render_pass(*self, None) };
+        unsafe { params.device().destroy_render_pass(*self, None) };
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
     }
 }
 
@@ -91,7 +171,11 @@ impl Destroyable for vk::PipelineLayout {
     type Params<'w, 's> = DeviceStorage<'w>;
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
-        unsafe { params.data.destroy_pipeline_layout(*self, None) };
+        unsafe { params.device().destroy_pipeline_layout(*self, None) };
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
     }
 }
 
@@ -100,9 +184,13 @@ impl Destroyable for vk::Pipeline {
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
         unsafe {
-            params.data.destroy_pipeline(*self, None);
+            params.device().destroy_pipeline(*self, None);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
 }
 
 impl Destroyable for vk::CommandPool {
@@ -110,9 +198,13 @@ impl Destroyable for vk::CommandPool {
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
         unsafe {
-            params.data.destroy_command_pool(*self, None);
+            params.device().destroy_command_pool(*self, None);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
 }
 
 impl Destroyable for vk::Fence {
@@ -120,9 +212,13 @@ impl Destroyable for vk::Fence {
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
         unsafe {
-            params.data.destroy_fence(*self, None);
+            params.device().destroy_fence(*self, None);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
 }
 
 impl Destroyable for vk::Semaphore {
@@ -130,9 +226,13 @@ impl Destroyable for vk::Semaphore {
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
         unsafe {
-            params.data.destroy_semaphore(*self, None);
+            params.device().destroy_semaphore(*self, None);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
 }
 
 pub type SwapchainPack = (khr::swapchain::Device, vk::SwapchainKHR);
@@ -144,6 +244,13 @@ impl Destroyable for SwapchainPack {
             self.0.destroy_swapchain(self.1, None);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![
+            StorageId::of::<Single<ash::Device>>(),
+            StorageId::of::<Single<SurfacePack>>(),
+        ]
+    }
 }
 
 impl Destroyable for vk::ImageView {
@@ -151,9 +258,27 @@ impl Destroyable for vk::ImageView {
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
         unsafe {
-            params.data.destroy_image_view(*self, None);
+            params.device().destroy_image_view(*self, None);
+        }
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
+}
+
+impl Destroyable for vk::Sampler {
+    type Params<'w, 's> = DeviceStorage<'w>;
+
+    fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
+        unsafe {
+            params.device().destroy_sampler(*self, None);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
 }
 
 impl Destroyable for vk::Framebuffer {
@@ -161,7 +286,118 @@ impl Destroyable for vk::Framebuffer {
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
         unsafe {
-            params.data.destroy_framebuffer(*self, None);
+            params.device().destroy_framebuffer(*self, None);
+        }
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
+}
+
+impl Destroyable for vk::DescriptorPool {
+    type Params<'w, 's> = DeviceStorage<'w>;
+
+    fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
+        unsafe {
+            params.device().destroy_descriptor_pool(*self, None);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
+}
+
+impl Destroyable for vk::DescriptorSetLayout {
+    type Params<'w, 's> = DeviceStorage<'w>;
+
+    fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
+        unsafe {
+            params.device().destroy_descriptor_set_layout(*self, None);
+        }
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
+}
+
+impl Destroyable for Texture {
+    type Params<'w, 's> = (DeviceStorage<'w>, StorageSingleMut<'w, Allocator>);
+
+    fn destroy(&mut self, (device, allocator): &mut Self::Params<'_, '_>) {
+        let allocator = allocator
+            .get_mut()
+            .expect("`Allocator` must outlive every allocated texture");
+        Texture::destroy(self, device.device(), allocator);
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![
+            StorageId::of::<Single<ash::Device>>(),
+            StorageId::of::<Single<Allocator>>(),
+        ]
+    }
+}
+
+impl Destroyable for Buffer {
+    type Params<'w, 's> = (DeviceStorage<'w>, StorageSingleMut<'w, Allocator>);
+
+    fn destroy(&mut self, (device, allocator): &mut Self::Params<'_, '_>) {
+        let allocator = allocator
+            .get_mut()
+            .expect("`Allocator` must outlive every allocated buffer");
+        Buffer::destroy(self, device.device(), allocator);
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![
+            StorageId::of::<Single<ash::Device>>(),
+            StorageId::of::<Single<Allocator>>(),
+        ]
+    }
+}
+
+impl Destroyable for Image {
+    type Params<'w, 's> = (DeviceStorage<'w>, StorageSingleMut<'w, Allocator>);
+
+    fn destroy(&mut self, (device, allocator): &mut Self::Params<'_, '_>) {
+        let allocator = allocator
+            .get_mut()
+            .expect("`Allocator` must outlive every allocated image");
+        Image::destroy(self, device.device(), allocator);
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![
+            StorageId::of::<Single<ash::Device>>(),
+            StorageId::of::<Single<Allocator>>(),
+        ]
+    }
+}
+
+#[cfg(feature = "compute")]
+impl Destroyable for ComputePipeline {
+    type Params<'w, 's> = DeviceStorage<'w>;
+
+    fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
+        ComputePipeline::destroy(self, params.device());
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
+}
+
+impl Destroyable for DescriptorSetLayoutCache {
+    type Params<'w, 's> = DeviceStorage<'w>;
+
+    fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
+        DescriptorSetLayoutCache::destroy(self, params.device());
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        vec![StorageId::of::<Single<ash::Device>>()]
+    }
 }
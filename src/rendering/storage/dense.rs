@@ -0,0 +1,164 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+use super::{Destroyable, order::StorageId};
+use crate::dense_storage::{DenseStorage, Index};
+
+/// Same as [`Handled`](super::Handled) but stores values in a [`DenseStorage`].
+///
+/// Handles are generational indices into a vector instead of `Uuid` keys of a
+/// hash map, which makes lookups cheaper and keeps values contiguous. Use it
+/// for storages holding many objects, like the GPU resources of chunks.
+///
+/// Indices of removed values are reused with a new generation, so a handle
+/// to a removed value never reaches the one that replaced it.
+pub struct DenseHandled<T> {
+    inner: DenseStorage<T>,
+}
+
+impl<T> Default for DenseHandled<T> {
+    fn default() -> Self {
+        Self {
+            inner: DenseStorage::default(),
+        }
+    }
+}
+
+impl<T> DenseHandled<T> {
+    pub fn insert(&mut self, value: T) -> DenseHandle<T> {
+        let index = self.inner.index_allocator_mut().reserve();
+        self.inner
+            .insert(index, value)
+            .expect("A freshly reserved index has the current generation");
+        DenseHandle(index, PhantomData)
+    }
+
+    pub fn get(&self, handle: &DenseHandle<T>) -> Option<&T> {
+        self.inner.get(handle.0)
+    }
+
+    /// Removes the value without destroying it. Its index is reused.
+    pub fn remove(&mut self, handle: &DenseHandle<T>) -> Option<T> {
+        self.inner.remove_recycle(handle.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<T: Destroyable> Destroyable for DenseHandled<T> {
+    type Params<'w, 's> = T::Params<'w, 's>;
+
+    /// Destroys the stored values and leaves the storage empty.
+    fn destroy(&mut self, params: &mut T::Params<'_, '_>) {
+        for (_, val) in std::mem::take(&mut self.inner).iter_mut() {
+            val.destroy(params);
+        }
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        T::dependencies()
+    }
+}
+
+/// Identifies a value in a [`DenseHandled`] storage.
+pub struct DenseHandle<T>(Index, PhantomData<fn() -> T>);
+
+impl<T> Clone for DenseHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for DenseHandle<T> {}
+
+impl<T> PartialEq for DenseHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for DenseHandle<T> {}
+
+impl<T> Hash for DenseHandle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for DenseHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DenseHandle").field(&self.0).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{hint::black_box, time::Instant};
+
+    use super::*;
+    use crate::rendering::storage::Handled;
+
+    #[test]
+    fn stale_handles_miss_reused_slots() {
+        let mut handled = DenseHandled::<u32>::default();
+        let first = handled.insert(1);
+        let second = handled.insert(2);
+
+        assert_eq!(handled.remove(&first), Some(1));
+        assert_eq!(handled.get(&first), None);
+
+        // Reuses the slot of `first` with a new generation.
+        let third = handled.insert(3);
+        assert_ne!(first, third);
+        assert_eq!(handled.get(&first), None);
+        assert_eq!(handled.get(&third), Some(&3));
+        assert_eq!(handled.get(&second), Some(&2));
+        assert_eq!(handled.len(), 2);
+    }
+
+    /// Compares [`DenseHandled`] to [`Handled`]. Run with
+    /// `cargo test --release dense_vs_handled -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark, prints its timings with --nocapture"]
+    fn dense_vs_handled() {
+        const COUNT: u64 = 100_000;
+
+        let start = Instant::now();
+        let mut handled = Handled::<u64>::default();
+        let handles = (0..COUNT).map(|i| handled.insert(i)).collect::<Vec<_>>();
+        let insert = start.elapsed();
+        let start = Instant::now();
+        let sum = handles
+            .iter()
+            .map(|h| *handled.get(h).unwrap())
+            .sum::<u64>();
+        let get = start.elapsed();
+        let start = Instant::now();
+        handles.iter().for_each(|h| {
+            black_box(handled.remove(h));
+        });
+        let remove = start.elapsed();
+        println!("Handled:      insert {insert:?}, get {get:?}, remove {remove:?}");
+
+        let start = Instant::now();
+        let mut dense = DenseHandled::<u64>::default();
+        let handles = (0..COUNT).map(|i| dense.insert(i)).collect::<Vec<_>>();
+        let insert = start.elapsed();
+        let start = Instant::now();
+        let dense_sum = handles.iter().map(|h| *dense.get(h).unwrap()).sum::<u64>();
+        let get = start.elapsed();
+        let start = Instant::now();
+        handles.iter().for_each(|h| {
+            black_box(dense.remove(h));
+        });
+        let remove = start.elapsed();
+        println!("DenseHandled: insert {insert:?}, get {get:?}, remove {remove:?}");
+
+        assert_eq!(black_box(sum), dense_sum);
+    }
+}
@@ -1,31 +1,100 @@
-use std::marker::{PhantomData, PhantomPinned};
+use std::{
+    collections::VecDeque,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::{PhantomData, PhantomPinned},
+};
 
-use bevy_app::{App, Plugin, Startup};
+use bevy_app::{App, First, Plugin, Startup};
 use bevy_ecs::{
     entity::EntityHashMap,
+    error::BevyError,
     resource::Resource,
     schedule::{IntoScheduleConfigs, IntoSystemSet, Schedule, ScheduleLabel, SystemSet},
-    system::{Commands, IntoSystem, ParamSet, Res, ResMut, SystemParam},
+    system::{
+        Commands, IntoSystem, ParamSet, Res, ResMut, StaticSystemParam, SystemParam, SystemState,
+    },
+    world::{Mut, World},
 };
 
 use derive_more::{Deref, DerefMut};
+use itertools::Itertools;
+use order::{DestroyGraph, DestroyOrderCycleError, StorageId};
+use thiserror::Error;
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+use self::dense::DenseHandled;
+use super::MAX_FRAMES_IN_FLIGHT;
+
 pub mod common;
+pub mod dense;
+pub mod order;
 
 pub struct StoragePlugin;
 
 impl Plugin for StoragePlugin {
     fn build(&self, app: &mut App) {
-        app.add_schedule(Schedule::new(Destroy));
+        if !app.is_plugin_added::<DeferredDestroyPlugin>() {
+            app.add_plugins(DeferredDestroyPlugin);
+        }
+
+        app.add_schedule(Schedule::new(Destroy))
+            .init_resource::<DestroyGraph>()
+            .configure_sets(
+                Startup,
+                StorageInitSet::InitSingleStorages.before(StorageInitSet::InitHandledStorages),
+            )
+            .add_systems(Startup, verify_destroy_order_system)
+            .configure_sets(
+                Destroy,
+                (
+                    DestroySet::FlushDeferred,
+                    DestroySet::Release,
+                    DestroySet::Storages,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Destroy,
+                (
+                    flush_deferred_destroy_system.in_set(DestroySet::FlushDeferred),
+                    destroy_storages_system.in_set(DestroySet::Storages),
+                ),
+            );
+    }
+}
+
+/// Runs the [`DeferredDestroyQueue`], which is also used outside of storages.
+pub struct DeferredDestroyPlugin;
+
+impl Plugin for DeferredDestroyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DeferredDestroyQueue>()
+            .add_systems(First, deferred_destroy_system);
     }
 }
 
+/// Startup sets the empty storages are inserted in. Singles come first as
+/// they hold the objects, like the device, handled values are created from.
 #[derive(SystemSet, PartialEq, Eq, Debug, Clone, Hash)]
 pub enum StorageInitSet {
+    InitSingleStorages,
     InitHandledStorages,
 }
 
+/// Sets of the [`Destroy`] schedule, in the order they run.
+#[derive(SystemSet, PartialEq, Eq, Debug, Clone, Hash)]
+pub enum DestroySet {
+    /// Runs what is left in the [`DeferredDestroyQueue`].
+    FlushDeferred,
+    /// Owners of objects hand them over to the storages.
+    Release,
+    /// The storages are destroyed by [`destroy_storages`], see
+    /// [`DestroyGraph::add_storage`].
+    Storages,
+}
+
 pub trait StoragesAppExt {
     fn app_mut(&mut self) -> &mut App;
 
@@ -37,6 +106,35 @@ pub trait StoragesAppExt {
         );
         app
     }
+
+    /// Same as [`register_handled_storage`](Self::register_handled_storage)
+    /// for a [`DenseHandled`] storage.
+    fn register_dense_storage<T: Send + Sync + 'static>(&mut self) -> &mut App {
+        let app = self.app_mut();
+        app.add_systems(
+            Startup,
+            init_dense_storage_system::<T>.in_set(StorageInitSet::InitHandledStorages),
+        );
+        app
+    }
+
+    fn register_single_storage<T: Send + Sync + 'static>(&mut self) -> &mut App {
+        let app = self.app_mut();
+        app.add_systems(
+            Startup,
+            init_single_storage_system::<T>.in_set(StorageInitSet::InitSingleStorages),
+        );
+        app
+    }
+}
+
+fn verify_destroy_order_system(world: &mut World) -> Result<(), BevyError> {
+    let order = world.resource_scope(|world, graph: Mut<DestroyGraph>| graph.verified(world))?;
+    debug!(
+        "Destroy order: {}",
+        order.iter().map(StorageId::name).join(" -> ")
+    );
+    Ok(())
 }
 
 impl StoragesAppExt for App {
@@ -49,6 +147,15 @@ fn init_handled_storage_system<T: Send + Sync + 'static>(mut commands: Commands)
     commands.insert_storage(Handled::<T>::default());
 }
 
+fn init_dense_storage_system<T: Send + Sync + 'static>(mut commands: Commands) {
+    commands.insert_storage(DenseHandled::<T>::default());
+}
+
+fn init_single_storage_system<T: Send + Sync + 'static>(mut commands: Commands) {
+    commands.insert_storage(Single::<T>::default());
+}
+
+/// Destroys the storages, run once by the app runner after the last update.
 #[derive(ScheduleLabel, PartialEq, Eq, Hash, Clone, Debug)]
 pub struct Destroy;
 
@@ -59,11 +166,43 @@ pub fn destroy_storage_system<T: Destroyable>(
     storage.data.destroy(&mut params);
 }
 
+/// Destroys the storages added to the [`DestroyGraph`] of `world`, in its
+/// order. Storages that were never inserted are skipped.
+pub fn destroy_storages(world: &mut World) -> Result<(), DestroyOrderCycleError> {
+    for destroy in world.resource::<DestroyGraph>().destroyers()? {
+        destroy(world);
+    }
+    Ok(())
+}
+
+fn destroy_storages_system(world: &mut World) -> Result<(), BevyError> {
+    destroy_storages(world)?;
+    Ok(())
+}
+
+/// Destroys the `T` storage of `world`, if it was inserted.
+fn destroy_storage_in_world<T: Destroyable>(world: &mut World) {
+    if !world.contains_resource::<RawStorage<T>>() {
+        return;
+    }
+
+    let mut state = SystemState::<StaticSystemParam<T::Params<'static, 'static>>>::new(world);
+    world.resource_scope(|world, mut storage: Mut<RawStorage<T>>| {
+        storage.data.destroy(&mut *state.get_mut(world));
+    });
+    state.apply(world);
+}
+
 pub fn destroy_storage_handled<T: Destroyable>()
 -> impl Fn(StorageMut<Handled<T>>, T::Params<'_, '_>) {
     destroy_storage_system::<Handled<T>>
 }
 
+pub fn destroy_storage_single<T: Destroyable>() -> impl Fn(StorageMut<Single<T>>, T::Params<'_, '_>)
+{
+    destroy_storage_system::<Single<T>>
+}
+
 pub fn destroy_storage<T: Destroyable>() -> impl Fn(StorageMut<T>, T::Params<'_, '_>) {
     destroy_storage_system::<T>
 }
@@ -87,6 +226,12 @@ pub type StorageOpt<'w, T> = Option<Res<'w, RawStorage<T>>>;
 pub type StorageHandled<'w, T> = Storage<'w, Handled<T>>;
 pub type StorageHandledMut<'w, T> = StorageMut<'w, Handled<T>>;
 
+pub type StorageDense<'w, T> = Storage<'w, DenseHandled<T>>;
+pub type StorageDenseMut<'w, T> = StorageMut<'w, DenseHandled<T>>;
+
+pub type StorageSingle<'w, T> = Storage<'w, Single<T>>;
+pub type StorageSingleMut<'w, T> = StorageMut<'w, Single<T>>;
+
 #[derive(Resource, Deref, DerefMut)]
 pub struct RawStorage<T> {
     pub data: T,
@@ -102,6 +247,150 @@ pub trait InsertStorageCommandsExt<'w, 's> {
     fn insert_storage<T: Send + Sync + 'static>(&mut self, data: T) {
         self.commands_mut().insert_resource(RawStorage { data });
     }
+
+    /// Stores `value` in the `Single<T>` storage, creating the storage if it
+    /// isn't registered.
+    ///
+    /// A value that is already stored is dropped without being destroyed.
+    fn insert_single_storage<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.commands_mut().queue(move |world: &mut World| {
+            let mut single = world.get_resource_or_insert_with(|| storage(Single::<T>::default()));
+            if single.insert_single(value).is_some() {
+                warn!(
+                    "Replaced the value of the `{}` single storage without destroying it",
+                    std::any::type_name::<T>()
+                );
+            }
+        });
+    }
+
+    /// Inserts `value` into the `Handled<T>` storage, creating the storage if
+    /// it doesn't exist, so it is destroyed with the rest of the storage.
+    fn track<T: Destroyable>(&mut self, value: T) -> Handle<T> {
+        let handle = Handle::new();
+        self.commands_mut().queue(move |world: &mut World| {
+            world
+                .get_resource_or_insert_with(|| storage(Handled::<T>::default()))
+                .inner
+                .insert(handle, value);
+        });
+        handle
+    }
+
+    /// Same as [`track`](Self::track) for a [`DenseHandled`] storage, used
+    /// for the many objects that are only destroyed with their storage, like
+    /// the buffers of chunk meshes.
+    fn track_dense<T: Destroyable>(&mut self, value: T) {
+        self.commands_mut().queue(move |world: &mut World| {
+            world
+                .get_resource_or_insert_with(|| storage(DenseHandled::<T>::default()))
+                .insert(value);
+        });
+    }
+
+    /// Removes the value of `handle` from its storage and destroys it right away.
+    ///
+    /// The caller must make sure the GPU no longer uses it.
+    fn untrack<T: Destroyable>(&mut self, handle: Handle<T>) {
+        self.commands_mut()
+            .queue(move |world: &mut World| destroy_tracked(world, handle));
+    }
+}
+
+/// Removes the value of `handle` from its `Handled<T>` storage and destroys it.
+fn destroy_tracked<T: Destroyable>(world: &mut World, handle: Handle<T>) {
+    let Some(mut value) = world
+        .get_resource_mut::<RawStorage<Handled<T>>>()
+        .and_then(|mut handled| handled.remove(&handle))
+    else {
+        return;
+    };
+
+    let mut state = SystemState::<StaticSystemParam<T::Params<'static, 'static>>>::new(world);
+    value.destroy(&mut *state.get_mut(world));
+    state.apply(world);
+}
+
+type DeferredDestroyFn = Box<dyn FnOnce(&mut World) + Send + Sync>;
+
+/// Tracked objects waiting to be destroyed once no frame in flight can use them.
+#[derive(Resource)]
+pub struct DeferredDestroyQueue {
+    frame: u64,
+    delay: u64,
+    pending: VecDeque<(u64, DeferredDestroyFn)>,
+}
+
+impl Default for DeferredDestroyQueue {
+    fn default() -> Self {
+        Self::with_delay(MAX_FRAMES_IN_FLIGHT as u64)
+    }
+}
+
+impl DeferredDestroyQueue {
+    /// A queue that destroys objects `delay` frames after they are pushed.
+    pub fn with_delay(delay: u64) -> Self {
+        Self {
+            frame: 0,
+            delay,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Destroys the value of `handle` once the delay has elapsed.
+    pub fn push<T: Destroyable>(&mut self, handle: Handle<T>) {
+        self.push_with(move |world| destroy_tracked(world, handle));
+    }
+
+    /// Runs `destroy` once the delay has elapsed, for objects that aren't tracked in a storage.
+    pub fn push_with(&mut self, destroy: impl FnOnce(&mut World) + Send + Sync + 'static) {
+        self.pending
+            .push_back((self.frame + self.delay, Box::new(destroy)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Takes every entry, due or not.
+    fn drain(&mut self) -> Vec<DeferredDestroyFn> {
+        self.pending.drain(..).map(|(_, destroy)| destroy).collect()
+    }
+
+    /// Starts the next frame and returns the entries that are due.
+    fn advance(&mut self) -> Vec<DeferredDestroyFn> {
+        self.frame += 1;
+
+        let due = self
+            .pending
+            .iter()
+            .take_while(|(destroy_at, _)| *destroy_at <= self.frame)
+            .count();
+        self.pending
+            .drain(..due)
+            .map(|(_, destroy)| destroy)
+            .collect()
+    }
+}
+
+fn deferred_destroy_system(world: &mut World) {
+    let due = world.resource_mut::<DeferredDestroyQueue>().advance();
+    for destroy in due {
+        destroy(world);
+    }
+}
+
+/// Destroys the entries of the queue without waiting for their delay, the
+/// device is idle once the app exits.
+fn flush_deferred_destroy_system(world: &mut World) {
+    let pending = world.resource_mut::<DeferredDestroyQueue>().drain();
+    for destroy in pending {
+        destroy(world);
+    }
 }
 
 impl<'w, 's> InsertStorageCommandsExt<'w, 's> for Commands<'w, 's> {
@@ -112,9 +401,16 @@ impl<'w, 's> InsertStorageCommandsExt<'w, 's> for Commands<'w, 's> {
 
 /// A type that must be destroyed at the end of the program execution.
 pub trait Destroyable: Send + Sync + 'static {
-    type Params<'w, 's>: SystemParam;
+    /// Params fetched for the destroy system. Their `Item` must name the same
+    /// type so the params can be fetched from generic systems.
+    type Params<'w, 's>: for<'w2, 's2> SystemParam<Item<'w2, 's2> = Self::Params<'w2, 's2>>;
 
     fn destroy(&mut self, params: &mut Self::Params<'_, '_>);
+
+    /// Storages that must still be alive while this one is destroyed.
+    fn dependencies() -> Vec<StorageId> {
+        Vec::new()
+    }
 }
 
 pub struct Handled<T> {
@@ -129,16 +425,550 @@ impl<T> Default for Handled<T> {
     }
 }
 
-// TODO: Custom `Hash` impl
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Handle<T>(Uuid, PhantomData<T>);
+impl<T> Handled<T> {
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        let handle = Handle::new();
+        self.inner.insert(handle, value);
+        handle
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.inner.get(handle)
+    }
+
+    /// Whether the value of `handle` is still stored, it isn't once removed.
+    pub fn contains(&self, handle: &Handle<T>) -> bool {
+        self.inner.contains_key(handle)
+    }
+
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
+        self.inner.get_mut(handle)
+    }
+
+    /// Removes the value without destroying it.
+    pub fn remove(&mut self, handle: &Handle<T>) -> Option<T> {
+        self.inner.remove(handle)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Iterates over the stored values in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.inner.iter().map(|(handle, value)| (*handle, value))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle<T>, &mut T)> {
+        self.inner
+            .iter_mut()
+            .map(|(handle, value)| (*handle, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = Handle<T>> {
+        self.inner.keys().copied()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.inner.values()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.inner.values_mut()
+    }
+}
+
+/// Identifies a value in a [`Handled`] storage.
+///
+/// The traits are implemented by hand so they don't require them from `T`.
+pub struct Handle<T>(Uuid, PhantomData<fn() -> T>);
+
+impl<T> Handle<T> {
+    fn new() -> Self {
+        Self(Uuid::new_v4(), PhantomData)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.0).finish()
+    }
+}
 
 impl<T: Destroyable> Destroyable for Handled<T> {
     type Params<'w, 's> = T::Params<'w, 's>;
 
+    /// Destroys the stored values and leaves the storage empty.
     fn destroy(&mut self, params: &mut T::Params<'_, '_>) {
-        for (_, val) in &mut self.inner {
+        for (_, mut val) in self.inner.drain() {
             val.destroy(params);
         }
     }
+
+    fn dependencies() -> Vec<StorageId> {
+        T::dependencies()
+    }
+}
+
+/// A storage of values under stable keys chosen by the caller, e.g. the mesh
+/// buffers of a chunk coordinate.
+///
+/// Unlike [`Handled`], inserting under a key that is already used gives the
+/// replaced value back so it can be destroyed.
+pub struct KeyedHandled<K, T> {
+    inner: hashbrown::HashMap<K, T>,
+}
+
+impl<K, T> Default for KeyedHandled<K, T> {
+    fn default() -> Self {
+        Self {
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, T> KeyedHandled<K, T> {
+    /// Stores `value` under `key` and returns the value it replaced, which
+    /// isn't destroyed.
+    pub fn insert(&mut self, key: K, value: T) -> Option<T> {
+        self.inner.insert(key, value)
+    }
+
+    /// Removes the value without destroying it.
+    pub fn remove(&mut self, key: &K) -> Option<T> {
+        self.inner.remove(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    /// Iterates over the stored values in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &T)> {
+        self.inner.iter()
+    }
+
+    /// Removes all values without destroying them.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, T)> {
+        self.inner.drain()
+    }
+}
+
+impl<K: Eq + Hash + Send + Sync + 'static, T: Destroyable> Destroyable for KeyedHandled<K, T> {
+    type Params<'w, 's> = T::Params<'w, 's>;
+
+    /// Destroys the stored values and leaves the storage empty.
+    fn destroy(&mut self, params: &mut T::Params<'_, '_>) {
+        for (_, mut val) in self.inner.drain() {
+            val.destroy(params);
+        }
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        T::dependencies()
+    }
+}
+
+/// A storage that holds at most one `T`, e.g. the `ash::Device`.
+pub struct Single<T> {
+    inner: Option<T>,
+}
+
+impl<T> Default for Single<T> {
+    fn default() -> Self {
+        Self { inner: None }
+    }
+}
+
+impl<T> Single<T> {
+    /// Stores `value` and returns the previously stored one, if any.
+    pub fn insert_single(&mut self, value: T) -> Option<T> {
+        self.inner.replace(value)
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.inner.as_ref()
+    }
+
+    /// Same as [`Single::get`] but reports an empty storage as an error.
+    pub fn try_get(&self) -> Result<&T, EmptySingleError> {
+        self.inner
+            .as_ref()
+            .ok_or(EmptySingleError(std::any::type_name::<T>()))
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.inner.as_mut()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_none()
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Single storage of `{0}` is empty")]
+pub struct EmptySingleError(&'static str);
+
+impl<T: Destroyable> Destroyable for Single<T> {
+    type Params<'w, 's> = T::Params<'w, 's>;
+
+    /// Destroys the stored value and leaves the storage empty.
+    fn destroy(&mut self, params: &mut T::Params<'_, '_>) {
+        if let Some(mut val) = self.inner.take() {
+            val.destroy(params);
+        }
+    }
+
+    fn dependencies() -> Vec<StorageId> {
+        T::dependencies()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct DestroyedCount(u32);
+
+    struct DummyDevice;
+
+    impl Destroyable for DummyDevice {
+        type Params<'w, 's> = ResMut<'w, DestroyedCount>;
+
+        fn destroy(&mut self, params: &mut Self::Params<'_, '_>) {
+            params.0 += 1;
+        }
+    }
+
+    #[test]
+    fn single_storage_test() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .init_resource::<DestroyedCount>()
+            .register_single_storage::<DummyDevice>()
+            .add_systems(Destroy, destroy_storage_single::<DummyDevice>());
+
+        app.update();
+
+        let mut single = app
+            .world_mut()
+            .resource_mut::<RawStorage<Single<DummyDevice>>>();
+        assert!(single.is_empty());
+        assert!(single.insert_single(DummyDevice).is_none());
+        assert!(single.get().is_some());
+
+        app.world_mut().run_schedule(Destroy);
+        assert_eq!(app.world().resource::<DestroyedCount>().0, 1);
+        assert!(
+            app.world()
+                .resource::<RawStorage<Single<DummyDevice>>>()
+                .is_empty()
+        );
+
+        // An emptied storage has nothing left to destroy.
+        app.world_mut().run_schedule(Destroy);
+        assert_eq!(app.world().resource::<DestroyedCount>().0, 1);
+    }
+
+    #[test]
+    fn empty_single_reports_its_type() {
+        let mut single = Single::<u8>::default();
+        let err = single.try_get().unwrap_err();
+        assert_eq!(err.to_string(), "Single storage of `u8` is empty");
+
+        single.insert_single(4);
+        assert_eq!(single.try_get().ok(), Some(&4));
+    }
+
+    #[test]
+    fn registered_storages_are_accessible() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .register_handled_storage::<u32>()
+            .register_single_storage::<u64>();
+        app.update();
+
+        let mut state =
+            SystemState::<(StorageHandledMut<u32>, StorageSingleMut<u64>)>::new(app.world_mut());
+        let (mut handled, mut single) = state.get_mut(app.world_mut());
+        let handle = handled.insert(7);
+        single.insert_single(11);
+
+        let mut state =
+            SystemState::<(StorageHandled<u32>, StorageSingle<u64>)>::new(app.world_mut());
+        let (handled, single) = state.get(app.world());
+        assert_eq!(handled.get(&handle), Some(&7));
+        assert_eq!(single.get(), Some(&11));
+    }
+
+    #[test]
+    fn dense_storage_is_destroyed_like_handled() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .init_resource::<DestroyedCount>()
+            .register_dense_storage::<DummyDevice>();
+        app.world_mut()
+            .resource_mut::<DestroyGraph>()
+            .add_storage::<DenseHandled<DummyDevice>>();
+        app.update();
+
+        let mut state = SystemState::<StorageDenseMut<DummyDevice>>::new(app.world_mut());
+        let mut dense = state.get_mut(app.world_mut());
+        let handle = dense.insert(DummyDevice);
+        dense.insert(DummyDevice);
+        assert!(dense.remove(&handle).is_some());
+
+        app.world_mut().run_schedule(Destroy);
+        assert_eq!(app.world().resource::<DestroyedCount>().0, 1);
+    }
+
+    #[test]
+    fn track_dense_creates_the_storage() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .init_resource::<DestroyedCount>();
+        app.world_mut()
+            .resource_mut::<DestroyGraph>()
+            .add_storage::<DenseHandled<DummyDevice>>();
+
+        let world = app.world_mut();
+        world.commands().track_dense(DummyDevice);
+        world.commands().track_dense(DummyDevice);
+        world.flush();
+        assert_eq!(
+            world
+                .resource::<RawStorage<DenseHandled<DummyDevice>>>()
+                .len(),
+            2
+        );
+
+        world.run_schedule(Destroy);
+        assert_eq!(app.world().resource::<DestroyedCount>().0, 2);
+    }
+
+    #[test]
+    fn iterate_handled() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .register_handled_storage::<u32>();
+        app.update();
+
+        let mut state = SystemState::<StorageHandledMut<u32>>::new(app.world_mut());
+        let mut handled = state.get_mut(app.world_mut());
+        let handles = [1, 2, 3].map(|value| handled.insert(value));
+
+        for value in handled.values_mut() {
+            *value *= 10;
+        }
+
+        let mut state = SystemState::<StorageHandled<u32>>::new(app.world_mut());
+        let handled = state.get(app.world());
+        let mut values = handled.values().copied().collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, [10, 20, 30]);
+
+        for (handle, value) in handled.iter() {
+            assert_eq!(handled.get(&handle), Some(value));
+        }
+        assert!(handled.keys().all(|handle| handles.contains(&handle)));
+        assert_eq!(handled.keys().count(), 3);
+    }
+
+    #[test]
+    fn removed_handles_are_not_contained() {
+        let mut handled = Handled::<u32>::default();
+        let first = handled.insert(1);
+        let second = handled.insert(2);
+
+        assert!(handled.contains(&first));
+
+        assert_eq!(handled.remove(&first), Some(1));
+        assert!(!handled.contains(&first));
+        assert!(handled.contains(&second));
+    }
+
+    #[test]
+    fn keyed_insert_returns_the_replaced_value() {
+        let mut keyed = KeyedHandled::<(i32, i32), u32>::default();
+        assert_eq!(keyed.insert((0, 1), 5), None);
+        assert_eq!(keyed.insert((0, 2), 6), None);
+        assert_eq!(keyed.insert((0, 1), 7), Some(5));
+
+        assert_eq!(keyed.iter().count(), 2);
+        assert_eq!(keyed.remove(&(0, 2)), Some(6));
+        assert!(!keyed.contains_key(&(0, 2)));
+        assert_eq!(keyed.drain().collect_vec(), [((0, 1), 7)]);
+        assert!(!keyed.contains_key(&(0, 1)));
+    }
+
+    #[test]
+    fn keyed_storage_destroys_current_values() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .init_resource::<DestroyedCount>();
+        app.world_mut()
+            .resource_mut::<DestroyGraph>()
+            .add_storage::<KeyedHandled<u8, DummyDevice>>();
+
+        let world = app.world_mut();
+        let mut keyed = KeyedHandled::<u8, DummyDevice>::default();
+        keyed.insert(0, DummyDevice);
+        // The replaced value is handed back instead of being destroyed with the storage.
+        assert!(keyed.insert(0, DummyDevice).is_some());
+        keyed.insert(1, DummyDevice);
+        world.insert_resource(storage(keyed));
+
+        world.run_schedule(Destroy);
+        assert_eq!(world.resource::<DestroyedCount>().0, 2);
+    }
+
+    #[test]
+    fn insert_single_storage() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin);
+
+        // Without registering the storage first.
+        let world = app.world_mut();
+        world.commands().insert_single_storage(3u8);
+        world.flush();
+        assert_eq!(world.resource::<RawStorage<Single<u8>>>().get(), Some(&3));
+
+        // Into a registered storage.
+        app.register_single_storage::<u16>();
+        app.update();
+        let world = app.world_mut();
+        assert!(world.resource::<RawStorage<Single<u16>>>().is_empty());
+        world.commands().insert_single_storage(5u16);
+        world.flush();
+
+        let mut state = SystemState::<StorageSingle<u16>>::new(world);
+        assert_eq!(state.get(world).get(), Some(&5));
+    }
+
+    #[test]
+    fn track_and_untrack() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .init_resource::<DestroyedCount>();
+        app.world_mut()
+            .resource_mut::<DestroyGraph>()
+            .add_storage::<Handled<DummyDevice>>();
+
+        let world = app.world_mut();
+        let first = world.commands().track(DummyDevice);
+        let second = world.commands().track(DummyDevice);
+        world.flush();
+
+        let handled = world.resource::<RawStorage<Handled<DummyDevice>>>();
+        assert_eq!(handled.len(), 2);
+        assert!(handled.get(&first).is_some());
+        assert_ne!(first, second);
+
+        world.commands().untrack(first);
+        world.flush();
+
+        assert_eq!(world.resource::<DestroyedCount>().0, 1);
+        let handled = world.resource::<RawStorage<Handled<DummyDevice>>>();
+        assert!(handled.get(&first).is_none());
+        assert!(handled.get(&second).is_some());
+
+        // Only the object that is still tracked is destroyed at exit.
+        world.run_schedule(Destroy);
+        assert_eq!(world.resource::<DestroyedCount>().0, 2);
+        assert!(
+            world
+                .resource::<RawStorage<Handled<DummyDevice>>>()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn deferred_destroy_after_delay() {
+        const DELAY: u64 = 3;
+
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .init_resource::<DestroyedCount>()
+            .insert_resource(DeferredDestroyQueue::with_delay(DELAY));
+
+        let world = app.world_mut();
+        let handle = world.commands().track(DummyDevice);
+        world.flush();
+        world.resource_mut::<DeferredDestroyQueue>().push(handle);
+
+        for _ in 0..DELAY - 1 {
+            app.update();
+            assert_eq!(app.world().resource::<DestroyedCount>().0, 0);
+        }
+
+        app.update();
+        assert_eq!(app.world().resource::<DestroyedCount>().0, 1);
+        assert!(app.world().resource::<DeferredDestroyQueue>().is_empty());
+        assert!(
+            app.world()
+                .resource::<RawStorage<Handled<DummyDevice>>>()
+                .is_empty()
+        );
+
+        app.update();
+        assert_eq!(app.world().resource::<DestroyedCount>().0, 1);
+    }
+
+    #[test]
+    fn destroy_flushes_and_releases_before_the_storages() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin)
+            .init_resource::<DestroyedCount>()
+            .register_handled_storage::<DummyDevice>()
+            .add_systems(
+                Destroy,
+                (|mut commands: Commands| {
+                    commands.track(DummyDevice);
+                })
+                .in_set(DestroySet::Release),
+            );
+        app.world_mut()
+            .resource_mut::<DestroyGraph>()
+            .add_storage::<Handled<DummyDevice>>();
+        app.update();
+
+        let world = app.world_mut();
+        let handle = world.commands().track(DummyDevice);
+        world.flush();
+        world.resource_mut::<DeferredDestroyQueue>().push(handle);
+
+        // The queued object is destroyed without waiting for its delay, and
+        // the released one with its storage.
+        world.run_schedule(Destroy);
+        assert_eq!(world.resource::<DestroyedCount>().0, 2);
+        assert!(world.resource::<DeferredDestroyQueue>().is_empty());
+    }
 }
@@ -0,0 +1,387 @@
+use std::any::{TypeId, type_name};
+
+use bevy_ecs::{
+    component::ComponentId,
+    query::Access,
+    resource::Resource,
+    system::{StaticSystemParam, SystemState},
+    world::World,
+};
+use itertools::Itertools;
+use thiserror::Error;
+
+use super::{Destroyable, RawStorage, destroy_storage_in_world};
+
+/// Identifies a storage type (e.g. `Handled<vk::Pipeline>`) in the destroy order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StorageId {
+    type_id: TypeId,
+    name: &'static str,
+}
+
+impl StorageId {
+    pub fn of<S: 'static>() -> Self {
+        Self {
+            type_id: TypeId::of::<S>(),
+            name: type_name::<S>(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Dependency graph between storages, walked in order by [`destroy_storages`](super::destroy_storages).
+///
+/// An edge `(a, b)` means that `a` must be destroyed before `b`.
+#[derive(Resource, Default)]
+pub struct DestroyGraph {
+    nodes: Vec<StorageId>,
+    edges: Vec<(usize, usize)>,
+    storages: hashbrown::HashMap<StorageId, AddedStorage>,
+}
+
+/// Functions of a storage added with [`DestroyGraph::add_storage`].
+#[derive(Clone, Copy)]
+struct AddedStorage {
+    destroy: fn(&mut World),
+    resource_id: fn(&mut World) -> ComponentId,
+    destroy_access: fn(&mut World) -> Access<ComponentId>,
+}
+
+fn storage_resource_id<S: Send + Sync + 'static>(world: &mut World) -> ComponentId {
+    world.register_resource::<RawStorage<S>>()
+}
+
+/// Resources fetched by the params used to destroy the `S` storage.
+fn destroy_access<S: Destroyable>(world: &mut World) -> Access<ComponentId> {
+    SystemState::<StaticSystemParam<S::Params<'static, 'static>>>::new(world)
+        .meta()
+        .component_access_set()
+        .combined_access()
+        .clone()
+}
+
+impl DestroyGraph {
+    pub fn add_node(&mut self, id: StorageId) -> usize {
+        match self.nodes.iter().position(|node| *node == id) {
+            Some(index) => index,
+            None => {
+                self.nodes.push(id);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Adds the `S` storage, destroyed after the storages it depends on
+    /// according to [`Destroyable::dependencies`].
+    pub fn add_storage<S: Destroyable>(&mut self) -> &mut Self {
+        let id = StorageId::of::<S>();
+        self.add_node(id);
+        self.storages.insert(
+            id,
+            AddedStorage {
+                destroy: destroy_storage_in_world::<S>,
+                resource_id: storage_resource_id::<S>,
+                destroy_access: destroy_access::<S>,
+            },
+        );
+        for dependency in S::dependencies() {
+            self.add_edge(id, dependency);
+        }
+        self
+    }
+
+    /// Declares that storage `A` must be destroyed after storage `B`.
+    pub fn add_destroy_after<A: 'static, B: 'static>(&mut self) -> &mut Self {
+        self.add_edge(StorageId::of::<B>(), StorageId::of::<A>());
+        self
+    }
+
+    /// Declares that `after` must be destroyed after `before`.
+    pub fn add_edge(&mut self, before: StorageId, after: StorageId) {
+        let before = self.add_node(before);
+        let after = self.add_node(after);
+        if !self.edges.contains(&(before, after)) {
+            self.edges.push((before, after));
+        }
+    }
+
+    /// Returns the storages in the order they will be destroyed.
+    ///
+    /// Storages that don't depend on each other keep their registration order.
+    pub fn sorted(&self) -> Result<Vec<StorageId>, DestroyOrderCycleError> {
+        let mut in_degree = vec![0usize; self.nodes.len()];
+        for (_, after) in &self.edges {
+            in_degree[*after] += 1;
+        }
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut visited = vec![false; self.nodes.len()];
+
+        while order.len() < self.nodes.len() {
+            let Some(next) = (0..self.nodes.len()).find(|i| !visited[*i] && in_degree[*i] == 0)
+            else {
+                let remaining = (0..self.nodes.len())
+                    .filter(|i| !visited[*i])
+                    .map(|i| self.nodes[i].name)
+                    .collect();
+                return Err(DestroyOrderCycleError(remaining));
+            };
+
+            visited[next] = true;
+            order.push(self.nodes[next]);
+
+            for (before, after) in &self.edges {
+                if *before == next {
+                    in_degree[*after] -= 1;
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Same as [`sorted`](Self::sorted), but also checks that no added storage
+    /// is destroyed after a storage its destroy params fetch, like the
+    /// `Single<ash::Device>` storage, which a missing dependency would allow.
+    pub fn verified(&self, world: &mut World) -> Result<Vec<StorageId>, DestroyOrderError> {
+        let order = self.sorted()?;
+        let resource_ids = order
+            .iter()
+            .map(|id| {
+                self.storages
+                    .get(id)
+                    .map(|storage| (storage.resource_id)(world))
+            })
+            .collect_vec();
+
+        for (index, id) in order.iter().enumerate() {
+            let Some(storage) = self.storages.get(id) else {
+                continue;
+            };
+            let access = (storage.destroy_access)(world);
+            for (used, resource_id) in order[..index].iter().zip(&resource_ids) {
+                if resource_id.is_some_and(|resource_id| {
+                    access.has_resource_read(resource_id) || access.has_resource_write(resource_id)
+                }) {
+                    return Err(DestroyOrderError::UsedAfterDestroy {
+                        storage: id.name,
+                        used: used.name,
+                    });
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Returns the destroy functions of the added storages, in order.
+    pub fn destroyers(&self) -> Result<Vec<fn(&mut World)>, DestroyOrderCycleError> {
+        Ok(self
+            .sorted()?
+            .iter()
+            .filter_map(|id| self.storages.get(id).map(|storage| storage.destroy))
+            .collect())
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Destroy order contains a cycle between: {0:?}")]
+pub struct DestroyOrderCycleError(Vec<&'static str>);
+
+#[derive(Error, Debug)]
+pub enum DestroyOrderError {
+    #[error(transparent)]
+    Cycle(#[from] DestroyOrderCycleError),
+    #[error("`{storage}` is destroyed after `{used}`, which it uses to be destroyed")]
+    UsedAfterDestroy {
+        storage: &'static str,
+        used: &'static str,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use bevy_app::App;
+    use bevy_ecs::world::Mut;
+
+    use super::*;
+    use crate::rendering::storage::{
+        Destroy, Handled, Single, Storage, StoragePlugin,
+        common::{CommonStoragesPlugin, SurfacePack, SwapchainPack},
+        dense::DenseHandled,
+    };
+
+    #[test]
+    fn common_destroy_order() {
+        let mut app = App::new();
+        app.add_plugins((StoragePlugin, CommonStoragesPlugin));
+
+        let order = app
+            .world_mut()
+            .resource_scope(|world, graph: Mut<DestroyGraph>| graph.verified(world))
+            .unwrap();
+        let position = |id: StorageId| order.iter().position(|node| *node == id).unwrap();
+
+        assert_eq!(
+            order.last(),
+            Some(&StorageId::of::<Single<ash::Instance>>())
+        );
+
+        let device = position(StorageId::of::<Single<ash::Device>>());
+        for handles in [
+            StorageId::of::<Handled<vk::Framebuffer>>(),
+            StorageId::of::<Handled<vk::ImageView>>(),
+            StorageId::of::<Handled<vk::Semaphore>>(),
+            StorageId::of::<Handled<vk::Fence>>(),
+            StorageId::of::<Handled<vk::CommandPool>>(),
+            StorageId::of::<Handled<vk::Pipeline>>(),
+            StorageId::of::<Handled<vk::PipelineLayout>>(),
+            StorageId::of::<Handled<vk::RenderPass>>(),
+            StorageId::of::<Single<SwapchainPack>>(),
+            StorageId::of::<Single<gpu_allocator::vulkan::Allocator>>(),
+            StorageId::of::<Handled<vk::DescriptorPool>>(),
+            StorageId::of::<Handled<vk::DescriptorSetLayout>>(),
+            StorageId::of::<Handled<crate::rendering::buffer::Buffer>>(),
+            StorageId::of::<DenseHandled<crate::rendering::buffer::Buffer>>(),
+            StorageId::of::<Handled<crate::rendering::image::Image>>(),
+            StorageId::of::<Handled<vk::Sampler>>(),
+        ] {
+            assert!(
+                position(handles) < device,
+                "{} outlives the device",
+                handles.name()
+            );
+        }
+
+        #[cfg(feature = "compute")]
+        assert!(
+            position(StorageId::of::<
+                Handled<crate::rendering::compute::ComputePipeline>,
+            >()) < device
+        );
+
+        assert!(
+            position(StorageId::of::<Single<SwapchainPack>>())
+                < position(StorageId::of::<Single<SurfacePack>>())
+        );
+        assert!(
+            position(StorageId::of::<Handled<crate::rendering::texture::Texture>>())
+                < position(StorageId::of::<Single<gpu_allocator::vulkan::Allocator>>())
+        );
+        assert!(
+            position(StorageId::of::<Handled<crate::rendering::buffer::Buffer>>())
+                < position(StorageId::of::<Single<gpu_allocator::vulkan::Allocator>>())
+        );
+        assert!(
+            position(StorageId::of::<Handled<crate::rendering::image::Image>>())
+                < position(StorageId::of::<Single<gpu_allocator::vulkan::Allocator>>())
+        );
+        assert!(
+            position(StorageId::of::<Handled<vk::ImageView>>())
+                < position(StorageId::of::<Handled<crate::rendering::image::Image>>())
+        );
+        assert!(
+            position(StorageId::of::<Handled<vk::Framebuffer>>())
+                < position(StorageId::of::<Handled<vk::ImageView>>())
+        );
+
+        // Storages are initialized empty, so this only checks that the schedule builds.
+        app.update();
+        app.world_mut().run_schedule(Destroy);
+    }
+
+    #[test]
+    fn destroy_order_cycle() {
+        struct A;
+        struct B;
+
+        let mut graph = DestroyGraph::default();
+        graph
+            .add_destroy_after::<A, B>()
+            .add_destroy_after::<B, A>();
+
+        assert!(graph.sorted().is_err());
+    }
+
+    struct Device;
+
+    impl Destroyable for Device {
+        type Params<'w, 's> = ();
+
+        fn destroy(&mut self, _params: &mut Self::Params<'_, '_>) {}
+    }
+
+    /// Destroyed with the device, like the Vulkan handles.
+    struct DeviceObject;
+
+    impl Destroyable for DeviceObject {
+        type Params<'w, 's> = Storage<'w, Single<Device>>;
+
+        fn destroy(&mut self, _params: &mut Self::Params<'_, '_>) {}
+
+        fn dependencies() -> Vec<StorageId> {
+            vec![StorageId::of::<Single<Device>>()]
+        }
+    }
+
+    /// Same as [`DeviceObject`] without declaring its dependency on the device.
+    struct UndeclaredDeviceObject;
+
+    impl Destroyable for UndeclaredDeviceObject {
+        type Params<'w, 's> = Storage<'w, Single<Device>>;
+
+        fn destroy(&mut self, _params: &mut Self::Params<'_, '_>) {}
+    }
+
+    #[test]
+    fn device_is_destroyed_after_its_objects() {
+        let mut graph = DestroyGraph::default();
+        graph
+            .add_storage::<Single<Device>>()
+            .add_storage::<Handled<DeviceObject>>();
+
+        let order = graph.verified(&mut World::new()).unwrap();
+        assert_eq!(
+            order,
+            [
+                StorageId::of::<Handled<DeviceObject>>(),
+                StorageId::of::<Single<Device>>()
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_device_dependency_is_reported() {
+        let mut graph = DestroyGraph::default();
+        graph
+            .add_storage::<Single<Device>>()
+            .add_storage::<Handled<UndeclaredDeviceObject>>();
+
+        // Without the dependency the registration order destroys the device first.
+        assert!(graph.sorted().is_ok());
+        let err = graph.verified(&mut World::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            DestroyOrderError::UsedAfterDestroy { storage, used }
+                if storage == StorageId::of::<Handled<UndeclaredDeviceObject>>().name()
+                    && used == StorageId::of::<Single<Device>>().name()
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "which it uses to be destroyed")]
+    fn missing_dependency_fails_startup() {
+        let mut app = App::new();
+        app.add_plugins(StoragePlugin);
+        app.world_mut()
+            .resource_mut::<DestroyGraph>()
+            .add_storage::<Single<Device>>()
+            .add_storage::<Handled<UndeclaredDeviceObject>>();
+
+        app.update();
+    }
+}
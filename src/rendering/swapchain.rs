@@ -0,0 +1,398 @@
+use std::f32::consts::FRAC_PI_2;
+
+use ash::vk;
+use bevy_ecs::resource::Resource;
+use glam::Mat4;
+
+/// Swapchain options kept by the app for every recreation, collected from
+/// their resources.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SwapchainConfig {
+    /// Whether an HDR format is chosen when the surface supports one, see
+    /// [`HdrMode`](super::HdrMode).
+    pub hdr: bool,
+    pub surface_formats: SurfaceFormatPreference,
+    pub image_count: SwapchainImageCount,
+    pub clipped: SwapchainClipped,
+    pub composite_alpha: CompositeAlpha,
+    pub present_mode: PresentMode,
+}
+
+/// Acceptable surface formats of the swapchain, most preferred first.
+///
+/// The first format of the surface is used when none of them is available.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct SurfaceFormatPreference(pub Vec<vk::SurfaceFormatKHR>);
+
+impl Default for SurfaceFormatPreference {
+    fn default() -> Self {
+        Self(vec![vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }])
+    }
+}
+
+impl SurfaceFormatPreference {
+    /// The most preferred format in `available_formats`.
+    pub fn choose(
+        &self,
+        available_formats: &[vk::SurfaceFormatKHR],
+    ) -> Option<vk::SurfaceFormatKHR> {
+        self.0
+            .iter()
+            .copied()
+            .find(|preferred| available_formats.contains(preferred))
+    }
+}
+
+/// Desired number of swapchain images, e.g. 2 for double or 3 for triple
+/// buffering. `None` uses one more than the surface minimum.
+///
+/// Changing it recreates the swapchain.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SwapchainImageCount(pub Option<u32>);
+
+impl SwapchainImageCount {
+    /// The count to request from a surface supporting `[min, max]` images,
+    /// where a `max` of 0 means there is no upper limit.
+    pub fn clamp(&self, min: u32, max: u32) -> u32 {
+        let desired = self.0.unwrap_or(min + 1).max(min);
+        if max > 0 { desired.min(max) } else { desired }
+    }
+}
+
+/// Whether the presentation engine may skip rendering pixels of the swapchain
+/// images that are hidden, e.g. by other windows. Enabled by default, which
+/// is faster on some platforms.
+///
+/// Hidden pixels are undefined when clipped, so reading the swapchain images
+/// back, like for screenshots, may want it disabled. Changing it recreates
+/// the swapchain.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapchainClipped(pub bool);
+
+impl Default for SwapchainClipped {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// How the swapchain images are composited with what is behind the window.
+///
+/// Anything but [`CompositeAlpha::Opaque`] makes the primary window
+/// transparent, so the alpha of the clear color and of the rendered pixels
+/// shows the desktop through. Changing it recreates the swapchain, but the
+/// window only becomes transparent if it is set before startup.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompositeAlpha {
+    #[default]
+    Opaque,
+    /// Colors are already multiplied by their alpha.
+    PreMultiplied,
+    /// The compositor multiplies colors by their alpha.
+    PostMultiplied,
+    /// Decided by the platform, e.g. through native window APIs.
+    Inherit,
+}
+
+impl CompositeAlpha {
+    pub fn flags(self) -> vk::CompositeAlphaFlagsKHR {
+        match self {
+            Self::Opaque => vk::CompositeAlphaFlagsKHR::OPAQUE,
+            Self::PreMultiplied => vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            Self::PostMultiplied => vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+            Self::Inherit => vk::CompositeAlphaFlagsKHR::INHERIT,
+        }
+    }
+
+    /// The mode to create the swapchain with on a surface supporting the
+    /// `supported` modes.
+    ///
+    /// Falls back to opaque, or to any supported mode on the surfaces that
+    /// don't support opaque.
+    pub fn choose(self, supported: vk::CompositeAlphaFlagsKHR) -> vk::CompositeAlphaFlagsKHR {
+        [self.flags(), vk::CompositeAlphaFlagsKHR::OPAQUE]
+            .into_iter()
+            .find(|mode| supported.contains(*mode))
+            .unwrap_or_else(|| {
+                // The lowest supported bit.
+                vk::CompositeAlphaFlagsKHR::from_raw(
+                    supported.as_raw() & supported.as_raw().wrapping_neg(),
+                )
+            })
+    }
+}
+
+/// How presented images are queued for display.
+///
+/// Falls back to [`PresentMode::Fifo`], which every surface supports, when
+/// the surface lacks the mode. Changing it recreates the swapchain.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Replaces the queued image with the newest one, without tearing.
+    #[default]
+    Mailbox,
+    /// Waits for the vertical blank, the frame rate is capped by the display.
+    Fifo,
+    /// Like [`PresentMode::Fifo`], but presents late images right away, which
+    /// may tear.
+    FifoRelaxed,
+    /// Presents right away, which may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn vk(self) -> vk::PresentModeKHR {
+        match self {
+            Self::Mailbox => vk::PresentModeKHR::MAILBOX,
+            Self::Fifo => vk::PresentModeKHR::FIFO,
+            Self::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            Self::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+
+    /// The mode to create the swapchain with on a surface supporting `available`.
+    pub fn choose(self, available: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+        if available.contains(&self.vk()) {
+            self.vk()
+        } else {
+            vk::PresentModeKHR::FIFO
+        }
+    }
+}
+
+/// Clockwise quarter turns of a surface `transform`. Mirroring transforms
+/// aren't compensated and count as no rotation.
+pub fn quarter_turns(transform: vk::SurfaceTransformFlagsKHR) -> u32 {
+    match transform {
+        vk::SurfaceTransformFlagsKHR::ROTATE_90 => 1,
+        vk::SurfaceTransformFlagsKHR::ROTATE_180 => 2,
+        vk::SurfaceTransformFlagsKHR::ROTATE_270 => 3,
+        _ => 0,
+    }
+}
+
+/// Extent of the swapchain images presented with `transform` on a surface of
+/// `extent`: the sides are swapped for a quarter turn.
+pub fn pre_rotated_extent(
+    extent: vk::Extent2D,
+    transform: vk::SurfaceTransformFlagsKHR,
+) -> vk::Extent2D {
+    if quarter_turns(transform) % 2 == 1 {
+        vk::Extent2D {
+            width: extent.height,
+            height: extent.width,
+        }
+    } else {
+        extent
+    }
+}
+
+/// Applied after the projection, rotates clip space by the turns of
+/// `transform` so that the presented image appears upright.
+pub fn pre_rotation(transform: vk::SurfaceTransformFlagsKHR) -> Mat4 {
+    Mat4::from_rotation_z(quarter_turns(transform) as f32 * FRAC_PI_2)
+}
+
+/// Decides when a suboptimal swapchain gets recreated.
+///
+/// Some drivers keep reporting `VK_SUBOPTIMAL_KHR` even for a freshly created
+/// swapchain, so it is only recreated once per run of suboptimal frames.
+#[derive(Debug, Default)]
+pub struct SuboptimalTracker {
+    recreated: bool,
+}
+
+impl SuboptimalTracker {
+    /// Records whether the last acquire or present reported the swapchain as
+    /// suboptimal and returns whether it should be recreated.
+    pub fn observe(&mut self, suboptimal: bool) -> bool {
+        if !suboptimal {
+            self.recreated = false;
+            return false;
+        }
+
+        !std::mem::replace(&mut self.recreated, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_format(format: vk::Format) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }
+    }
+
+    #[test]
+    fn default_preference_is_bgra_srgb() {
+        let available = [
+            surface_format(vk::Format::R8G8B8A8_UNORM),
+            surface_format(vk::Format::B8G8R8A8_SRGB),
+        ];
+
+        assert_eq!(
+            SurfaceFormatPreference::default().choose(&available),
+            Some(available[1])
+        );
+    }
+
+    #[test]
+    fn preference_order_wins_over_surface_order() {
+        let preference = SurfaceFormatPreference(vec![
+            surface_format(vk::Format::R8G8B8A8_SRGB),
+            surface_format(vk::Format::A2B10G10R10_UNORM_PACK32),
+        ]);
+        let available = [
+            surface_format(vk::Format::A2B10G10R10_UNORM_PACK32),
+            surface_format(vk::Format::R8G8B8A8_SRGB),
+        ];
+
+        assert_eq!(preference.choose(&available), Some(available[1]));
+    }
+
+    #[test]
+    fn color_space_must_match() {
+        let available = [vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT,
+        }];
+
+        assert_eq!(SurfaceFormatPreference::default().choose(&available), None);
+    }
+
+    #[test]
+    fn clipped_by_default() {
+        assert!(SwapchainConfig::default().clipped.0);
+    }
+
+    #[test]
+    fn composite_alpha_falls_back_to_opaque() {
+        let supported =
+            vk::CompositeAlphaFlagsKHR::OPAQUE | vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED;
+
+        assert_eq!(
+            CompositeAlpha::PreMultiplied.choose(supported),
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED
+        );
+        assert_eq!(
+            CompositeAlpha::PostMultiplied.choose(supported),
+            vk::CompositeAlphaFlagsKHR::OPAQUE
+        );
+        assert_eq!(
+            CompositeAlpha::Opaque.choose(supported),
+            vk::CompositeAlphaFlagsKHR::OPAQUE
+        );
+    }
+
+    #[test]
+    fn composite_alpha_without_opaque_support() {
+        let supported =
+            vk::CompositeAlphaFlagsKHR::INHERIT | vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED;
+
+        assert_eq!(
+            CompositeAlpha::Opaque.choose(supported),
+            vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED
+        );
+        assert_eq!(
+            CompositeAlpha::Inherit.choose(supported),
+            vk::CompositeAlphaFlagsKHR::INHERIT
+        );
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let available = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+
+        assert_eq!(
+            PresentMode::Immediate.choose(&available),
+            vk::PresentModeKHR::IMMEDIATE
+        );
+        assert_eq!(
+            PresentMode::default().choose(&available),
+            vk::PresentModeKHR::FIFO
+        );
+        assert_eq!(
+            PresentMode::Mailbox.choose(&[vk::PresentModeKHR::MAILBOX]),
+            vk::PresentModeKHR::MAILBOX
+        );
+    }
+
+    #[test]
+    fn quarter_turns_swap_the_extent() {
+        let extent = vk::Extent2D {
+            width: 1920,
+            height: 1080,
+        };
+        let swapped = vk::Extent2D {
+            width: 1080,
+            height: 1920,
+        };
+
+        let extent_for = |transform| pre_rotated_extent(extent, transform);
+        assert_eq!(extent_for(vk::SurfaceTransformFlagsKHR::IDENTITY), extent);
+        assert_eq!(extent_for(vk::SurfaceTransformFlagsKHR::ROTATE_90), swapped);
+        assert_eq!(extent_for(vk::SurfaceTransformFlagsKHR::ROTATE_180), extent);
+        assert_eq!(
+            extent_for(vk::SurfaceTransformFlagsKHR::ROTATE_270),
+            swapped
+        );
+    }
+
+    #[test]
+    fn pre_rotation_turns_clip_space() {
+        let right = glam::Vec3::X;
+        let rotate = |transform| pre_rotation(transform).transform_point3(right);
+
+        assert!(rotate(vk::SurfaceTransformFlagsKHR::IDENTITY).abs_diff_eq(right, 1e-6));
+        assert!(rotate(vk::SurfaceTransformFlagsKHR::ROTATE_90).abs_diff_eq(glam::Vec3::Y, 1e-6));
+        assert!(rotate(vk::SurfaceTransformFlagsKHR::ROTATE_180).abs_diff_eq(-right, 1e-6));
+        assert!(
+            rotate(vk::SurfaceTransformFlagsKHR::ROTATE_270).abs_diff_eq(glam::Vec3::NEG_Y, 1e-6)
+        );
+        // Mirroring isn't compensated.
+        assert_eq!(
+            pre_rotation(vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR),
+            Mat4::IDENTITY
+        );
+    }
+
+    #[test]
+    fn image_count_defaults_to_one_above_min() {
+        assert_eq!(SwapchainImageCount(None).clamp(2, 8), 3);
+        assert_eq!(SwapchainImageCount(None).clamp(2, 0), 3);
+        assert_eq!(SwapchainImageCount(None).clamp(3, 3), 3);
+    }
+
+    #[test]
+    fn image_count_is_clamped_to_surface_range() {
+        assert_eq!(SwapchainImageCount(Some(2)).clamp(2, 8), 2);
+        assert_eq!(SwapchainImageCount(Some(3)).clamp(2, 8), 3);
+        assert_eq!(SwapchainImageCount(Some(1)).clamp(2, 8), 2);
+        assert_eq!(SwapchainImageCount(Some(16)).clamp(2, 8), 8);
+        assert_eq!(SwapchainImageCount(Some(16)).clamp(2, 0), 16);
+    }
+
+    #[test]
+    fn recreates_once_while_suboptimal() {
+        let mut tracker = SuboptimalTracker::default();
+
+        assert!(!tracker.observe(false));
+        assert!(tracker.observe(true));
+        assert!(!tracker.observe(true));
+        assert!(!tracker.observe(true));
+    }
+
+    #[test]
+    fn recreates_again_after_optimal_frame() {
+        let mut tracker = SuboptimalTracker::default();
+
+        assert!(tracker.observe(true));
+        assert!(!tracker.observe(false));
+        assert!(tracker.observe(true));
+    }
+}
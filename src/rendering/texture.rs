@@ -0,0 +1,707 @@
+use std::path::{Path, PathBuf};
+
+use ash::{Device, Instance, vk};
+use bevy_ecs::{
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use gpu_allocator::{MemoryLocation, vulkan::Allocator};
+use thiserror::Error;
+use tracing::warn;
+
+use super::{
+    VulkanApp, VulkanError,
+    buffer::{Buffer, create_buffer},
+    image::{Image, ImageDesc, create_image, mip_levels},
+    layout::transition_image_layout,
+    storage::DeferredDestroyQueue,
+    transfer::{UploadQueues, begin_single_time_commands, end_single_time_commands},
+};
+use crate::utils::OnChange;
+
+/// Anisotropic filtering applied to the texture samplers.
+///
+/// Read once when the [`VulkanApp`](super::VulkanApp) is created.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnisotropyLevel {
+    Off,
+    X2,
+    X4,
+    #[default]
+    X8,
+    X16,
+}
+
+impl AnisotropyLevel {
+    pub fn samples(self) -> f32 {
+        match self {
+            AnisotropyLevel::Off => 1.0,
+            AnisotropyLevel::X2 => 2.0,
+            AnisotropyLevel::X4 => 4.0,
+            AnisotropyLevel::X8 => 8.0,
+            AnisotropyLevel::X16 => 16.0,
+        }
+    }
+
+    /// The `max_anisotropy` to create samplers with, or `None` to disable it.
+    ///
+    /// `supported` is the device's `sampler_anisotropy` feature and `limit` is
+    /// its `max_sampler_anisotropy`.
+    pub fn max_anisotropy(self, supported: bool, limit: f32) -> Option<f32> {
+        if self == AnisotropyLevel::Off {
+            return None;
+        }
+
+        if !supported {
+            warn!("Device doesn't support anisotropic filtering, sampling without it");
+            return None;
+        }
+
+        Some(self.samples().min(limit))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TextureError {
+    #[error("Failed to load texture `{path}`: {source}")]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: ::image::ImageError,
+    },
+    #[error("Texture `{path}` is {found:?}, the other layers are {expected:?}")]
+    LayerExtent {
+        path: PathBuf,
+        expected: vk::Extent2D,
+        found: vk::Extent2D,
+    },
+}
+
+/// Filtering and addressing of the texture samplers, which decide whether
+/// block textures look blocky or smooth.
+///
+/// Defaults to nearest filtering, which keeps the texels of pixel art sharp.
+/// Changing it recreates the samplers.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerConfig {
+    pub mag: vk::Filter,
+    pub min: vk::Filter,
+    pub mipmap: vk::SamplerMipmapMode,
+    pub address: vk::SamplerAddressMode,
+}
+
+impl SamplerConfig {
+    /// Sharp texels at every distance.
+    pub const NEAREST: Self = Self {
+        mag: vk::Filter::NEAREST,
+        min: vk::Filter::NEAREST,
+        mipmap: vk::SamplerMipmapMode::NEAREST,
+        address: vk::SamplerAddressMode::REPEAT,
+    };
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self::NEAREST
+    }
+}
+
+/// How the sampler of a [`Texture`] is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerDesc {
+    pub config: SamplerConfig,
+    /// See [`AnisotropyLevel::max_anisotropy`].
+    pub max_anisotropy: Option<f32>,
+}
+
+/// A sampled `R8G8B8A8_SRGB` 2D array image, with a layer per block texture.
+#[derive(Default)]
+pub struct Texture {
+    pub image: Image,
+    /// A `TYPE_2D_ARRAY` view of every layer.
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub mip_levels: u32,
+    /// Kept to recreate the sampler, see [`Texture::replace_sampler`].
+    pub max_anisotropy: Option<f32>,
+}
+
+impl Texture {
+    /// Creates the sampler anew with `config` and returns the old one, which
+    /// must be destroyed once no frame in flight uses it.
+    pub fn replace_sampler(
+        &mut self,
+        device: &Device,
+        config: &SamplerConfig,
+    ) -> Result<vk::Sampler, VulkanError> {
+        let desc = SamplerDesc {
+            config: *config,
+            max_anisotropy: self.max_anisotropy,
+        };
+        let sampler = create_sampler(device, &desc, self.mip_levels)?;
+        Ok(std::mem::replace(&mut self.sampler, sampler))
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.view, None);
+        }
+        self.image.destroy(device, allocator);
+    }
+}
+
+/// Reads the image at `path` as tightly packed RGBA8 pixels.
+pub fn decode_rgba(path: &Path) -> Result<(vk::Extent2D, Vec<u8>), TextureError> {
+    let image = ::image::open(path).map_err(|source| TextureError::Decode {
+        path: path.to_owned(),
+        source,
+    })?;
+    let rgba = image.into_rgba8();
+    let extent = vk::Extent2D {
+        width: rgba.width(),
+        height: rgba.height(),
+    };
+
+    Ok((extent, rgba.into_raw()))
+}
+
+/// Decoded pixels of an image file, kept to upload the texture again when the
+/// device is rebuilt.
+pub struct TextureSource {
+    pub path: PathBuf,
+    pub extent: vk::Extent2D,
+    /// RGBA8 pixels, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl TextureSource {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TextureError> {
+        let path = path.as_ref();
+        let (extent, pixels) = decode_rgba(path)?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            extent,
+            pixels,
+        })
+    }
+
+    /// Loads the layers of a texture array, which must all have the same extent.
+    pub fn load_layers<'a>(
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Self>, TextureError> {
+        let layers = paths
+            .into_iter()
+            .map(Self::load)
+            .collect::<Result<Vec<_>, _>>()?;
+        check_layer_extents(&layers)?;
+
+        Ok(layers)
+    }
+}
+
+fn check_layer_extents(layers: &[TextureSource]) -> Result<(), TextureError> {
+    let Some(first) = layers.first() else {
+        return Ok(());
+    };
+
+    match layers.iter().find(|layer| layer.extent != first.extent) {
+        Some(layer) => Err(TextureError::LayerExtent {
+            path: layer.path.clone(),
+            expected: first.extent,
+            found: layer.extent,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// The first `layers` that fit in an image of `max_layers`, which is the
+/// `maxImageArrayLayers` limit of the device.
+///
+/// Vertices referencing a dropped layer sample the last one, as layer indices
+/// are clamped by the sampler.
+pub fn usable_layers(layers: &[TextureSource], max_layers: u32) -> &[TextureSource] {
+    if layers.len() > max_layers as usize {
+        warn!(
+            "{} texture layers don't fit in the limit of {max_layers}, dropping the rest",
+            layers.len()
+        );
+    }
+
+    &layers[..layers.len().min(max_layers as usize)]
+}
+
+/// Uploads the `layers` of a texture array into a `DEVICE_LOCAL` image ready to
+/// be sampled from the fragment shader. There must be at least one layer and
+/// all of them must have the same extent, see [`TextureSource::load_layers`].
+///
+/// The full mip chain is generated on the GPU if the format supports linear
+/// blits, otherwise the texture only has its base level. The image is destroyed
+/// if it fails.
+pub fn load_texture(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    device: &Device,
+    allocator: &mut Allocator,
+    queues: &UploadQueues,
+    layers: &[TextureSource],
+    sampler_desc: &SamplerDesc,
+) -> Result<Texture, VulkanError> {
+    let TextureSource { path, extent, .. } = &layers[0];
+    let extent = *extent;
+    let layer_count = layers.len() as u32;
+    let pixels = layers
+        .iter()
+        .flat_map(|layer| &layer.pixels)
+        .copied()
+        .collect::<Vec<_>>();
+    let format = vk::Format::R8G8B8A8_SRGB;
+
+    let format_properties =
+        unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+    let linear_blit = format_properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR);
+    let mip_levels = if linear_blit {
+        mip_levels(extent)
+    } else {
+        warn!(
+            "{format:?} doesn't support linear blits, `{}` won't have mipmaps",
+            path.display()
+        );
+        1
+    };
+
+    let mut staging = create_buffer(
+        device,
+        allocator,
+        "texture staging",
+        pixels.len() as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::CpuToGpu,
+    );
+    staging
+        .allocation
+        .mapped_slice_mut()
+        .expect("Staging memory must be host visible")[..pixels.len()]
+        .copy_from_slice(&pixels);
+
+    let desc = ImageDesc {
+        mip_levels,
+        array_layers: layer_count,
+        ..ImageDesc::new(
+            extent,
+            format,
+            vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::SAMPLED,
+        )
+    };
+    let mut image = create_image(device, allocator, &path.to_string_lossy(), &desc);
+
+    let uploaded = upload_layers(
+        device,
+        queues,
+        &staging,
+        &image,
+        extent,
+        mip_levels,
+        layer_count,
+    );
+    staging.destroy(device, allocator);
+    let handles = uploaded.and_then(|()| {
+        let view = create_array_view(device, image.image, format, mip_levels, layer_count)?;
+        let sampler = create_sampler(device, sampler_desc, mip_levels)
+            .inspect_err(|_| unsafe { device.destroy_image_view(view, None) })?;
+        Ok((view, sampler))
+    });
+    let (view, sampler) = match handles {
+        Ok(handles) => handles,
+        Err(err) => {
+            image.destroy(device, allocator);
+            return Err(err);
+        }
+    };
+
+    Ok(Texture {
+        image,
+        view,
+        sampler,
+        mip_levels,
+        max_anisotropy: sampler_desc.max_anisotropy,
+    })
+}
+
+/// Copies `staging` into the base level of `image` and generates the other
+/// levels, waiting for both queues.
+fn upload_layers(
+    device: &Device,
+    queues: &UploadQueues,
+    staging: &Buffer,
+    image: &Image,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    layer_count: u32,
+) -> Result<(), VulkanError> {
+    // Blits need a graphics queue, which acquires the image first when the
+    // copy runs on another family.
+    let blit_command_buffer = begin_single_time_commands(device, queues.graphics.command_pool);
+
+    let recorded = queues
+        .transfer
+        .submit_once(device, |command_buffer| -> Result<(), VulkanError> {
+            transition_image_layout(
+                device,
+                command_buffer,
+                image.image,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageAspectFlags::COLOR,
+                0..mip_levels,
+            )?;
+            copy_buffer_to_image(
+                device,
+                command_buffer,
+                staging.buffer,
+                image.image,
+                extent,
+                layer_count,
+            );
+            queues.transfer_image_ownership(
+                device,
+                command_buffer,
+                blit_command_buffer,
+                image.image,
+                mip_levels,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+            Ok(())
+        })
+        .and_then(|()| {
+            // Leaves every level in `SHADER_READ_ONLY_OPTIMAL`.
+            generate_mipmaps(
+                device,
+                blit_command_buffer,
+                image.image,
+                extent,
+                mip_levels,
+                layer_count,
+            )
+        });
+
+    match recorded {
+        Ok(()) => end_single_time_commands(
+            device,
+            queues.graphics.queue,
+            queues.graphics.command_pool,
+            blit_command_buffer,
+        ),
+        Err(_) => unsafe {
+            device.free_command_buffers(queues.graphics.command_pool, &[blit_command_buffer])
+        },
+    }
+    recorded
+}
+
+fn create_array_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+    mip_levels: u32,
+    layers: u32,
+) -> Result<vk::ImageView, VulkanError> {
+    let create_info = vk::ImageViewCreateInfo::default()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+        .format(format)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(mip_levels)
+                .base_array_layer(0)
+                .layer_count(layers),
+        );
+
+    Ok(unsafe { device.create_image_view(&create_info, None)? })
+}
+
+/// Fills the mip chain of every layer of `image` by blitting every level from
+/// the previous one.
+///
+/// Expects all levels in `TRANSFER_DST_OPTIMAL` with the base level filled in.
+fn generate_mipmaps(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    layers: u32,
+) -> Result<(), VulkanError> {
+    let mut width = extent.width as i32;
+    let mut height = extent.height as i32;
+
+    for level in 1..mip_levels {
+        transition_image_layout(
+            device,
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageAspectFlags::COLOR,
+            level - 1..level,
+        )?;
+
+        let next_width = (width / 2).max(1);
+        let next_height = (height / 2).max(1);
+
+        let subresource = |mip_level| {
+            vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(mip_level)
+                .base_array_layer(0)
+                .layer_count(layers)
+        };
+        let blit = vk::ImageBlit::default()
+            .src_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: width,
+                    y: height,
+                    z: 1,
+                },
+            ])
+            .src_subresource(subresource(level - 1))
+            .dst_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: next_width,
+                    y: next_height,
+                    z: 1,
+                },
+            ])
+            .dst_subresource(subresource(level));
+
+        unsafe {
+            device.cmd_blit_image(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::LINEAR,
+            );
+        }
+
+        transition_image_layout(
+            device,
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageAspectFlags::COLOR,
+            level - 1..level,
+        )?;
+
+        width = next_width;
+        height = next_height;
+    }
+
+    // The last level is only ever written to.
+    transition_image_layout(
+        device,
+        command_buffer,
+        image,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        vk::ImageAspectFlags::COLOR,
+        mip_levels - 1..mip_levels,
+    )
+}
+
+fn copy_buffer_to_image(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    layers: u32,
+) {
+    // The layers are tightly packed one after the other in `buffer`.
+    let region = vk::BufferImageCopy::default()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(
+            vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(0)
+                .base_array_layer(0)
+                .layer_count(layers),
+        )
+        .image_offset(vk::Offset3D::default())
+        .image_extent(extent.into());
+
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        );
+    }
+}
+
+fn sampler_create_info(desc: &SamplerDesc, mip_levels: u32) -> vk::SamplerCreateInfo<'static> {
+    let SamplerDesc {
+        config,
+        max_anisotropy,
+    } = desc;
+    vk::SamplerCreateInfo::default()
+        .mag_filter(config.mag)
+        .min_filter(config.min)
+        .address_mode_u(config.address)
+        .address_mode_v(config.address)
+        .address_mode_w(config.address)
+        .anisotropy_enable(max_anisotropy.is_some())
+        .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .compare_op(vk::CompareOp::ALWAYS)
+        .mipmap_mode(config.mipmap)
+        .mip_lod_bias(0.0)
+        .min_lod(0.0)
+        .max_lod(mip_levels as f32)
+}
+
+fn create_sampler(
+    device: &Device,
+    desc: &SamplerDesc,
+    mip_levels: u32,
+) -> Result<vk::Sampler, VulkanError> {
+    let create_info = sampler_create_info(desc, mip_levels);
+    Ok(unsafe { device.create_sampler(&create_info, None)? })
+}
+
+/// Recreates the texture sampler when [`SamplerConfig`] changed.
+pub fn update_sampler_config_system(
+    mut vulkan_app: ResMut<VulkanApp>,
+    config: Res<SamplerConfig>,
+    mut change: OnChange<SamplerConfig>,
+    mut destroy_queue: ResMut<DeferredDestroyQueue>,
+) {
+    if let Some(config) = change.changed(&config) {
+        vulkan_app.set_sampler_config(*config, &mut destroy_queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::BLOCK_TEXTURES;
+
+    #[test]
+    fn sampler_reflects_the_config() {
+        let config = SamplerConfig {
+            mag: vk::Filter::LINEAR,
+            min: vk::Filter::NEAREST,
+            mipmap: vk::SamplerMipmapMode::LINEAR,
+            address: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        };
+        let desc = SamplerDesc {
+            config,
+            max_anisotropy: Some(8.0),
+        };
+        let info = sampler_create_info(&desc, 5);
+
+        assert_eq!(info.mag_filter, vk::Filter::LINEAR);
+        assert_eq!(info.min_filter, vk::Filter::NEAREST);
+        assert_eq!(info.mipmap_mode, vk::SamplerMipmapMode::LINEAR);
+        for address_mode in [
+            info.address_mode_u,
+            info.address_mode_v,
+            info.address_mode_w,
+        ] {
+            assert_eq!(address_mode, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        }
+        assert_eq!(info.max_lod, 5.0);
+        assert_eq!(
+            (info.anisotropy_enable, info.max_anisotropy),
+            (vk::TRUE, 8.0)
+        );
+    }
+
+    #[test]
+    fn default_sampler_is_nearest() {
+        let desc = SamplerDesc {
+            config: SamplerConfig::default(),
+            max_anisotropy: None,
+        };
+        let info = sampler_create_info(&desc, 1);
+        assert_eq!(info.mag_filter, vk::Filter::NEAREST);
+        assert_eq!(info.min_filter, vk::Filter::NEAREST);
+        assert_eq!(info.mipmap_mode, vk::SamplerMipmapMode::NEAREST);
+        assert_eq!(info.address_mode_u, vk::SamplerAddressMode::REPEAT);
+        assert_eq!(info.anisotropy_enable, vk::FALSE);
+    }
+
+    fn source(name: &str, width: u32, height: u32) -> TextureSource {
+        TextureSource {
+            path: PathBuf::from(name),
+            extent: vk::Extent2D { width, height },
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    #[test]
+    fn decode_block_texture() {
+        let (extent, pixels) = decode_rgba(Path::new(BLOCK_TEXTURES[0].1)).unwrap();
+        assert_eq!((extent.width, extent.height), (16, 16));
+        assert_eq!(pixels.len(), 16 * 16 * 4);
+    }
+
+    #[test]
+    fn block_textures_load_as_layers() {
+        let layers = TextureSource::load_layers(BLOCK_TEXTURES.map(|(_, path)| path)).unwrap();
+        assert_eq!(layers.len(), BLOCK_TEXTURES.len());
+    }
+
+    #[test]
+    fn layers_need_the_same_extent() {
+        assert!(check_layer_extents(&[source("a", 16, 16), source("b", 16, 16)]).is_ok());
+        assert!(matches!(
+            check_layer_extents(&[source("a", 16, 16), source("b", 32, 16)]),
+            Err(TextureError::LayerExtent { path, .. }) if path == Path::new("b")
+        ));
+    }
+
+    #[test]
+    fn layers_past_the_limit_are_dropped() {
+        let layers = [source("a", 1, 1), source("b", 1, 1), source("c", 1, 1)];
+        assert_eq!(usable_layers(&layers, 2048).len(), 3);
+        assert_eq!(usable_layers(&layers, 2).len(), 2);
+    }
+
+    #[test]
+    fn anisotropy_is_clamped_to_the_device_limit() {
+        assert_eq!(AnisotropyLevel::X16.max_anisotropy(true, 8.0), Some(8.0));
+        assert_eq!(AnisotropyLevel::X4.max_anisotropy(true, 16.0), Some(4.0));
+        assert_eq!(AnisotropyLevel::X2.max_anisotropy(true, 16.0), Some(2.0));
+        assert_eq!(AnisotropyLevel::Off.max_anisotropy(true, 16.0), None);
+    }
+
+    #[test]
+    fn anisotropy_requires_the_device_feature() {
+        assert_eq!(AnisotropyLevel::X8.max_anisotropy(false, 16.0), None);
+    }
+
+    #[test]
+    fn missing_texture_is_an_error() {
+        assert!(decode_rgba(Path::new("assets/textures/missing.png")).is_err());
+        assert!(TextureSource::load("assets/textures/missing.png").is_err());
+    }
+}
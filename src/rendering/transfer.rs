@@ -0,0 +1,514 @@
+use ash::{Device, vk};
+
+/// A queue together with its family and the pool one-shot command buffers are
+/// allocated from.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueContext {
+    pub family: u32,
+    pub queue: vk::Queue,
+    pub command_pool: vk::CommandPool,
+}
+
+impl QueueContext {
+    /// Records `record` into a one-shot command buffer, submits it and waits
+    /// for the queue to become idle. Returns what `record` returned.
+    pub fn submit_once<R>(
+        &self,
+        device: &Device,
+        record: impl FnOnce(vk::CommandBuffer) -> R,
+    ) -> R {
+        let command_buffer = begin_single_time_commands(device, self.command_pool);
+        let recorded = record(command_buffer);
+        end_single_time_commands(device, self.queue, self.command_pool, command_buffer);
+        recorded
+    }
+
+    /// Like [`submit_once`](Self::submit_once), but returns as soon as the
+    /// commands are submitted so that several uploads can overlap.
+    pub fn submit_once_async(
+        &self,
+        device: &Device,
+        record: impl FnOnce(vk::CommandBuffer),
+    ) -> SubmittedCommands {
+        let command_buffer = begin_single_time_commands(device, self.command_pool);
+        record(command_buffer);
+        submit_single_time_commands(device, self.queue, command_buffer)
+    }
+}
+
+/// Allocates a primary command buffer from `command_pool` and begins it for a
+/// single submission.
+pub fn begin_single_time_commands(
+    device: &Device,
+    command_pool: vk::CommandPool,
+) -> vk::CommandBuffer {
+    let allocate_info = vk::CommandBufferAllocateInfo::default()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
+
+    unsafe {
+        let command_buffer = device.allocate_command_buffers(&allocate_info).unwrap()[0];
+
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .unwrap();
+
+        command_buffer
+    }
+}
+
+/// Ends and submits a command buffer from [`begin_single_time_commands`],
+/// waits for `queue` to become idle and frees it.
+pub fn end_single_time_commands(
+    device: &Device,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+) {
+    unsafe {
+        device.end_command_buffer(command_buffer).unwrap();
+
+        let command_buffers = &[command_buffer];
+        let submit_info = vk::SubmitInfo::default().command_buffers(command_buffers);
+        device
+            .queue_submit(queue, &[submit_info], vk::Fence::null())
+            .unwrap();
+        device.queue_wait_idle(queue).unwrap();
+
+        device.free_command_buffers(command_pool, command_buffers);
+    }
+}
+
+/// Ends and submits a command buffer from [`begin_single_time_commands`]
+/// with a fence instead of waiting for the whole queue.
+pub fn submit_single_time_commands(
+    device: &Device,
+    queue: vk::Queue,
+    command_buffer: vk::CommandBuffer,
+) -> SubmittedCommands {
+    unsafe {
+        device.end_command_buffer(command_buffer).unwrap();
+
+        let fence = device
+            .create_fence(&vk::FenceCreateInfo::default(), None)
+            .unwrap();
+
+        let command_buffers = &[command_buffer];
+        let submit_info = vk::SubmitInfo::default().command_buffers(command_buffers);
+        device.queue_submit(queue, &[submit_info], fence).unwrap();
+
+        SubmittedCommands {
+            command_buffer,
+            fence,
+        }
+    }
+}
+
+/// One-shot commands that may still be executing.
+#[must_use = "the command buffer and fence are leaked unless waited on"]
+pub struct SubmittedCommands {
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+}
+
+impl SubmittedCommands {
+    /// Waits for the commands to finish and frees their command buffer, which
+    /// must have been allocated from `command_pool`.
+    pub fn wait(self, device: &Device, command_pool: vk::CommandPool) {
+        unsafe {
+            device
+                .wait_for_fences(&[self.fence], true, u64::MAX)
+                .unwrap();
+            device.destroy_fence(self.fence, None);
+            device.free_command_buffers(command_pool, &[self.command_buffer]);
+        }
+    }
+}
+
+/// Queues used to upload resources that are then used for rendering.
+///
+/// Copies run on `transfer`. When it belongs to another family than
+/// `graphics`, the ownership of the uploaded resources is transferred to the
+/// graphics family.
+#[derive(Debug, Clone, Copy)]
+pub struct UploadQueues {
+    pub transfer: QueueContext,
+    pub graphics: QueueContext,
+}
+
+impl UploadQueues {
+    pub fn needs_ownership_transfer(&self) -> bool {
+        self.transfer.family != self.graphics.family
+    }
+
+    /// Transfers `buffer` to the graphics family after its upload, see
+    /// [`queue_family_ownership_transfer`].
+    pub fn transfer_buffer_ownership(
+        &self,
+        device: &Device,
+        release_command_buffer: vk::CommandBuffer,
+        acquire_command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+    ) {
+        queue_family_ownership_transfer(
+            device,
+            release_command_buffer,
+            acquire_command_buffer,
+            OwnedResource::Buffer(buffer),
+            self.transfer_usage(),
+            self.buffer_usage(),
+        );
+    }
+
+    /// Transfers the first `mip_levels` of a color image in `layout` to the
+    /// graphics family after its upload, see [`queue_family_ownership_transfer`].
+    pub fn transfer_image_ownership(
+        &self,
+        device: &Device,
+        release_command_buffer: vk::CommandBuffer,
+        acquire_command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        mip_levels: u32,
+        layout: vk::ImageLayout,
+    ) {
+        queue_family_ownership_transfer(
+            device,
+            release_command_buffer,
+            acquire_command_buffer,
+            OwnedResource::color_image(image, mip_levels, layout),
+            self.transfer_usage(),
+            self.image_usage(),
+        );
+    }
+
+    /// Uploads are released after their copy.
+    fn transfer_usage(&self) -> QueueUsage {
+        QueueUsage {
+            family: self.transfer.family,
+            stage: vk::PipelineStageFlags::TRANSFER,
+            access: vk::AccessFlags::TRANSFER_WRITE,
+        }
+    }
+
+    /// Uploaded buffers may be read by any command on the graphics queue.
+    fn buffer_usage(&self) -> QueueUsage {
+        QueueUsage {
+            family: self.graphics.family,
+            stage: vk::PipelineStageFlags::ALL_COMMANDS,
+            access: vk::AccessFlags::MEMORY_READ,
+        }
+    }
+
+    /// Uploaded images have their mip chain generated on the graphics queue.
+    fn image_usage(&self) -> QueueUsage {
+        QueueUsage {
+            family: self.graphics.family,
+            stage: vk::PipelineStageFlags::TRANSFER,
+            access: vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE,
+        }
+    }
+}
+
+/// How a resource is used by a queue family on one side of an ownership transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueUsage {
+    pub family: u32,
+    pub stage: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+}
+
+/// A resource created with `EXCLUSIVE` sharing whose ownership is transferred.
+#[derive(Debug, Clone, Copy)]
+pub enum OwnedResource {
+    Buffer(vk::Buffer),
+    /// The layout is kept by the transfer.
+    Image {
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        layout: vk::ImageLayout,
+    },
+}
+
+impl OwnedResource {
+    /// The first `mip_levels` of every layer of a color image.
+    pub fn color_image(image: vk::Image, mip_levels: u32, layout: vk::ImageLayout) -> Self {
+        Self::Image {
+            image,
+            subresource_range: vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(mip_levels)
+                .base_array_layer(0)
+                .layer_count(vk::REMAINING_ARRAY_LAYERS),
+            layout,
+        }
+    }
+}
+
+/// Parameters of one pipeline barrier of an ownership transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnershipBarrier {
+    pub src_family: u32,
+    pub dst_family: u32,
+    pub src_stage: vk::PipelineStageFlags,
+    pub dst_stage: vk::PipelineStageFlags,
+    pub src_access: vk::AccessFlags,
+    pub dst_access: vk::AccessFlags,
+}
+
+impl OwnershipBarrier {
+    pub fn record(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        resource: OwnedResource,
+    ) {
+        let (buffer_barriers, image_barriers) = match resource {
+            OwnedResource::Buffer(buffer) => (
+                vec![
+                    vk::BufferMemoryBarrier::default()
+                        .src_access_mask(self.src_access)
+                        .dst_access_mask(self.dst_access)
+                        .src_queue_family_index(self.src_family)
+                        .dst_queue_family_index(self.dst_family)
+                        .buffer(buffer)
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE),
+                ],
+                Vec::new(),
+            ),
+            OwnedResource::Image {
+                image,
+                subresource_range,
+                layout,
+            } => (
+                Vec::new(),
+                vec![
+                    vk::ImageMemoryBarrier::default()
+                        .src_access_mask(self.src_access)
+                        .dst_access_mask(self.dst_access)
+                        .old_layout(layout)
+                        .new_layout(layout)
+                        .src_queue_family_index(self.src_family)
+                        .dst_queue_family_index(self.dst_family)
+                        .image(image)
+                        .subresource_range(subresource_range),
+                ],
+            ),
+        };
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                self.src_stage,
+                self.dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            );
+        }
+    }
+}
+
+/// The release and acquire barriers transferring a resource from the `src`
+/// queue family to `dst`.
+///
+/// The release only makes the writes of `src` available and the acquire only
+/// makes them visible to `dst`, the other halves of the barriers are ignored.
+pub fn ownership_barriers(src: QueueUsage, dst: QueueUsage) -> [OwnershipBarrier; 2] {
+    let release = OwnershipBarrier {
+        src_family: src.family,
+        dst_family: dst.family,
+        src_stage: src.stage,
+        dst_stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        src_access: src.access,
+        dst_access: vk::AccessFlags::empty(),
+    };
+    let acquire = OwnershipBarrier {
+        src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+        dst_stage: dst.stage,
+        src_access: vk::AccessFlags::empty(),
+        dst_access: dst.access,
+        ..release
+    };
+
+    [release, acquire]
+}
+
+/// Transfers `resource` from the queue family of `src` to the one of `dst`,
+/// which resources created with `EXCLUSIVE` sharing need before they are used
+/// on another family.
+///
+/// The release barrier is recorded into `release_command_buffer`, which must
+/// be submitted to a queue of `src`, and the acquire barrier into
+/// `acquire_command_buffer`, which must be submitted to a queue of `dst` after
+/// the release completed, e.g. by waiting on a semaphore it signals. Nothing is
+/// recorded when both families are the same.
+pub fn queue_family_ownership_transfer(
+    device: &Device,
+    release_command_buffer: vk::CommandBuffer,
+    acquire_command_buffer: vk::CommandBuffer,
+    resource: OwnedResource,
+    src: QueueUsage,
+    dst: QueueUsage,
+) {
+    if src.family == dst.family {
+        return;
+    }
+
+    let [release, acquire] = ownership_barriers(src, dst);
+    release.record(device, release_command_buffer, resource);
+    acquire.record(device, acquire_command_buffer, resource);
+}
+
+/// Picks the queue family uploads run on.
+///
+/// Prefers a transfer family without graphics support, which usually maps to
+/// the dedicated DMA engine, and falls back to `graphics_family` since
+/// graphics queues always support transfers.
+pub fn pick_transfer_family(properties: &[vk::QueueFamilyProperties], graphics_family: u32) -> u32 {
+    let dedicated = |family: &vk::QueueFamilyProperties| {
+        family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+            && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+    };
+
+    properties
+        .iter()
+        .position(|family| {
+            dedicated(family) && !family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+        })
+        .or_else(|| properties.iter().position(dedicated))
+        .map_or(graphics_family, |index| index as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::Entry;
+    use gpu_allocator::{
+        MemoryLocation,
+        vulkan::{Allocator, AllocatorCreateDesc},
+    };
+
+    use super::*;
+    use crate::rendering::buffer::{Buffer, create_buffer};
+
+    #[test]
+    fn ownership_barriers_split_the_dependency() {
+        let src = QueueUsage {
+            family: 1,
+            stage: vk::PipelineStageFlags::TRANSFER,
+            access: vk::AccessFlags::TRANSFER_WRITE,
+        };
+        let dst = QueueUsage {
+            family: 0,
+            stage: vk::PipelineStageFlags::VERTEX_INPUT,
+            access: vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        };
+        let [release, acquire] = ownership_barriers(src, dst);
+
+        assert_eq!((release.src_family, release.dst_family), (1, 0));
+        assert_eq!((acquire.src_family, acquire.dst_family), (1, 0));
+
+        assert_eq!(release.src_stage, vk::PipelineStageFlags::TRANSFER);
+        assert_eq!(release.src_access, vk::AccessFlags::TRANSFER_WRITE);
+        assert_eq!(release.dst_stage, vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+        assert_eq!(release.dst_access, vk::AccessFlags::empty());
+
+        assert_eq!(acquire.src_stage, vk::PipelineStageFlags::TOP_OF_PIPE);
+        assert_eq!(acquire.src_access, vk::AccessFlags::empty());
+        assert_eq!(acquire.dst_stage, vk::PipelineStageFlags::VERTEX_INPUT);
+        assert_eq!(acquire.dst_access, vk::AccessFlags::VERTEX_ATTRIBUTE_READ);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn single_time_commands_copy_buffer() {
+        const DATA: [u32; 4] = [1, 2, 3, 4];
+
+        let entry = unsafe { Entry::load().unwrap() };
+        let app_info = vk::ApplicationInfo::default().api_version(vk::API_VERSION_1_0);
+        let instance_info = vk::InstanceCreateInfo::default().application_info(&app_info);
+        let instance = unsafe { entry.create_instance(&instance_info, None).unwrap() };
+
+        let physical_device = unsafe { instance.enumerate_physical_devices().unwrap()[0] };
+        let properties =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let graphics_family = properties
+            .iter()
+            .position(|family| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .unwrap() as u32;
+        let family = pick_transfer_family(&properties, graphics_family);
+
+        let queue_infos = &[vk::DeviceQueueCreateInfo::default()
+            .queue_family_index(family)
+            .queue_priorities(&[1.0])];
+        let device_info = vk::DeviceCreateInfo::default().queue_create_infos(queue_infos);
+        let device = unsafe {
+            instance
+                .create_device(physical_device, &device_info, None)
+                .unwrap()
+        };
+
+        let mut allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: false,
+            allocation_sizes: Default::default(),
+        })
+        .unwrap();
+
+        let pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(family);
+        let command_pool = unsafe { device.create_command_pool(&pool_info, None).unwrap() };
+        let queue = unsafe { device.get_device_queue(family, 0) };
+
+        let size = size_of_val(&DATA) as vk::DeviceSize;
+        let mut buffers = [vk::BufferUsageFlags::TRANSFER_SRC; 3].map(|usage| {
+            create_buffer(
+                &device,
+                &mut allocator,
+                "single time copy",
+                size,
+                usage | vk::BufferUsageFlags::TRANSFER_DST,
+                MemoryLocation::GpuToCpu,
+            )
+        });
+        buffers[0].allocation.mapped_slice_mut().unwrap()[..size as usize]
+            .copy_from_slice(bytemuck::cast_slice(&DATA));
+
+        let copy = |command_buffer, src: &Buffer, dst: &Buffer| unsafe {
+            let region = vk::BufferCopy::default().size(size);
+            device.cmd_copy_buffer(command_buffer, src.buffer, dst.buffer, &[region]);
+        };
+
+        let command_buffer = begin_single_time_commands(&device, command_pool);
+        copy(command_buffer, &buffers[0], &buffers[1]);
+        end_single_time_commands(&device, queue, command_pool, command_buffer);
+
+        let command_buffer = begin_single_time_commands(&device, command_pool);
+        copy(command_buffer, &buffers[1], &buffers[2]);
+        submit_single_time_commands(&device, queue, command_buffer).wait(&device, command_pool);
+
+        for buffer in &buffers[1..] {
+            let bytes = &buffer.allocation.mapped_slice().unwrap()[..size as usize];
+            assert_eq!(bytemuck::cast_slice::<u8, u32>(bytes), DATA);
+        }
+
+        unsafe {
+            for mut buffer in buffers {
+                buffer.destroy(&device, &mut allocator);
+            }
+            drop(allocator);
+            device.destroy_command_pool(command_pool, None);
+            device.destroy_device(None);
+            instance.destroy_instance(None);
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use ash::{Device, vk};
 use bevy_app::Plugin;
 
+use super::create_shader_module;
+
 pub struct TrianglePlugin;
 
 impl Plugin for TrianglePlugin {
@@ -7,3 +10,89 @@ impl Plugin for TrianglePlugin {
         todo!()
     }
 }
+
+/// Pipeline drawing the hardcoded triangle of `shaders/triangle.vert` with
+/// three vertices and no vertex buffers.
+pub fn create_triangle_pipeline(
+    device: &Device,
+    render_pass: vk::RenderPass,
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let vertex = include_bytes!("../../shaders/out/triangle.vert.spv");
+    let fragment = include_bytes!("../../shaders/out/triangle.frag.spv");
+
+    let vertex_shader_module = create_shader_module(device, vertex).unwrap();
+    let fragment_shader_module = create_shader_module(device, fragment).unwrap();
+
+    let shader_stages = &[
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vertex_shader_module)
+            .name(c"main"),
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(fragment_shader_module)
+            .name(c"main"),
+    ];
+
+    let vertex_input_create_info = vk::PipelineVertexInputStateCreateInfo::default();
+
+    let input_assembly_create_info = vk::PipelineInputAssemblyStateCreateInfo::default()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(dynamic_states);
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer_create_info = vk::PipelineRasterizationStateCreateInfo::default()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+
+    let multisampling_create_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    // The render pass has a depth attachment, the triangle ignores it.
+    let depth_stencil_create_info = vk::PipelineDepthStencilStateCreateInfo::default();
+
+    let attachments = &[vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)];
+    let color_blending_create_info =
+        vk::PipelineColorBlendStateCreateInfo::default().attachments(attachments);
+
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&vk::PipelineLayoutCreateInfo::default(), None)
+            .unwrap()
+    };
+
+    let pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
+        .stages(shader_stages)
+        .vertex_input_state(&vertex_input_create_info)
+        .input_assembly_state(&input_assembly_create_info)
+        .viewport_state(&viewport_state_create_info)
+        .rasterization_state(&rasterizer_create_info)
+        .multisample_state(&multisampling_create_info)
+        .depth_stencil_state(&depth_stencil_create_info)
+        .color_blend_state(&color_blending_create_info)
+        .dynamic_state(&dynamic_state_create_info)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_create_info], None)
+            .unwrap()[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader_module, None);
+        device.destroy_shader_module(fragment_shader_module, None);
+    }
+
+    (pipeline, pipeline_layout)
+}
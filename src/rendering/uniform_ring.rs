@@ -0,0 +1,193 @@
+use ash::{Device, vk};
+use bytemuck::Pod;
+use gpu_allocator::{MemoryLocation, vulkan::Allocator};
+
+use super::{
+    MAX_FRAMES_IN_FLIGHT,
+    buffer::{Buffer, create_buffer},
+};
+
+/// Rounds `offset` up to a multiple of `alignment`, which must be a power of two.
+pub fn align_up(offset: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    debug_assert!(alignment.is_power_of_two());
+    (offset + alignment - 1) & !(alignment - 1)
+}
+
+/// Hands out aligned offsets into a buffer of `capacity` bytes, from the start
+/// again after every [`reset`](Self::reset).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingCursor {
+    capacity: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    head: vk::DeviceSize,
+}
+
+impl RingCursor {
+    pub fn new(capacity: vk::DeviceSize, alignment: vk::DeviceSize) -> Self {
+        Self {
+            capacity,
+            alignment,
+            head: 0,
+        }
+    }
+
+    /// Offset of `size` bytes, or `None` when they don't fit anymore.
+    pub fn allocate(&mut self, size: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        let offset = align_up(self.head, self.alignment);
+        if offset + size > self.capacity {
+            return None;
+        }
+
+        self.head = offset + size;
+        Some(offset)
+    }
+
+    pub fn reset(&mut self) {
+        self.head = 0;
+    }
+}
+
+/// Small uniform blocks of many draws, sub-allocated from one host-visible
+/// buffer per frame in flight.
+///
+/// The buffer is bound once as an `UNIFORM_BUFFER_DYNAMIC` descriptor, see
+/// [`dynamic_uniform_binding`], and every draw selects its block with the
+/// dynamic offset returned by [`push`](Self::push).
+pub struct DynamicUniformRing {
+    buffers: Vec<Buffer>,
+    cursor: RingCursor,
+    frame: usize,
+}
+
+impl DynamicUniformRing {
+    /// `min_alignment` is the `minUniformBufferOffsetAlignment` limit of the device.
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        capacity: vk::DeviceSize,
+        min_alignment: vk::DeviceSize,
+    ) -> Self {
+        let buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                create_buffer(
+                    device,
+                    allocator,
+                    "dynamic uniform ring",
+                    capacity,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    MemoryLocation::CpuToGpu,
+                )
+            })
+            .collect();
+
+        Self {
+            buffers,
+            cursor: RingCursor::new(capacity, min_alignment),
+            frame: 0,
+        }
+    }
+
+    /// Starts writing to the buffer of `frame`, whose previous submission must
+    /// have completed. Offsets of earlier frames are invalidated.
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.frame = frame;
+        self.cursor.reset();
+    }
+
+    /// Writes `value` to the buffer of the current frame and returns its
+    /// dynamic offset, or `None` when the buffer is full.
+    pub fn push<T: Pod>(&mut self, value: &T) -> Option<u32> {
+        let bytes = bytemuck::bytes_of(value);
+        let offset = self.cursor.allocate(bytes.len() as vk::DeviceSize)? as usize;
+
+        self.buffers[self.frame]
+            .allocation
+            .mapped_slice_mut()
+            .expect("Uniform memory must be host visible")[offset..offset + bytes.len()]
+            .copy_from_slice(bytes);
+
+        Some(offset as u32)
+    }
+
+    /// Descriptor of the buffer of `frame`, each dynamic offset exposing `range` bytes.
+    pub fn buffer_info(&self, frame: usize, range: vk::DeviceSize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffers[frame].buffer)
+            .offset(0)
+            .range(range)
+    }
+
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        for buffer in &mut self.buffers {
+            buffer.destroy(device, allocator);
+        }
+    }
+}
+
+/// Layout binding of a [`DynamicUniformRing`].
+pub fn dynamic_uniform_binding(
+    binding: u32,
+    stages: vk::ShaderStageFlags,
+) -> vk::DescriptorSetLayoutBinding<'static> {
+    vk::DescriptorSetLayoutBinding::default()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+        .descriptor_count(1)
+        .stage_flags(stages)
+}
+
+/// Binds `descriptor_set`, whose only dynamic binding is a
+/// [`DynamicUniformRing`], with the block at `offset`.
+pub fn bind_with_dynamic_offset(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    set: u32,
+    descriptor_set: vk::DescriptorSet,
+    offset: u32,
+) {
+    unsafe {
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline_layout,
+            set,
+            &[descriptor_set],
+            &[offset],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_are_rounded_up() {
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert_eq!(align_up(257, 64), 320);
+    }
+
+    #[test]
+    fn cursor_respects_the_alignment() {
+        let mut cursor = RingCursor::new(1024, 256);
+        assert_eq!(cursor.allocate(48), Some(0));
+        assert_eq!(cursor.allocate(48), Some(256));
+        assert_eq!(cursor.allocate(300), Some(512));
+        // 812 rounds up to 1024, which leaves no room.
+        assert_eq!(cursor.allocate(1), None);
+
+        cursor.reset();
+        assert_eq!(cursor.allocate(1024), Some(0));
+    }
+
+    #[test]
+    fn full_cursor_keeps_its_head() {
+        let mut cursor = RingCursor::new(512, 16);
+        assert_eq!(cursor.allocate(100), Some(0));
+        assert_eq!(cursor.allocate(512), None);
+        assert_eq!(cursor.allocate(100), Some(112));
+    }
+}
@@ -0,0 +1,350 @@
+use ash::{Device, vk};
+use glam::IVec3;
+use gpu_allocator::{MemoryLocation, vulkan::Allocator};
+use tracing::error;
+
+use super::{
+    VulkanError,
+    buffer::{Buffer, create_buffer},
+    debug_utils::chunk_object_name,
+    mesh::GpuMesh,
+    transfer::{UploadQueues, begin_single_time_commands},
+};
+use crate::world::meshing::Vertex;
+
+/// Fences of finished submissions, reset and kept for the next ones.
+#[derive(Debug, Default)]
+pub struct FencePool {
+    free: Vec<vk::Fence>,
+}
+
+impl FencePool {
+    /// An unsignaled fence, reused if one is free.
+    pub fn acquire(&mut self, device: &Device) -> Result<vk::Fence, VulkanError> {
+        match self.free.pop() {
+            Some(fence) => Ok(fence),
+            None => Ok(unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None)? }),
+        }
+    }
+
+    /// Resets `fence`, whose submission must have completed, for reuse.
+    pub fn release(&mut self, device: &Device, fence: vk::Fence) -> Result<(), VulkanError> {
+        unsafe { device.reset_fences(&[fence])? };
+        self.recycle(fence);
+        Ok(())
+    }
+
+    /// Keeps `fence`, which must be unsignaled, for reuse.
+    fn recycle(&mut self, fence: vk::Fence) {
+        self.free.push(fence);
+    }
+
+    /// Destroys the free fences. Acquired ones are left to their owner.
+    pub fn destroy(&mut self, device: &Device) {
+        for fence in self.free.drain(..) {
+            unsafe { device.destroy_fence(fence, None) };
+        }
+    }
+}
+
+/// A chunk mesh being copied to the GPU.
+struct PendingUpload {
+    coord: IVec3,
+    mesh: GpuMesh,
+    staging: Buffer,
+    /// Signaled once the mesh can be drawn.
+    fence: vk::Fence,
+    /// The copy on the transfer queue, then the ownership acquire on the
+    /// graphics queue if there is one.
+    command_buffers: Vec<(vk::CommandPool, vk::CommandBuffer)>,
+    /// Orders the acquire after the copy, null without an ownership transfer.
+    semaphore: vk::Semaphore,
+    /// Replaced by a newer upload of the chunk or unloaded, destroyed once
+    /// finished instead of being drawn.
+    discarded: bool,
+}
+
+impl PendingUpload {
+    /// Records the copy of `staging` into the mesh buffers and submits it
+    /// with `fence`. The command buffers and semaphore are kept as soon as
+    /// they are created, so [`finish`](Self::finish) frees them on errors too.
+    fn submit(
+        &mut self,
+        device: &Device,
+        queues: &UploadQueues,
+        vertex_size: vk::DeviceSize,
+        index_size: vk::DeviceSize,
+    ) -> Result<(), VulkanError> {
+        let buffers = [
+            self.mesh.vertex_buffer.buffer,
+            self.mesh.index_buffer.buffer,
+        ];
+
+        let copy_command_buffer = begin_single_time_commands(device, queues.transfer.command_pool);
+        self.command_buffers
+            .push((queues.transfer.command_pool, copy_command_buffer));
+        unsafe {
+            let vertex_region = vk::BufferCopy::default().size(vertex_size);
+            device.cmd_copy_buffer(
+                copy_command_buffer,
+                self.staging.buffer,
+                buffers[0],
+                &[vertex_region],
+            );
+            let index_region = vk::BufferCopy::default()
+                .src_offset(vertex_size)
+                .size(index_size);
+            device.cmd_copy_buffer(
+                copy_command_buffer,
+                self.staging.buffer,
+                buffers[1],
+                &[index_region],
+            );
+
+            if !queues.needs_ownership_transfer() {
+                device.end_command_buffer(copy_command_buffer)?;
+                let copy_submit = vk::SubmitInfo::default()
+                    .command_buffers(std::slice::from_ref(&copy_command_buffer));
+                device.queue_submit(queues.transfer.queue, &[copy_submit], self.fence)?;
+                return Ok(());
+            }
+
+            let acquire_command_buffer =
+                begin_single_time_commands(device, queues.graphics.command_pool);
+            self.command_buffers
+                .push((queues.graphics.command_pool, acquire_command_buffer));
+            for buffer in buffers {
+                queues.transfer_buffer_ownership(
+                    device,
+                    copy_command_buffer,
+                    acquire_command_buffer,
+                    buffer,
+                );
+            }
+            device.end_command_buffer(copy_command_buffer)?;
+            device.end_command_buffer(acquire_command_buffer)?;
+
+            self.semaphore = device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)?;
+            let signal_semaphores = [self.semaphore];
+            let copy_submit = vk::SubmitInfo::default()
+                .command_buffers(std::slice::from_ref(&copy_command_buffer))
+                .signal_semaphores(&signal_semaphores);
+            device.queue_submit(queues.transfer.queue, &[copy_submit], vk::Fence::null())?;
+
+            let wait_stages = [vk::PipelineStageFlags::ALL_COMMANDS];
+            let acquire_submit = vk::SubmitInfo::default()
+                .command_buffers(std::slice::from_ref(&acquire_command_buffer))
+                .wait_semaphores(&signal_semaphores)
+                .wait_dst_stage_mask(&wait_stages);
+            device.queue_submit(queues.graphics.queue, &[acquire_submit], self.fence)?;
+        }
+
+        Ok(())
+    }
+
+    /// Destroys everything but the mesh and the fence. The submissions must
+    /// have completed.
+    fn finish(mut self, device: &Device, allocator: &mut Allocator) -> (GpuMesh, vk::Fence) {
+        unsafe {
+            for (command_pool, command_buffer) in &self.command_buffers {
+                device.free_command_buffers(*command_pool, &[*command_buffer]);
+            }
+            if self.semaphore != vk::Semaphore::null() {
+                device.destroy_semaphore(self.semaphore, None);
+            }
+        }
+        self.staging.destroy(device, allocator);
+
+        (self.mesh, self.fence)
+    }
+}
+
+/// Chunk mesh uploads in flight, each submitted with a fence of its own.
+///
+/// Uploads are started by [`upload`](Self::upload) without waiting for the
+/// copies, and [`poll`](Self::poll) hands out the meshes whose fence is
+/// signaled, which are only then drawn. Neither waits on a queue or the
+/// device.
+#[derive(Default)]
+pub struct UploadTracker {
+    fences: FencePool,
+    pending: Vec<PendingUpload>,
+}
+
+impl UploadTracker {
+    /// Starts copying the mesh of the chunk at `coord`, replacing any earlier
+    /// upload of it still in flight.
+    ///
+    /// Nothing is kept when it fails, the buffers created for the upload are
+    /// destroyed.
+    pub fn upload(
+        &mut self,
+        device: &Device,
+        allocator: &mut Allocator,
+        queues: &UploadQueues,
+        coord: IVec3,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<(), VulkanError> {
+        self.discard(coord);
+
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(vertices);
+        let index_bytes: &[u8] = bytemuck::cast_slice(indices);
+        let vertex_size = vertex_bytes.len() as vk::DeviceSize;
+        let index_size = index_bytes.len() as vk::DeviceSize;
+
+        let fence = self.fences.acquire(device)?;
+
+        let mut staging = create_buffer(
+            device,
+            allocator,
+            "staging",
+            vertex_size + index_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        );
+        let mapped = staging
+            .allocation
+            .mapped_slice_mut()
+            .expect("Staging memory must be host visible");
+        mapped[..vertex_bytes.len()].copy_from_slice(vertex_bytes);
+        mapped[vertex_bytes.len()..vertex_bytes.len() + index_bytes.len()]
+            .copy_from_slice(index_bytes);
+
+        let mesh = GpuMesh {
+            vertex_buffer: create_buffer(
+                device,
+                allocator,
+                &chunk_object_name(coord, "vertex_buffer"),
+                vertex_size,
+                vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER,
+                MemoryLocation::GpuOnly,
+            ),
+            index_buffer: create_buffer(
+                device,
+                allocator,
+                &chunk_object_name(coord, "index_buffer"),
+                index_size,
+                vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER,
+                MemoryLocation::GpuOnly,
+            ),
+            index_count: indices.len() as u32,
+        };
+
+        let mut upload = PendingUpload {
+            coord,
+            mesh,
+            staging,
+            fence,
+            command_buffers: Vec::new(),
+            semaphore: vk::Semaphore::null(),
+            discarded: false,
+        };
+        if let Err(err) = upload.submit(device, queues, vertex_size, index_size) {
+            // The copy may have been submitted before the acquire failed.
+            if let Err(wait_err) = unsafe { device.queue_wait_idle(queues.transfer.queue) } {
+                error!("Failed to wait for the transfer queue: {wait_err}");
+            }
+            let (mut mesh, fence) = upload.finish(device, allocator);
+            mesh.destroy(device, allocator);
+            // Only the last submission signals the fence, which failed.
+            self.fences.recycle(fence);
+            return Err(err);
+        }
+
+        self.pending.push(upload);
+        Ok(())
+    }
+
+    /// Whether a mesh of the chunk at `coord` is being uploaded.
+    pub fn is_pending(&self, coord: IVec3) -> bool {
+        self.pending
+            .iter()
+            .any(|upload| upload.coord == coord && !upload.discarded)
+    }
+
+    /// Drops the upload of the chunk at `coord` in flight, if there is one.
+    pub fn discard(&mut self, coord: IVec3) {
+        for upload in &mut self.pending {
+            if upload.coord == coord {
+                upload.discarded = true;
+            }
+        }
+    }
+
+    /// Takes the meshes whose upload finished, without waiting for the others.
+    ///
+    /// An upload stays pending when its fence can't be reset, and is taken by
+    /// a later call.
+    pub fn poll(
+        &mut self,
+        device: &Device,
+        allocator: &mut Allocator,
+    ) -> Result<Vec<(IVec3, GpuMesh)>, VulkanError> {
+        let mut finished = Vec::new();
+        let mut index = 0;
+        while index < self.pending.len() {
+            let fence = self.pending[index].fence;
+            if !unsafe { device.get_fence_status(fence)? } {
+                index += 1;
+                continue;
+            }
+            self.fences.release(device, fence)?;
+
+            let upload = self.pending.swap_remove(index);
+            let (coord, discarded) = (upload.coord, upload.discarded);
+            let (mut mesh, _) = upload.finish(device, allocator);
+            if discarded {
+                mesh.destroy(device, allocator);
+            } else {
+                finished.push((coord, mesh));
+            }
+        }
+
+        Ok(finished)
+    }
+
+    /// Destroys the pending uploads and the fences. The device must be idle.
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        for upload in std::mem::take(&mut self.pending) {
+            let (mut mesh, fence) = upload.finish(device, allocator);
+            mesh.destroy(device, allocator);
+            unsafe { device.destroy_fence(fence, None) };
+        }
+        self.fences.destroy(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::VulkanApp;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn released_fences_are_reset_and_reused() {
+        let app = VulkanApp::new_headless(vk::Extent2D {
+            width: 4,
+            height: 4,
+        })
+        .unwrap();
+        let device = app.device();
+        let mut pool = FencePool::default();
+
+        let fence = pool.acquire(device).unwrap();
+        assert!(!unsafe { device.get_fence_status(fence) }.unwrap());
+
+        // An empty submission signals the fence once the queue reaches it.
+        unsafe {
+            device.queue_submit(app.graphics_queue, &[], fence).unwrap();
+            device.wait_for_fences(&[fence], true, u64::MAX).unwrap();
+        }
+        pool.release(device, fence).unwrap();
+        assert!(!unsafe { device.get_fence_status(fence) }.unwrap());
+
+        assert_eq!(pool.acquire(device).unwrap(), fence);
+        pool.recycle(fence);
+        pool.destroy(device);
+        app.destroy();
+    }
+}
@@ -0,0 +1,514 @@
+use std::{
+    ffi::c_void,
+    sync::{Mutex, MutexGuard, RwLock},
+};
+
+use ash::vk;
+use bevy_ecs::resource::Resource;
+use tracing::{Level, event};
+
+/// Target of the messages logged by [`vulkan_debug_callback`], so they can be
+/// filtered apart from the app logs, e.g. `RUST_LOG=wolrdgen_voxels=info,vulkan::validation=warn`.
+pub const VALIDATION_TARGET: &str = "vulkan::validation";
+
+/// Which validation messages are reported and how they are logged.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationConfig {
+    pub severities: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub levels: SeverityLevels,
+    pub error_policy: ValidationErrorPolicy,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            severities: vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+                | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            message_types: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            levels: SeverityLevels::DEFAULT,
+            error_policy: ValidationErrorPolicy::default(),
+        }
+    }
+}
+
+impl ValidationConfig {
+    /// Create info for a messenger reporting through [`vulkan_debug_callback`].
+    ///
+    /// The callback has no access to the world, so this also makes `levels`
+    /// and `error_policy` the ones it uses.
+    pub fn messenger_create_info(&self) -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
+        *CALLBACK_CONFIG
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = CallbackConfig {
+            levels: self.levels,
+            error_policy: self.error_policy,
+        };
+
+        vk::DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(self.severities)
+            .message_type(self.message_types)
+            .pfn_user_callback(Some(vulkan_debug_callback))
+    }
+}
+
+/// The `tracing` level each message severity is logged at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeverityLevels {
+    pub verbose: Level,
+    pub info: Level,
+    pub warning: Level,
+    pub error: Level,
+}
+
+impl SeverityLevels {
+    pub const DEFAULT: Self = Self {
+        verbose: Level::TRACE,
+        info: Level::INFO,
+        warning: Level::WARN,
+        error: Level::ERROR,
+    };
+
+    /// Level of the most severe bit in `severity`.
+    pub fn level(&self, severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Level {
+        if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+            self.error
+        } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+            self.warning
+        } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+            self.info
+        } else {
+            self.verbose
+        }
+    }
+}
+
+impl Default for SeverityLevels {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// What happens when a message is logged at [`Level::ERROR`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationErrorPolicy {
+    /// Only log the message.
+    Log,
+    /// Panic on the next [`check_validation_errors`], which runs after the
+    /// Vulkan calls of a frame. The callback itself can't unwind across FFI.
+    Panic,
+    /// Abort the process from the callback, while the offending call is still
+    /// on the stack of a debugger.
+    Abort,
+}
+
+impl Default for ValidationErrorPolicy {
+    /// [`ValidationErrorPolicy::Panic`] in tests, so validation errors fail them.
+    fn default() -> Self {
+        if cfg!(test) { Self::Panic } else { Self::Log }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CallbackConfig {
+    levels: SeverityLevels,
+    error_policy: ValidationErrorPolicy,
+}
+
+static CALLBACK_CONFIG: RwLock<CallbackConfig> = RwLock::new(CallbackConfig {
+    levels: SeverityLevels::DEFAULT,
+    error_policy: ValidationErrorPolicy::Log,
+});
+
+/// First error reported under [`ValidationErrorPolicy::Panic`] since the last check.
+#[derive(Debug, Default)]
+struct PendingError(Option<String>);
+
+impl PendingError {
+    /// Keeps `message` unless an earlier error is still pending.
+    fn record(&mut self, message: String) {
+        self.0.get_or_insert(message);
+    }
+
+    /// The panic message of the pending error, clearing it.
+    fn take_panic_message(&mut self) -> Option<String> {
+        self.0
+            .take()
+            .map(|message| format!("Vulkan validation error: {message}"))
+    }
+}
+
+static PENDING_ERROR: Mutex<PendingError> = Mutex::new(PendingError(None));
+
+fn pending_error() -> MutexGuard<'static, PendingError> {
+    PENDING_ERROR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Panics if a validation error was reported under [`ValidationErrorPolicy::Panic`].
+pub fn check_validation_errors() {
+    let message = pending_error().take_panic_message();
+    if let Some(message) = message {
+        panic!("{message}");
+    }
+}
+
+/// Objects a message is about, which may be neither set nor named.
+///
+/// # Safety
+/// `data` must be valid as passed to [`vulkan_debug_callback`].
+unsafe fn callback_objects<'a>(
+    data: &'a vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+) -> &'a [vk::DebugUtilsObjectNameInfoEXT<'a>] {
+    if data.p_objects.is_null() || data.object_count == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(data.p_objects, data.object_count as usize) }
+    }
+}
+
+/// Lists `objects` as `TYPE 0xhandle "name"`, leaving out missing names.
+///
+/// # Safety
+/// The names of `objects` must be null or valid C strings.
+unsafe fn format_objects(objects: &[vk::DebugUtilsObjectNameInfoEXT]) -> String {
+    let objects = objects.iter().map(|object| {
+        let handle = format!("{:?} {:#x}", object.object_type, object.object_handle);
+        match unsafe { object.object_name_as_c_str() } {
+            Some(name) => format!("{handle} {:?}", name.to_string_lossy()),
+            None => handle,
+        }
+    });
+
+    format!("[{}]", objects.collect::<Vec<_>>().join(", "))
+}
+
+/// Logs a message at `level` with its ID, type and objects as fields, so
+/// messages can be grouped by ID.
+macro_rules! validation_event {
+    ($level:expr, $data:expr, $message_types:expr, $objects:expr, $message:expr) => {
+        event!(
+            target: VALIDATION_TARGET,
+            $level,
+            message_id_name = %unsafe { $data.message_id_name_as_c_str() }
+                .unwrap_or_default()
+                .to_string_lossy(),
+            message_id_number = $data.message_id_number,
+            message_type = ?$message_types,
+            objects = %$objects,
+            "{}",
+            $message
+        )
+    };
+}
+
+unsafe extern "system" fn vulkan_debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+    _p_user_data: *mut c_void,
+) -> u32 {
+    let data = unsafe { &*p_callback_data };
+    let message = unsafe { data.message_as_c_str() }
+        .unwrap_or_default()
+        .to_string_lossy();
+    let objects = unsafe { format_objects(callback_objects(data)) };
+
+    let config = *CALLBACK_CONFIG
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let level = config.levels.level(message_severity);
+    match level {
+        Level::ERROR => validation_event!(Level::ERROR, data, message_types, objects, message),
+        Level::WARN => validation_event!(Level::WARN, data, message_types, objects, message),
+        Level::INFO => validation_event!(Level::INFO, data, message_types, objects, message),
+        Level::DEBUG => validation_event!(Level::DEBUG, data, message_types, objects, message),
+        _ => validation_event!(Level::TRACE, data, message_types, objects, message),
+    }
+
+    if level == Level::ERROR {
+        match config.error_policy {
+            ValidationErrorPolicy::Log => {}
+            ValidationErrorPolicy::Panic => {
+                pending_error().record(format!("{message_types:?} - {message}"))
+            }
+            ValidationErrorPolicy::Abort => std::process::abort(),
+        }
+    }
+
+    vk::FALSE
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, ffi::CStr, sync::Arc};
+
+    use tracing::{
+        Event, Subscriber,
+        field::{Field, Visit},
+        info,
+    };
+    use tracing_subscriber::{EnvFilter, Layer, layer::Context, prelude::*};
+
+    use super::*;
+
+    /// An event passed by the filter.
+    #[derive(Debug, Clone, Default)]
+    struct Captured {
+        target: String,
+        level: Option<Level>,
+        message: String,
+        fields: HashMap<String, String>,
+    }
+
+    impl Visit for Captured {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.fields
+                .insert(field.name().to_owned(), value.to_owned());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.message = format!("{value:?}");
+            } else {
+                self.fields
+                    .insert(field.name().to_owned(), format!("{value:?}"));
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct CaptureLayer(Arc<Mutex<Vec<Captured>>>);
+
+    impl<S: Subscriber> Layer<S> for CaptureLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut captured = Captured {
+                target: event.metadata().target().to_owned(),
+                level: Some(*event.metadata().level()),
+                ..Default::default()
+            };
+            event.record(&mut captured);
+            self.0.lock().unwrap().push(captured);
+        }
+    }
+
+    /// Runs `f` with the events passing `filter` captured.
+    fn capture(filter: &str, f: impl FnOnce()) -> Vec<Captured> {
+        let layer = CaptureLayer::default();
+        let subscriber =
+            tracing_subscriber::registry().with(layer.clone().with_filter(EnvFilter::new(filter)));
+        tracing::subscriber::with_default(subscriber, f);
+
+        layer.0.lock().unwrap().clone()
+    }
+
+    /// Calls the callback the way the validation layers do.
+    fn report(severity: vk::DebugUtilsMessageSeverityFlagsEXT, message: &CStr) {
+        report_data(
+            severity,
+            &vk::DebugUtilsMessengerCallbackDataEXT::default().message(message),
+        );
+    }
+
+    fn report_data(
+        severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        data: &vk::DebugUtilsMessengerCallbackDataEXT,
+    ) {
+        unsafe {
+            vulkan_debug_callback(
+                severity,
+                vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+                data,
+                std::ptr::null_mut(),
+            );
+        }
+    }
+
+    #[test]
+    fn default_levels_match_severities() {
+        let levels = SeverityLevels::default();
+
+        assert_eq!(
+            levels.level(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR),
+            Level::ERROR
+        );
+        assert_eq!(
+            levels.level(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING),
+            Level::WARN
+        );
+        assert_eq!(
+            levels.level(vk::DebugUtilsMessageSeverityFlagsEXT::INFO),
+            Level::INFO
+        );
+        assert_eq!(
+            levels.level(vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE),
+            Level::TRACE
+        );
+    }
+
+    #[test]
+    fn remapped_warning_logs_as_error() {
+        let levels = SeverityLevels {
+            warning: Level::ERROR,
+            ..SeverityLevels::DEFAULT
+        };
+
+        assert_eq!(
+            levels.level(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING),
+            Level::ERROR
+        );
+    }
+
+    #[test]
+    fn first_recorded_error_is_kept_until_checked() {
+        let mut pending = PendingError::default();
+        assert_eq!(pending.take_panic_message(), None);
+
+        pending.record("VALIDATION - first".to_owned());
+        pending.record("VALIDATION - second".to_owned());
+        assert_eq!(
+            pending.take_panic_message().as_deref(),
+            Some("Vulkan validation error: VALIDATION - first")
+        );
+        assert_eq!(pending.take_panic_message(), None);
+    }
+
+    /// Set for the process [`abort_policy_aborts_on_errors`] runs itself in.
+    const ABORT_CHILD_ENV: &str = "WOLRDGEN_VALIDATION_ABORT_CHILD";
+
+    #[test]
+    fn abort_policy_aborts_on_errors() {
+        if std::env::var_os(ABORT_CHILD_ENV).is_some() {
+            // Creating the info makes the policy the one of the callback.
+            let _ = ValidationConfig {
+                error_policy: ValidationErrorPolicy::Abort,
+                ..Default::default()
+            }
+            .messenger_create_info();
+            report(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, c"fatal");
+            unreachable!("the callback aborts the process");
+        }
+
+        // Aborting would take the other tests down, so the test runs itself alone.
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "rendering::validation::tests::abort_policy_aborts_on_errors",
+            ])
+            .env(ABORT_CHILD_ENV, "1")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+
+        assert!(!status.success());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            assert_eq!(status.signal(), Some(6), "SIGABRT");
+        }
+    }
+
+    #[test]
+    fn validation_messages_are_filtered_apart() {
+        let events = capture("wolrdgen_voxels=trace,vulkan::validation=warn", || {
+            report(vk::DebugUtilsMessageSeverityFlagsEXT::INFO, c"loaded layer");
+            report(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, c"slow path");
+            info!("app message");
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].target, VALIDATION_TARGET);
+        assert_eq!(events[0].level, Some(Level::WARN));
+        assert!(events[0].message.ends_with("slow path"));
+        assert_eq!(events[1].message, "app message");
+    }
+
+    #[test]
+    fn messages_carry_structured_fields() {
+        let objects = [
+            vk::DebugUtilsObjectNameInfoEXT {
+                object_type: vk::ObjectType::BUFFER,
+                ..Default::default()
+            }
+            .object_name(c"chunk(0,0,0).vertices"),
+            vk::DebugUtilsObjectNameInfoEXT {
+                object_type: vk::ObjectType::DEVICE,
+                object_handle: 0x2a,
+                ..Default::default()
+            },
+        ];
+        let data = vk::DebugUtilsMessengerCallbackDataEXT::default()
+            .message_id_name(c"VUID-vkCmdDraw-None-02699")
+            .message_id_number(-1234)
+            .message(c"descriptor set not bound")
+            .objects(&objects);
+
+        let events = capture("vulkan::validation=trace", || {
+            report_data(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, &data);
+        });
+
+        let fields = &events[0].fields;
+        assert_eq!(events[0].message, "descriptor set not bound");
+        assert_eq!(fields["message_id_name"], "VUID-vkCmdDraw-None-02699");
+        assert_eq!(fields["message_id_number"], "-1234");
+        assert_eq!(fields["message_type"], "VALIDATION");
+        assert_eq!(
+            fields["objects"],
+            r#"[BUFFER 0x0 "chunk(0,0,0).vertices", DEVICE 0x2a]"#
+        );
+    }
+
+    #[test]
+    fn missing_objects_and_id_are_empty() {
+        let events = capture("vulkan::validation=trace", || {
+            report(vk::DebugUtilsMessageSeverityFlagsEXT::INFO, c"no objects");
+        });
+
+        let fields = &events[0].fields;
+        assert_eq!(fields["message_id_name"], "");
+        assert_eq!(fields["objects"], "[]");
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn validation_error_carries_its_id() {
+        let events = capture("vulkan::validation=error", || {
+            let app = crate::rendering::VulkanApp::new_headless(vk::Extent2D {
+                width: 4,
+                height: 4,
+            })
+            .unwrap();
+
+            // A size of 0 is invalid.
+            let create_info = vk::BufferCreateInfo::default()
+                .size(0)
+                .usage(vk::BufferUsageFlags::VERTEX_BUFFER);
+            if let Ok(buffer) = unsafe { app.device().create_buffer(&create_info, None) } {
+                unsafe { app.device().destroy_buffer(buffer, None) };
+            }
+            app.destroy();
+        });
+        // The error was expected, don't fail the next check.
+        pending_error().take_panic_message();
+
+        assert!(events.iter().any(|event| {
+            event.fields["message_id_name"].starts_with("VUID-VkBufferCreateInfo-size")
+                && event.fields["objects"].contains("DEVICE")
+        }));
+    }
+
+    #[test]
+    fn most_severe_bit_wins() {
+        let levels = SeverityLevels::default();
+        let severity = vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+            | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+
+        assert_eq!(levels.level(severity), Level::WARN);
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use bevy_app::{App, First, Plugin};
+use bevy_ecs::{resource::Resource, system::ResMut};
+
+pub struct TimePlugin;
+
+impl Plugin for TimePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Time>()
+            .add_systems(First, update_time_system);
+    }
+}
+
+/// Frame timing, updated at the start of every frame.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Time {
+    startup: Instant,
+    last_update: Option<Instant>,
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl Time {
+    pub fn new(startup: Instant) -> Self {
+        Self {
+            startup,
+            last_update: None,
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Starts a frame at `now`.
+    pub fn update_with_instant(&mut self, now: Instant) {
+        self.delta = match self.last_update {
+            Some(last_update) => now.saturating_duration_since(last_update),
+            None => Duration::ZERO,
+        };
+        self.elapsed = now.saturating_duration_since(self.startup);
+        self.last_update = Some(now);
+    }
+
+    /// Time between the start of the previous frame and this one.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Time since startup at the start of this frame.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// When the current frame started, `None` before the first frame.
+    pub fn last_update(&self) -> Option<Instant> {
+        self.last_update
+    }
+}
+
+fn update_time_system(mut time: ResMut<Time>) {
+    time.update_with_instant(Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_update_has_no_delta() {
+        let startup = Instant::now();
+        let mut time = Time::new(startup);
+
+        time.update_with_instant(startup + Duration::from_millis(5));
+
+        assert_eq!(time.delta(), Duration::ZERO);
+        assert_eq!(time.elapsed(), Duration::from_millis(5));
+    }
+
+    #[test]
+    fn delta_between_updates() {
+        let startup = Instant::now();
+        let mut time = Time::new(startup);
+
+        time.update_with_instant(startup + Duration::from_millis(10));
+        time.update_with_instant(startup + Duration::from_millis(26));
+
+        assert_eq!(time.delta(), Duration::from_millis(16));
+        assert_eq!(time.elapsed(), Duration::from_millis(26));
+        assert_eq!(
+            time.last_update(),
+            Some(startup + Duration::from_millis(26))
+        );
+    }
+}
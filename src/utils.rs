@@ -1,4 +1,11 @@
-use bevy_ecs::system::{Local, SystemParam};
+use std::time::{Duration, Instant};
+
+use bevy_ecs::{
+    resource::Resource,
+    system::{Local, Res, SystemParam},
+};
+
+use crate::time::Time;
 
 #[derive(SystemParam)]
 pub struct FirstRun<'s>(Local<'s, bool>);
@@ -13,3 +20,172 @@ impl FirstRun<'_> {
         }
     }
 }
+
+/// Throttles a system to run its body at most once per period of [`Time`].
+#[derive(SystemParam)]
+pub struct RunEvery<'w, 's> {
+    time: Res<'w, Time>,
+    last_run: Local<'s, Option<Instant>>,
+}
+
+impl RunEvery<'_, '_> {
+    /// Returns `true` on the first call and then once `period` has passed
+    /// since the last time it returned `true`.
+    pub fn elapsed(&mut self, period: Duration) -> bool {
+        let Some(now) = self.time.last_update() else {
+            return false;
+        };
+
+        match *self.last_run {
+            Some(last_run) if now.saturating_duration_since(last_run) < period => false,
+            _ => {
+                *self.last_run = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Detects when the value of a resource differs from the last one seen,
+/// unlike `is_changed` which also fires on any mutable access.
+#[derive(SystemParam)]
+pub struct OnChange<'s, T: Resource + Clone + PartialEq>(Local<'s, Option<T>>);
+
+impl<T: Resource + Clone + PartialEq> OnChange<'_, T> {
+    /// Returns `value` if it differs from the previous call.
+    ///
+    /// The first call only records `value`, as whatever depends on it was
+    /// created with it.
+    pub fn changed<'a>(&mut self, value: &'a T) -> Option<&'a T> {
+        match self.0.replace(value.clone()) {
+            Some(previous) if previous != *value => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{
+        change_detection::DetectChangesMut,
+        system::{IntoSystem, ResMut, RunSystemOnce, System},
+        world::World,
+    };
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Runs(u32);
+
+    fn throttled_system(mut run_every: RunEvery, mut runs: ResMut<Runs>) {
+        if run_every.elapsed(Duration::from_secs(1)) {
+            runs.0 += 1;
+        }
+    }
+
+    #[test]
+    fn run_every_fires_once_per_period() {
+        let startup = Instant::now();
+        let mut world = World::new();
+        world.insert_resource(Time::new(startup));
+        world.init_resource::<Runs>();
+
+        let mut system = IntoSystem::into_system(throttled_system);
+        system.initialize(&mut world);
+
+        let mut runs_at = |world: &mut World, millis: u64| {
+            world
+                .resource_mut::<Time>()
+                .update_with_instant(startup + Duration::from_millis(millis));
+            system.run((), world);
+            world.resource::<Runs>().0
+        };
+
+        assert_eq!(runs_at(&mut world, 0), 1);
+        assert_eq!(runs_at(&mut world, 16), 1);
+        assert_eq!(runs_at(&mut world, 999), 1);
+        assert_eq!(runs_at(&mut world, 1000), 2);
+        assert_eq!(runs_at(&mut world, 1500), 2);
+        // The period restarts from the last run, not from startup.
+        assert_eq!(runs_at(&mut world, 2100), 3);
+        assert_eq!(runs_at(&mut world, 3000), 3);
+        assert_eq!(runs_at(&mut world, 3100), 4);
+    }
+
+    #[test]
+    fn run_every_waits_for_first_frame() {
+        let mut world = World::new();
+        world.insert_resource(Time::new(Instant::now()));
+        world.init_resource::<Runs>();
+
+        world.run_system_once(throttled_system).unwrap();
+
+        assert_eq!(world.resource::<Runs>().0, 0);
+    }
+
+    #[derive(Resource, Debug, Clone, Copy, PartialEq)]
+    struct Mode(u32);
+
+    #[derive(Resource, Default)]
+    struct Changes(Vec<u32>);
+
+    fn recreate_system(
+        mode: Res<Mode>,
+        mut on_change: OnChange<Mode>,
+        mut changes: ResMut<Changes>,
+    ) {
+        if let Some(mode) = on_change.changed(&mode) {
+            changes.0.push(mode.0);
+        }
+    }
+
+    fn run_with_mode(system: &mut impl System<In = (), Out = ()>, world: &mut World, mode: u32) {
+        *world.resource_mut::<Mode>() = Mode(mode);
+        system.run((), world);
+    }
+
+    fn on_change_world() -> (World, impl System<In = (), Out = ()>) {
+        let mut world = World::new();
+        world.insert_resource(Mode(0));
+        world.init_resource::<Changes>();
+
+        let mut system = IntoSystem::into_system(recreate_system);
+        system.initialize(&mut world);
+        (world, system)
+    }
+
+    #[test]
+    fn on_change_ignores_same_value() {
+        let (mut world, mut system) = on_change_world();
+
+        run_with_mode(&mut system, &mut world, 0);
+        run_with_mode(&mut system, &mut world, 0);
+        // Writing the same value still triggers `is_changed`, but not `OnChange`.
+        world.resource_mut::<Mode>().set_changed();
+        system.run((), &mut world);
+
+        assert!(world.resource::<Changes>().0.is_empty());
+    }
+
+    #[test]
+    fn on_change_reports_new_value() {
+        let (mut world, mut system) = on_change_world();
+
+        run_with_mode(&mut system, &mut world, 0);
+        run_with_mode(&mut system, &mut world, 2);
+        run_with_mode(&mut system, &mut world, 2);
+
+        assert_eq!(world.resource::<Changes>().0, [2]);
+    }
+
+    #[test]
+    fn on_change_reports_change_back() {
+        let (mut world, mut system) = on_change_world();
+
+        run_with_mode(&mut system, &mut world, 0);
+        run_with_mode(&mut system, &mut world, 1);
+        run_with_mode(&mut system, &mut world, 0);
+
+        assert_eq!(world.resource::<Changes>().0, [1, 0]);
+    }
+}
@@ -1,5 +1,11 @@
 use core::str;
-use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use bevy_app::{App, AppExit, Plugin, PluginsState};
 use bevy_ecs::{
@@ -7,29 +13,189 @@ use bevy_ecs::{
     resource::Resource,
     system::{ResMut, SystemState},
 };
-use tracing::error;
+use raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
+use tracing::{debug, error, info, warn};
 use winit::{
     application::ApplicationHandler,
     dpi::LogicalSize,
-    event::WindowEvent,
-    event_loop::{ControlFlow, EventLoop, OwnedDisplayHandle},
+    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopBuilder, OwnedDisplayHandle},
+    keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowAttributes, WindowId},
 };
 
-use crate::rendering::VulkanApp;
+use glam::Vec2;
+
+use crate::{
+    rendering::{CompositeAlpha, Destroy, RenderDevice},
+    time::Time,
+};
 
-pub struct WindowingPlugin;
+#[derive(Default)]
+pub struct WindowingPlugin {
+    /// Display server to connect to on Linux, see [`DisplayBackend`].
+    pub backend: DisplayBackend,
+}
 
 impl Plugin for WindowingPlugin {
     fn build(&self, app: &mut App) {
-        let event_loop = EventLoop::new().unwrap();
+        let backend = self
+            .backend
+            .resolve(std::env::var(DisplayBackend::ENV_VAR).ok().as_deref());
+        let event_loop = build_event_loop(backend);
+
+        match event_loop.owned_display_handle().display_handle() {
+            Ok(handle) => info!(
+                "Connected to the {} display server",
+                display_backend_name(&handle.as_raw())
+            ),
+            Err(err) => warn!("Failed to get the display handle: {err}"),
+        }
 
-        event_loop.set_control_flow(ControlFlow::Poll);
+        // Frames are driven by `request_redraw`, see `UpdateMode`.
+        event_loop.set_control_flow(ControlFlow::Wait);
+
+        app.add_event::<Screenshot>()
+            .add_event::<WindowClosed>()
+            .init_resource::<FpsCap>()
+            .init_resource::<UpdateMode>()
+            .init_resource::<ScaleFactor>();
 
         app.set_runner(|app| runner(app, event_loop));
     }
 }
 
+/// Runs the app for a fixed number of updates without a display, in place of
+/// [`WindowingPlugin`]. Only built for tests of the ECS systems in CI.
+///
+/// No window is created, so [`RenderingPlugin`](crate::rendering::RenderingPlugin)
+/// never creates the `VulkanApp` (it waits for [`AppWindows`]) and every system
+/// of the `Render` schedule is skipped, as they all require it. Systems that
+/// only use the world, like streaming, meshing and the `Destroy` schedule,
+/// run as usual. Tests of render-side systems insert a stub renderer instead.
+///
+/// Window events can be simulated by sending [`RawWnitWindowEvent`]s.
+#[cfg(test)]
+pub struct HeadlessRunnerPlugin {
+    /// Number of `update` calls before the runner returns, unless the app exits earlier.
+    pub updates: usize,
+}
+
+#[cfg(test)]
+impl Plugin for HeadlessRunnerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Screenshot>()
+            .add_event::<RawWnitWindowEvent>()
+            .add_event::<WindowClosed>()
+            .init_resource::<FpsCap>()
+            .init_resource::<UpdateMode>()
+            .init_resource::<ScaleFactor>();
+
+        let updates = self.updates;
+        app.set_runner(move |app| headless_runner(app, updates));
+    }
+}
+
+#[cfg(test)]
+fn headless_runner(mut app: App, updates: usize) -> AppExit {
+    if app.plugins_state() == PluginsState::Ready {
+        app.finish();
+        app.cleanup();
+    }
+
+    let mut app_exit = None;
+    for _ in 0..updates {
+        app.update();
+        app_exit = app.should_exit();
+        if app_exit.is_some() {
+            break;
+        }
+    }
+
+    teardown(&mut app);
+    app_exit.unwrap_or(AppExit::Success)
+}
+
+/// Display server used on Linux, where both Wayland and X11 may be available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayBackend {
+    /// Wayland when available, X11 otherwise. Can be overridden with [`DisplayBackend::ENV_VAR`].
+    #[default]
+    Auto,
+    Wayland,
+    /// Useful for screen capture tools that don't support Wayland.
+    X11,
+}
+
+impl DisplayBackend {
+    /// Environment variable forcing a backend when [`DisplayBackend::Auto`] is
+    /// configured, either `wayland` or `x11`.
+    pub const ENV_VAR: &str = "WINIT_UNIX_BACKEND";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "wayland" => Some(Self::Wayland),
+            "x11" => Some(Self::X11),
+            _ => None,
+        }
+    }
+
+    /// The backend to use given the value of [`DisplayBackend::ENV_VAR`].
+    /// An explicitly configured backend wins over the variable.
+    fn resolve(self, env: Option<&str>) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        let Some(env) = env else {
+            return self;
+        };
+        Self::from_name(env).unwrap_or_else(|| {
+            warn!(
+                "Unknown {} `{env}`, expected `wayland` or `x11`",
+                Self::ENV_VAR
+            );
+            self
+        })
+    }
+}
+
+fn build_event_loop(backend: DisplayBackend) -> EventLoop<()> {
+    let mut builder: EventLoopBuilder<()> = EventLoop::builder();
+
+    #[cfg(target_os = "linux")]
+    match backend {
+        DisplayBackend::Auto => {}
+        DisplayBackend::Wayland => {
+            use winit::platform::wayland::EventLoopBuilderExtWayland;
+            builder.with_wayland();
+        }
+        DisplayBackend::X11 => {
+            use winit::platform::x11::EventLoopBuilderExtX11;
+            builder.with_x11();
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    if backend != DisplayBackend::Auto {
+        warn!("The {backend:?} display backend can only be selected on Linux");
+    }
+
+    builder.build().unwrap()
+}
+
+/// Human readable name of the display server behind `handle`.
+fn display_backend_name(handle: &RawDisplayHandle) -> &'static str {
+    match handle {
+        RawDisplayHandle::Wayland(_) => "Wayland",
+        RawDisplayHandle::Xlib(_) => "X11 (Xlib)",
+        RawDisplayHandle::Xcb(_) => "X11 (XCB)",
+        RawDisplayHandle::Windows(_) => "Windows",
+        RawDisplayHandle::AppKit(_) => "AppKit",
+        RawDisplayHandle::Android(_) => "Android",
+        _ => "unknown",
+    }
+}
+
 fn runner(mut app: App, event_loop: EventLoop<()>) -> AppExit {
     if app.plugins_state() == PluginsState::Ready {
         app.finish();
@@ -47,12 +213,7 @@ fn runner(mut app: App, event_loop: EventLoop<()>) -> AppExit {
         error!("winit event loop returned an error: {err}");
     };
 
-    // TODO: Use dedicated resource for `Device`
-    let vulkan_app = runner_state.app.world_mut().resource::<VulkanApp>();
-    // TODO: Move it to the custom implementation of the clear for the `Storage` plugin
-    unsafe { vulkan_app.device.device_wait_idle().unwrap() };
-
-    runner_state.app.world_mut().clear_all();
+    teardown(&mut runner_state.app);
 
     runner_state.app_exit.unwrap_or_else(|| {
         error!("Failed to receive an app exit code! This is a bug");
@@ -60,6 +221,32 @@ fn runner(mut app: App, event_loop: EventLoop<()>) -> AppExit {
     })
 }
 
+/// Waits for the GPU and drops every resource of the app.
+///
+/// The app may exit before `init_vulkan_app` ran (the window was never resumed) or after it
+/// failed, in which case there is no device to wait for.
+// TODO: Move it to the custom implementation of the clear for the `Storage` plugin
+fn teardown(app: &mut App) {
+    if let Some(render_device) = app.world().get_resource::<RenderDevice>()
+        && let Err(err) = unsafe { render_device.device.device_wait_idle() }
+    {
+        error!("Failed to wait for the device to become idle: {err}");
+    }
+
+    // Destroys everything created through Vulkan, once. The `VulkanApp` hands
+    // its objects over to the storages first.
+    if app.world_mut().try_run_schedule(Destroy).is_err() {
+        debug!("No `Destroy` schedule to run, the storage plugin isn't added");
+    }
+
+    app.world_mut().clear_all();
+}
+
+/// Windows of the app, created by the runner.
+///
+/// Closing a secondary window removes it and sends [`WindowClosed`]. Closing
+/// the primary one exits the app, even if secondary windows are still open,
+/// so it is always the last window to close.
 #[derive(Resource)]
 pub struct AppWindows {
     pub primary: Arc<Window>,
@@ -68,12 +255,66 @@ pub struct AppWindows {
     pub secondary: HashMap<Cow<'static, str>, Arc<Window>>,
 }
 
+impl AppWindows {
+    fn find(&self, window_id: WindowId) -> FoundWindow {
+        find_window(
+            self.primary.id(),
+            self.secondary
+                .iter()
+                .map(|(name, window)| (name, window.id())),
+            window_id,
+        )
+    }
+}
+
+/// Which of the [`AppWindows`] a window ID belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FoundWindow {
+    Primary,
+    Secondary(Cow<'static, str>),
+    /// Already closed, or not created by the runner.
+    Unknown,
+}
+
+fn find_window<'a>(
+    primary: WindowId,
+    secondary: impl IntoIterator<Item = (&'a Cow<'static, str>, WindowId)>,
+    window_id: WindowId,
+) -> FoundWindow {
+    if window_id == primary {
+        return FoundWindow::Primary;
+    }
+
+    secondary
+        .into_iter()
+        .find(|(_, id)| *id == window_id)
+        .map_or(FoundWindow::Unknown, |(name, _)| {
+            FoundWindow::Secondary(name.clone())
+        })
+}
+
+/// Sent after a secondary window of [`AppWindows`] was closed and removed.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct WindowClosed {
+    pub name: Cow<'static, str>,
+    pub window_id: WindowId,
+}
+
 #[derive(Event)]
 pub struct RawWnitWindowEvent {
     pub event: WindowEvent,
     pub window_id: WindowId,
 }
 
+/// Unaccelerated mouse movement reported by the device rather than the window.
+///
+/// Unlike `CursorMoved` it keeps reporting at the window edge, so it is used
+/// for mouse-look.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RawDeviceMouseMotion {
+    pub delta: Vec2,
+}
+
 #[derive(Resource)]
 pub struct WinitOwnedDisplayHandle(pub OwnedDisplayHandle);
 
@@ -95,20 +336,96 @@ impl WinitAppRunnerState {
     }
 }
 
+impl WinitAppRunnerState {
+    /// Sleeps out the rest of the frame when [`FpsCap`] is set.
+    fn pace_frame(&self) {
+        let world = self.app.world();
+        let Some(FpsCap(Some(target_fps))) = world.get_resource::<FpsCap>().copied() else {
+            return;
+        };
+        let Some(frame_start) = world.get_resource::<Time>().and_then(Time::last_update) else {
+            return;
+        };
+
+        let sleep = frame_sleep(target_fps, frame_start.elapsed());
+        if !sleep.is_zero() {
+            std::thread::sleep(sleep);
+        }
+    }
+
+    fn update_mode(&self) -> UpdateMode {
+        self.app
+            .world()
+            .get_resource::<UpdateMode>()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn is_primary_window(&self, window_id: WindowId) -> bool {
+        self.app
+            .world()
+            .get_resource::<AppWindows>()
+            .is_some_and(|windows| windows.find(window_id) == FoundWindow::Primary)
+    }
+
+    /// Removes a closed secondary window, or exits when the primary one is closed.
+    fn close_window(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        window_id: WindowId,
+    ) {
+        let world = self.app.world_mut();
+        let found = world
+            .get_resource::<AppWindows>()
+            .map_or(FoundWindow::Primary, |windows| windows.find(window_id));
+
+        match found {
+            FoundWindow::Primary => {
+                self.app_exit = Some(AppExit::Success);
+                event_loop.exit();
+            }
+            FoundWindow::Secondary(name) => {
+                // Dropping the last handle to the window destroys it.
+                world.resource_mut::<AppWindows>().secondary.remove(&name);
+                info!("Closed the `{name}` window");
+                world.send_event(WindowClosed { name, window_id });
+            }
+            FoundWindow::Unknown => debug!("Close requested for unknown window {window_id:?}"),
+        }
+    }
+
+    fn request_redraw(&self) {
+        if let Some(windows) = self.app.world().get_resource::<AppWindows>() {
+            windows.primary.request_redraw();
+        }
+    }
+}
+
 impl ApplicationHandler for WinitAppRunnerState {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let transparent = self
+            .app
+            .world()
+            .get_resource::<CompositeAlpha>()
+            .is_some_and(|composite_alpha| *composite_alpha != CompositeAlpha::Opaque);
         let primary_window = event_loop
             .create_window(
                 WindowAttributes::default()
                     .with_resizable(true)
+                    .with_transparent(transparent)
                     .with_inner_size(LogicalSize::new(1280, 720)),
             )
             .unwrap();
 
+        self.app
+            .world_mut()
+            .insert_resource(ScaleFactor(primary_window.scale_factor()));
         self.app.world_mut().insert_resource(AppWindows {
             primary: Arc::new(primary_window),
             secondary: HashMap::new(),
         });
+
+        self.request_redraw();
     }
 
     fn window_event(
@@ -120,14 +437,36 @@ impl ApplicationHandler for WinitAppRunnerState {
         // let system_state = &mut self.system_state;
 
         match event {
-            WindowEvent::CloseRequested => {
-                self.app_exit = Some(AppExit::Success);
-                event_loop.exit();
-            }
+            WindowEvent::CloseRequested => self.close_window(event_loop, window_id),
             WindowEvent::RedrawRequested => {
                 self.app.update();
+                self.pace_frame();
+
+                if let Some(app_exit) = self.app.should_exit() {
+                    self.app_exit = Some(app_exit);
+                    event_loop.exit();
+                } else if self.update_mode() == UpdateMode::Continuous {
+                    self.request_redraw();
+                }
             }
             event => {
+                // `ScaleFactor` only follows the primary window.
+                if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event
+                    && self.is_primary_window(window_id)
+                {
+                    self.app
+                        .world_mut()
+                        .insert_resource(ScaleFactor(scale_factor));
+                }
+
+                if self.update_mode() == UpdateMode::Reactive && wakes_reactive_update(&event) {
+                    self.request_redraw();
+                }
+
+                if is_screenshot_key(&event) {
+                    self.app.world_mut().send_event(Screenshot::timestamped());
+                }
+
                 self.app
                     .world_mut()
                     .send_event(RawWnitWindowEvent { event, window_id });
@@ -135,5 +474,315 @@ impl ApplicationHandler for WinitAppRunnerState {
         }
     }
 
+    fn device_event(
+        &mut self,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            self.app.world_mut().send_event(RawDeviceMouseMotion {
+                delta: Vec2::new(x as f32, y as f32),
+            });
+        }
+    }
+
     fn exiting(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {}
 }
+
+/// Requests the next presented frame to be saved as a PNG at `path`.
+///
+/// Sent when F2 is pressed.
+#[derive(Event, Debug, Clone)]
+pub struct Screenshot {
+    pub path: PathBuf,
+}
+
+impl Screenshot {
+    /// A screenshot in `screenshots/` named after the current time.
+    pub fn timestamped() -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        Self {
+            path: PathBuf::from(format!("screenshots/screenshot-{millis}.png")),
+        }
+    }
+}
+
+fn is_screenshot_key(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                physical_key: PhysicalKey::Code(KeyCode::F2),
+                state: ElementState::Pressed,
+                repeat: false,
+                ..
+            },
+            ..
+        }
+    )
+}
+
+/// Scale factor of the primary window, the ratio of physical to logical pixels.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct ScaleFactor(pub f64);
+
+impl Default for ScaleFactor {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// When the app is updated and a frame rendered.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UpdateMode {
+    /// Requests the next redraw as soon as a frame is done, paced by [`FpsCap`]
+    /// and the present mode.
+    #[default]
+    Continuous,
+    /// Only redraws after input or a resize. [`FpsCap`] still limits how fast
+    /// a burst of input, like moving the mouse, is rendered.
+    Reactive,
+}
+
+/// Window events that trigger an update in [`UpdateMode::Reactive`].
+fn wakes_reactive_update(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::Resized(_)
+            | WindowEvent::ScaleFactorChanged { .. }
+            | WindowEvent::Focused(_)
+            | WindowEvent::KeyboardInput { .. }
+            | WindowEvent::ModifiersChanged(_)
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::CursorEntered { .. }
+            | WindowEvent::CursorLeft { .. }
+    )
+}
+
+/// Upper bound on the frame rate, `None` renders as fast as presenting allows.
+///
+/// Only uncapped present modes like `MAILBOX` and `IMMEDIATE` can exceed the
+/// cap, with vsync the present already waits long enough.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FpsCap(pub Option<u32>);
+
+/// How long to sleep after a frame that took `elapsed` to reach `target_fps`.
+fn frame_sleep(target_fps: u32, elapsed: Duration) -> Duration {
+    if target_fps == 0 {
+        return Duration::ZERO;
+    }
+
+    let frame_time = Duration::from_secs(1) / target_fps;
+    frame_time.saturating_sub(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bevy_app::Update;
+    use bevy_ecs::event::EventWriter;
+
+    use super::*;
+    use crate::{
+        camera::CameraPlugin,
+        input::InputPlugin,
+        rendering::{RenderingPlugin, VulkanApp},
+        time::TimePlugin,
+        world::{WorldPlugin, store::ChunkStore},
+    };
+
+    #[test]
+    fn backend_override() {
+        assert_eq!(
+            DisplayBackend::Auto.resolve(Some("X11")),
+            DisplayBackend::X11
+        );
+        assert_eq!(
+            DisplayBackend::Auto.resolve(Some("wayland")),
+            DisplayBackend::Wayland
+        );
+        assert_eq!(DisplayBackend::Auto.resolve(None), DisplayBackend::Auto);
+        assert_eq!(
+            DisplayBackend::Auto.resolve(Some("mir")),
+            DisplayBackend::Auto
+        );
+        // The configured backend wins over the environment.
+        assert_eq!(
+            DisplayBackend::Wayland.resolve(Some("x11")),
+            DisplayBackend::Wayland
+        );
+    }
+
+    #[test]
+    fn surface_extension_matches_backend() {
+        use std::{ffi::CStr, ptr::NonNull};
+
+        use ash::khr;
+        use raw_window_handle::{WaylandDisplayHandle, XcbDisplayHandle, XlibDisplayHandle};
+
+        let cases = [
+            (
+                RawDisplayHandle::Wayland(WaylandDisplayHandle::new(NonNull::dangling())),
+                khr::wayland_surface::NAME,
+            ),
+            (
+                RawDisplayHandle::Xlib(XlibDisplayHandle::new(None, 0)),
+                khr::xlib_surface::NAME,
+            ),
+            (
+                RawDisplayHandle::Xcb(XcbDisplayHandle::new(None, 0)),
+                khr::xcb_surface::NAME,
+            ),
+        ];
+
+        for (handle, surface_extension) in cases {
+            let extensions = ash_window::enumerate_required_extensions(handle).unwrap();
+            let extensions = extensions
+                .iter()
+                .map(|name| unsafe { CStr::from_ptr(*name) })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                extensions,
+                [khr::surface::NAME, surface_extension],
+                "{}",
+                display_backend_name(&handle)
+            );
+        }
+    }
+
+    #[test]
+    fn sleeps_for_the_rest_of_the_frame() {
+        assert_eq!(
+            frame_sleep(50, Duration::from_millis(5)),
+            Duration::from_millis(15)
+        );
+        assert_eq!(
+            frame_sleep(60, Duration::ZERO),
+            Duration::from_nanos(16_666_666)
+        );
+    }
+
+    #[test]
+    fn slow_frames_do_not_sleep() {
+        assert_eq!(frame_sleep(60, Duration::from_millis(20)), Duration::ZERO);
+        assert_eq!(frame_sleep(50, Duration::from_millis(20)), Duration::ZERO);
+    }
+
+    #[test]
+    fn teardown_before_init() {
+        let mut app = App::new();
+        app.init_resource::<FpsCap>();
+
+        teardown(&mut app);
+
+        assert!(!app.world().contains_resource::<FpsCap>());
+        assert!(!app.world().contains_resource::<RenderDevice>());
+    }
+
+    fn headless_app(updates: usize) -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            HeadlessRunnerPlugin { updates },
+            TimePlugin,
+            InputPlugin,
+            RenderingPlugin::default(),
+            CameraPlugin,
+            WorldPlugin,
+        ));
+        app
+    }
+
+    #[test]
+    fn headless_runner_steps_updates() {
+        let mut app = headless_app(3);
+        let updates = Arc::new(AtomicUsize::new(0));
+        let counter = updates.clone();
+        app.add_systems(Update, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        assert_eq!(app.run(), AppExit::Success);
+        assert_eq!(updates.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn headless_runner_stops_on_exit() {
+        let mut app = headless_app(10);
+        app.add_systems(Update, |mut exit: EventWriter<AppExit>| {
+            exit.write(AppExit::error());
+        });
+
+        assert_eq!(app.run(), AppExit::error());
+    }
+
+    #[test]
+    fn storages_are_destroyed_once_at_exit() {
+        let mut app = headless_app(10);
+        let destroyed = Arc::new(AtomicUsize::new(0));
+        let counter = destroyed.clone();
+        app.add_systems(Destroy, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        app.add_systems(Update, |mut exit: EventWriter<AppExit>| {
+            exit.write(AppExit::Success);
+        });
+
+        assert_eq!(app.run(), AppExit::Success);
+        assert_eq!(destroyed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn world_updates_without_rendering() {
+        let mut app = headless_app(0);
+        app.update();
+        app.update();
+
+        let world = app.world();
+        assert!(world.resource::<ChunkStore>().iter().next().is_some());
+        assert!(!world.contains_resource::<VulkanApp>());
+    }
+
+    #[test]
+    fn closed_windows_are_found() {
+        let tools = Cow::Borrowed("tools");
+        let secondary = [(&tools, WindowId::from(2))];
+
+        assert_eq!(
+            find_window(WindowId::from(1), secondary, WindowId::from(1)),
+            FoundWindow::Primary
+        );
+        assert_eq!(
+            find_window(WindowId::from(1), secondary, WindowId::from(2)),
+            FoundWindow::Secondary(tools.clone())
+        );
+        assert_eq!(
+            find_window(WindowId::from(1), secondary, WindowId::from(3)),
+            FoundWindow::Unknown
+        );
+    }
+
+    #[test]
+    fn zero_target_is_uncapped() {
+        assert_eq!(frame_sleep(0, Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn input_and_resize_wake_reactive_updates() {
+        assert!(wakes_reactive_update(&WindowEvent::Resized(
+            winit::dpi::PhysicalSize::new(800, 600)
+        )));
+        assert!(wakes_reactive_update(&WindowEvent::Focused(true)));
+        assert!(!wakes_reactive_update(&WindowEvent::Moved(
+            winit::dpi::PhysicalPosition::new(0, 0)
+        )));
+        assert!(!wakes_reactive_update(&WindowEvent::Occluded(true)));
+    }
+}
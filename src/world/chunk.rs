@@ -0,0 +1,352 @@
+use glam::IVec3;
+
+/// Edge length of a cubic chunk in voxels.
+///
+/// Everything converting between voxel coordinates and indices goes through
+/// [`xyz_to_index`], [`index_to_xyz`] and [`is_local`], so changing it is enough
+/// to resize chunks.
+pub const CHUNK_SIZE: usize = 32;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// Index of the voxel at local coordinates in a chunk, X varying fastest.
+pub const fn xyz_to_index(x: usize, y: usize, z: usize) -> usize {
+    debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE);
+    x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE
+}
+
+/// Local coordinates of the voxel at `index`, inverse of [`xyz_to_index`].
+pub const fn index_to_xyz(index: usize) -> [usize; 3] {
+    debug_assert!(index < CHUNK_VOLUME);
+    [
+        index % CHUNK_SIZE,
+        index / CHUNK_SIZE % CHUNK_SIZE,
+        index / (CHUNK_SIZE * CHUNK_SIZE),
+    ]
+}
+
+/// Whether `pos` are local coordinates of a voxel inside of a chunk.
+pub fn is_local(pos: IVec3) -> bool {
+    pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(CHUNK_SIZE as i32)).all()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BlockId(pub u16);
+
+impl BlockId {
+    pub const AIR: BlockId = BlockId(0);
+    pub const STONE: BlockId = BlockId(1);
+    pub const DIRT: BlockId = BlockId(2);
+    pub const GRASS: BlockId = BlockId(3);
+
+    pub fn is_solid(self) -> bool {
+        self != Self::AIR
+    }
+
+    /// Flat color used until blocks are textured.
+    pub fn color(self) -> [f32; 3] {
+        match self {
+            Self::STONE => [0.5, 0.5, 0.5],
+            Self::DIRT => [0.45, 0.3, 0.15],
+            Self::GRASS => [0.3, 0.6, 0.2],
+            _ => [1.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Texture of every textured block, in the order of the layers of the block
+/// texture array.
+///
+/// Until each block has its own texture they all share `block.png`.
+pub const BLOCK_TEXTURES: [(BlockId, &str); 3] = [
+    (BlockId::STONE, "assets/textures/block.png"),
+    (BlockId::DIRT, "assets/textures/block.png"),
+    (BlockId::GRASS, "assets/textures/block.png"),
+];
+
+/// Layer of the block texture array `block` is drawn with. Blocks without a
+/// texture use the first layer.
+pub fn layer_for(block: BlockId) -> u32 {
+    BLOCK_TEXTURES
+        .iter()
+        .position(|(textured, _)| *textured == block)
+        .unwrap_or(0) as u32
+}
+
+/// A cube of `CHUNK_SIZE`³ voxels.
+///
+/// Voxels are stored as indices into a palette of the distinct blocks of the
+/// chunk. Indices are bit-packed with the smallest power of two width that can
+/// address the palette, so a chunk of a single block doesn't store any indices.
+#[derive(Clone)]
+pub struct Chunk {
+    palette: Vec<PaletteEntry>,
+    bits_per_index: u32,
+    data: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PaletteEntry {
+    block: BlockId,
+    /// Number of voxels referencing this entry. Unused entries are reused.
+    count: u32,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::filled(BlockId::AIR)
+    }
+}
+
+impl PartialEq for Chunk {
+    /// Compares voxels, ignoring how the palettes are laid out.
+    fn eq(&self, other: &Self) -> bool {
+        (0..CHUNK_VOLUME).all(|index| {
+            self.palette[self.palette_index(index)].block
+                == other.palette[other.palette_index(index)].block
+        })
+    }
+}
+
+impl Chunk {
+    pub fn filled(block: BlockId) -> Self {
+        Self {
+            palette: vec![PaletteEntry {
+                block,
+                count: CHUNK_VOLUME as u32,
+            }],
+            bits_per_index: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockId {
+        let palette_index = self.palette_index(xyz_to_index(x, y, z));
+        self.palette[palette_index].block
+    }
+
+    /// Block at the local coordinates `pos`, which must satisfy [`is_local`].
+    pub fn get_local(&self, pos: IVec3) -> BlockId {
+        debug_assert!(is_local(pos));
+        let [x, y, z] = pos.to_array().map(|c| c as usize);
+        self.get(x, y, z)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: BlockId) {
+        let index = xyz_to_index(x, y, z);
+        let old = self.palette_index(index);
+        if self.palette[old].block == block {
+            return;
+        }
+
+        self.palette[old].count -= 1;
+        let new = match self.palette.iter().position(|entry| entry.block == block) {
+            Some(new) => new,
+            None => self.add_to_palette(block),
+        };
+        self.palette[new].count += 1;
+        self.set_palette_index(index, new);
+    }
+
+    /// Sets the block at the local coordinates `pos`, which must satisfy [`is_local`].
+    pub fn set_local(&mut self, pos: IVec3, block: BlockId) {
+        debug_assert!(is_local(pos));
+        let [x, y, z] = pos.to_array().map(|c| c as usize);
+        self.set(x, y, z, block);
+    }
+
+    /// Returns `true` if every voxel of the chunk is the same block.
+    pub fn is_uniform(&self) -> bool {
+        self.palette.iter().filter(|entry| entry.count > 0).count() == 1
+    }
+
+    /// Number of bits used to store a single voxel.
+    pub fn bits_per_index(&self) -> u32 {
+        self.bits_per_index
+    }
+
+    /// Distinct blocks the chunk can reference, including currently unused ones.
+    pub fn palette(&self) -> impl Iterator<Item = BlockId> {
+        self.palette.iter().map(|entry| entry.block)
+    }
+
+    fn add_to_palette(&mut self, block: BlockId) -> usize {
+        let entry = PaletteEntry { block, count: 0 };
+
+        if let Some(unused) = self.palette.iter().position(|entry| entry.count == 0) {
+            self.palette[unused] = entry;
+            return unused;
+        }
+
+        self.palette.push(entry);
+        let required_bits = (self.palette.len() as u32 - 1)
+            .checked_ilog2()
+            .map_or(0, |log| (log + 1).next_power_of_two());
+        if required_bits > self.bits_per_index {
+            self.repack(required_bits);
+        }
+
+        self.palette.len() - 1
+    }
+
+    fn repack(&mut self, bits_per_index: u32) {
+        let indices = (0..CHUNK_VOLUME)
+            .map(|index| self.palette_index(index))
+            .collect::<Vec<_>>();
+
+        self.bits_per_index = bits_per_index;
+        self.data = vec![0; CHUNK_VOLUME * bits_per_index as usize / u64::BITS as usize];
+
+        for (index, palette_index) in indices.into_iter().enumerate() {
+            self.set_palette_index(index, palette_index);
+        }
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits_per_index == 0 {
+            return 0;
+        }
+
+        let (word, shift) = self.bit_position(index);
+        let mask = (1 << self.bits_per_index) - 1;
+        ((self.data[word] >> shift) & mask) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        let (word, shift) = self.bit_position(index);
+        let mask = (1 << self.bits_per_index) - 1;
+        self.data[word] &= !(mask << shift);
+        self.data[word] |= (palette_index as u64) << shift;
+    }
+
+    /// Word and bit offset of a voxel in `data`. Widths are powers of two so
+    /// an index never straddles two words.
+    fn bit_position(&self, index: usize) -> (usize, u32) {
+        let bit = index * self.bits_per_index as usize;
+        (bit / u64::BITS as usize, (bit % u64::BITS as usize) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corner_indices() {
+        let last = CHUNK_SIZE - 1;
+        assert_eq!(xyz_to_index(0, 0, 0), 0);
+        assert_eq!(xyz_to_index(last, last, last), CHUNK_VOLUME - 1);
+        assert_eq!(xyz_to_index(1, 0, 0), 1);
+        assert_eq!(xyz_to_index(0, 1, 0), CHUNK_SIZE);
+        assert_eq!(xyz_to_index(0, 0, 1), CHUNK_SIZE * CHUNK_SIZE);
+
+        assert_eq!(index_to_xyz(0), [0, 0, 0]);
+        assert_eq!(index_to_xyz(CHUNK_VOLUME - 1), [last, last, last]);
+        assert_eq!(index_to_xyz(last), [last, 0, 0]);
+    }
+
+    #[test]
+    fn index_round_trip() {
+        for pos in [[1, 2, 3], [CHUNK_SIZE / 2; 3], [CHUNK_SIZE - 1, 0, 7]] {
+            let [x, y, z] = pos;
+            assert_eq!(index_to_xyz(xyz_to_index(x, y, z)), pos);
+        }
+        for index in [1, CHUNK_SIZE + 1, CHUNK_VOLUME / 2, CHUNK_VOLUME - 2] {
+            let [x, y, z] = index_to_xyz(index);
+            assert_eq!(xyz_to_index(x, y, z), index);
+        }
+    }
+
+    #[test]
+    fn local_coordinates() {
+        let size = CHUNK_SIZE as i32;
+        assert!(is_local(IVec3::ZERO));
+        assert!(is_local(IVec3::splat(size - 1)));
+        assert!(!is_local(IVec3::new(-1, 0, 0)));
+        assert!(!is_local(IVec3::new(0, size, 0)));
+    }
+
+    #[test]
+    fn empty_chunk_has_no_data() {
+        let chunk = Chunk::default();
+        assert!(chunk.is_uniform());
+        assert_eq!(chunk.bits_per_index(), 0);
+        assert!(chunk.data.is_empty());
+        assert_eq!(chunk.get(3, 4, 5), BlockId::AIR);
+    }
+
+    #[test]
+    fn set_get_round_trip() {
+        let mut chunk = Chunk::default();
+        let block_at = |x: usize, y: usize, z: usize| BlockId(((x * 7 + y * 3 + z) % 5) as u16);
+
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    chunk.set(x, y, z, block_at(x, y, z));
+                }
+            }
+        }
+
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    assert_eq!(chunk.get(x, y, z), block_at(x, y, z));
+                }
+            }
+        }
+        assert_eq!(chunk.bits_per_index(), 4);
+    }
+
+    #[test]
+    fn palette_growth() {
+        let mut chunk = Chunk::default();
+
+        chunk.set(0, 0, 0, BlockId(1));
+        assert_eq!(chunk.bits_per_index(), 1);
+
+        for i in 2..16 {
+            chunk.set(i, 0, 0, BlockId(i as u16));
+        }
+        assert_eq!(chunk.palette().count(), 16);
+        assert_eq!(chunk.bits_per_index(), 4);
+
+        chunk.set(16, 0, 0, BlockId(16));
+        assert_eq!(chunk.bits_per_index(), 8);
+
+        assert_eq!(chunk.get(0, 0, 0), BlockId(1));
+        for i in 2..=16 {
+            assert_eq!(chunk.get(i, 0, 0), BlockId(i as u16));
+        }
+        assert_eq!(chunk.get(17, 0, 0), BlockId::AIR);
+    }
+
+    #[test]
+    fn unused_palette_entries_are_reused() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, BlockId::STONE);
+        chunk.set(0, 0, 0, BlockId::DIRT);
+
+        assert_eq!(chunk.palette().count(), 2);
+        assert_eq!(chunk.get(0, 0, 0), BlockId::DIRT);
+    }
+
+    #[test]
+    fn uniform_detection() {
+        let mut chunk = Chunk::filled(BlockId::STONE);
+        assert!(chunk.is_uniform());
+
+        chunk.set(1, 2, 3, BlockId::AIR);
+        assert!(!chunk.is_uniform());
+
+        chunk.set(1, 2, 3, BlockId::STONE);
+        assert!(chunk.is_uniform());
+    }
+
+    #[test]
+    fn blocks_have_their_own_layer() {
+        assert_eq!(layer_for(BlockId::STONE), 0);
+        assert_eq!(layer_for(BlockId::DIRT), 1);
+        assert_eq!(layer_for(BlockId::GRASS), 2);
+        assert_eq!(layer_for(BlockId(1000)), 0);
+    }
+}
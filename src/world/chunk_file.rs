@@ -0,0 +1,259 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy_ecs::resource::Resource;
+use glam::IVec3;
+use thiserror::Error;
+use tracing::warn;
+
+use super::chunk::{BlockId, CHUNK_VOLUME, Chunk, index_to_xyz};
+
+/// First bytes of every chunk file.
+pub const CHUNK_FILE_MAGIC: [u8; 4] = *b"VXCH";
+/// Version of the chunk file format written by [`encode_chunk`].
+pub const CHUNK_FILE_VERSION: u16 = 1;
+
+/// Directory chunks are saved to and loaded from, `None` to always generate
+/// them and never save.
+///
+/// Defaults to `None`, the game saves to [`WorldSaveDir::GAME`].
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorldSaveDir(pub Option<PathBuf>);
+
+impl WorldSaveDir {
+    /// Directory of the world of the game, relative to the working directory.
+    pub const GAME: &str = "saves/world";
+
+    pub fn game() -> Self {
+        Self(Some(PathBuf::from(Self::GAME)))
+    }
+}
+
+/// Why a chunk file couldn't be read.
+#[derive(Error, Debug)]
+pub enum ChunkFileError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Not a chunk file")]
+    Magic,
+    #[error("Unsupported chunk file version {0}, expected {CHUNK_FILE_VERSION}")]
+    Version(u16),
+    #[error("Chunk file ends early")]
+    Truncated,
+    #[error("Palette index {0} is out of the palette")]
+    PaletteIndex(u16),
+    #[error("Chunk file has {0} voxels instead of {CHUNK_VOLUME}")]
+    VoxelCount(usize),
+}
+
+/// Path of the file of the chunk at `coord` in `dir`.
+pub fn chunk_path(dir: &Path, coord: IVec3) -> PathBuf {
+    dir.join(format!("{}.{}.{}.chunk", coord.x, coord.y, coord.z))
+}
+
+/// Serializes `chunk`, all integers little endian:
+///
+/// - the [`CHUNK_FILE_MAGIC`] and the `u16` [`CHUNK_FILE_VERSION`],
+/// - a `u16` number of palette entries followed by their `u16` blocks,
+/// - a `u32` number of runs followed by the `u16` palette index and `u32`
+///   length of each run of voxels, in the order of [`index_to_xyz`].
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut palette = Vec::<BlockId>::new();
+    let mut runs = Vec::<(u16, u32)>::new();
+    for index in 0..CHUNK_VOLUME {
+        let [x, y, z] = index_to_xyz(index);
+        let block = chunk.get(x, y, z);
+        let palette_index = match palette.iter().position(|entry| *entry == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                palette.push(block);
+                palette.len() - 1
+            }
+        } as u16;
+
+        match runs.last_mut() {
+            Some((last, length)) if *last == palette_index => *length += 1,
+            _ => runs.push((palette_index, 1)),
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(12 + palette.len() * 2 + runs.len() * 6);
+    bytes.extend(CHUNK_FILE_MAGIC);
+    bytes.extend(CHUNK_FILE_VERSION.to_le_bytes());
+    bytes.extend((palette.len() as u16).to_le_bytes());
+    for block in palette {
+        bytes.extend(block.0.to_le_bytes());
+    }
+    bytes.extend((runs.len() as u32).to_le_bytes());
+    for (palette_index, length) in runs {
+        bytes.extend(palette_index.to_le_bytes());
+        bytes.extend(length.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Reads a chunk serialized by [`encode_chunk`].
+pub fn decode_chunk(bytes: &[u8]) -> Result<Chunk, ChunkFileError> {
+    let mut reader = Reader(bytes);
+    if reader.take::<4>()? != CHUNK_FILE_MAGIC {
+        return Err(ChunkFileError::Magic);
+    }
+    let version = u16::from_le_bytes(reader.take()?);
+    if version != CHUNK_FILE_VERSION {
+        return Err(ChunkFileError::Version(version));
+    }
+
+    let palette_len = u16::from_le_bytes(reader.take()?);
+    let palette = (0..palette_len)
+        .map(|_| Ok(BlockId(u16::from_le_bytes(reader.take()?))))
+        .collect::<Result<Vec<_>, ChunkFileError>>()?;
+
+    let run_count = u32::from_le_bytes(reader.take()?);
+    let mut chunk = Chunk::default();
+    let mut index = 0;
+    for _ in 0..run_count {
+        let palette_index = u16::from_le_bytes(reader.take()?);
+        let length = u32::from_le_bytes(reader.take()?) as usize;
+        let block = *palette
+            .get(palette_index as usize)
+            .ok_or(ChunkFileError::PaletteIndex(palette_index))?;
+
+        let end = index + length;
+        if end > CHUNK_VOLUME {
+            return Err(ChunkFileError::VoxelCount(end));
+        }
+        for index in index..end {
+            let [x, y, z] = index_to_xyz(index);
+            chunk.set(x, y, z, block);
+        }
+        index = end;
+    }
+    if index != CHUNK_VOLUME {
+        return Err(ChunkFileError::VoxelCount(index));
+    }
+
+    Ok(chunk)
+}
+
+/// Cursor over the bytes of a chunk file.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ChunkFileError> {
+        let (bytes, rest) = self
+            .0
+            .split_first_chunk::<N>()
+            .ok_or(ChunkFileError::Truncated)?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+}
+
+/// Writes `chunk` to its file in `dir`, creating the directory if needed.
+pub fn save_chunk(coord: IVec3, chunk: &Chunk, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(chunk_path(dir, coord), encode_chunk(chunk))
+}
+
+/// Reads the chunk at `coord` from its file in `dir`.
+///
+/// Returns `None` if it was never saved, or if the file can't be read, in
+/// which case a warning is logged.
+pub fn load_chunk(coord: IVec3, dir: &Path) -> Option<Chunk> {
+    let path = chunk_path(dir, coord);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+        Err(err) => {
+            warn!("Failed to read `{}`: {err}", path.display());
+            return None;
+        }
+    };
+
+    decode_chunk(&bytes)
+        .inspect_err(|err| warn!("Failed to load `{}`: {err}", path.display()))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::generation::generate_chunk;
+
+    fn round_trip(chunk: &Chunk) -> Chunk {
+        decode_chunk(&encode_chunk(chunk)).unwrap()
+    }
+
+    #[test]
+    fn empty_chunk_round_trip() {
+        let chunk = Chunk::default();
+        assert!(round_trip(&chunk) == chunk);
+        // Header, one palette entry and a single run.
+        assert_eq!(encode_chunk(&chunk).len(), 6 + 2 + 2 + 4 + 6);
+    }
+
+    #[test]
+    fn uniform_chunk_round_trip() {
+        let chunk = Chunk::filled(BlockId::STONE);
+        let decoded = round_trip(&chunk);
+        assert!(decoded == chunk);
+        assert!(decoded.is_uniform());
+    }
+
+    #[test]
+    fn mixed_chunk_round_trip() {
+        let mut chunk = generate_chunk(IVec3::ZERO, 7);
+        chunk.set(3, 31, 4, BlockId::GRASS);
+        chunk.set(0, 0, 0, BlockId::AIR);
+        assert!(round_trip(&chunk) == chunk);
+    }
+
+    #[test]
+    fn header_is_checked() {
+        let mut bytes = encode_chunk(&Chunk::default());
+
+        bytes[4..6].copy_from_slice(&(CHUNK_FILE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            decode_chunk(&bytes),
+            Err(ChunkFileError::Version(version)) if version == CHUNK_FILE_VERSION + 1
+        ));
+
+        bytes[0] = b'X';
+        assert!(matches!(decode_chunk(&bytes), Err(ChunkFileError::Magic)));
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let bytes = encode_chunk(&Chunk::filled(BlockId::DIRT));
+        assert!(matches!(
+            decode_chunk(&bytes[..bytes.len() - 1]),
+            Err(ChunkFileError::Truncated)
+        ));
+
+        // A single run one voxel short of the chunk.
+        let mut short = bytes.clone();
+        let length = bytes.len() - 4;
+        short[length..].copy_from_slice(&(CHUNK_VOLUME as u32 - 1).to_le_bytes());
+        assert!(matches!(
+            decode_chunk(&short),
+            Err(ChunkFileError::VoxelCount(count)) if count == CHUNK_VOLUME - 1
+        ));
+    }
+
+    #[test]
+    fn saved_chunks_are_loaded() {
+        let dir = std::env::temp_dir().join(format!("chunk-file-test-{}", std::process::id()));
+        let coord = IVec3::new(-1, 2, -3);
+        let chunk = generate_chunk(coord, 3);
+
+        assert!(load_chunk(coord, &dir).is_none());
+        save_chunk(coord, &chunk, &dir).unwrap();
+        assert!(chunk_path(&dir, coord).ends_with("-1.2.-3.chunk"));
+        assert!(load_chunk(coord, &dir) == Some(chunk));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
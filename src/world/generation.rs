@@ -0,0 +1,82 @@
+use bevy_ecs::resource::Resource;
+use glam::IVec3;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::chunk::{BlockId, CHUNK_SIZE, Chunk};
+
+/// World height around which the terrain surface oscillates.
+const SURFACE_LEVEL: f64 = 16.0;
+/// Maximum deviation of the surface from [`SURFACE_LEVEL`].
+const SURFACE_AMPLITUDE: f64 = 24.0;
+const SURFACE_FREQUENCY: f64 = 1.0 / 128.0;
+/// Number of dirt layers between the surface and the stone.
+const DIRT_DEPTH: i32 = 3;
+
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct WorldGenConfig {
+    pub seed: u64,
+}
+
+/// Generates the chunk at `coord` from a noise height-map.
+///
+/// The result only depends on `coord` and `seed`, so regenerating a chunk
+/// always yields the same voxels.
+pub fn generate_chunk(coord: IVec3, seed: u64) -> Chunk {
+    let noise = Fbm::<Perlin>::new((seed ^ (seed >> 32)) as u32)
+        .set_octaves(4)
+        .set_frequency(SURFACE_FREQUENCY);
+
+    let size = CHUNK_SIZE as i32;
+    let origin = coord * size;
+    let mut chunk = Chunk::default();
+
+    for z in 0..size {
+        for x in 0..size {
+            let world_x = (origin.x + x) as f64;
+            let world_z = (origin.z + z) as f64;
+            let height = (SURFACE_LEVEL + noise.get([world_x, world_z]) * SURFACE_AMPLITUDE) as i32;
+
+            // Everything above the surface stays air.
+            let top = (height - origin.y).min(size - 1);
+            for y in 0..=top {
+                let depth = height - (origin.y + y);
+                let block = match depth {
+                    0 => BlockId::GRASS,
+                    1..=DIRT_DEPTH => BlockId::DIRT,
+                    _ => BlockId::STONE,
+                };
+                chunk.set_local(IVec3::new(x, y, z), block);
+            }
+        }
+    }
+
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        for coord in [IVec3::ZERO, IVec3::new(-3, 0, 7), IVec3::new(2, -1, -5)] {
+            assert!(generate_chunk(coord, 42) == generate_chunk(coord, 42));
+        }
+    }
+
+    #[test]
+    fn seeds_differ() {
+        assert!(generate_chunk(IVec3::ZERO, 1) != generate_chunk(IVec3::ZERO, 2));
+    }
+
+    #[test]
+    fn layers() {
+        let above = generate_chunk(IVec3::new(0, 4, 0), 0);
+        assert!(above.is_uniform());
+        assert_eq!(above.get(0, 0, 0), BlockId::AIR);
+
+        let below = generate_chunk(IVec3::new(0, -4, 0), 0);
+        assert!(below.is_uniform());
+        assert_eq!(below.get(0, 0, 0), BlockId::STONE);
+    }
+}
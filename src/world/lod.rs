@@ -0,0 +1,165 @@
+use bevy_ecs::system::{Res, ResMut};
+use glam::{IVec3, Vec3};
+
+use super::{
+    chunk::{BlockId, CHUNK_SIZE, Chunk},
+    mesh_queue::MeshQueue,
+    store::ChunkStore,
+};
+use crate::camera::Camera;
+
+/// Distances in chunks from the camera at which chunks switch to LOD 1, 2, ...
+pub const LOD_DISTANCES: [f32; 2] = [3.0, 5.0];
+
+/// Level of detail of a chunk mesh. Level `n` meshes cells of `2^n` voxels
+/// along each axis, so level 0 is the full resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct LodLevel(pub u8);
+
+impl LodLevel {
+    pub const FULL: LodLevel = LodLevel(0);
+
+    /// Edge length of a cell in voxels.
+    pub fn cell_size(self) -> usize {
+        1 << self.0
+    }
+
+    /// Number of cells along each axis of a chunk.
+    pub fn cells_per_axis(self) -> usize {
+        CHUNK_SIZE / self.cell_size()
+    }
+}
+
+/// Level of detail of a chunk `distance` chunks away from the camera.
+pub fn chunk_lod(distance: f32) -> LodLevel {
+    LodLevel(
+        LOD_DISTANCES
+            .iter()
+            .take_while(|start| distance >= **start)
+            .count() as u8,
+    )
+}
+
+/// Distance in chunks from `position` to the center of the chunk at `coord`.
+pub fn chunk_distance(position: Vec3, coord: IVec3) -> f32 {
+    let center = (coord.as_vec3() + 0.5) * CHUNK_SIZE as f32;
+    center.distance(position) / CHUNK_SIZE as f32
+}
+
+/// Merges the voxels of `chunk` into cells of `lod`, X varying fastest.
+///
+/// A cell is solid when at least half of its voxels are, and takes the most
+/// common of their blocks.
+pub fn downsample(chunk: &Chunk, lod: LodLevel) -> Vec<BlockId> {
+    let cell_size = lod.cell_size();
+    let cells = lod.cells_per_axis();
+    let cell_volume = cell_size * cell_size * cell_size;
+
+    let mut downsampled = Vec::with_capacity(cells * cells * cells);
+    let mut counts = Vec::<(BlockId, usize)>::new();
+    for cz in 0..cells {
+        for cy in 0..cells {
+            for cx in 0..cells {
+                counts.clear();
+                for z in cz * cell_size..(cz + 1) * cell_size {
+                    for y in cy * cell_size..(cy + 1) * cell_size {
+                        for x in cx * cell_size..(cx + 1) * cell_size {
+                            let block = chunk.get(x, y, z);
+                            if !block.is_solid() {
+                                continue;
+                            }
+                            match counts.iter_mut().find(|(counted, _)| *counted == block) {
+                                Some((_, count)) => *count += 1,
+                                None => counts.push((block, 1)),
+                            }
+                        }
+                    }
+                }
+
+                let solid = counts.iter().map(|(_, count)| count).sum::<usize>();
+                let block = if solid * 2 >= cell_volume {
+                    counts
+                        .iter()
+                        .max_by_key(|(_, count)| *count)
+                        .map_or(BlockId::AIR, |(block, _)| *block)
+                } else {
+                    BlockId::AIR
+                };
+                downsampled.push(block);
+            }
+        }
+    }
+
+    downsampled
+}
+
+/// Picks the level of detail of every loaded chunk from its distance to the
+/// camera, and queues the meshed ones whose level changed to be meshed again.
+pub fn update_chunk_lods_system(
+    camera: Res<Camera>,
+    store: Res<ChunkStore>,
+    mut mesh_queue: ResMut<MeshQueue>,
+) {
+    mesh_queue.retain_lods(|coord| store.is_loaded(coord));
+
+    for (coord, _) in store.iter() {
+        let lod = chunk_lod(chunk_distance(camera.position, coord));
+        if mesh_queue.set_lod(coord, lod) {
+            mesh_queue.enqueue(coord);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::CHUNK_VOLUME;
+
+    #[test]
+    fn lod_grows_with_distance() {
+        assert_eq!(chunk_lod(0.0), LodLevel::FULL);
+        assert_eq!(chunk_lod(2.9), LodLevel::FULL);
+        assert_eq!(chunk_lod(3.0), LodLevel(1));
+        assert_eq!(chunk_lod(5.5), LodLevel(2));
+        assert_eq!(chunk_lod(100.0), LodLevel(LOD_DISTANCES.len() as u8));
+    }
+
+    #[test]
+    fn uniform_chunk_has_an_eighth_of_the_cells_at_lod_1() {
+        let chunk = Chunk::filled(BlockId::STONE);
+
+        let full = downsample(&chunk, LodLevel::FULL);
+        let halved = downsample(&chunk, LodLevel(1));
+        assert_eq!(full.len(), CHUNK_VOLUME);
+        assert_eq!(halved.len(), CHUNK_VOLUME / 8);
+        assert!(halved.iter().all(|block| *block == BlockId::STONE));
+    }
+
+    #[test]
+    fn cells_take_the_majority_block() {
+        let mut chunk = Chunk::default();
+        // Half of the first cell is solid, 3 dirt and 1 stone voxels, and
+        // less than half of the second.
+        for (x, y, block) in [
+            (0, 0, BlockId::DIRT),
+            (1, 0, BlockId::DIRT),
+            (0, 1, BlockId::DIRT),
+            (1, 1, BlockId::STONE),
+            (2, 0, BlockId::STONE),
+            (3, 0, BlockId::STONE),
+            (2, 1, BlockId::STONE),
+        ] {
+            chunk.set(x, y, 0, block);
+        }
+
+        let cells = downsample(&chunk, LodLevel(1));
+        assert_eq!(cells[0], BlockId::DIRT);
+        assert_eq!(cells[1], BlockId::AIR);
+    }
+
+    #[test]
+    fn distance_to_the_chunk_center() {
+        let distance = chunk_distance(Vec3::splat(16.0), IVec3::new(2, 0, 0));
+        assert_eq!(distance, 2.0);
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+
+use bevy_ecs::resource::Resource;
+use glam::IVec3;
+use hashbrown::{HashMap, HashSet};
+
+use super::{
+    chunk::Chunk,
+    lod::LodLevel,
+    meshing::{ChunkNeighbors, Vertex, greedy_mesh_lod},
+    store::ChunkStore,
+    workers::{WorkerPool, worker_count},
+};
+
+/// Default number of meshes handed out by [`MeshQueue::drain_ready`] per frame.
+pub const DEFAULT_MAX_UPLOADS_PER_FRAME: usize = 8;
+
+/// A chunk mesh built by a mesh worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkMesh {
+    pub coord: IVec3,
+    pub lod: LodLevel,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+/// Where a chunk is in the [`MeshQueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshState {
+    /// Waiting to be sent to a worker.
+    Pending,
+    /// Being meshed by a worker.
+    InFlight,
+    /// Meshed and waiting to be uploaded.
+    Ready,
+}
+
+/// Snapshot of a chunk and its neighbors sent to a worker.
+struct MeshJob {
+    coord: IVec3,
+    lod: LodLevel,
+    chunk: Chunk,
+    neighbors: [Option<Chunk>; 6],
+}
+
+impl MeshJob {
+    fn run(self) -> ChunkMesh {
+        let neighbors = ChunkNeighbors::new(self.neighbors.each_ref().map(Option::as_ref));
+        let (vertices, indices) = greedy_mesh_lod(&self.chunk, &neighbors, self.lod);
+
+        ChunkMesh {
+            coord: self.coord,
+            lod: self.lod,
+            vertices,
+            indices,
+        }
+    }
+}
+
+/// Meshes chunks on a pool of worker threads.
+///
+/// Chunks are [enqueued](Self::enqueue), [dispatched](Self::dispatch) to the
+/// workers together with a copy of their neighbors and [collected](Self::collect)
+/// once meshed. None of these calls wait for the workers.
+#[derive(Resource)]
+pub struct MeshQueue {
+    pending: VecDeque<IVec3>,
+    in_flight: HashSet<IVec3>,
+    ready: VecDeque<ChunkMesh>,
+    /// Level of detail chunks are meshed at, the full one if they have none.
+    lods: HashMap<IVec3, LodLevel>,
+
+    max_uploads_per_frame: usize,
+
+    workers: WorkerPool<MeshJob, ChunkMesh>,
+}
+
+impl MeshQueue {
+    pub fn new(worker_count: usize, max_uploads_per_frame: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            in_flight: HashSet::new(),
+            ready: VecDeque::new(),
+            lods: HashMap::new(),
+            max_uploads_per_frame,
+            workers: WorkerPool::new("mesh-worker", worker_count, MeshJob::run),
+        }
+    }
+
+    /// Queues the chunk at `coord` to be meshed.
+    ///
+    /// A chunk that is already queued isn't queued twice. A chunk that is
+    /// being meshed is meshed again once its current mesh is collected.
+    pub fn enqueue(&mut self, coord: IVec3) {
+        if !self.pending.contains(&coord) {
+            self.pending.push_back(coord);
+        }
+    }
+
+    /// Sends the pending chunks to the workers.
+    ///
+    /// Chunks that are no longer loaded are dropped from the queue.
+    pub fn dispatch(&mut self, store: &ChunkStore) {
+        let mut waiting = VecDeque::new();
+        while let Some(coord) = self.pending.pop_front() {
+            if self.in_flight.contains(&coord) {
+                waiting.push_back(coord);
+                continue;
+            }
+
+            let Some(chunk) = store.get(coord) else {
+                continue;
+            };
+
+            let job = MeshJob {
+                coord,
+                lod: self.lod(coord),
+                chunk: chunk.clone(),
+                neighbors: ChunkStore::neighbors(coord)
+                    .map(|neighbor| store.get(neighbor).cloned()),
+            };
+
+            if self.workers.send(job) {
+                self.in_flight.insert(coord);
+            }
+        }
+        self.pending = waiting;
+    }
+
+    pub fn lod(&self, coord: IVec3) -> LodLevel {
+        self.lods.get(&coord).copied().unwrap_or_default()
+    }
+
+    /// Sets the level of detail the chunk at `coord` is meshed at from now on.
+    ///
+    /// Returns `true` if it changed and the chunk should be meshed again.
+    pub fn set_lod(&mut self, coord: IVec3, lod: LodLevel) -> bool {
+        self.lods.insert(coord, lod).unwrap_or_default() != lod
+    }
+
+    /// Forgets the level of detail of the chunks for which `keep` returns `false`.
+    pub fn retain_lods(&mut self, mut keep: impl FnMut(IVec3) -> bool) {
+        self.lods.retain(|coord, _| keep(*coord));
+    }
+
+    /// Moves the meshes finished by the workers to the ready queue.
+    pub fn collect(&mut self) {
+        for mesh in self.workers.finished() {
+            self.in_flight.remove(&mesh.coord);
+            self.ready.push_back(mesh);
+        }
+    }
+
+    /// Takes at most `max_uploads_per_frame` ready meshes in the order they were finished.
+    pub fn drain_ready(&mut self) -> impl Iterator<Item = ChunkMesh> + '_ {
+        let count = self.ready.len().min(self.max_uploads_per_frame);
+        self.ready.drain(..count)
+    }
+
+    pub fn state(&self, coord: IVec3) -> Option<MeshState> {
+        if self.pending.contains(&coord) {
+            Some(MeshState::Pending)
+        } else if self.in_flight.contains(&coord) {
+            Some(MeshState::InFlight)
+        } else if self.ready.iter().any(|mesh| mesh.coord == coord) {
+            Some(MeshState::Ready)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, coord: IVec3) -> bool {
+        self.state(coord).is_some()
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn in_flight_len(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn ready_len(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Returns `true` if no chunk is waiting to be meshed or uploaded.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.is_empty() && self.ready.is_empty()
+    }
+}
+
+impl Default for MeshQueue {
+    /// Shares the cores with the [`ChunkLoader`](super::streaming::ChunkLoader).
+    fn default() -> Self {
+        Self::new(worker_count(2), DEFAULT_MAX_UPLOADS_PER_FRAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::world::{
+        chunk::BlockId, generation::generate_chunk, meshing::greedy_mesh_with_neighbors,
+    };
+
+    /// Collects until every dispatched chunk is meshed.
+    fn wait_for_workers(queue: &mut MeshQueue) {
+        let start = Instant::now();
+        while queue.in_flight_len() > 0 {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "mesh workers stalled"
+            );
+            thread::sleep(Duration::from_millis(1));
+            queue.collect();
+        }
+    }
+
+    #[test]
+    fn worker_meshes_match_synchronous_meshing() {
+        let mut store = ChunkStore::default();
+        for coord in [IVec3::ZERO, IVec3::X, IVec3::NEG_Y] {
+            store.load(coord, generate_chunk(coord, 7));
+        }
+
+        let mut queue = MeshQueue::new(2, usize::MAX);
+        for (coord, _) in store.iter() {
+            queue.enqueue(coord);
+        }
+        queue.dispatch(&store);
+        wait_for_workers(&mut queue);
+
+        let meshes = queue.drain_ready().collect::<Vec<_>>();
+        assert_eq!(meshes.len(), 3);
+        assert!(queue.is_idle());
+
+        for mesh in meshes {
+            let chunk = store.get(mesh.coord).unwrap();
+            let neighbors = ChunkNeighbors::from_store(&store, mesh.coord);
+            let (vertices, indices) = greedy_mesh_with_neighbors(chunk, &neighbors);
+            assert_eq!(mesh.vertices, vertices);
+            assert_eq!(mesh.indices, indices);
+        }
+    }
+
+    #[test]
+    fn uploads_are_bounded_per_frame() {
+        let mut store = ChunkStore::default();
+        for x in 0..5 {
+            store.load(IVec3::new(x, 0, 0), Chunk::filled(BlockId::STONE));
+        }
+
+        let mut queue = MeshQueue::new(1, 2);
+        for (coord, _) in store.iter() {
+            queue.enqueue(coord);
+        }
+        queue.dispatch(&store);
+        wait_for_workers(&mut queue);
+
+        assert_eq!(queue.ready_len(), 5);
+        assert_eq!(queue.drain_ready().count(), 2);
+        assert_eq!(queue.drain_ready().count(), 2);
+        assert_eq!(queue.drain_ready().count(), 1);
+        assert!(queue.is_idle());
+    }
+
+    #[test]
+    fn tracks_chunk_state() {
+        let mut store = ChunkStore::default();
+        store.load(IVec3::ZERO, Chunk::filled(BlockId::STONE));
+
+        let mut queue = MeshQueue::new(1, 1);
+        assert_eq!(queue.state(IVec3::ZERO), None);
+
+        queue.enqueue(IVec3::ZERO);
+        queue.enqueue(IVec3::ZERO);
+        assert_eq!(queue.state(IVec3::ZERO), Some(MeshState::Pending));
+        assert_eq!(queue.pending_len(), 1);
+
+        queue.dispatch(&store);
+        assert_eq!(queue.state(IVec3::ZERO), Some(MeshState::InFlight));
+
+        wait_for_workers(&mut queue);
+        assert_eq!(queue.state(IVec3::ZERO), Some(MeshState::Ready));
+
+        queue.drain_ready().for_each(drop);
+        assert_eq!(queue.state(IVec3::ZERO), None);
+    }
+
+    #[test]
+    fn chunks_are_meshed_at_their_lod() {
+        let mut store = ChunkStore::default();
+        store.load(IVec3::ZERO, Chunk::filled(BlockId::STONE));
+
+        let mut queue = MeshQueue::new(1, 1);
+        assert!(!queue.set_lod(IVec3::ZERO, LodLevel::FULL));
+        assert!(queue.set_lod(IVec3::ZERO, LodLevel(2)));
+        assert!(!queue.set_lod(IVec3::ZERO, LodLevel(2)));
+
+        queue.enqueue(IVec3::ZERO);
+        queue.dispatch(&store);
+        wait_for_workers(&mut queue);
+
+        let mesh = queue.drain_ready().next().unwrap();
+        assert_eq!(mesh.lod, LodLevel(2));
+
+        queue.retain_lods(|_| false);
+        assert_eq!(queue.lod(IVec3::ZERO), LodLevel::FULL);
+    }
+
+    #[test]
+    fn unloaded_chunks_are_dropped() {
+        let store = ChunkStore::default();
+        let mut queue = MeshQueue::new(1, 1);
+
+        queue.enqueue(IVec3::ZERO);
+        queue.dispatch(&store);
+
+        assert!(queue.is_idle());
+    }
+}
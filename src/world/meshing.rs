@@ -0,0 +1,585 @@
+use bytemuck::{Pod, Zeroable};
+use glam::IVec3;
+
+use super::{
+    chunk::{BlockId, CHUNK_SIZE, Chunk, is_local, layer_for},
+    lod::{LodLevel, downsample},
+    store::ChunkStore,
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    /// Texture coordinate repeating once per voxel across merged quads.
+    pub uv: [f32; 2],
+    /// Ambient occlusion factor the color is multiplied by, `1.0` is unoccluded.
+    pub ao: f32,
+    /// Unit normal of the face, lit by the directional light.
+    pub normal: [f32; 3],
+    /// Layer of the block texture array, see [`layer_for`].
+    pub layer: u32,
+}
+
+/// Brightness of a vertex per [`vertex_ao`] level.
+const AO_FACTORS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+/// Ambient occlusion level of a face corner from the solidity of the two
+/// voxels sharing an edge with it and the voxel diagonal to it, all on the
+/// air side of the face. `0` is fully occluded and `3` unoccluded.
+///
+/// When both sides are solid the corner voxel is hidden behind them.
+pub fn vertex_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - (u8::from(side1) + u8::from(side2) + u8::from(corner))
+    }
+}
+
+/// Indices of a quad whose corners are ordered counter-clockwise, split along
+/// the diagonal with the brighter corners so occlusion is interpolated
+/// the same way whatever the orientation of the quad.
+fn quad_indices(ao: [u8; 4]) -> [u32; 6] {
+    if ao[0] + ao[2] >= ao[1] + ao[3] {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [1, 2, 3, 1, 3, 0]
+    }
+}
+
+/// A visible voxel face stored in the meshing mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Face {
+    block: BlockId,
+    /// The face points towards the negative direction of the sweep axis.
+    backface: bool,
+    /// [`vertex_ao`] of the corners at `(0, 0)`, `(1, 0)`, `(1, 1)` and `(0, 1)`
+    /// along the `u` and `v` axes. Only faces with the same occlusion are merged.
+    ao: [u8; 4],
+}
+
+/// Chunks sharing a face with the meshed chunk, used to cull faces on its border.
+#[derive(Default, Clone, Copy)]
+pub struct ChunkNeighbors<'a> {
+    /// Ordered as [`ChunkStore::neighbors`]: `+X`, `-X`, `+Y`, `-Y`, `+Z`, `-Z`.
+    chunks: [Option<&'a Chunk>; 6],
+}
+
+impl<'a> ChunkNeighbors<'a> {
+    /// Creates the neighbors from chunks ordered as [`ChunkStore::neighbors`].
+    pub fn new(chunks: [Option<&'a Chunk>; 6]) -> Self {
+        Self { chunks }
+    }
+
+    pub fn from_store(store: &'a ChunkStore, coord: IVec3) -> Self {
+        Self {
+            chunks: ChunkStore::neighbors(coord).map(|neighbor| store.get(neighbor)),
+        }
+    }
+
+    /// Samples a voxel right outside of the chunk in local coordinates.
+    ///
+    /// Returns `None` if the neighbor containing it is not loaded, or if it is
+    /// outside along several axes as only face neighbors are known.
+    fn sample(&self, pos: [i32; 3]) -> Option<BlockId> {
+        let size = CHUNK_SIZE as i32;
+        let mut outside = (0..3).filter(|axis| !(0..size).contains(&pos[*axis]));
+        let axis = outside.next()?;
+        if outside.next().is_some() {
+            return None;
+        }
+        let side = axis * 2 + usize::from(pos[axis] < 0);
+        let chunk = self.chunks[side]?;
+
+        let (_, local) = ChunkStore::split(IVec3::from_array(pos));
+        Some(chunk.get_local(local))
+    }
+}
+
+/// Builds a mesh of the chunk merging coplanar faces of the same block into
+/// as few quads as possible.
+///
+/// Faces between two solid voxels are hidden. Everything outside of the chunk
+/// is treated as air, so faces on the chunk border are always emitted.
+pub fn greedy_mesh(chunk: &Chunk) -> (Vec<Vertex>, Vec<u32>) {
+    greedy_mesh_with_neighbors(chunk, &ChunkNeighbors::default())
+}
+
+/// Same as [`greedy_mesh`] but culls faces on the chunk border against the
+/// neighboring chunks.
+///
+/// Border faces next to a chunk that isn't loaded are emitted.
+pub fn greedy_mesh_with_neighbors(
+    chunk: &Chunk,
+    neighbors: &ChunkNeighbors,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let inside = |pos: [i32; 3]| is_local(IVec3::from_array(pos));
+    let sample = |pos: [i32; 3]| {
+        if inside(pos) {
+            chunk.get_local(IVec3::from_array(pos))
+        } else {
+            neighbors.sample(pos).unwrap_or(BlockId::AIR)
+        }
+    };
+
+    greedy_mesh_cells(CHUNK_SIZE as i32, 1.0, inside, sample)
+}
+
+/// Same as [`greedy_mesh_with_neighbors`] at the level of detail `lod`, meshing
+/// the cells of [`downsample`] instead of the voxels.
+///
+/// Below the full resolution the neighbors are ignored and the faces on the
+/// chunk border always emitted. They hide most of the cracks along chunks of
+/// another level, the remaining ones are accepted for now.
+pub fn greedy_mesh_lod(
+    chunk: &Chunk,
+    neighbors: &ChunkNeighbors,
+    lod: LodLevel,
+) -> (Vec<Vertex>, Vec<u32>) {
+    if lod == LodLevel::FULL {
+        return greedy_mesh_with_neighbors(chunk, neighbors);
+    }
+
+    let cells = downsample(chunk, lod);
+    let size = lod.cells_per_axis() as i32;
+    let inside = |pos: [i32; 3]| pos.iter().all(|c| (0..size).contains(c));
+    let sample = |[x, y, z]: [i32; 3]| {
+        if inside([x, y, z]) {
+            cells[(x + y * size + z * size * size) as usize]
+        } else {
+            BlockId::AIR
+        }
+    };
+
+    greedy_mesh_cells(size, lod.cell_size() as f32, inside, sample)
+}
+
+/// Greedy meshes a cube of `size` cells with an edge of `scale` voxels.
+///
+/// `sample` also covers the cells right outside of the cube, whose faces
+/// aren't emitted.
+fn greedy_mesh_cells(
+    size: i32,
+    scale: f32,
+    inside: impl Fn([i32; 3]) -> bool,
+    sample: impl Fn([i32; 3]) -> BlockId,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut mask = vec![None; (size * size) as usize];
+    let mask_index = |i: i32, j: i32| (i + j * size) as usize;
+
+    for d in 0..3 {
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+
+        // Slice `s` holds the faces between the voxel layers `s - 1` and `s`.
+        for s in 0..=size {
+            let mut pos = [0; 3];
+            pos[d] = s;
+
+            for j in 0..size {
+                for i in 0..size {
+                    pos[u] = i;
+                    pos[v] = j;
+                    let mut behind_pos = pos;
+                    behind_pos[d] -= 1;
+
+                    let behind = sample(behind_pos);
+                    let front = sample(pos);
+
+                    // Occlusion of the face corners by the voxels next to the air voxel.
+                    let corners_ao = |air: [i32; 3]| {
+                        let solid = |du: i32, dv: i32| {
+                            let mut pos = air;
+                            pos[u] += du;
+                            pos[v] += dv;
+                            sample(pos).is_solid()
+                        };
+                        [(-1, -1), (1, -1), (1, 1), (-1, 1)]
+                            .map(|(du, dv)| vertex_ao(solid(du, 0), solid(0, dv), solid(du, dv)))
+                    };
+
+                    // Faces of the voxels outside of the chunk belong to the neighbors.
+                    mask[mask_index(i, j)] = match (behind.is_solid(), front.is_solid()) {
+                        (true, false) if inside(behind_pos) => Some(Face {
+                            block: behind,
+                            backface: false,
+                            ao: corners_ao(pos),
+                        }),
+                        (false, true) if inside(pos) => Some(Face {
+                            block: front,
+                            backface: true,
+                            ao: corners_ao(behind_pos),
+                        }),
+                        _ => None,
+                    };
+                }
+            }
+
+            for j in 0..size {
+                let mut i = 0;
+                while i < size {
+                    let Some(face) = mask[mask_index(i, j)] else {
+                        i += 1;
+                        continue;
+                    };
+
+                    let mut width = 1;
+                    while i + width < size && mask[mask_index(i + width, j)] == Some(face) {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    'grow: while j + height < size {
+                        for k in 0..width {
+                            if mask[mask_index(i + k, j + height)] != Some(face) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+
+                    for jj in 0..height {
+                        for ii in 0..width {
+                            mask[mask_index(i + ii, j + jj)] = None;
+                        }
+                    }
+
+                    let mut origin = [0.0; 3];
+                    origin[d] = s as f32 * scale;
+                    origin[u] = i as f32 * scale;
+                    origin[v] = j as f32 * scale;
+
+                    let mut du = [0.0; 3];
+                    du[u] = width as f32 * scale;
+                    let mut dv = [0.0; 3];
+                    dv[v] = height as f32 * scale;
+
+                    push_quad(&mut vertices, &mut indices, origin, du, dv, face);
+
+                    i += width;
+                }
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+/// Pushes a quad spanning `du` and `dv` from `origin`, wound counter-clockwise
+/// when looking at its front side.
+fn push_quad(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    origin: [f32; 3],
+    du: [f32; 3],
+    dv: [f32; 3],
+    face: Face,
+) {
+    let add = |a: [f32; 3], b: [f32; 3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+
+    let width = du.iter().sum::<f32>();
+    let height = dv.iter().sum::<f32>();
+
+    let [ao00, ao10, ao11, ao01] = face.ao;
+
+    // The sweep axis is the one the quad doesn't span.
+    let axis = (0..3)
+        .find(|&axis| du[axis] == 0.0 && dv[axis] == 0.0)
+        .unwrap();
+    let mut normal = [0.0; 3];
+    normal[axis] = if face.backface { -1.0 } else { 1.0 };
+
+    // `du × dv` points along the positive sweep axis.
+    let corners = if face.backface {
+        [
+            (origin, [0.0, 0.0], ao00),
+            (add(origin, dv), [0.0, height], ao01),
+            (add(add(origin, du), dv), [width, height], ao11),
+            (add(origin, du), [width, 0.0], ao10),
+        ]
+    } else {
+        [
+            (origin, [0.0, 0.0], ao00),
+            (add(origin, du), [width, 0.0], ao10),
+            (add(add(origin, du), dv), [width, height], ao11),
+            (add(origin, dv), [0.0, height], ao01),
+        ]
+    };
+
+    let base = vertices.len() as u32;
+    let color = face.block.color();
+    let layer = layer_for(face.block);
+    vertices.extend(corners.map(|(position, uv, ao)| Vertex {
+        position,
+        color,
+        uv,
+        ao: AO_FACTORS[ao as usize],
+        normal,
+        layer,
+    }));
+    indices.extend(quad_indices(corners.map(|(_, _, ao)| ao)).map(|i| base + i));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad_count(mesh: &(Vec<Vertex>, Vec<u32>)) -> usize {
+        assert_eq!(mesh.0.len() % 4, 0);
+        assert_eq!(mesh.0.len() / 4 * 6, mesh.1.len());
+        mesh.0.len() / 4
+    }
+
+    #[test]
+    fn empty_chunk() {
+        assert_eq!(quad_count(&greedy_mesh(&Chunk::default())), 0);
+    }
+
+    #[test]
+    fn solid_chunk() {
+        let chunk = Chunk::filled(BlockId::STONE);
+        assert_eq!(quad_count(&greedy_mesh(&chunk)), 6);
+    }
+
+    #[test]
+    fn single_voxel() {
+        let mut chunk = Chunk::default();
+        chunk.set(4, 5, 6, BlockId::STONE);
+        let (vertices, _) = greedy_mesh(&chunk);
+        assert_eq!(vertices.len(), 6 * 4);
+
+        for vertex in vertices {
+            assert!((4.0..=5.0).contains(&vertex.position[0]));
+            assert!((5.0..=6.0).contains(&vertex.position[1]));
+            assert!((6.0..=7.0).contains(&vertex.position[2]));
+        }
+    }
+
+    #[test]
+    fn different_blocks_are_not_merged() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, BlockId::STONE);
+        chunk.set(1, 0, 0, BlockId::DIRT);
+
+        // Each voxel has 5 visible faces, the shared one is hidden.
+        assert_eq!(quad_count(&greedy_mesh(&chunk)), 10);
+    }
+
+    #[test]
+    fn checkerboard_chunk() {
+        let mut chunk = Chunk::default();
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    if (x + y + z) % 2 == 0 {
+                        chunk.set(x, y, z, BlockId::STONE);
+                    }
+                }
+            }
+        }
+
+        // No two faces are adjacent so nothing can be merged.
+        let solid = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE / 2;
+        assert_eq!(quad_count(&greedy_mesh(&chunk)), solid * 6);
+    }
+
+    #[test]
+    fn lod_meshes_span_the_chunk() {
+        let chunk = Chunk::filled(BlockId::STONE);
+        let (vertices, _) = greedy_mesh_lod(&chunk, &ChunkNeighbors::default(), LodLevel(1));
+
+        assert_eq!(vertices.len(), 6 * 4);
+        assert!(vertices.iter().all(|vertex| {
+            vertex
+                .position
+                .iter()
+                .all(|c| *c == 0.0 || *c == CHUNK_SIZE as f32)
+        }));
+        // Textures still repeat once per voxel.
+        assert!(vertices.iter().any(|vertex| vertex.uv == [32.0, 32.0]));
+    }
+
+    #[test]
+    fn lod_merges_voxels_into_cells() {
+        let mut chunk = Chunk::default();
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    if (x + y + z) % 2 == 0 {
+                        chunk.set(x, y, z, BlockId::STONE);
+                    }
+                }
+            }
+        }
+
+        // Every cell is half solid, so the chunk becomes one solid block.
+        let neighbors = ChunkNeighbors::default();
+        assert_eq!(
+            quad_count(&greedy_mesh_lod(&chunk, &neighbors, LodLevel(1))),
+            6
+        );
+        assert_eq!(
+            greedy_mesh_lod(&chunk, &neighbors, LodLevel::FULL),
+            greedy_mesh(&chunk)
+        );
+    }
+
+    #[test]
+    fn adjacent_chunks_cull_shared_faces() {
+        let mut store = ChunkStore::default();
+        store.load(IVec3::ZERO, Chunk::filled(BlockId::STONE));
+        store.load(IVec3::X, Chunk::filled(BlockId::STONE));
+
+        let chunk = store.get(IVec3::ZERO).unwrap();
+        let neighbors = ChunkNeighbors::from_store(&store, IVec3::ZERO);
+        assert_eq!(
+            quad_count(&greedy_mesh_with_neighbors(chunk, &neighbors)),
+            5
+        );
+
+        let chunk = store.get(IVec3::X).unwrap();
+        let neighbors = ChunkNeighbors::from_store(&store, IVec3::X);
+        assert_eq!(
+            quad_count(&greedy_mesh_with_neighbors(chunk, &neighbors)),
+            5
+        );
+    }
+
+    #[test]
+    fn missing_neighbors_keep_border_faces() {
+        let mut store = ChunkStore::default();
+        store.load(IVec3::ZERO, Chunk::filled(BlockId::STONE));
+
+        let chunk = store.get(IVec3::ZERO).unwrap();
+        let neighbors = ChunkNeighbors::from_store(&store, IVec3::ZERO);
+        assert_eq!(
+            quad_count(&greedy_mesh_with_neighbors(chunk, &neighbors)),
+            6
+        );
+    }
+
+    #[test]
+    fn neighbor_faces_are_not_emitted() {
+        let mut store = ChunkStore::default();
+        store.load(IVec3::ZERO, Chunk::filled(BlockId::STONE));
+        store.load(IVec3::NEG_Y, Chunk::default());
+
+        // The bottom face of the solid chunk above belongs to it, not to the air chunk.
+        let chunk = store.get(IVec3::NEG_Y).unwrap();
+        let neighbors = ChunkNeighbors::from_store(&store, IVec3::NEG_Y);
+        assert_eq!(
+            quad_count(&greedy_mesh_with_neighbors(chunk, &neighbors)),
+            0
+        );
+    }
+
+    #[test]
+    fn uvs_repeat_per_voxel() {
+        let mut chunk = Chunk::default();
+        for x in 0..3 {
+            chunk.set(x, 0, 0, BlockId::STONE);
+        }
+        let (vertices, _) = greedy_mesh(&chunk);
+
+        // The top face spans 3 voxels along X and 1 along Z.
+        let top = vertices
+            .chunks(4)
+            .find(|quad| quad.iter().all(|vertex| vertex.position[1] == 1.0))
+            .unwrap();
+        let max_u = top.iter().map(|vertex| vertex.uv[0]).fold(0.0, f32::max);
+        let max_v = top.iter().map(|vertex| vertex.uv[1]).fold(0.0, f32::max);
+        assert_eq!((max_u, max_v), (1.0, 3.0));
+    }
+
+    #[test]
+    fn corner_occlusion_levels() {
+        assert_eq!(vertex_ao(false, false, false), 3);
+        assert_eq!(vertex_ao(false, false, true), 2);
+        assert_eq!(vertex_ao(true, false, false), 2);
+        assert_eq!(vertex_ao(true, false, true), 1);
+        assert_eq!(vertex_ao(false, true, true), 1);
+        assert_eq!(vertex_ao(true, true, false), 0);
+        assert_eq!(vertex_ao(true, true, true), 0);
+    }
+
+    #[test]
+    fn inner_corner_is_darkened() {
+        // A floor with a wall along its -X edge.
+        let mut chunk = Chunk::default();
+        for z in 0..4 {
+            for x in 0..4 {
+                chunk.set(x, 0, z, BlockId::STONE);
+            }
+            chunk.set(0, 1, z, BlockId::STONE);
+        }
+        let (vertices, _) = greedy_mesh(&chunk);
+
+        let floor = vertices
+            .chunks(4)
+            .filter(|quad| quad.iter().all(|vertex| vertex.position[1] == 1.0))
+            .flatten();
+        for vertex in floor {
+            // Along the wall both the side and the diagonal voxels occlude,
+            // except at its ends where the diagonal is outside of the chunk.
+            let [x, _, z] = vertex.position;
+            let expected = match (x, z) {
+                (1.0, 0.0 | 4.0) => 0.8,
+                (1.0, _) => 0.6,
+                _ => 1.0,
+            };
+            assert_eq!(vertex.ao, expected, "{:?}", vertex.position);
+        }
+    }
+
+    #[test]
+    fn quads_split_along_the_brighter_diagonal() {
+        assert_eq!(quad_indices([3; 4]), [0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad_indices([0, 3, 3, 3]), [1, 2, 3, 1, 3, 0]);
+        assert_eq!(quad_indices([3, 0, 3, 3]), [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn normals_point_out_of_the_voxel() {
+        let mut chunk = Chunk::default();
+        chunk.set(1, 1, 1, BlockId::STONE);
+        let (vertices, _) = greedy_mesh(&chunk);
+
+        for vertex in vertices {
+            // Every vertex of a face lies on the side its normal points to.
+            for axis in 0..3 {
+                match vertex.normal[axis] {
+                    1.0 => assert_eq!(vertex.position[axis], 2.0),
+                    -1.0 => assert_eq!(vertex.position[axis], 1.0),
+                    _ => assert_eq!(vertex.normal[axis], 0.0),
+                }
+            }
+            assert_eq!(vertex.normal.iter().map(|c| c.abs()).sum::<f32>(), 1.0);
+        }
+    }
+
+    #[test]
+    fn quads_face_outwards() {
+        let mut chunk = Chunk::default();
+        chunk.set(1, 1, 1, BlockId::STONE);
+        let (vertices, indices) = greedy_mesh(&chunk);
+
+        let center = [1.5, 1.5, 1.5];
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+            let sub = |a: [f32; 3], b: [f32; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+            let (e1, e2) = (sub(b, a), sub(c, a));
+            let normal = [
+                e1[1] * e2[2] - e1[2] * e2[1],
+                e1[2] * e2[0] - e1[0] * e2[2],
+                e1[0] * e2[1] - e1[1] * e2[0],
+            ];
+            let outwards = sub(a, center);
+            let dot: f32 = (0..3).map(|i| normal[i] * outwards[i]).sum();
+            assert!(dot > 0.0);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use bevy_app::{App, Plugin, Startup, Update};
+use bevy_ecs::{
+    schedule::IntoScheduleConfigs,
+    system::{Res, ResMut},
+};
+use chunk_file::WorldSaveDir;
+use generation::WorldGenConfig;
+use glam::IVec3;
+use lod::update_chunk_lods_system;
+use mesh_queue::MeshQueue;
+use store::ChunkStore;
+use streaming::{
+    ChunkLoader, ChunkUnloaded, StreamingConfig, load_or_generate, save_edited_chunks_system,
+    stream_chunks_system,
+};
+
+use crate::rendering::Destroy;
+
+pub mod chunk;
+pub mod chunk_file;
+pub mod generation;
+pub mod lod;
+pub mod mesh_queue;
+pub mod meshing;
+pub mod raycast;
+pub mod store;
+pub mod streaming;
+pub mod workers;
+
+/// Horizontal radius in chunks of the area generated around the origin.
+const SPAWN_RADIUS: i32 = 2;
+/// Vertical radius in chunks of the area generated around the origin.
+const SPAWN_HEIGHT: i32 = 1;
+
+/// Generates, streams and meshes the chunks around the camera.
+///
+/// Chunks are saved to and loaded from the [`WorldSaveDir`] inserted before
+/// adding the plugin, without one the world is generated anew on every run.
+/// Edited chunks are saved when they are unloaded and when the app exits.
+pub struct WorldPlugin;
+
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkStore>()
+            .init_resource::<WorldGenConfig>()
+            .init_resource::<MeshQueue>()
+            .init_resource::<ChunkLoader>()
+            .init_resource::<StreamingConfig>()
+            .init_resource::<WorldSaveDir>()
+            .add_event::<ChunkUnloaded>()
+            .add_systems(Startup, generate_spawn_chunks)
+            .add_systems(
+                Update,
+                (stream_chunks_system, update_chunk_lods_system).chain(),
+            )
+            .add_systems(Destroy, save_edited_chunks_system);
+    }
+}
+
+fn generate_spawn_chunks(
+    mut store: ResMut<ChunkStore>,
+    config: Res<WorldGenConfig>,
+    save_dir: Res<WorldSaveDir>,
+) {
+    for y in -SPAWN_HEIGHT..=SPAWN_HEIGHT {
+        for z in -SPAWN_RADIUS..=SPAWN_RADIUS {
+            for x in -SPAWN_RADIUS..=SPAWN_RADIUS {
+                let coord = IVec3::new(x, y, z);
+                store.load(coord, load_or_generate(coord, config.seed, &save_dir));
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use glam::{IVec3, Vec3};
+
+use super::{chunk::BlockId, store::ChunkStore};
+
+/// The first solid voxel along a ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelHit {
+    /// World voxel coordinate of the hit voxel.
+    pub position: IVec3,
+    /// Normal of the face the ray entered through. Zero when the ray starts
+    /// inside the voxel.
+    pub normal: IVec3,
+    /// Distance along the ray to the entered face.
+    pub distance: f32,
+    pub block: BlockId,
+}
+
+impl VoxelHit {
+    /// Voxel in front of the hit face, where a block would be placed.
+    pub fn adjacent(&self) -> IVec3 {
+        self.position + self.normal
+    }
+}
+
+/// Walks the voxels crossed by the ray from `origin` along `direction` and
+/// returns the first solid one within `max_distance`.
+///
+/// Voxels are visited in order with the Amanatides & Woo traversal, chunks
+/// that aren't loaded are treated as air.
+pub fn raycast_voxel(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    store: &ChunkStore,
+) -> Option<VoxelHit> {
+    let direction = direction.try_normalize()?;
+
+    let mut cell = origin.floor().as_ivec3();
+    let step = IVec3::from_array(std::array::from_fn(|axis| match direction[axis] {
+        d if d > 0.0 => 1,
+        d if d < 0.0 => -1,
+        _ => 0,
+    }));
+    // Distance along the ray between two voxel boundaries on each axis.
+    let t_delta = direction.recip().abs();
+    // Distance along the ray to the next voxel boundary on each axis.
+    let mut t_max = Vec3::from_array(std::array::from_fn(|axis| match step[axis] {
+        1 => (cell[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis],
+        -1 => (origin[axis] - cell[axis] as f32) * t_delta[axis],
+        _ => f32::INFINITY,
+    }));
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+    loop {
+        if let Some(block) = store.block(cell).filter(|block| block.is_solid()) {
+            return Some(VoxelHit {
+                position: cell,
+                normal,
+                distance,
+                block,
+            });
+        }
+
+        let axis = if t_max.x <= t_max.y && t_max.x <= t_max.z {
+            0
+        } else if t_max.y <= t_max.z {
+            1
+        } else {
+            2
+        };
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+
+        cell[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::Chunk;
+
+    const TARGET: IVec3 = IVec3::new(5, 2, 3);
+
+    /// Loads the chunks around the origin with a single stone voxel at `target`.
+    fn store_with_voxel(target: IVec3) -> ChunkStore {
+        let mut store = ChunkStore::default();
+        for coord in [IVec3::ZERO, IVec3::NEG_X] {
+            store.load(coord, Chunk::default());
+        }
+
+        let (coord, local) = ChunkStore::split(target);
+        store
+            .get_mut(coord)
+            .unwrap()
+            .set_local(local, BlockId::STONE);
+        store
+    }
+
+    fn center(voxel: IVec3) -> Vec3 {
+        voxel.as_vec3() + 0.5
+    }
+
+    #[test]
+    fn hits_each_face() {
+        let store = store_with_voxel(TARGET);
+
+        for normal in [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ] {
+            let origin = center(TARGET + normal * 3);
+            let hit = raycast_voxel(origin, -normal.as_vec3(), 10.0, &store).unwrap();
+
+            assert_eq!(hit.position, TARGET);
+            assert_eq!(hit.normal, normal);
+            assert_eq!(hit.block, BlockId::STONE);
+            assert!((hit.distance - 2.5).abs() < 1e-5);
+            assert_eq!(hit.adjacent(), TARGET + normal);
+        }
+    }
+
+    #[test]
+    fn diagonal_ray() {
+        let store = store_with_voxel(TARGET);
+        let origin = center(TARGET) - Vec3::new(3.0, 3.0, 0.2);
+
+        let hit = raycast_voxel(origin, center(TARGET) - origin, 10.0, &store).unwrap();
+
+        assert_eq!(hit.position, TARGET);
+        assert!([IVec3::NEG_X, IVec3::NEG_Y].contains(&hit.normal));
+    }
+
+    #[test]
+    fn crosses_chunk_boundaries() {
+        let target = IVec3::new(-2, 2, 3);
+        let store = store_with_voxel(target);
+
+        let hit = raycast_voxel(center(IVec3::new(6, 2, 3)), Vec3::NEG_X, 16.0, &store).unwrap();
+
+        assert_eq!(hit.position, target);
+        assert_eq!(hit.normal, IVec3::X);
+        assert!((hit.distance - 7.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn misses() {
+        let store = store_with_voxel(TARGET);
+        let origin = center(TARGET + IVec3::X * 3);
+
+        // Too far.
+        assert!(raycast_voxel(origin, Vec3::NEG_X, 2.0, &store).is_none());
+        // Wrong direction.
+        assert!(raycast_voxel(origin, Vec3::X, 10.0, &store).is_none());
+        // No direction.
+        assert!(raycast_voxel(origin, Vec3::ZERO, 10.0, &store).is_none());
+    }
+
+    #[test]
+    fn starts_inside_voxel() {
+        let store = store_with_voxel(TARGET);
+
+        let hit = raycast_voxel(center(TARGET), Vec3::X, 10.0, &store).unwrap();
+
+        assert_eq!(hit.position, TARGET);
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.distance, 0.0);
+    }
+}
@@ -0,0 +1,261 @@
+use bevy_ecs::resource::Resource;
+use derive_more::{Deref, DerefMut};
+use glam::IVec3;
+use hashbrown::{HashMap, HashSet};
+
+use super::chunk::{BlockId, CHUNK_SIZE, Chunk};
+
+/// Loaded chunks of the world keyed by chunk coordinate.
+#[derive(Resource, Default)]
+pub struct ChunkStore {
+    chunks: HashMap<IVec3, Chunk>,
+    dirty: DirtyChunks,
+    /// Loaded chunks edited since they were loaded, saved when unloaded.
+    edited: HashSet<IVec3>,
+}
+
+/// Coordinates of loaded chunks edited since they were last meshed.
+#[derive(Debug, Default, Deref, DerefMut)]
+pub struct DirtyChunks(HashSet<IVec3>);
+
+impl ChunkStore {
+    /// Stores the chunk at `coord` returning the chunk it replaced.
+    ///
+    /// Its loaded face neighbors are marked dirty, since their boundary faces
+    /// were meshed without it.
+    pub fn load(&mut self, coord: IVec3, chunk: Chunk) -> Option<Chunk> {
+        self.edited.remove(&coord);
+        for neighbor in Self::neighbors(coord) {
+            if self.is_loaded(neighbor) {
+                self.dirty.insert(neighbor);
+            }
+        }
+        self.chunks.insert(coord, chunk)
+    }
+
+    pub fn unload(&mut self, coord: IVec3) -> Option<Chunk> {
+        self.edited.remove(&coord);
+        self.chunks.remove(&coord)
+    }
+
+    /// Whether the chunk at `coord` was edited with [`set_block`](Self::set_block)
+    /// since it was loaded.
+    pub fn is_edited(&self, coord: IVec3) -> bool {
+        self.edited.contains(&coord)
+    }
+
+    pub fn get(&self, coord: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&coord)
+    }
+
+    pub fn get_mut(&mut self, coord: IVec3) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&coord)
+    }
+
+    /// Block at the world voxel coordinate `pos`, `None` if its chunk isn't loaded.
+    pub fn block(&self, pos: IVec3) -> Option<BlockId> {
+        let (coord, local) = Self::split(pos);
+        Some(self.get(coord)?.get_local(local))
+    }
+
+    /// Sets the block at the world voxel coordinate `pos` and returns the
+    /// block it replaced, `None` if its chunk isn't loaded.
+    ///
+    /// The chunk is marked dirty, along with the loaded neighbors whose faces
+    /// touch the voxel, since their boundary faces may appear or disappear.
+    pub fn set_block(&mut self, pos: IVec3, block: BlockId) -> Option<BlockId> {
+        let (coord, local) = Self::split(pos);
+        let chunk = self.chunks.get_mut(&coord)?;
+
+        let previous = chunk.get_local(local);
+        if previous == block {
+            return Some(previous);
+        }
+        chunk.set_local(local, block);
+
+        self.edited.insert(coord);
+        self.dirty.insert(coord);
+        let last = CHUNK_SIZE as i32 - 1;
+        for axis in 0..3 {
+            let side = match local[axis] {
+                0 => -1,
+                c if c == last => 1,
+                _ => continue,
+            };
+            let mut neighbor = coord;
+            neighbor[axis] += side;
+            if self.is_loaded(neighbor) {
+                self.dirty.insert(neighbor);
+            }
+        }
+
+        Some(previous)
+    }
+
+    /// Takes the chunks edited since the last call, to mesh them again.
+    pub fn take_dirty(&mut self) -> DirtyChunks {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Splits a world voxel coordinate into the coordinate of its chunk and
+    /// its position inside of it.
+    pub fn split(pos: IVec3) -> (IVec3, IVec3) {
+        let size = CHUNK_SIZE as i32;
+        (
+            pos.div_euclid(IVec3::splat(size)),
+            pos.rem_euclid(IVec3::splat(size)),
+        )
+    }
+
+    pub fn is_loaded(&self, coord: IVec3) -> bool {
+        self.chunks.contains_key(&coord)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, &Chunk)> {
+        self.chunks.iter().map(|(coord, chunk)| (*coord, chunk))
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Coordinates of the six chunks sharing a face with `coord`.
+    pub fn neighbors(coord: IVec3) -> [IVec3; 6] {
+        [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ]
+        .map(|offset| coord + offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::BlockId;
+
+    #[test]
+    fn load_unload() {
+        let mut store = ChunkStore::default();
+        let coord = IVec3::new(1, -2, 3);
+
+        assert!(store.load(coord, Chunk::filled(BlockId::STONE)).is_none());
+        assert!(store.is_loaded(coord));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get(coord).unwrap().get(0, 0, 0), BlockId::STONE);
+        assert!(store.get(IVec3::ZERO).is_none());
+
+        let replaced = store.load(coord, Chunk::default()).unwrap();
+        assert_eq!(replaced.get(0, 0, 0), BlockId::STONE);
+
+        assert!(store.unload(coord).is_some());
+        assert!(store.unload(coord).is_none());
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn loading_dirties_loaded_neighbors() {
+        let mut store = ChunkStore::default();
+        store.load(IVec3::ZERO, Chunk::default());
+        store.load(IVec3::new(0, 5, 0), Chunk::default());
+        assert!(store.take_dirty().is_empty());
+
+        store.load(IVec3::X, Chunk::default());
+        let dirty = store.take_dirty();
+        assert_eq!(dirty.len(), 1);
+        assert!(dirty.contains(&IVec3::ZERO));
+    }
+
+    #[test]
+    fn block_by_world_position() {
+        let mut store = ChunkStore::default();
+        let mut chunk = Chunk::default();
+        chunk.set(31, 0, 5, BlockId::DIRT);
+        store.load(IVec3::new(-1, 0, 0), chunk);
+
+        assert_eq!(
+            ChunkStore::split(IVec3::new(-1, 0, 5)),
+            (IVec3::new(-1, 0, 0), IVec3::new(31, 0, 5))
+        );
+        assert_eq!(store.block(IVec3::new(-1, 0, 5)), Some(BlockId::DIRT));
+        assert_eq!(store.block(IVec3::new(-2, 0, 5)), Some(BlockId::AIR));
+        assert_eq!(store.block(IVec3::new(0, 0, 5)), None);
+    }
+
+    #[test]
+    fn edits_dirty_affected_chunks() {
+        let mut store = ChunkStore::default();
+        for coord in [IVec3::ZERO, IVec3::NEG_X, IVec3::X, IVec3::Y] {
+            store.load(coord, Chunk::default());
+        }
+
+        // Interior voxel.
+        assert_eq!(
+            store.set_block(IVec3::new(5, 5, 5), BlockId::STONE),
+            Some(BlockId::AIR)
+        );
+        let dirty = store.take_dirty();
+        assert_eq!(dirty.len(), 1);
+        assert!(dirty.contains(&IVec3::ZERO));
+
+        // Voxel on the face shared with the chunk at -X.
+        store.set_block(IVec3::new(0, 5, 5), BlockId::STONE);
+        let dirty = store.take_dirty();
+        assert_eq!(dirty.len(), 2);
+        assert!(dirty.contains(&IVec3::ZERO) && dirty.contains(&IVec3::NEG_X));
+
+        // Setting the same block again changes nothing.
+        store.set_block(IVec3::new(0, 5, 5), BlockId::STONE);
+        assert!(store.take_dirty().is_empty());
+
+        // Neighbors that aren't loaded aren't marked.
+        store.set_block(IVec3::new(5, 5, 0), BlockId::STONE);
+        assert_eq!(store.take_dirty().len(), 1);
+
+        assert_eq!(store.set_block(IVec3::new(5, 5, -1), BlockId::STONE), None);
+        assert!(store.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn edits_are_kept_until_unloaded() {
+        let mut store = ChunkStore::default();
+        for coord in [IVec3::ZERO, IVec3::NEG_X] {
+            store.load(coord, Chunk::default());
+        }
+
+        store.set_block(IVec3::new(0, 5, 5), BlockId::STONE);
+        store.take_dirty();
+        // Only the edited chunk changed, its neighbor is just meshed again.
+        assert!(store.is_edited(IVec3::ZERO));
+        assert!(!store.is_edited(IVec3::NEG_X));
+
+        store.unload(IVec3::ZERO);
+        store.load(IVec3::ZERO, Chunk::default());
+        assert!(!store.is_edited(IVec3::ZERO));
+    }
+
+    #[test]
+    fn neighbors_of_origin() {
+        let neighbors = ChunkStore::neighbors(IVec3::ZERO);
+
+        for expected in [
+            IVec3::new(1, 0, 0),
+            IVec3::new(-1, 0, 0),
+            IVec3::new(0, 1, 0),
+            IVec3::new(0, -1, 0),
+            IVec3::new(0, 0, 1),
+            IVec3::new(0, 0, -1),
+        ] {
+            assert!(neighbors.contains(&expected));
+        }
+        assert!(!neighbors.contains(&IVec3::ZERO));
+    }
+}
@@ -0,0 +1,347 @@
+use bevy_ecs::{
+    event::{Event, EventWriter},
+    resource::Resource,
+    system::{Res, ResMut},
+};
+use glam::{IVec3, Vec3};
+use hashbrown::HashSet;
+use tracing::error;
+
+use super::{
+    chunk::{CHUNK_SIZE, Chunk},
+    chunk_file::{WorldSaveDir, load_chunk, save_chunk},
+    generation::{WorldGenConfig, generate_chunk},
+    store::ChunkStore,
+    workers::{WorkerPool, worker_count},
+};
+use crate::camera::Camera;
+
+/// Distances in chunks around the camera within which chunks are loaded and
+/// beyond which they are unloaded.
+///
+/// `unload_radius` should be larger than `load_radius`, otherwise chunks on
+/// the boundary are loaded and unloaded again whenever the camera moves a bit.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamingConfig {
+    pub load_radius: u32,
+    pub unload_radius: u32,
+    /// Chunks handed to the [`ChunkLoader`] per frame, nearest first.
+    pub max_loads_per_frame: usize,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            load_radius: 4,
+            unload_radius: 6,
+            max_loads_per_frame: 4,
+        }
+    }
+}
+
+/// Sent when the chunk at `coord` is removed from the [`ChunkStore`].
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkUnloaded {
+    pub coord: IVec3,
+}
+
+/// Coordinate of the chunk containing `position`.
+pub fn chunk_coord(position: Vec3) -> IVec3 {
+    (position / CHUNK_SIZE as f32).floor().as_ivec3()
+}
+
+/// Chunks within `radius` of `center`, nearest first.
+pub fn chunks_in_radius(center: IVec3, radius: u32) -> Vec<IVec3> {
+    let radius = radius as i32;
+    let mut coords = Vec::new();
+    for y in -radius..=radius {
+        for z in -radius..=radius {
+            for x in -radius..=radius {
+                let offset = IVec3::new(x, y, z);
+                if offset.length_squared() <= radius * radius {
+                    coords.push(center + offset);
+                }
+            }
+        }
+    }
+
+    coords.sort_by_key(|coord| (*coord - center).length_squared());
+    coords
+}
+
+/// The saved chunk at `coord` if there is one, otherwise a generated one.
+pub fn load_or_generate(coord: IVec3, seed: u64, save_dir: &WorldSaveDir) -> Chunk {
+    save_dir
+        .0
+        .as_deref()
+        .and_then(|dir| load_chunk(coord, dir))
+        .unwrap_or_else(|| generate_chunk(coord, seed))
+}
+
+/// A chunk to read from its file or generate.
+struct LoadJob {
+    coord: IVec3,
+    seed: u64,
+    save_dir: WorldSaveDir,
+}
+
+impl LoadJob {
+    fn run(self) -> (IVec3, Chunk) {
+        let chunk = load_or_generate(self.coord, self.seed, &self.save_dir);
+        (self.coord, chunk)
+    }
+}
+
+/// Loads or generates chunks on worker threads, so that streaming doesn't
+/// stall the frame.
+#[derive(Resource)]
+pub struct ChunkLoader {
+    in_flight: HashSet<IVec3>,
+    workers: WorkerPool<LoadJob, (IVec3, Chunk)>,
+}
+
+impl ChunkLoader {
+    pub fn new(worker_count: usize) -> Self {
+        Self {
+            in_flight: HashSet::new(),
+            workers: WorkerPool::new("chunk-loader", worker_count, LoadJob::run),
+        }
+    }
+
+    /// Starts loading the chunk at `coord`, see [`load_or_generate`], unless
+    /// it is already being loaded.
+    pub fn request(&mut self, coord: IVec3, seed: u64, save_dir: &WorldSaveDir) {
+        if self.in_flight.contains(&coord) {
+            return;
+        }
+
+        let job = LoadJob {
+            coord,
+            seed,
+            save_dir: save_dir.clone(),
+        };
+        if self.workers.send(job) {
+            self.in_flight.insert(coord);
+        }
+    }
+
+    pub fn is_loading(&self, coord: IVec3) -> bool {
+        self.in_flight.contains(&coord)
+    }
+
+    pub fn in_flight_len(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Chunks loaded since the last call, without waiting for the others.
+    pub fn finished(&mut self) -> Vec<(IVec3, Chunk)> {
+        let finished = self.workers.finished().collect::<Vec<_>>();
+        for (coord, _) in &finished {
+            self.in_flight.remove(coord);
+        }
+        finished
+    }
+}
+
+impl Default for ChunkLoader {
+    /// Shares the cores with the [`MeshQueue`](super::mesh_queue::MeshQueue).
+    fn default() -> Self {
+        Self::new(worker_count(2))
+    }
+}
+
+/// Whether `coord` is further than `radius` chunks from `center`.
+pub fn is_beyond(center: IVec3, coord: IVec3, radius: u32) -> bool {
+    (coord - center).length_squared() > (radius * radius) as i32
+}
+
+/// Inserts the chunks the [`ChunkLoader`] finished, unloads the chunks beyond
+/// the unload radius and requests the missing ones within the load radius.
+pub fn stream_chunks_system(
+    camera: Res<Camera>,
+    config: Res<StreamingConfig>,
+    gen_config: Res<WorldGenConfig>,
+    save_dir: Res<WorldSaveDir>,
+    mut loader: ResMut<ChunkLoader>,
+    mut store: ResMut<ChunkStore>,
+    mut unloaded: EventWriter<ChunkUnloaded>,
+) {
+    let center = chunk_coord(camera.position);
+
+    for (coord, chunk) in loader.finished() {
+        // The camera may have moved away while the chunk was loading.
+        if !store.is_loaded(coord) && !is_beyond(center, coord, config.unload_radius) {
+            store.load(coord, chunk);
+        }
+    }
+
+    let far = store
+        .iter()
+        .map(|(coord, _)| coord)
+        .filter(|coord| is_beyond(center, *coord, config.unload_radius))
+        .collect::<Vec<_>>();
+    for coord in far {
+        save_if_edited(&store, coord, &save_dir);
+        store.unload(coord);
+        unloaded.write(ChunkUnloaded { coord });
+    }
+
+    let missing = chunks_in_radius(center, config.load_radius)
+        .into_iter()
+        .filter(|coord| !store.is_loaded(*coord) && !loader.is_loading(*coord))
+        .take(config.max_loads_per_frame)
+        .collect::<Vec<_>>();
+    for coord in missing {
+        loader.request(coord, gen_config.seed, &save_dir);
+    }
+}
+
+/// Saves the chunk at `coord` to `save_dir` if it was edited since it was
+/// loaded. Failures are logged.
+fn save_if_edited(store: &ChunkStore, coord: IVec3, save_dir: &WorldSaveDir) {
+    if store.is_edited(coord)
+        && let Some(chunk) = store.get(coord)
+        && let Some(dir) = &save_dir.0
+        && let Err(err) = save_chunk(coord, chunk, dir)
+    {
+        error!("Failed to save the chunk at {coord}: {err}");
+    }
+}
+
+/// Saves the edited chunks that are still loaded when the app exits.
+pub fn save_edited_chunks_system(store: Res<ChunkStore>, save_dir: Res<WorldSaveDir>) {
+    for (coord, _) in store.iter() {
+        save_if_edited(&store, coord, &save_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::BlockId;
+
+    #[test]
+    fn camera_chunk() {
+        assert_eq!(chunk_coord(Vec3::new(0.5, 31.9, 0.0)), IVec3::ZERO);
+        assert_eq!(
+            chunk_coord(Vec3::new(32.0, -0.1, -33.0)),
+            IVec3::new(1, -1, -2)
+        );
+    }
+
+    #[test]
+    fn saved_chunks_are_preferred() {
+        let dir = std::env::temp_dir().join(format!("streaming-test-{}", std::process::id()));
+        let save_dir = WorldSaveDir(Some(dir.clone()));
+        let coord = IVec3::new(0, -1, 0);
+        let generated = generate_chunk(coord, 1);
+
+        assert!(load_or_generate(coord, 1, &save_dir) == generated);
+        assert!(load_or_generate(coord, 1, &WorldSaveDir(None)) == generated);
+
+        let saved = Chunk::filled(BlockId::DIRT);
+        save_chunk(coord, &saved, &dir).unwrap();
+        assert!(load_or_generate(coord, 1, &save_dir) == saved);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loader_generates_requested_chunks_once() {
+        let mut loader = ChunkLoader::new(2);
+        let coords = [IVec3::ZERO, IVec3::new(1, -1, 2)];
+        for coord in coords {
+            loader.request(coord, 5, &WorldSaveDir(None));
+            loader.request(coord, 5, &WorldSaveDir(None));
+        }
+        assert_eq!(loader.in_flight_len(), 2);
+
+        let start = std::time::Instant::now();
+        let mut loaded = Vec::new();
+        while loader.in_flight_len() > 0 {
+            assert!(
+                start.elapsed() < std::time::Duration::from_secs(10),
+                "chunk loader stalled"
+            );
+            loaded.extend(loader.finished());
+            std::thread::yield_now();
+        }
+
+        assert_eq!(loaded.len(), 2);
+        for (coord, chunk) in loaded {
+            assert!(coords.contains(&coord));
+            assert!(!loader.is_loading(coord));
+            assert!(chunk == generate_chunk(coord, 5));
+        }
+    }
+
+    #[test]
+    fn edited_chunks_are_saved_at_exit() {
+        let dir = std::env::temp_dir().join(format!("exit-save-test-{}", std::process::id()));
+        let mut world = bevy_ecs::world::World::new();
+        world.insert_resource(WorldSaveDir(Some(dir.clone())));
+
+        let mut store = ChunkStore::default();
+        store.load(IVec3::ZERO, Chunk::default());
+        store.load(IVec3::X, Chunk::default());
+        store.set_block(IVec3::new(1, 2, 3), BlockId::STONE);
+        world.insert_resource(store);
+
+        world.run_system_cached(save_edited_chunks_system).unwrap();
+
+        let saved = load_chunk(IVec3::ZERO, &dir).unwrap();
+        assert_eq!(saved.get(1, 2, 3), BlockId::STONE);
+        assert!(load_chunk(IVec3::X, &dir).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn radius_zero_is_the_center() {
+        let center = IVec3::new(3, -1, 2);
+        assert_eq!(chunks_in_radius(center, 0), vec![center]);
+    }
+
+    #[test]
+    fn chunks_within_radius() {
+        let center = IVec3::new(10, 0, -4);
+        let coords = chunks_in_radius(center, 1);
+
+        assert_eq!(coords.len(), 7);
+        assert_eq!(coords[0], center);
+        for neighbor in ChunkStore::neighbors(center) {
+            assert!(coords.contains(&neighbor));
+        }
+        assert!(!coords.contains(&(center + IVec3::new(1, 1, 0))));
+
+        let coords = chunks_in_radius(center, 3);
+        assert!(coords.iter().all(|coord| !is_beyond(center, *coord, 3)));
+        assert!(coords.contains(&(center + IVec3::new(0, 0, 3))));
+        assert!(!coords.contains(&(center + IVec3::new(3, 3, 0))));
+        assert!(
+            coords
+                .windows(2)
+                .all(|pair| (pair[0] - center).length_squared()
+                    <= (pair[1] - center).length_squared())
+        );
+    }
+
+    #[test]
+    fn hysteresis_keeps_boundary_chunks() {
+        let config = StreamingConfig::default();
+        let center = IVec3::ZERO;
+        let loaded = center + IVec3::new(config.load_radius as i32, 0, 0);
+
+        // Moving the camera by one chunk keeps the chunk loaded.
+        assert!(!is_beyond(
+            center + IVec3::NEG_X,
+            loaded,
+            config.unload_radius
+        ));
+        assert!(is_beyond(
+            center + IVec3::new(-3, 0, 0),
+            loaded,
+            config.unload_radius
+        ));
+    }
+}
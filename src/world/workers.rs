@@ -0,0 +1,112 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Threads turning jobs of type `J` into results of type `R`.
+///
+/// Neither [`send`](Self::send) nor [`finished`](Self::finished) wait for the
+/// workers, and results come in the order they are finished. Dropping the pool
+/// waits for the workers to finish their current job.
+pub struct WorkerPool<J, R> {
+    jobs: Option<Sender<J>>,
+    // Receivers aren't `Sync`, which resources must be.
+    results: Mutex<Receiver<R>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<J: Send + 'static, R: Send + 'static> WorkerPool<J, R> {
+    /// Spawns at least one worker named `{name}-{index}`, running `run` on every job.
+    pub fn new(name: &str, worker_count: usize, run: fn(J) -> R) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<J>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count.max(1))
+            .map(|index| {
+                let jobs = job_receiver.clone();
+                let results = result_sender.clone();
+                thread::Builder::new()
+                    .name(format!("{name}-{index}"))
+                    .spawn(move || {
+                        loop {
+                            // The lock is released before running the job so other workers can pick up jobs.
+                            let job = jobs.lock().unwrap().recv();
+                            let Ok(job) = job else {
+                                break;
+                            };
+                            if results.send(run(job)).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .unwrap_or_else(|err| panic!("Failed to spawn a {name} worker: {err}"))
+            })
+            .collect();
+
+        Self {
+            jobs: Some(job_sender),
+            results: Mutex::new(result_receiver),
+            workers,
+        }
+    }
+
+    /// Hands `job` to the next free worker. Returns `false` if the workers stopped.
+    pub fn send(&self, job: J) -> bool {
+        self.jobs
+            .as_ref()
+            .is_some_and(|jobs| jobs.send(job).is_ok())
+    }
+
+    /// Results finished since the last call.
+    pub fn finished(&mut self) -> impl Iterator<Item = R> + '_ {
+        self.results.get_mut().unwrap().try_iter()
+    }
+}
+
+/// Number of workers of a pool sharing the cores with `pools - 1` others,
+/// leaving one core to the main thread.
+pub fn worker_count(pools: usize) -> usize {
+    let cores = thread::available_parallelism().map_or(2, |count| count.get());
+    (cores.saturating_sub(1) / pools.max(1)).max(1)
+}
+
+impl<J, R> Drop for WorkerPool<J, R> {
+    fn drop(&mut self) {
+        // Closing the channel stops the workers once they finish their current job.
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_job_is_run_once() {
+        let mut pool = WorkerPool::new("test", 3, |job: u32| job * 2);
+        for job in 0..20 {
+            assert!(pool.send(job));
+        }
+
+        let mut results = Vec::new();
+        while results.len() < 20 {
+            results.extend(pool.finished());
+            thread::yield_now();
+        }
+        results.sort();
+        assert_eq!(results, (0..20).map(|job| job * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pools_share_the_cores() {
+        assert!(worker_count(1) >= worker_count(2));
+        assert_eq!(worker_count(usize::MAX), 1);
+    }
+}